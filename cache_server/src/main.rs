@@ -0,0 +1,189 @@
+use std::io::Read as _;
+use std::path::PathBuf;
+
+use clap::Parser as _;
+use kedi_lang::verify::{parse_verdict_path, CacheEntry, VerdictCache, VerdictStore};
+
+/// Serves a verdict cache from a directory, so that a team can share the
+/// results of property checks.
+#[derive(clap::Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Directory holding the entries, laid out like a local cache.
+    #[arg(long)]
+    dir: PathBuf,
+
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    addr: String,
+
+    /// Path the entries are served under, the path of the cache url given
+    /// to clients.
+    #[arg(long, default_value = "")]
+    prefix: String,
+}
+
+/// Entries are a few lines, so longer bodies are refused rather than read.
+const MAX_BODY: usize = 1 << 20;
+
+fn main() {
+    let args = Args::parse();
+    let server = tiny_http::Server::http(&args.addr).expect("Could not start server");
+    let prefix = args.prefix.trim_end_matches('/');
+    eprintln!(
+        "Serving {} on http://{}{}",
+        args.dir.display(),
+        args.addr,
+        prefix
+    );
+    serve(&server, &VerdictCache::new(args.dir), prefix);
+}
+
+fn serve(server: &tiny_http::Server, cache: &VerdictCache, prefix: &str) {
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+        let (status, body) = if request.body_length().is_some_and(|len| len > MAX_BODY) {
+            (413, "Entry too large\n".to_string())
+        } else {
+            let read = request
+                .as_reader()
+                .take(MAX_BODY as u64 + 1)
+                .read_to_string(&mut body);
+            match read {
+                Ok(len) if len > MAX_BODY => (413, "Entry too large\n".to_string()),
+                Ok(_) => match request.url().strip_prefix(prefix) {
+                    Some(path) => respond(cache, request.method(), path, &body),
+                    None => (404, "Not found\n".to_string()),
+                },
+                Err(_) => (400, "Body is not valid UTF-8\n".to_string()),
+            }
+        };
+        let response = tiny_http::Response::from_string(body).with_status_code(status);
+        if let Err(e) = request.respond(response) {
+            eprintln!("Could not respond: {}", e);
+        }
+    }
+}
+
+/// Answers a request with a status code and a body.
+fn respond(
+    cache: &VerdictCache,
+    method: &tiny_http::Method,
+    url: &str,
+    body: &str,
+) -> (u16, String) {
+    let Some((hash, pred)) = parse_verdict_path(url) else {
+        return (404, "Not found\n".to_string());
+    };
+
+    match method {
+        tiny_http::Method::Get => match cache.get(&hash, pred) {
+            Ok(Some(entry)) => (200, entry.encode(&hash, pred)),
+            Ok(None) => (404, "Not found\n".to_string()),
+            Err(e) => (500, format!("{}\n", e)),
+        },
+        tiny_http::Method::Put => match CacheEntry::decode(body, &hash, pred) {
+            Some(entry) => match cache.put(&hash, pred, &entry) {
+                Ok(()) => (204, String::new()),
+                Err(e) => (500, format!("{}\n", e)),
+            },
+            None => (400, "Invalid or corrupted entry\n".to_string()),
+        },
+        _ => (405, "Method not allowed\n".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write as _;
+
+    use kedi_lang::hash::FunHash;
+    use kedi_lang::verify::{verdict_path, CachedVerdict, CheckLimits, RemoteCache, VerifyOptions};
+
+    fn entry() -> CacheEntry {
+        CacheEntry {
+            verdict: CachedVerdict::Failed {
+                args: vec![3.into()],
+                shrinks: vec![],
+            },
+            cases: 100,
+            seed: 0,
+            limits: CheckLimits::of(&VerifyOptions::default()),
+        }
+    }
+
+    #[test]
+    fn corrupted_entries_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = VerdictCache::new(dir.path().to_path_buf());
+        let hash = FunHash([1; 32]);
+        let path = verdict_path(&hash, 0);
+        let encoded = entry().encode(&hash, 0);
+
+        let put = |body: &str| respond(&cache, &tiny_http::Method::Put, &path, body);
+        assert_eq!(put(&encoded.replace("arg 3", "arg 4")).0, 400);
+        assert_eq!(put(&entry().encode(&hash, 1)).0, 400);
+        assert_eq!(put(&encoded).0, 204);
+
+        let get = respond(&cache, &tiny_http::Method::Get, &path, "");
+        assert_eq!(get, (200, encoded));
+        assert_eq!(
+            respond(&cache, &tiny_http::Method::Get, "/other", "").0,
+            404
+        );
+    }
+
+    #[test]
+    fn remote_cache_talks_to_the_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = VerdictCache::new(dir.path().to_path_buf());
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| serve(&server, &cache, "/kedi"));
+
+            let remote = RemoteCache::new(&format!("http://{}/kedi/", addr)).unwrap();
+            let hash = FunHash([2; 32]);
+            assert_eq!(remote.get(&hash, 0).unwrap(), None);
+            remote.put(&hash, 0, &entry()).unwrap();
+            assert_eq!(remote.get(&hash, 0).unwrap(), Some(entry()));
+
+            // The server stores entries like a local cache does.
+            assert_eq!(cache.get(&hash, 0).unwrap(), Some(entry()));
+
+            // Entries are only served under the prefix.
+            let other = RemoteCache::new(&format!("http://{}/other", addr)).unwrap();
+            assert_eq!(other.get(&hash, 0).unwrap(), None);
+            assert!(other.put(&hash, 0, &entry()).is_err());
+            server.unblock();
+        });
+    }
+
+    #[test]
+    fn long_bodies_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = VerdictCache::new(dir.path().to_path_buf());
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_ip().unwrap();
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| serve(&server, &cache, ""));
+
+            let hash = FunHash([3; 32]);
+            // The length alone is refused, without waiting for the body.
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            write!(
+                stream,
+                "PUT {} HTTP/1.1\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                verdict_path(&hash, 0),
+                MAX_BODY + 1
+            )
+            .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 413"), "{}", response);
+            server.unblock();
+        });
+    }
+}
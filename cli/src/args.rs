@@ -10,7 +10,16 @@ pub struct Args {
 #[derive(clap::Subcommand, Debug)]
 pub enum Command {
     Compile(CompileArgs),
+    /// Call a function and print its result.
     Run(RunArgs),
+    /// Check predicates with inputs mutated from a corpus kept across runs.
+    Fuzz(FuzzArgs),
+    /// Print the content hash of every function.
+    Hash(HashArgs),
+    /// Define functions and evaluate expressions interactively.
+    Repl(ReplArgs),
+    /// Format source files in place.
+    Fmt(FmtArgs),
 }
 
 #[derive(clap::Args, Debug)]
@@ -32,6 +41,14 @@ pub struct CompileArgs {
     pub out_linked: Option<patharg::OutputArg>,
     #[arg(long)]
     pub out_wat: Option<patharg::OutputArg>,
+
+    /// Where to keep the verdicts of property checks across runs.
+    #[arg(long, default_value = kedi_lang::verify::DEFAULT_CACHE_DIR)]
+    pub cache_dir: std::path::PathBuf,
+
+    /// A shared cache server to use instead of the cache directory.
+    #[arg(long)]
+    pub cache_url: Option<String>,
 }
 
 #[derive(clap::Args, Debug)]
@@ -41,8 +58,66 @@ pub struct RunArgs {
     #[arg(long)]
     pub export: Option<String>,
 
-    #[arg(long)]
+    #[arg(long, num_args = 1.., allow_negative_numbers = true)]
     pub parameters: Option<Vec<i32>>,
+
+    #[arg(long, value_enum, default_value_t = Backend::Interp)]
+    pub backend: Backend,
+
+    /// Maximum number of statements to execute, only for the interpreter.
+    #[arg(long)]
+    pub fuel: Option<u64>,
+
+    /// Fail on the first `invariant` that does not hold, only for the
+    /// interpreter.
+    #[arg(long)]
+    pub check_invariants: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FuzzArgs {
+    pub entry: patharg::InputArg,
+
+    /// Where to keep the inputs reaching new branches, one directory per
+    /// function.
+    #[arg(long, default_value = kedi_lang::verify::DEFAULT_CORPUS_DIR)]
+    pub corpus_dir: std::path::PathBuf,
+
+    /// Number of passing inputs to run for each function.
+    #[arg(long, default_value_t = 1000)]
+    pub cases: u32,
+
+    /// Seed for the mutations, the same seed and corpus give the same inputs.
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Interp,
+    Wasm,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct HashArgs {
+    pub entry: patharg::InputArg,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReplArgs {
+    /// A file to load the definitions of.
+    pub entry: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FmtArgs {
+    #[arg(required = true)]
+    pub files: Vec<std::path::PathBuf>,
+
+    /// List the files that are not formatted instead of formatting them,
+    /// failing if there are any.
+    #[arg(long)]
+    pub check: bool,
 }
 
 pub fn run() -> Args {
@@ -1,32 +1,109 @@
 use crate::args::CompileArgs;
-use kedi_lang::{error::annotate_error, util};
+use kedi_lang::error::annotate_error;
+use kedi_lang::hash::hash_module;
+use kedi_lang::util::loc::SrcLoc;
+use kedi_lang::verify::{self, Verdict};
+use kedi_lang::{binder, codegen_wasm, parser, renamer, simplifier};
+use miette::IntoDiagnostic;
 
 pub fn compile(opts: CompileArgs) -> Result<(), miette::Report> {
     // Read input file.
     let contents = opts.entry.read_to_string().expect("Could not read file");
+    let annotate = |e: kedi_lang::error::Error| annotate_error(e, contents.clone());
 
-    // let syntax =
-    //     kedi_lang::parser::parse(&contents).map_err(|e| annotate_error(e, contents.clone()))?;
-    // if let Some(out_syntax) = opts.out_syntax {
-    //     write_sexpr(&syntax, &out_syntax)
-    // }
+    // Each phase is dumped as soon as it succeeds, so that the phases before
+    // a failing one can be inspected.
+    let syntax = parser::parse(&contents).map_err(|e| annotate(e.into()))?;
+    if let Some(path) = &opts.out_syntax {
+        write_sexpr(&syntax, path)?;
+    }
 
-    // let plain =
-    //     kedi_lang::renamer::rename(&syntax).map_err(|e| annotate_error(e, contents.clone()))?;
+    let plain = renamer::rename(&syntax).map_err(|e| annotate(e.into()))?;
+    if let Some(path) = &opts.out_plain {
+        write_sexpr(&plain, path)?;
+    }
 
-    // if let Some(out_plain) = opts.out_plain {
-    //     write_sexpr(&plain, &out_plain)
-    // }
+    let bound =
+        binder::bind(&plain, &binder::prelude(SrcLoc::Unknown)).map_err(|e| annotate(e.into()))?;
+    let simple = simplifier::simplify(&bound).map_err(|e| annotate(e.into()))?;
+    if let Some(path) = &opts.out_simple {
+        write_sexpr(&simple, path)?;
+    }
 
-    // let simple = kedi_lang::simplifier::run(&plain);
-    // if let Some(out_simple) = opts.out_simple {
-    //     write_sexpr(&simple, &out_simple)
-    // }
+    // Check the predicates, skipping the functions that did not change.
+    let hashes = hash_module(&bound);
+    let cache: Box<dyn verify::VerdictStore> = match opts.cache_url {
+        Some(url) => match verify::RemoteCache::new(&url) {
+            Ok(remote) => Box::new(remote),
+            Err(e) => {
+                let warning = miette::miette!(
+                    severity = miette::Severity::Warning,
+                    "Not using the cache server at {url}: {e}"
+                );
+                eprintln!("{warning:?}");
+                Box::new(verify::VerdictCache::new(opts.cache_dir))
+            }
+        },
+        None => Box::new(verify::VerdictCache::new(opts.cache_dir)),
+    };
+    let reports = verify::verify_cached(&simple, &hashes, &Default::default(), &*cache);
+    if let Some(e) = reports.cache_error {
+        let warning = miette::miette!(
+            severity = miette::Severity::Warning,
+            help = "The remaining functions were checked without it.",
+            "Stopped using the verdict cache: {e}"
+        );
+        eprintln!("{warning:?}");
+    }
+    let reports = verify::check_reports(reports.reports).map_err(|e| annotate(e.into()))?;
+    for report in reports {
+        if let Verdict::GaveUp { cases, discarded } = report.verdict {
+            let warning = miette::MietteDiagnostic::new(format!(
+                "Gave up checking `{}` after {} cases, {} inputs discarded",
+                report.name.v.0, cases, discarded
+            ))
+            .with_severity(miette::Severity::Warning)
+            .with_help(
+                "Its argument predicates rejected almost every generated input, or it failed \
+                     on the type of an input no predicate describes.",
+            )
+            .with_label(miette::LabeledSpan::new_primary_with_span(
+                None,
+                report.name.a,
+            ));
+            eprintln!(
+                "{:?}",
+                miette::Report::new(warning).with_source_code(contents.clone())
+            );
+        }
+    }
+
+    let fragment = codegen_wasm::run(&simple);
+    if let Some(path) = &opts.out_fragment {
+        write_sexpr(&fragment, path)?;
+    }
+
+    let linked = codegen_wasm::link(&fragment);
+    if let Some(path) = &opts.out_linked {
+        write_sexpr(&linked, path)?;
+    }
+
+    let wasm = codegen_wasm::mk_wasm(&linked);
+    if let Some(path) = &opts.out_wat {
+        let wat = wasm
+            .to_wat()
+            .ok_or_else(|| miette::miette!("Generated an invalid module"))?;
+        path.write(wat.text).into_diagnostic()?;
+    }
+    opts.out.write(wasm.bytes).into_diagnostic()?;
 
     Ok(())
 }
 
-// fn write_sexpr<T: util::pp::SExpr>(sexpr: &T, path: &patharg::OutputArg) {
-//     path.write(util::pp::print(sexpr, &util::pp::Options::default()) + "\n")
-//         .expect("Could not write file");
-// }
+fn write_sexpr<T: sexpr::SExpr>(
+    value: &T,
+    path: &patharg::OutputArg,
+) -> Result<(), miette::Report> {
+    path.write(value.to_sexpr().pretty(80) + "\n")
+        .into_diagnostic()
+}
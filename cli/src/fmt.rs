@@ -0,0 +1,30 @@
+use std::process::ExitCode;
+
+use crate::args::FmtArgs;
+use kedi_lang::error::annotate_error;
+use kedi_lang::formatter::format;
+
+pub fn fmt(opts: FmtArgs) -> Result<ExitCode, miette::Report> {
+    let mut unformatted = false;
+    for path in &opts.files {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| miette::miette!("Could not read {}: {}", path.display(), e))?;
+        let formatted = format(&contents).map_err(|e| annotate_error(e, contents.clone()))?;
+        if formatted == contents {
+            continue;
+        }
+
+        if opts.check {
+            println!("{}", path.display());
+            unformatted = true;
+        } else {
+            std::fs::write(path, formatted)
+                .map_err(|e| miette::miette!("Could not write {}: {}", path.display(), e))?;
+        }
+    }
+
+    match unformatted {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
@@ -0,0 +1,181 @@
+use std::io::{self, Write};
+
+use crate::args::FuzzArgs;
+use kedi_lang::error::annotate_error;
+use kedi_lang::runner::CompileResult;
+use kedi_lang::verify::{self, FunctionReport, FuzzOptions, Verdict, VerifyOptions};
+
+pub fn fuzz(opts: FuzzArgs) -> Result<(), miette::Report> {
+    // Read input file.
+    let contents = opts.entry.read_to_string().expect("Could not read file");
+
+    let out =
+        kedi_lang::runner::runner(&contents).map_err(|e| annotate_error(e, contents.clone()))?;
+
+    fuzz_compiled(&opts, &out, &contents, &mut io::stdout())
+}
+
+/// Fuzzes the functions of `out` that have predicates, writing how each one
+/// went to `w` and failing on the first counterexample.
+fn fuzz_compiled(
+    opts: &FuzzArgs,
+    out: &CompileResult,
+    contents: &str,
+    w: &mut impl Write,
+) -> Result<(), miette::Report> {
+    let options = FuzzOptions {
+        verify: VerifyOptions {
+            cases: opts.cases,
+            seed: opts.seed,
+            ..Default::default()
+        },
+        corpus_dir: Some(opts.corpus_dir.clone()),
+    };
+    let reports = verify::fuzz(&out.simple, &options).map_err(|e| {
+        miette::miette!(
+            "Could not update the corpus in {}: {}",
+            opts.corpus_dir.display(),
+            e
+        )
+    })?;
+
+    for report in &reports {
+        let name = &report.name.v.0;
+        match &report.verdict {
+            Verdict::Passed { cases } => writeln!(
+                w,
+                "{}: passed {} cases, {} branches covered, {} inputs in the corpus",
+                name,
+                cases,
+                report.coverage.len(),
+                report.corpus.len()
+            ),
+            Verdict::GaveUp { cases, discarded } => writeln!(
+                w,
+                "{}: gave up after {} cases, {} inputs discarded",
+                name, cases, discarded
+            ),
+            Verdict::Failed(_) => continue,
+        }
+        .expect("Could not write output");
+    }
+
+    let reports = reports
+        .into_iter()
+        .map(|report| FunctionReport {
+            id: report.id,
+            name: report.name,
+            verdict: report.verdict,
+            cached: false,
+        })
+        .collect();
+    verify::check_reports(reports).map_err(|e| annotate_error(e, contents.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::args::{Args, Command};
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("kedi-cli-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn fuzz_src(src: &str, opts: &FuzzArgs) -> (Result<(), miette::Report>, String) {
+        let out = kedi_lang::runner::runner(src).unwrap();
+        let mut w = vec![];
+        let result = fuzz_compiled(opts, &out, src, &mut w);
+        (result, String::from_utf8(w).unwrap())
+    }
+
+    fn parse(args: &[&str]) -> FuzzArgs {
+        match Args::try_parse_from(args).unwrap().command {
+            Command::Fuzz(opts) => opts,
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arguments() {
+        let opts = parse(&["kedi", "fuzz", "f.kedi"]);
+        assert_eq!(
+            opts.corpus_dir,
+            std::path::Path::new(verify::DEFAULT_CORPUS_DIR)
+        );
+        assert_eq!((opts.cases, opts.seed), (1000, 0));
+
+        let opts = parse(&[
+            "kedi",
+            "fuzz",
+            "f.kedi",
+            "--corpus-dir",
+            "corpus",
+            "--cases",
+            "10",
+            "--seed",
+            "3",
+        ]);
+        assert_eq!(opts.corpus_dir, std::path::Path::new("corpus"));
+        assert_eq!((opts.cases, opts.seed), (10, 3));
+    }
+
+    #[test]
+    fn corpora_are_kept_across_runs() {
+        let dir = temp_dir("fuzz");
+        let dir_arg = dir.to_str().unwrap();
+        let opts = parse(&[
+            "kedi",
+            "fuzz",
+            "f.kedi",
+            "--corpus-dir",
+            dir_arg,
+            "--cases",
+            "100",
+        ]);
+        let src = "fn sign n : number? n : number? result {
+            if gt? n 0 { return 1; }
+            return 0;
+        }";
+
+        let (result, printed) = fuzz_src(src, &opts);
+        result.unwrap();
+        assert!(
+            printed.starts_with("sign: passed 100 cases, "),
+            "{}",
+            printed
+        );
+        let saved = std::fs::read_dir(dir.join("sign")).unwrap().count();
+        assert!(saved > 0);
+
+        let (result, _) = fuzz_src(src, &opts);
+        result.unwrap();
+        assert!(std::fs::read_dir(dir.join("sign")).unwrap().count() >= saved);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn counterexamples_fail() {
+        let dir = temp_dir("fuzz-fail");
+        let opts = parse(&[
+            "kedi",
+            "fuzz",
+            "f.kedi",
+            "--corpus-dir",
+            dir.to_str().unwrap(),
+        ]);
+        let src = "fn small n : number? n : lt? result 10 { return n; }";
+
+        let (result, printed) = fuzz_src(src, &opts);
+        let err = format!("{:?}", result.unwrap_err());
+        assert!(err.contains("small 10"), "{}", err);
+        assert_eq!(printed, "");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
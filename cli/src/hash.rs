@@ -0,0 +1,17 @@
+use crate::args::HashArgs;
+use kedi_lang::error::annotate_error;
+use kedi_lang::hash::hash_module;
+
+pub fn hash(opts: HashArgs) -> Result<(), miette::Report> {
+    // Read input file.
+    let contents = opts.entry.read_to_string().expect("Could not read file");
+
+    let out =
+        kedi_lang::runner::runner(&contents).map_err(|e| annotate_error(e, contents.clone()))?;
+
+    let hashes = hash_module(&out.bound);
+    for (id, fun) in out.bound.module_funs() {
+        println!("{}  {}", hashes[&id], fun.v.name.v.0);
+    }
+    Ok(())
+}
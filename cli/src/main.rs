@@ -1,19 +1,21 @@
 mod args;
 mod compile;
+mod fmt;
+mod fuzz;
+mod hash;
+mod repl;
 mod run;
 
-// fn main() -> Result<(), miette::Report> {
-//     let args = args::run();
-//     match args.command {
-//         args::Command::Compile(opts) => compile::compile(opts),
-//         args::Command::Run(opts) => run::run(opts),
-//     }
-// }
+use std::process::ExitCode;
 
-fn main() {
-    print!("{}", f(0));
-}
-
-fn f(i: i32) -> i32 {
-    f(i)
+fn main() -> Result<ExitCode, miette::Report> {
+    let args = args::run();
+    match args.command {
+        args::Command::Compile(opts) => compile::compile(opts).map(|()| ExitCode::SUCCESS),
+        args::Command::Run(opts) => run::run(opts),
+        args::Command::Fuzz(opts) => fuzz::fuzz(opts).map(|()| ExitCode::SUCCESS),
+        args::Command::Hash(opts) => hash::hash(opts).map(|()| ExitCode::SUCCESS),
+        args::Command::Repl(opts) => repl::repl(opts).map(|()| ExitCode::SUCCESS),
+        args::Command::Fmt(opts) => fmt::fmt(opts),
+    }
 }
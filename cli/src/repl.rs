@@ -0,0 +1,619 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+
+use crate::args::ReplArgs;
+use kedi_lang::error::{annotate_error, to_diagnostic, Error};
+use kedi_lang::formatter::{segments, Segment};
+use kedi_lang::interpreter::{self, InterpretOptions};
+use kedi_lang::parser::{self, syntax};
+use kedi_lang::renamer::plain;
+use kedi_lang::runner::{runner, CompileResult};
+use kedi_lang::util::loc::SrcLoc;
+use kedi_lang::verify::{self, Verdict, VerifyOptions};
+use sexpr::SExpr;
+
+/// The function expressions are wrapped in to be evaluated.
+const EXPR_FUN: &str = "__repl_expr";
+
+const HELP: &str = "\
+fn <name> ... { ... }   define a function, replacing any with the same name
+struct <name> { ... }   define a struct, replacing any with the same name
+type <name> = ...;      define a type, replacing any with the same name
+<expr>                  evaluate an expression
+:ir plain <fn>          print the plain IR of a function
+:ir simple <fn>         print the simple IR of a function
+:check <fn>             check the predicates of a function
+:reload [file]          replace the definitions with the ones of a file
+:defs                   list the definitions
+:help                   print this message
+:quit                   exit";
+
+pub fn repl(opts: ReplArgs) -> Result<(), miette::Report> {
+    let mut session = Session::default();
+    if let Some(path) = opts.entry {
+        print_result(session.reload(Some(path.as_str())));
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let Some(input) = read_input(&mut lines) else {
+            return Ok(());
+        };
+        match input.trim() {
+            "" => continue,
+            ":quit" | ":q" => return Ok(()),
+            input => print_result(session.handle(input)),
+        }
+    }
+}
+
+/// Reads a line, and the following ones while braces are left open.
+/// Braces in strings and comments do not count.
+fn read_input(lines: &mut impl Iterator<Item = io::Result<String>>) -> Option<String> {
+    let mut input = String::new();
+    let mut prompt = "kedi> ";
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush().ok()?;
+
+        let line = lines.next()?.ok()?;
+        input.push_str(&line);
+        input.push('\n');
+
+        if open_braces(&input) <= 0 {
+            return Some(input);
+        }
+        prompt = "  ... ";
+    }
+}
+
+/// The number of `{` in the code of `input` not closed by a `}`.
+fn open_braces(input: &str) -> isize {
+    segments(input)
+        .into_iter()
+        .filter(|(kind, _)| *kind == Segment::Code)
+        .flat_map(|(_, range)| input[range].chars())
+        .map(|c| match c {
+            '{' => 1,
+            '}' => -1,
+            _ => 0,
+        })
+        .sum()
+}
+
+fn print_result(result: Result<String, miette::Report>) {
+    match result {
+        Ok(out) if out.is_empty() => {}
+        Ok(out) => println!("{}", out),
+        Err(report) => eprintln!("{:?}", report),
+    }
+}
+
+/// The functions defined so far. They are kept as source, and compiled
+/// again together with every new input.
+#[derive(Default)]
+struct Session {
+    /// Name and source of each definition, in the order they were entered.
+    defs: Vec<(String, String)>,
+    /// The file loaded last, for `:reload` without arguments.
+    file: Option<String>,
+}
+
+impl Session {
+    fn handle(&mut self, input: &str) -> Result<String, miette::Report> {
+        let mut words = input.split_whitespace();
+        match words.next() {
+            Some(":help") => Ok(HELP.to_string()),
+            Some(":defs") => Ok(self
+                .defs
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join("\n")),
+            Some(":ir") => match (words.next(), words.next()) {
+                (Some("plain"), Some(name)) => self.ir_plain(name),
+                (Some("simple"), Some(name)) => self.ir_simple(name),
+                _ => Err(miette::miette!("Usage: :ir plain|simple <fn>")),
+            },
+            Some(":check") => match words.next() {
+                Some(name) => self.check(name),
+                None => Err(miette::miette!("Usage: :check <fn>")),
+            },
+            Some(":reload") => self.reload(words.next()),
+            Some(command) if command.starts_with(':') => {
+                Err(miette::miette!("Unknown command `{}`, try :help", command))
+            }
+            _ => match first_word(input) {
+                Some("fn" | "struct" | "type") => self.define(input),
+                _ => self.eval(input),
+            },
+        }
+    }
+
+    fn source(&self) -> Source {
+        let mut source = Source::default();
+        for (ix, (name, src)) in self.defs.iter().enumerate() {
+            if ix > 0 {
+                source.text.push('\n');
+            }
+            source.push(name, src);
+        }
+        source
+    }
+
+    fn compile(&self, source: &Source) -> Result<CompileResult, miette::Report> {
+        runner(&source.text).map_err(|e| source.annotate(e))
+    }
+
+    /// Adds the definitions in `input`, keeping the session unchanged if they
+    /// do not compile.
+    fn define(&mut self, input: &str) -> Result<String, miette::Report> {
+        let new_defs = split_defs(input)?;
+        let mut defs = self
+            .defs
+            .iter()
+            .filter(|(name, _)| new_defs.iter().all(|(new, _)| new != name))
+            .cloned()
+            .collect::<Vec<_>>();
+        defs.extend(new_defs.iter().cloned());
+
+        let candidate = Session {
+            defs,
+            file: self.file.clone(),
+        };
+        candidate.compile(&candidate.source())?;
+        *self = candidate;
+
+        Ok(new_defs
+            .iter()
+            .map(|(name, _)| format!("defined {}", name))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    fn eval(&self, expr: &str) -> Result<String, miette::Report> {
+        let mut source = self.source();
+        source
+            .text
+            .push_str(&format!("\nfn {} {{ return ", EXPR_FUN));
+        source.push("input", expr.trim().trim_end_matches(';'));
+        source.text.push_str("; }");
+        let out = self.compile(&source)?;
+
+        let options = InterpretOptions {
+            fuel_limit: Some(10_000_000),
+            ..Default::default()
+        };
+        let result =
+            interpreter::run(options, &out.simple, EXPR_FUN, vec![]).map_err(|mut e| {
+                e.stack.retain(|frame| frame.function.0 != EXPR_FUN);
+                source.annotate(e)
+            })?;
+        Ok(result.value.to_string())
+    }
+
+    fn ir_plain(&self, name: &str) -> Result<String, miette::Report> {
+        let out = self.compile(&self.source())?;
+        out.plain
+            .statements
+            .iter()
+            .find_map(|stmt| match stmt {
+                plain::TopLevelStmt::FunDef(f) if f.name.v.0 == name => {
+                    Some(f.to_sexpr().pretty(80))
+                }
+                plain::TopLevelStmt::StructDef(s) if s.name.v.0 == name => {
+                    Some(s.to_sexpr().pretty(80))
+                }
+                plain::TopLevelStmt::TypeDef(t) if t.name.v.0 == name => {
+                    Some(t.to_sexpr().pretty(80))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| unknown_function(name))
+    }
+
+    fn ir_simple(&self, name: &str) -> Result<String, miette::Report> {
+        let out = self.compile(&self.source())?;
+        let id = out
+            .simple
+            .lookup(&syntax::Ident(name.to_string()))
+            .ok_or_else(|| unknown_function(name))?;
+        Ok(out.simple.get(id).to_sexpr().pretty(80))
+    }
+
+    fn check(&self, name: &str) -> Result<String, miette::Report> {
+        let source = self.source();
+        let out = self.compile(&source)?;
+        let (id, fun) = out
+            .simple
+            .module_funs()
+            .find(|(_, fun)| fun.name.v.0 == name)
+            .ok_or_else(|| unknown_function(name))?;
+        if fun.implementation.preds.is_empty() {
+            return Ok(format!("{} has no predicates", name));
+        }
+
+        match verify::verify_fun(&out.simple, id, &VerifyOptions::default()) {
+            Verdict::Passed { cases } => Ok(format!("passed {} cases", cases)),
+            Verdict::GaveUp { cases, discarded } => Ok(format!(
+                "gave up after {} cases, {} inputs discarded",
+                cases, discarded
+            )),
+            Verdict::Failed(counterexample) => {
+                let error = verify::Error::from(verify::ContractViolationError {
+                    function: fun.name.clone(),
+                    counterexample: Box::new(counterexample),
+                });
+                Err(source.annotate(error))
+            }
+        }
+    }
+
+    /// Replaces the definitions with the ones in `path`, or in the file
+    /// loaded last.
+    fn reload(&mut self, path: Option<&str>) -> Result<String, miette::Report> {
+        let path = path
+            .map(str::to_string)
+            .or_else(|| self.file.clone())
+            .ok_or_else(|| miette::miette!("Usage: :reload <file>"))?;
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| miette::miette!("Could not read {}: {}", path, e))?;
+        // Errors are shown in the file, not in the definitions split from it.
+        runner(&contents).map_err(|e| {
+            miette::Report::new(to_diagnostic(e))
+                .with_source_code(miette::NamedSource::new(&path, contents.clone()))
+        })?;
+
+        let candidate = Session {
+            defs: split_defs(&contents)?,
+            file: Some(path.clone()),
+        };
+        *self = candidate;
+        Ok(format!(
+            "loaded {} definitions from {}",
+            self.defs.len(),
+            path
+        ))
+    }
+}
+
+/// The source compiled for an input, and the part of it each definition,
+/// or the evaluated expression, takes. Errors are shown in the part they
+/// are in, so that they point at the code as it was entered rather than at
+/// the whole session.
+#[derive(Default)]
+struct Source {
+    text: String,
+    /// Name and range in `text` of each part.
+    parts: Vec<(String, Range<usize>)>,
+}
+
+impl Source {
+    fn push(&mut self, name: &str, code: &str) {
+        let start = self.text.len();
+        self.text.push_str(code);
+        self.parts.push((name.to_string(), start..self.text.len()));
+    }
+
+    fn annotate<T: Into<Error>>(&self, error: T) -> miette::Report {
+        let mut diagnostic = to_diagnostic(error);
+        let labels = diagnostic.labels.take().unwrap_or_default();
+
+        // A label is in the part it overlaps, otherwise in the one before
+        // it, for labels in the code wrapping the expression.
+        let part_of = |label: &miette::LabeledSpan| {
+            let end = label.offset() + label.len();
+            self.parts
+                .iter()
+                .position(|(_, range)| {
+                    (label.offset() < range.end && end > range.start)
+                        || (label.is_empty() && range.contains(&label.offset()))
+                })
+                .or_else(|| {
+                    self.parts
+                        .iter()
+                        .rposition(|(_, range)| range.start <= label.offset())
+                })
+        };
+        let mut parts: Vec<(usize, Vec<miette::LabeledSpan>)> = vec![];
+        for label in labels {
+            let Some(part) = part_of(&label) else {
+                continue;
+            };
+            let range = &self.parts[part].1;
+            let start = label.offset().clamp(range.start, range.end);
+            let end = (label.offset() + label.len()).clamp(start, range.end);
+            let span = (start - range.start, end - start);
+            let label = match label.primary() {
+                true => miette::LabeledSpan::new_primary_with_span(
+                    label.label().map(str::to_string),
+                    span,
+                ),
+                false => {
+                    miette::LabeledSpan::new_with_span(label.label().map(str::to_string), span)
+                }
+            };
+            match parts.iter_mut().find(|(ix, _)| *ix == part) {
+                Some((_, labels)) => labels.push(label),
+                None => parts.push((part, vec![label])),
+            }
+        }
+
+        // The error is shown in the part of its primary label.
+        if let Some(ix) = parts
+            .iter()
+            .position(|(_, labels)| labels.iter().any(|label| label.primary()))
+        {
+            let primary = parts.remove(ix);
+            parts.insert(0, primary);
+        }
+        let mut parts = parts.into_iter().map(|(part, labels)| {
+            let (name, range) = &self.parts[part];
+            let source = miette::NamedSource::new(name, self.text[range.clone()].to_string());
+            (name, labels, source)
+        });
+        let Some((_, labels, source)) = parts.next() else {
+            return miette::Report::new(diagnostic);
+        };
+        diagnostic.labels = Some(labels);
+        miette::Report::new(PartDiagnostic {
+            diagnostic,
+            source,
+            others: parts
+                .map(|(name, labels, source)| PartDiagnostic {
+                    diagnostic: miette::MietteDiagnostic::new(format!("In `{}`:", name))
+                        .with_severity(miette::Severity::Advice)
+                        .with_labels(labels),
+                    source,
+                    others: vec![],
+                })
+                .collect(),
+        })
+    }
+}
+
+/// A diagnostic shown against one part of a `Source`, followed by the
+/// labels it has in the other parts.
+#[derive(Debug)]
+struct PartDiagnostic {
+    diagnostic: miette::MietteDiagnostic,
+    source: miette::NamedSource<String>,
+    others: Vec<PartDiagnostic>,
+}
+
+impl fmt::Display for PartDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.diagnostic.fmt(f)
+    }
+}
+
+impl std::error::Error for PartDiagnostic {}
+
+impl miette::Diagnostic for PartDiagnostic {
+    fn severity(&self) -> Option<miette::Severity> {
+        self.diagnostic.severity()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.diagnostic.help()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = miette::LabeledSpan> + '_>> {
+        self.diagnostic.labels()
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.source)
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn miette::Diagnostic> + 'a>> {
+        if self.others.is_empty() {
+            return None;
+        }
+        Some(Box::new(
+            self.others.iter().map(|d| d as &dyn miette::Diagnostic),
+        ))
+    }
+}
+
+/// The first word of the code in `input`, skipping comments.
+fn first_word(input: &str) -> Option<&str> {
+    segments(input)
+        .into_iter()
+        .filter(|(kind, _)| *kind != Segment::Comment)
+        .find_map(|(_, range)| input[range].split_whitespace().next())
+}
+
+/// Splits the source of a module into its function, struct and type
+/// definitions.
+fn split_defs(source: &str) -> Result<Vec<(String, String)>, miette::Report> {
+    let module = parser::parse(source).map_err(|e| annotate_error(e, source.to_string()))?;
+    Ok(module
+        .statements
+        .v
+        .iter()
+        .map(|stmt| {
+            let (name, loc) = match stmt {
+                syntax::TopLevelStmt::FunDef(f) => (&f.name.v, &f.a),
+                syntax::TopLevelStmt::StructDef(s) => (&s.name.v, &s.a),
+                syntax::TopLevelStmt::TypeDef(t) => (&t.name.v, &t.a),
+            };
+            let src = match loc {
+                SrcLoc::Known(span) => &source[span.start.0..span.start.0 + span.length],
+                SrcLoc::Unknown => unreachable!("parsed definitions have a location"),
+            };
+            (name.0.clone(), src.to_string())
+        })
+        .collect())
+}
+
+fn unknown_function(name: &str) -> miette::Report {
+    miette::miette!("Unknown function `{}`", name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_are_kept_and_replaced() {
+        let mut session = Session::default();
+        assert_eq!(
+            session
+                .handle("fn double n { let r = add n n; return r; }")
+                .unwrap(),
+            "defined double"
+        );
+        assert_eq!(session.handle("double 21").unwrap(), "42");
+
+        session
+            .handle("fn double n { let r = add n 1; return r; }")
+            .unwrap();
+        assert_eq!(session.handle("double 21").unwrap(), "22");
+        assert_eq!(session.defs.len(), 1);
+
+        // Definitions that do not compile leave the session as it was.
+        assert!(session.handle("fn broken n { return nope; }").is_err());
+        assert_eq!(session.handle(":defs").unwrap(), "double");
+    }
+
+    #[test]
+    fn structs_are_definitions() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle("struct Point { x, y }").unwrap(),
+            "defined Point"
+        );
+        assert_eq!(session.handle("Point 1 2").unwrap(), "Point { x: 1, y: 2 }");
+        assert_eq!(session.handle("(Point 1 2).y").unwrap(), "2");
+        assert!(session.handle(":ir plain Point").unwrap().contains("Point"));
+    }
+
+    #[test]
+    fn types_are_definitions() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.handle("type Shape = Dot | Square side;").unwrap(),
+            "defined Shape"
+        );
+        session
+            .handle("fn area s { match s { Dot => { return 0; } Square n => { return n * n; } } }")
+            .unwrap();
+        assert_eq!(session.handle("area (Square 3)").unwrap(), "9");
+        assert_eq!(session.handle("area Dot").unwrap(), "0");
+        assert!(session
+            .handle(":ir plain Shape")
+            .unwrap()
+            .contains("Square"));
+    }
+
+    #[test]
+    fn commands() {
+        let mut session = Session::default();
+        session
+            .handle("fn inc n : gt? result n { let r = add n 1; return r; }")
+            .unwrap();
+
+        assert!(session.handle(":ir plain inc").unwrap().contains("inc"));
+        assert!(session.handle(":ir simple inc").unwrap().contains("inc"));
+        assert_eq!(session.handle(":check inc").unwrap(), "passed 100 cases");
+        assert!(session.handle(":ir plain nope").is_err());
+        assert!(session.handle(":nope").is_err());
+    }
+
+    /// The name of the source of each label, the line it is on, and the code
+    /// it points at.
+    fn labels(report: &dyn miette::Diagnostic) -> Vec<(String, usize, String)> {
+        let source = report.source_code().unwrap();
+        report
+            .labels()
+            .into_iter()
+            .flatten()
+            .map(|label| {
+                let contents = source.read_span(label.inner(), 0, 0).unwrap();
+                let code = std::str::from_utf8(contents.data()).unwrap();
+                let start = label.offset() - contents.span().offset();
+                (
+                    contents.name().unwrap().to_string(),
+                    contents.line(),
+                    code[start..start + label.len()].to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn errors_are_shown_in_the_code_entered() {
+        let mut session = Session::default();
+        session.handle("fn inc n { return n + 1; }").unwrap();
+        let error = session
+            .handle("fn first a {\n  let x = a.x;\n  return x;\n}")
+            .unwrap_err();
+        assert_eq!(
+            labels(error.as_ref()),
+            vec![("first".to_string(), 1, "x".to_string())]
+        );
+
+        session
+            .handle("fn at a i {\n  let x = a[i];\n  return x;\n}")
+            .unwrap();
+        let error = session.handle("at ([1]) 5").unwrap_err();
+        assert_eq!(
+            labels(error.as_ref()),
+            vec![("at".to_string(), 1, "a[i]".to_string())]
+        );
+        let help = error.help().unwrap().to_string();
+        assert!(!help.contains(EXPR_FUN), "{}", help);
+
+        let error = session.handle("inc nope").unwrap_err();
+        assert_eq!(
+            labels(error.as_ref()),
+            vec![("input".to_string(), 0, "nope".to_string())]
+        );
+
+        // Labels in other definitions are shown against them.
+        let error = session.handle("inc 1 2").unwrap_err();
+        assert_eq!(
+            labels(error.as_ref()),
+            vec![("input".to_string(), 0, "inc 1 2".to_string())]
+        );
+        let related = error.related().unwrap().collect::<Vec<_>>();
+        assert_eq!(
+            related.iter().flat_map(|d| labels(*d)).collect::<Vec<_>>(),
+            vec![("inc".to_string(), 0, "inc".to_string())]
+        );
+
+        let error = session.handle("div 1 \"a\"").unwrap_err();
+        let help = error.help().unwrap().to_string();
+        assert!(
+            help.contains("`div`") && !help.contains(EXPR_FUN),
+            "{}",
+            help
+        );
+    }
+
+    #[test]
+    fn doc_comments_are_kept() {
+        let mut session = Session::default();
+        assert_eq!(
+            session
+                .handle("/// Squares.\nfn sq n { return n * n; }")
+                .unwrap(),
+            "defined sq"
+        );
+        assert!(session.defs[0].1.starts_with("/// Squares.\n"));
+        assert_eq!(session.handle("// A comment.\nsq 3").unwrap(), "9");
+    }
+
+    #[test]
+    fn braces_in_strings_and_comments_are_not_counted() {
+        assert_eq!(open_braces("fn f { return 1; }"), 0);
+        assert_eq!(open_braces("fn f {"), 1);
+        assert_eq!(open_braces("fn f { return \"{\";"), 1);
+        assert_eq!(open_braces("fn f { return \"}\"; }"), 0);
+        assert_eq!(open_braces("fn f { // }\n"), 1);
+        assert_eq!(open_braces("concat \"a\\\"{\" \"b\""), 0);
+    }
+}
@@ -1,21 +1,292 @@
-use crate::args::RunArgs;
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+use crate::args::{Backend, RunArgs};
+use kedi_lang::codegen_wasm;
 use kedi_lang::error::annotate_error;
-use wasm_exec::execute_wasm;
+use kedi_lang::interpreter::{self, InterpretErrorKind, InterpretOptions, KediValue};
+use kedi_lang::runner::CompileResult;
+use wasm_exec::ExecuteWasmResult;
+
+/// How a run ended, printed as `status: <name>`. Every failure exits with
+/// its own code, compile errors exit with 1.
+#[derive(Debug, PartialEq, Eq)]
+enum Status {
+    Ok,
+    InvalidCall,
+    OutOfFuel,
+    Trap,
+    Timeout,
+}
+
+impl Status {
+    fn name(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::InvalidCall => "invalid-call",
+            Status::OutOfFuel => "out-of-fuel",
+            Status::Trap => "trap",
+            Status::Timeout => "timeout",
+        }
+    }
 
-pub fn run(opts: RunArgs) -> Result<(), miette::Report> {
-    let parameters = opts.parameters.unwrap_or_default();
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Status::Ok => ExitCode::SUCCESS,
+            Status::InvalidCall => ExitCode::from(2),
+            Status::OutOfFuel => ExitCode::from(3),
+            Status::Trap => ExitCode::from(4),
+            Status::Timeout => ExitCode::from(5),
+        }
+    }
+}
+
+pub fn run(opts: RunArgs) -> Result<ExitCode, miette::Report> {
+    let parameters = opts.parameters.clone().unwrap_or_default();
     let export = opts.export.as_deref().unwrap_or("main");
 
     // Read input file.
     let contents = opts.entry.read_to_string().expect("Could not read file");
 
-    // let syntax =
-    //     kedi_lang::parser::parse(&contents).map_err(|e| annotate_error(e, contents.clone()))?;
-    // let plain =
-    //     kedi_lang::renamer::rename(&syntax).map_err(|e| annotate_error(e, contents.clone()))?;
-    // let simple = kedi_lang::simplifier::run(&plain);
+    let out =
+        kedi_lang::runner::runner(&contents).map_err(|e| annotate_error(e, contents.clone()))?;
+
+    let status = run_compiled(
+        &opts,
+        &out,
+        &contents,
+        export,
+        parameters,
+        &mut io::stdout(),
+    );
+    Ok(status.exit_code())
+}
+
+/// Runs `export` with the backend of `opts`, writing the result, the fuel
+/// used and the status to `w`. Errors go to stderr.
+fn run_compiled(
+    opts: &RunArgs,
+    out: &CompileResult,
+    contents: &str,
+    export: &str,
+    parameters: Vec<i32>,
+    w: &mut impl Write,
+) -> Status {
+    let status = match opts.backend {
+        Backend::Interp => run_interp(opts, out, contents, export, parameters, w),
+        Backend::Wasm => run_wasm(out, export, &parameters, w),
+    };
+    writeln!(w, "status: {}", status.name()).expect("Could not write output");
+    status
+}
+
+fn run_interp(
+    opts: &RunArgs,
+    out: &CompileResult,
+    contents: &str,
+    export: &str,
+    parameters: Vec<i32>,
+    w: &mut impl Write,
+) -> Status {
+    let options = InterpretOptions {
+        fuel_limit: opts.fuel,
+        check_invariants: opts.check_invariants,
+        ..Default::default()
+    };
+    let args = parameters.into_iter().map(KediValue::from).collect();
+
+    match interpreter::run(options, &out.simple, export, args) {
+        Ok(result) => {
+            writeln!(w, "result: {}", result.value).expect("Could not write output");
+            writeln!(w, "fuel: {}", result.fuel_used).expect("Could not write output");
+            Status::Ok
+        }
+        Err(e) => {
+            writeln!(w, "fuel: {}", e.fuel_used).expect("Could not write output");
+            let status = match e.kind {
+                InterpretErrorKind::UnknownFunction { .. }
+                | InterpretErrorKind::WrongArity { .. } => Status::InvalidCall,
+                InterpretErrorKind::OutOfFuel { .. } => Status::OutOfFuel,
+                InterpretErrorKind::Timeout { .. } => Status::Timeout,
+                InterpretErrorKind::MaxDepthExceeded { .. }
+                | InterpretErrorKind::MissingReturn
+                | InterpretErrorKind::InvariantViolated { .. }
+                | InterpretErrorKind::DivisionByZero
+                | InterpretErrorKind::TypeMismatch { .. }
+                | InterpretErrorKind::InvalidSlice { .. }
+                | InterpretErrorKind::IndexOutOfBounds { .. }
+                | InterpretErrorKind::MissingField { .. }
+                | InterpretErrorKind::NoMatchingArm { .. } => Status::Trap,
+            };
+            eprintln!("{:?}", annotate_error(e, contents.to_string()));
+            status
+        }
+    }
+}
+
+/// Runs the exported wrapper of `export`, the engine does not count fuel.
+fn run_wasm(out: &CompileResult, export: &str, parameters: &[i32], w: &mut impl Write) -> Status {
+    let arity = out
+        .simple
+        .module_funs()
+        .find(|(_, fun)| fun.name.v.0 == export)
+        .map(|(_, fun)| fun.arity());
+    match arity {
+        None => {
+            eprintln!("Unknown function `{}`", export);
+            return Status::InvalidCall;
+        }
+        Some(arity) if arity != parameters.len() => {
+            eprintln!("Expected {} arguments, got {}", arity, parameters.len());
+            return Status::InvalidCall;
+        }
+        Some(_) => {}
+    }
+
+    let linked = codegen_wasm::link(&codegen_wasm::run(&out.simple));
+    let wasm = codegen_wasm::mk_wasm(&linked);
+
+    match wasm_exec::execute_wasm(&wasm.bytes, export, parameters) {
+        ExecuteWasmResult::Ok(value) => {
+            writeln!(w, "result: {}", value).expect("Could not write output");
+            Status::Ok
+        }
+        ExecuteWasmResult::Trap(message) => {
+            eprintln!("{}", message);
+            Status::Trap
+        }
+        ExecuteWasmResult::Timeout() => Status::Timeout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Args, Command};
+    use clap::Parser as _;
+
+    fn args(backend: Backend, fuel: Option<u64>, check_invariants: bool) -> RunArgs {
+        RunArgs {
+            entry: patharg::InputArg::Stdin,
+            export: None,
+            parameters: None,
+            backend,
+            fuel,
+            check_invariants,
+        }
+    }
+
+    /// Runs `export` of `src` with the interpreter, returning the status and
+    /// what was printed.
+    fn interp(src: &str, opts: &RunArgs, export: &str, parameters: Vec<i32>) -> (Status, String) {
+        let out = kedi_lang::runner::runner(src).unwrap();
+        let mut w = vec![];
+        let status = run_compiled(opts, &out, src, export, parameters, &mut w);
+        (status, String::from_utf8(w).unwrap())
+    }
+
+    const SRC: &str = "
+        fn double n { return add n n; }
+        fn spin n { while true { n = add n 1; } return n; }
+        fn broken n { return div n 0; }
+        fn checked n { invariant gt? n 0; return n; }
+    ";
+
+    fn parse(args: &[&str]) -> RunArgs {
+        match Args::try_parse_from(args).unwrap().command {
+            Command::Run(opts) => opts,
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn arguments() {
+        let opts = parse(&["kedi", "run", "f.kedi", "--parameters", "-5"]);
+        assert_eq!(opts.parameters, Some(vec![-5]));
+
+        let opts = parse(&["kedi", "run", "f.kedi", "--parameters", "1", "2", "3"]);
+        assert_eq!(opts.parameters, Some(vec![1, 2, 3]));
+
+        let opts = parse(&["kedi", "run", "--parameters", "-1", "-2", "--", "f.kedi"]);
+        assert_eq!(opts.parameters, Some(vec![-1, -2]));
+        assert!(Args::try_parse_from(["kedi", "run", "f.kedi", "--parameters"]).is_err());
+    }
+
+    #[test]
+    fn ok_prints_the_result_and_fuel() {
+        let opts = args(Backend::Interp, None, false);
+        let (status, printed) = interp(SRC, &opts, "double", vec![21]);
+        assert_eq!(status, Status::Ok);
+        let lines = printed.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3, "{}", printed);
+        assert_eq!(lines[0], "result: 42");
+        assert!(lines[1]
+            .strip_prefix("fuel: ")
+            .unwrap()
+            .parse::<u64>()
+            .is_ok());
+        assert_eq!(lines[2], "status: ok");
+    }
+
+    #[test]
+    fn running_out_of_fuel() {
+        let opts = args(Backend::Interp, Some(50), false);
+        let (status, printed) = interp(SRC, &opts, "spin", vec![0]);
+        assert_eq!(status, Status::OutOfFuel);
+        // The same number as in "Out of fuel after 50 steps".
+        assert_eq!(printed, "fuel: 50\nstatus: out-of-fuel\n");
+    }
+
+    #[test]
+    fn runtime_errors_are_traps() {
+        let opts = args(Backend::Interp, None, false);
+        let (status, printed) = interp(SRC, &opts, "broken", vec![1]);
+        assert_eq!(status, Status::Trap);
+        assert!(printed.ends_with("status: trap\n"), "{}", printed);
+
+        // Invariants only trap when they are checked.
+        let (status, _) = interp(SRC, &opts, "checked", vec![0]);
+        assert_eq!(status, Status::Ok);
+        let opts = args(Backend::Interp, None, true);
+        let (status, printed) = interp(SRC, &opts, "checked", vec![0]);
+        assert_eq!(status, Status::Trap);
+        assert!(printed.ends_with("status: trap\n"), "{}", printed);
+    }
+
+    #[test]
+    fn invalid_calls() {
+        let opts = args(Backend::Interp, None, false);
+        let (status, printed) = interp(SRC, &opts, "nope", vec![]);
+        assert_eq!(status, Status::InvalidCall);
+        assert!(printed.ends_with("status: invalid-call\n"), "{}", printed);
+        let (status, _) = interp(SRC, &opts, "double", vec![1, 2]);
+        assert_eq!(status, Status::InvalidCall);
+
+        // The wasm backend checks the call before running anything.
+        let opts = args(Backend::Wasm, None, false);
+        let (status, printed) = interp(SRC, &opts, "nope", vec![]);
+        assert_eq!(status, Status::InvalidCall);
+        assert_eq!(printed, "status: invalid-call\n");
+        let (status, _) = interp(SRC, &opts, "double", vec![]);
+        assert_eq!(status, Status::InvalidCall);
+    }
 
-    // let result = execute_wasm(&wasm.bytes, export, parameters.as_slice());
-    // println!("{:?}", result);
-    Ok(())
+    #[test]
+    fn statuses_exit_with_their_own_code() {
+        let statuses = [
+            Status::Ok,
+            Status::InvalidCall,
+            Status::OutOfFuel,
+            Status::Trap,
+            Status::Timeout,
+        ];
+        let codes = statuses
+            .iter()
+            .map(|s| format!("{:?}", s.exit_code()))
+            .collect::<Vec<_>>();
+        for (i, code) in codes.iter().enumerate() {
+            assert_eq!(codes.iter().filter(|c| *c == code).count(), 1, "{}", code);
+            assert_eq!(code == &format!("{:?}", ExitCode::SUCCESS), i == 0);
+        }
+    }
 }
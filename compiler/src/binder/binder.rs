@@ -0,0 +1,877 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+
+use super::bound::{self, DefId, Origin};
+use super::error::{
+    DuplicateArmError, DuplicateDefinitionError, Error, NonExhaustiveMatchError, NotAVariantError,
+    UnknownFunctionError, UnknownIdentifierError, WrongArityError,
+};
+use crate::parser::{self, syntax};
+use crate::renamer::{self, plain};
+use crate::util::ax::{ax, Ax};
+use crate::util::bimap::Bimap;
+use crate::util::loc::LocLike;
+
+static PRELUDE_SRC: &str = include_str!("../../lib/prelude.kedi");
+
+/// Parses and renames the bundled prelude. Since the prelude does not share
+/// the source of the module that uses it, all its locations are replaced
+/// with `loc`.
+pub fn prelude<LocTy: LocLike>(loc: LocTy) -> plain::Module<LocTy, plain::Ident<LocTy>> {
+    let syntax = parser::parse(PRELUDE_SRC).expect("prelude should parse");
+    let plain = renamer::rename(&syntax).expect("prelude should rename");
+
+    plain
+        .fmap_loc(|_| loc.clone())
+        .fmap_ident(|ident| ident.fmap_loc(|_| loc.clone()))
+}
+
+pub fn bind<LocTy: LocLike + Debug>(
+    input: &plain::Module<LocTy, plain::Ident<LocTy>>,
+    prelude: &plain::Module<LocTy, plain::Ident<LocTy>>,
+) -> Result<bound::Module<LocTy>, Error<LocTy>> {
+    let mut sigs = Signatures::new();
+
+    let mut prim_scope = HashMap::new();
+    for prim in bound::Prim::ALL {
+        let id = sigs.push(prim.arity(), None, None);
+        prim_scope.insert(syntax::Ident(prim.name().to_string()), id);
+    }
+
+    let mut decls = vec![];
+    let prelude_scope = declare(&mut sigs, &mut decls, &prim_scope, Origin::Prelude, prelude)?;
+    let module_scope = declare(&mut sigs, &mut decls, &prelude_scope, Origin::Module, input)?;
+
+    let mut defs: Vec<bound::Def<LocTy>> = bound::Prim::ALL
+        .iter()
+        .map(|prim| bound::Def::Prim(*prim))
+        .collect();
+
+    for (origin, stmt) in decls {
+        let scope = match origin {
+            Origin::Prelude => &prelude_scope,
+            Origin::Module => &module_scope,
+            Origin::Lambda => unreachable!("lambdas are only lifted by the simplifier"),
+        };
+
+        match stmt {
+            plain::TopLevelStmt::FunDef(fun) => {
+                let env = BinderEnv {
+                    scope,
+                    prelude_scope: &prelude_scope,
+                    sigs: &sigs,
+                    refs: &fun.v.refs,
+                    prelude_refs: &fun.v.prelude_refs,
+                };
+
+                let implementation = bind_fun_impl(&env, &fun.v.implementation)?;
+                defs.push(bound::Def::Fun(ax(
+                    fun.a.clone(),
+                    bound::FunDef {
+                        doc: fun.v.doc.clone(),
+                        name: fun.v.name.clone(),
+                        origin,
+                        implementation,
+                    },
+                )));
+            }
+            plain::TopLevelStmt::StructDef(def) => push_struct(&mut defs, def),
+            plain::TopLevelStmt::TypeDef(def) => {
+                // The predicate of the type comes first, then each variant
+                // with its own predicate.
+                let first = defs.len() as u32 + 1;
+                let variants = (0..def.v.variants.v.len() as u32)
+                    .map(|ix| DefId { id: first + 2 * ix })
+                    .collect();
+                defs.push(bound::Def::TypePred(bound::TypePred {
+                    def: def.clone(),
+                    variants,
+                }));
+                for variant in def.v.variants.v.iter() {
+                    push_struct(&mut defs, variant);
+                }
+            }
+        }
+    }
+
+    Ok(bound::Module {
+        defs,
+        scope: module_scope,
+    })
+}
+
+fn push_struct<LocTy: Clone>(
+    defs: &mut Vec<bound::Def<LocTy>>,
+    def: &Ax<LocTy, bound::StructDef<LocTy>>,
+) {
+    let struct_id = DefId {
+        id: defs.len() as u32,
+    };
+    defs.push(bound::Def::Struct(def.clone()));
+    defs.push(bound::Def::StructPred(bound::StructPred {
+        struct_id,
+        name: bound::struct_pred_name(&def.v.name.v),
+    }));
+}
+
+type Decl<'t, LocTy> = (Origin, &'t plain::TopLevelStmt<LocTy, plain::Ident<LocTy>>);
+
+// Allocates ids for the definitions in `input`, returning the scope that
+// sees them on top of `parent`. Definitions from `input` shadow the ones in
+// `parent`, but can not be repeated. A struct defines its constructor and
+// its predicate, in that order. A type defines its predicate, then each of
+// its variants as a struct.
+fn declare<'t, LocTy: LocLike + Debug>(
+    sigs: &mut Signatures<LocTy>,
+    decls: &mut Vec<Decl<'t, LocTy>>,
+    parent: &HashMap<syntax::Ident, DefId>,
+    origin: Origin,
+    input: &'t plain::Module<LocTy, plain::Ident<LocTy>>,
+) -> Result<HashMap<syntax::Ident, DefId>, DuplicateDefinitionError<LocTy>> {
+    let mut scope = parent.clone();
+    let mut seen: HashMap<syntax::Ident, LocTy> = HashMap::new();
+    let mut define = |name: &Ax<LocTy, syntax::Ident>| match seen.get(&name.v) {
+        Some(original_loc) => Err(DuplicateDefinitionError {
+            error: name.clone(),
+            original_loc: original_loc.clone(),
+        }),
+        None => {
+            seen.insert(name.v.clone(), name.a.clone());
+            Ok(())
+        }
+    };
+
+    for stmt in input.statements.iter() {
+        match stmt {
+            plain::TopLevelStmt::FunDef(fun) => {
+                let name = &fun.v.name;
+                define(name)?;
+
+                let arity = fun.v.implementation.params.v.len();
+                let id = sigs.push(arity, Some(name.a.clone()), fun.v.doc.clone());
+                scope.insert(name.v.clone(), id);
+            }
+            plain::TopLevelStmt::StructDef(def) => {
+                declare_struct(sigs, &mut scope, &mut define, &def.v)?;
+            }
+            plain::TopLevelStmt::TypeDef(def) => {
+                let name = &def.v.name;
+                let pred = name.as_ref().map(bound::struct_pred_name).clone_a();
+                define(&pred)?;
+                let id = sigs.push(1, Some(name.a.clone()), def.v.doc.clone());
+                scope.insert(pred.v, id);
+
+                let mut variants = vec![];
+                for variant in def.v.variants.v.iter() {
+                    let id = declare_struct(sigs, &mut scope, &mut define, &variant.v)?;
+                    variants.push((id, variant.v.name.clone()));
+                }
+                let ty = sigs.types.len();
+                for (id, _) in variants.iter() {
+                    sigs.variant_of.insert(*id, ty);
+                }
+                sigs.types.push(TypeSig {
+                    name: name.v.clone(),
+                    variants,
+                });
+            }
+        }
+        decls.push((origin, stmt));
+    }
+
+    Ok(scope)
+}
+
+fn declare_struct<LocTy: LocLike + Debug>(
+    sigs: &mut Signatures<LocTy>,
+    scope: &mut HashMap<syntax::Ident, DefId>,
+    define: &mut impl FnMut(&Ax<LocTy, syntax::Ident>) -> Result<(), DuplicateDefinitionError<LocTy>>,
+    def: &bound::StructDef<LocTy>,
+) -> Result<DefId, DuplicateDefinitionError<LocTy>> {
+    let name = &def.name;
+    let pred = name.as_ref().map(bound::struct_pred_name).clone_a();
+    define(name)?;
+    define(&pred)?;
+
+    let arity = def.fields.v.len();
+    let id = sigs.push(arity, Some(name.a.clone()), def.doc.clone());
+    scope.insert(name.v.clone(), id);
+    if arity == 0 {
+        sigs.nullary.insert(id);
+    }
+    let pred_id = sigs.push(1, Some(name.a.clone()), None);
+    scope.insert(pred.v, pred_id);
+    Ok(id)
+}
+
+fn bind_fun_impl<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &plain::FunImpl<LocTy, plain::Ident<LocTy>>,
+) -> Result<bound::FunImpl<LocTy>, Error<LocTy>> {
+    let preds = input
+        .preds
+        .as_ref()
+        .map(|preds| {
+            preds
+                .iter()
+                .map(|pred| bind_expr(env, pred))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .clone_a();
+
+    let body = bind_block(env, &input.body)?;
+
+    Ok(plain::FunImpl {
+        params: input.params.clone(),
+        result: input.result.clone(),
+        preds,
+        body,
+        local_names: input.local_names.clone(),
+    })
+}
+
+fn bind_block<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &Ax<LocTy, Vec<plain::FunStmt<LocTy, plain::Ident<LocTy>>>>,
+) -> Result<Ax<LocTy, Vec<bound::FunStmt<LocTy>>>, Error<LocTy>> {
+    input
+        .as_ref()
+        .map(|stmts| {
+            stmts
+                .iter()
+                .map(|stmt| bind_fun_statement(env, stmt))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+        .map(|ret| ret.clone_a())
+}
+
+fn bind_fun_statement<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &plain::FunStmt<LocTy, plain::Ident<LocTy>>,
+) -> Result<bound::FunStmt<LocTy>, Error<LocTy>> {
+    match input {
+        plain::FunStmt::Return(ret) => {
+            let ret = ret
+                .as_ref()
+                .map(|ret| bind_expr(env, &ret.0).map(plain::Return))
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::Return(ret))
+        }
+
+        plain::FunStmt::Inv(inv) => {
+            let inv = inv
+                .as_ref()
+                .map(|inv| bind_expr(env, inv))
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::Inv(inv))
+        }
+
+        plain::FunStmt::LetDecl(decl) => {
+            let decl = decl
+                .as_ref()
+                .map(|decl| {
+                    Ok::<_, Error<_>>(plain::LetDecl {
+                        name: decl.name.clone(),
+                        value: bind_expr(env, &decl.value)?,
+                    })
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::LetDecl(decl))
+        }
+
+        plain::FunStmt::While(while_stmt) => {
+            let while_stmt = while_stmt
+                .as_ref()
+                .map(|while_stmt| {
+                    Ok::<_, Error<_>>(plain::While {
+                        condition: bind_expr(env, &while_stmt.condition)?,
+                        body: bind_block(env, &while_stmt.body)?,
+                    })
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::While(while_stmt))
+        }
+
+        plain::FunStmt::For(for_stmt) => {
+            let for_stmt = for_stmt
+                .as_ref()
+                .map(|for_stmt| {
+                    Ok::<_, Error<_>>(plain::For {
+                        var: for_stmt.var.clone(),
+                        start: bind_expr(env, &for_stmt.start)?,
+                        end: bind_expr(env, &for_stmt.end)?,
+                        next: for_stmt.next.clone(),
+                        limit: for_stmt.limit.clone(),
+                        condition: bind_expr(env, &for_stmt.condition)?,
+                        step: bind_expr(env, &for_stmt.step)?,
+                        body: bind_block(env, &for_stmt.body)?,
+                    })
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::For(for_stmt))
+        }
+
+        plain::FunStmt::Jump(jump) => Ok(plain::FunStmt::Jump(jump.clone())),
+
+        plain::FunStmt::Assignment(assignment) => {
+            let assignment = assignment
+                .as_ref()
+                .map(|assignment| {
+                    Ok::<_, Error<_>>(plain::Assignment {
+                        id: assignment.id.clone(),
+                        value: bind_expr(env, &assignment.value)?,
+                    })
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::Assignment(assignment))
+        }
+
+        plain::FunStmt::IndexAssignment(assignment) => {
+            let assignment = assignment
+                .as_ref()
+                .map(|assignment| {
+                    Ok::<_, Error<_>>(plain::IndexAssignment {
+                        target: bind_index(env, &assignment.target)?,
+                        value: bind_expr(env, &assignment.value)?,
+                    })
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::IndexAssignment(assignment))
+        }
+
+        plain::FunStmt::FieldAssignment(assignment) => {
+            let assignment = assignment
+                .as_ref()
+                .map(|assignment| {
+                    Ok::<_, Error<_>>(plain::FieldAssignment {
+                        target: bind_field(env, &assignment.target)?,
+                        value: bind_expr(env, &assignment.value)?,
+                    })
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::FieldAssignment(assignment))
+        }
+
+        plain::FunStmt::If(if_stmt) => {
+            let if_stmt = if_stmt
+                .as_ref()
+                .map(|if_stmt| {
+                    let else_ = match &if_stmt.else_ {
+                        Some(else_) => Some(bind_block(env, else_)?),
+                        None => None,
+                    };
+                    Ok::<_, Error<_>>(plain::If {
+                        condition: bind_expr(env, &if_stmt.condition)?,
+                        then: bind_block(env, &if_stmt.then)?,
+                        else_,
+                    })
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::If(if_stmt))
+        }
+
+        plain::FunStmt::Match(match_stmt) => {
+            Ok(plain::FunStmt::Match(bind_match(env, match_stmt)?))
+        }
+    }
+}
+
+// Every arm must name a different variant of the same type, binding all of
+// its fields, and every variant of that type needs an arm.
+fn bind_match<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &Ax<LocTy, plain::Match<LocTy, plain::Ident<LocTy>>>,
+) -> Result<Ax<LocTy, bound::Match<LocTy>>, Error<LocTy>> {
+    let value = bind_expr(env, &input.v.value)?;
+
+    let mut matched: Option<usize> = None;
+    let mut seen: HashMap<DefId, LocTy> = HashMap::new();
+    let mut arms = vec![];
+    for arm in input.v.arms.v.iter() {
+        let plain::Ident::Global(global) = &arm.variant else {
+            unreachable!("the renamer makes variants global")
+        };
+        let pattern = env.name_of(global);
+        let variant = env
+            .lookup(global)
+            .and_then(|id| Some((id, *env.sigs.variant_of.get(&id)?)));
+        let id = match variant {
+            Some((id, ty)) if matched.unwrap_or(ty) == ty => {
+                matched = Some(ty);
+                id
+            }
+            _ => {
+                return Err(NotAVariantError {
+                    pattern,
+                    type_name: matched.map(|ty| env.sigs.types[ty].name.clone()),
+                }
+                .into())
+            }
+        };
+
+        if let Some(original_loc) = seen.insert(id, pattern.a.clone()) {
+            return Err(DuplicateArmError {
+                error: pattern,
+                original_loc,
+            }
+            .into());
+        }
+
+        let expected = env.sigs.arity[id.id as usize];
+        let actual = arm.bindings.v.len();
+        if expected != actual {
+            return Err(WrongArityError {
+                call: ax(LocTy::enclosing(&pattern.a, &arm.bindings.a), pattern.v),
+                expected,
+                actual,
+                definition_loc: env.sigs.loc[id.id as usize].clone(),
+                definition_doc: env.sigs.doc[id.id as usize].clone(),
+            }
+            .into());
+        }
+
+        let arm = plain::Arm {
+            variant: bound::Ident::Global(ax(global.a.clone(), id)),
+            bindings: arm.bindings.clone(),
+            body: bind_block(env, &arm.body)?,
+        };
+        arms.push(arm);
+    }
+
+    let ty = &env.sigs.types[matched.expect("a match has arms")];
+    if let Some((_, missing)) = ty.variants.iter().find(|(id, _)| !seen.contains_key(id)) {
+        return Err(NonExhaustiveMatchError {
+            match_loc: input.a.clone(),
+            type_name: ty.name.clone(),
+            missing: missing.clone(),
+        }
+        .into());
+    }
+
+    Ok(ax(
+        input.a.clone(),
+        plain::Match {
+            value,
+            arms: ax(input.v.arms.a.clone(), arms),
+        },
+    ))
+}
+
+fn bind_expr<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &plain::Expr<LocTy, plain::Ident<LocTy>>,
+) -> Result<bound::Expr<LocTy>, Error<LocTy>> {
+    match input {
+        plain::Expr::LitNum(x) => Ok(plain::Expr::LitNum(x.clone())),
+        plain::Expr::LitStr(x) => Ok(plain::Expr::LitStr(x.clone())),
+        plain::Expr::LitBool(x) => Ok(plain::Expr::LitBool(x.clone())),
+        plain::Expr::Ident(x) => match env.resolve(x)? {
+            // A struct without fields is built by naming it.
+            bound::Ident::Global(id) if env.sigs.nullary.contains(&id.v) => {
+                let args = ax(id.a.clone(), vec![]);
+                Ok(plain::Expr::FunCall(plain::FunCall {
+                    name: bound::Ident::Global(id),
+                    args,
+                }))
+            }
+            ident => Ok(plain::Expr::Ident(ident)),
+        },
+        plain::Expr::Array(x) => Ok(plain::Expr::Array(
+            x.as_ref()
+                .map(|items| {
+                    items
+                        .iter()
+                        .map(|item| bind_expr(env, item))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?
+                .clone_a(),
+        )),
+        plain::Expr::Index(x) => Ok(plain::Expr::Index(bind_index(env, x)?)),
+        plain::Expr::Field(x) => Ok(plain::Expr::Field(bind_field(env, x)?)),
+        plain::Expr::FunCall(x) => Ok(plain::Expr::FunCall(bind_fun_call(env, x)?)),
+        plain::Expr::Logic(x) => {
+            let args = x
+                .args
+                .as_ref()
+                .map(|args| {
+                    args.iter()
+                        .map(|arg| bind_expr(env, arg))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?
+                .clone_a();
+            Ok(plain::Expr::Logic(plain::Logic {
+                op: x.op.clone(),
+                args,
+            }))
+        }
+        plain::Expr::Lambda(x) => Ok(plain::Expr::Lambda(ax(
+            x.a.clone(),
+            plain::Lambda {
+                params: x.v.params.clone(),
+                captures: x.v.captures.clone(),
+                body: bind_block(env, &x.v.body)?,
+            },
+        ))),
+    }
+}
+
+fn bind_index<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &plain::Index<LocTy, plain::Ident<LocTy>>,
+) -> Result<bound::Index<LocTy>, Error<LocTy>> {
+    Ok(plain::Index {
+        array: Box::new(bind_expr(env, &input.array)?),
+        index: ax(
+            input.index.a.clone(),
+            Box::new(bind_expr(env, &input.index.v)?),
+        ),
+    })
+}
+
+fn bind_field<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &plain::Field<LocTy, plain::Ident<LocTy>>,
+) -> Result<bound::Field<LocTy>, Error<LocTy>> {
+    Ok(plain::Field {
+        expr: Box::new(bind_expr(env, &input.expr)?),
+        name: input.name.clone(),
+    })
+}
+
+fn bind_fun_call<LocTy: LocLike + Debug>(
+    env: &BinderEnv<LocTy>,
+    input: &plain::FunCall<LocTy, plain::Ident<LocTy>>,
+) -> Result<bound::FunCall<LocTy>, Error<LocTy>> {
+    let args = input
+        .args
+        .as_ref()
+        .map(|args| {
+            args.iter()
+                .map(|arg| bind_expr(env, arg))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .clone_a();
+
+    let name = match &input.name {
+        plain::Ident::Local(local) => bound::Ident::Local(local.clone()),
+        plain::Ident::Global(global) => {
+            let ident = env.name_of(global);
+            let id = env.lookup(global).ok_or_else(|| UnknownFunctionError {
+                identifier: ident.clone(),
+            })?;
+
+            let expected = env.sigs.arity[id.id as usize];
+            if expected != args.v.len() {
+                return Err(WrongArityError {
+                    call: ax(LocTy::enclosing(&ident.a, &args.a), ident.v),
+                    expected,
+                    actual: args.v.len(),
+                    definition_loc: env.sigs.loc[id.id as usize].clone(),
+                    definition_doc: env.sigs.doc[id.id as usize].clone(),
+                }
+                .into());
+            }
+
+            bound::Ident::Global(ax(global.a.clone(), id))
+        }
+    };
+
+    Ok(plain::FunCall { name, args })
+}
+
+// Arity, location and documentation of every definition, indexed by `DefId`.
+struct Signatures<LocTy> {
+    arity: Vec<usize>,
+    loc: Vec<Option<LocTy>>,
+    doc: Vec<Option<String>>,
+    /// Structs without fields.
+    nullary: HashSet<DefId>,
+    types: Vec<TypeSig<LocTy>>,
+    /// The index in `types` of the type every variant belongs to.
+    variant_of: HashMap<DefId, usize>,
+}
+
+struct TypeSig<LocTy> {
+    name: syntax::Ident,
+    variants: Vec<(DefId, Ax<LocTy, syntax::Ident>)>,
+}
+
+impl<LocTy> Signatures<LocTy> {
+    fn new() -> Self {
+        Signatures {
+            arity: vec![],
+            loc: vec![],
+            doc: vec![],
+            nullary: HashSet::new(),
+            types: vec![],
+            variant_of: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, arity: usize, loc: Option<LocTy>, doc: Option<String>) -> DefId {
+        let id = DefId {
+            id: self.arity.len() as u32,
+        };
+        self.arity.push(arity);
+        self.loc.push(loc);
+        self.doc.push(doc);
+        id
+    }
+}
+
+struct BinderEnv<'t, LocTy> {
+    scope: &'t HashMap<syntax::Ident, DefId>,
+    prelude_scope: &'t HashMap<syntax::Ident, DefId>,
+    sigs: &'t Signatures<LocTy>,
+    refs: &'t Bimap<plain::UnresolvedIdent, syntax::Ident>,
+    prelude_refs: &'t Bimap<plain::UnresolvedIdent, syntax::Ident>,
+}
+
+impl<LocTy: LocLike + Debug> BinderEnv<'_, LocTy> {
+    fn name_of(&self, global: &Ax<LocTy, plain::UnresolvedIdent>) -> Ax<LocTy, syntax::Ident> {
+        let name = self
+            .refs
+            .get_by_left(&global.v)
+            .or_else(|| self.prelude_refs.get_by_left(&global.v))
+            .unwrap_or_else(|| panic!("No reference found for {:?}", global.v));
+        ax(global.a.clone(), name.clone())
+    }
+
+    /// The definition `global` refers to. Operators only see the prelude.
+    fn lookup(&self, global: &Ax<LocTy, plain::UnresolvedIdent>) -> Option<DefId> {
+        match self.prelude_refs.get_by_left(&global.v) {
+            Some(name) => self.prelude_scope.get(name).copied(),
+            None => self.scope.get(&self.name_of(global).v).copied(),
+        }
+    }
+
+    fn resolve(&self, input: &plain::Ident<LocTy>) -> Result<bound::Ident<LocTy>, Error<LocTy>> {
+        match input {
+            plain::Ident::Local(local) => Ok(bound::Ident::Local(local.clone())),
+            plain::Ident::Global(global) => {
+                let ident = self.name_of(global);
+                match self.lookup(global) {
+                    Some(id) => Ok(bound::Ident::Global(ax(global.a.clone(), id))),
+                    None => Err(UnknownIdentifierError { identifier: ident }.into()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::loc::SrcLoc;
+
+    fn bind_src(src: &str) -> Result<bound::Module<SrcLoc>, Error<SrcLoc>> {
+        let syntax = parser::parse(src).unwrap();
+        let plain = renamer::rename(&syntax).unwrap();
+        bind(&plain, &prelude(SrcLoc::Unknown))
+    }
+
+    fn called(fun: &bound::FunDef<SrcLoc>) -> DefId {
+        match &fun.implementation.body.v[0] {
+            plain::FunStmt::Return(ret) => match &ret.v.0 {
+                plain::Expr::FunCall(plain::FunCall {
+                    name: bound::Ident::Global(id),
+                    ..
+                }) => id.v,
+                other => panic!("unexpected expression {:?}", other),
+            },
+            other => panic!("unexpected statement {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolves_prelude_and_module_functions() {
+        let module = bind_src("fn f x { return add x 1; } fn g y { return f y; }").unwrap();
+
+        let f = module.lookup(&syntax::Ident("f".to_string())).unwrap();
+        let add = module.lookup(&syntax::Ident("add".to_string())).unwrap();
+
+        let funs: HashMap<_, _> = module
+            .module_funs()
+            .map(|(_, f)| (f.name.v.0.clone(), &f.v))
+            .collect();
+        assert_eq!(called(funs["f"]), add);
+        assert_eq!(called(funs["g"]), f);
+        assert_eq!(
+            module.get(add).name(),
+            syntax::Ident("add".to_string()),
+            "add should come from the prelude"
+        );
+    }
+
+    #[test]
+    fn module_shadows_prelude() {
+        let module = bind_src("fn add l r { return l; } fn f { return add 1 2; }").unwrap();
+        let add = module.lookup(&syntax::Ident("add".to_string())).unwrap();
+        match module.get(add) {
+            bound::Def::Fun(f) => assert_eq!(f.origin, Origin::Module),
+            other => panic!("unexpected definition {:?}", other),
+        }
+    }
+
+    #[test]
+    fn operators_always_call_the_prelude() {
+        let module = bind_src("fn add a { return a; } fn f n { return n + 1; }").unwrap();
+        let funs: HashMap<_, _> = module
+            .module_funs()
+            .map(|(_, f)| (f.name.v.0.clone(), &f.v))
+            .collect();
+        match module.get(called(funs["f"])) {
+            bound::Def::Fun(f) => {
+                assert_eq!(f.name.v.0, "add");
+                assert_eq!(f.origin, Origin::Prelude);
+            }
+            other => panic!("unexpected definition {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_function() {
+        match bind_src("fn f x { return ad x 1; }") {
+            Err(Error::UnknownFunction(err)) => {
+                assert_eq!(err.identifier.v, syntax::Ident("ad".to_string()));
+                assert_eq!(
+                    err.identifier.a,
+                    SrcLoc::Known(crate::util::loc::Span::from_offset_bytes(16, 18))
+                );
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_identifier() {
+        assert!(matches!(
+            bind_src("fn f x { return y; }"),
+            Err(Error::UnknownIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn wrong_arity() {
+        match bind_src("fn f x { return add x; }") {
+            Err(Error::WrongArity(err)) => {
+                assert_eq!(err.expected, 2);
+                assert_eq!(err.actual, 1);
+                assert_eq!(err.definition_loc, Some(SrcLoc::Unknown));
+                assert_eq!(err.definition_doc.as_deref(), Some("The sum of l and r."));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn duplicate_definition() {
+        assert!(matches!(
+            bind_src("fn f { return 1; } fn f { return 2; }"),
+            Err(Error::DuplicateDefinition(_))
+        ));
+    }
+
+    #[test]
+    fn structs_define_a_constructor_and_a_predicate() {
+        let module =
+            bind_src("struct P { x, y } fn f a { return P a a; } fn g p { return P? p; }").unwrap();
+        let find = |name: &str| module.lookup(&syntax::Ident(name.to_string())).unwrap();
+        let (p, pred) = (find("P"), find("P?"));
+        assert!(matches!(module.get(p), bound::Def::Struct(_)));
+        assert_eq!(module.get(p).arity(), 2);
+        assert_eq!(
+            module.get(pred),
+            &bound::Def::StructPred(bound::StructPred {
+                struct_id: p,
+                name: syntax::Ident("P?".to_string())
+            })
+        );
+
+        let funs = module.funs().map(|(_, f)| f).collect::<Vec<_>>();
+        let f = funs.iter().find(|f| f.name.v.0 == "f").unwrap();
+        assert_eq!(called(f), p);
+
+        assert!(matches!(
+            bind_src("struct P { x } fn P? a { return true; }"),
+            Err(Error::DuplicateDefinition(_))
+        ));
+        assert!(matches!(
+            bind_src("struct P { x } fn f { return P 1 2; }"),
+            Err(Error::WrongArity(_))
+        ));
+    }
+
+    #[test]
+    fn types_define_a_predicate_and_their_variants() {
+        let module = bind_src(
+            "type T = A | B x; fn f { return A; } fn g t { match t { A => {} B x => {} } return t; }",
+        )
+        .unwrap();
+        let find = |name: &str| module.lookup(&syntax::Ident(name.to_string())).unwrap();
+        let (t, a, b) = (find("T?"), find("A"), find("B"));
+        match module.get(t) {
+            bound::Def::TypePred(pred) => assert_eq!(pred.variants, vec![a, b]),
+            other => panic!("unexpected definition {:?}", other),
+        }
+        assert_eq!(module.struct_pred(b), find("B?"));
+
+        // Naming a variant without fields builds it.
+        let funs = module.funs().map(|(_, f)| f).collect::<Vec<_>>();
+        let f = funs.iter().find(|f| f.name.v.0 == "f").unwrap();
+        assert_eq!(called(f), a);
+    }
+
+    #[test]
+    fn matches_are_exhaustive() {
+        let src = "type T = A | B x | C;\nfn f t { match t { B x => {} A => {} } }";
+        match bind_src(src) {
+            Err(Error::NonExhaustiveMatch(err)) => {
+                assert_eq!(err.type_name.0, "T");
+                assert_eq!(err.missing.v.0, "C");
+                assert_eq!(
+                    err.missing.a,
+                    SrcLoc::Known(crate::util::loc::Span::from_offset_len(
+                        src.find("C;").unwrap(),
+                        1
+                    ))
+                );
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        match bind_src("type T = A | B; type U = C; fn f t { match t { A => {} C => {} } }") {
+            Err(Error::NotAVariant(err)) => {
+                assert_eq!(err.pattern.v.0, "C");
+                assert_eq!(err.type_name, Some(syntax::Ident("T".to_string())));
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(
+            bind_src("struct S { x } fn f t { match t { S x => {} } }"),
+            Err(Error::NotAVariant(_))
+        ));
+        assert!(matches!(
+            bind_src("type T = A | B; fn f t { match t { A => {} B => {} A => {} } }"),
+            Err(Error::DuplicateArm(_))
+        ));
+        assert!(matches!(
+            bind_src("type T = A | B x; fn f t { match t { A => {} B => {} } }"),
+            Err(Error::WrongArity(_))
+        ));
+    }
+}
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use functor_derive::Functor;
+use sexpr_derive::SExpr;
+
+use crate::parser::syntax;
+use crate::renamer::plain;
+use crate::util::ax::Ax;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DefId {
+    pub id: u32,
+}
+
+impl sexpr::SExpr for DefId {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::call("def", &[self.id])
+    }
+}
+
+#[derive(Debug, Copy, Clone, Functor, PartialEq, Eq, SExpr)]
+#[functor(LocTy as loc)]
+pub enum Ident<LocTy> {
+    Local(Ax<LocTy, plain::LocalIdent>),
+    Global(Ax<LocTy, DefId>),
+}
+
+impl<LocTy> Ident<LocTy> {
+    pub fn loc(&self) -> &LocTy {
+        match self {
+            Ident::Local(i) => &i.a,
+            Ident::Global(i) => &i.a,
+        }
+    }
+}
+
+pub type FunImpl<LocTy> = plain::FunImpl<LocTy, Ident<LocTy>>;
+pub type FunStmt<LocTy> = plain::FunStmt<LocTy, Ident<LocTy>>;
+pub type Expr<LocTy> = plain::Expr<LocTy, Ident<LocTy>>;
+pub type FunCall<LocTy> = plain::FunCall<LocTy, Ident<LocTy>>;
+pub type Lambda<LocTy> = plain::Lambda<LocTy, Ident<LocTy>>;
+pub type Index<LocTy> = plain::Index<LocTy, Ident<LocTy>>;
+pub type Field<LocTy> = plain::Field<LocTy, Ident<LocTy>>;
+pub type Match<LocTy> = plain::Match<LocTy, Ident<LocTy>>;
+pub type Arm<LocTy> = plain::Arm<LocTy, Ident<LocTy>>;
+pub type StructDef<LocTy> = plain::StructDef<LocTy>;
+pub type TypeDef<LocTy> = plain::TypeDef<LocTy>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SExpr)]
+pub enum Origin {
+    Prelude,
+    Module,
+    /// A lambda the simplifier lifted out of a function.
+    Lambda,
+}
+
+#[derive(Debug, Clone, PartialEq, SExpr)]
+pub struct FunDef<LocTy> {
+    pub doc: Option<String>,
+    pub name: Ax<LocTy, syntax::Ident>,
+    pub origin: Origin,
+    pub implementation: FunImpl<LocTy>,
+}
+
+impl<LocTy> FunDef<LocTy> {
+    pub fn arity(&self) -> usize {
+        self.implementation.params.v.len()
+    }
+}
+
+// Primitives are implemented by the backends, the prelude wraps them
+// in ordinary functions.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SExpr)]
+pub enum Prim {
+    Gt,
+    Lt,
+    Gte,
+    Lte,
+    Eq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    IsNumber,
+    IsBool,
+    IsString,
+    Concat,
+    Length,
+    Slice,
+    Compare,
+    IsArray,
+    Push,
+}
+
+impl Prim {
+    pub const ALL: &'static [Prim] = &[
+        Prim::Gt,
+        Prim::Lt,
+        Prim::Gte,
+        Prim::Lte,
+        Prim::Eq,
+        Prim::Add,
+        Prim::Sub,
+        Prim::Mul,
+        Prim::Div,
+        Prim::Mod,
+        Prim::IsNumber,
+        Prim::IsBool,
+        Prim::IsString,
+        Prim::Concat,
+        Prim::Length,
+        Prim::Slice,
+        Prim::Compare,
+        Prim::IsArray,
+        Prim::Push,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Prim::Gt => "__prim_gt?",
+            Prim::Lt => "__prim_lt?",
+            Prim::Gte => "__prim_gte?",
+            Prim::Lte => "__prim_lte?",
+            Prim::Eq => "__prim_eq?",
+            Prim::Add => "__prim_add",
+            Prim::Sub => "__prim_sub",
+            Prim::Mul => "__prim_mul",
+            Prim::Div => "__prim_div",
+            Prim::Mod => "__prim_mod",
+            Prim::IsNumber => "__prim_number?",
+            Prim::IsBool => "__prim_bool?",
+            Prim::IsString => "__prim_string?",
+            Prim::Concat => "__prim_concat",
+            Prim::Length => "__prim_length",
+            Prim::Slice => "__prim_slice",
+            Prim::Compare => "__prim_compare",
+            Prim::IsArray => "__prim_array?",
+            Prim::Push => "__prim_push",
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Prim::Gt
+            | Prim::Lt
+            | Prim::Gte
+            | Prim::Lte
+            | Prim::Eq
+            | Prim::Add
+            | Prim::Sub
+            | Prim::Mul
+            | Prim::Div
+            | Prim::Mod
+            | Prim::Concat
+            | Prim::Compare
+            | Prim::Push => 2,
+            Prim::IsNumber | Prim::IsBool | Prim::IsString | Prim::Length | Prim::IsArray => 1,
+            Prim::Slice => 3,
+        }
+    }
+}
+
+/// `Name?`, which holds for the values built by the struct `struct_id`.
+#[derive(Debug, Clone, PartialEq, SExpr)]
+pub struct StructPred {
+    pub struct_id: DefId,
+    pub name: syntax::Ident,
+}
+
+/// The name of the predicate of the struct or type `name`.
+pub fn struct_pred_name(name: &syntax::Ident) -> syntax::Ident {
+    syntax::Ident(format!("{}?", name.0))
+}
+
+/// `Name?` of a type, which holds for the values built by any of the
+/// structs in `variants`, one per variant of `def`.
+#[derive(Debug, Clone, PartialEq, SExpr)]
+pub struct TypePred<LocTy> {
+    pub def: Ax<LocTy, TypeDef<LocTy>>,
+    pub variants: Vec<DefId>,
+}
+
+#[derive(Debug, Clone, PartialEq, SExpr)]
+pub enum Def<LocTy> {
+    Fun(Ax<LocTy, FunDef<LocTy>>),
+    Prim(Prim),
+    /// A struct, called as its constructor.
+    Struct(Ax<LocTy, StructDef<LocTy>>),
+    StructPred(StructPred),
+    TypePred(TypePred<LocTy>),
+}
+
+impl<LocTy> Def<LocTy> {
+    pub fn name(&self) -> syntax::Ident {
+        match self {
+            Def::Fun(f) => f.name.v.clone(),
+            Def::Prim(p) => syntax::Ident(p.name().to_string()),
+            Def::Struct(s) => s.name.v.clone(),
+            Def::StructPred(p) => p.name.clone(),
+            Def::TypePred(p) => struct_pred_name(&p.def.v.name.v),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Def::Fun(f) => f.arity(),
+            Def::Prim(p) => p.arity(),
+            Def::Struct(s) => s.fields.v.len(),
+            Def::StructPred(_) | Def::TypePred(_) => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, SExpr)]
+pub struct Module<LocTy> {
+    /// Every definition reachable from the module, indexed by `DefId`.
+    pub defs: Vec<Def<LocTy>>,
+    /// The names visible at the top-level of the module.
+    pub scope: HashMap<syntax::Ident, DefId>,
+}
+
+impl<LocTy> Module<LocTy> {
+    pub fn get(&self, id: DefId) -> &Def<LocTy> {
+        &self.defs[id.id as usize]
+    }
+
+    pub fn lookup(&self, name: &syntax::Ident) -> Option<DefId> {
+        self.scope.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (DefId, &Def<LocTy>)> {
+        self.defs
+            .iter()
+            .enumerate()
+            .map(|(ix, def)| (DefId { id: ix as u32 }, def))
+    }
+
+    pub fn funs(&self) -> impl Iterator<Item = (DefId, &Ax<LocTy, FunDef<LocTy>>)> {
+        self.iter().filter_map(|(id, def)| match def {
+            Def::Fun(f) => Some((id, f)),
+            Def::Prim(_) | Def::Struct(_) | Def::StructPred(_) | Def::TypePred(_) => None,
+        })
+    }
+
+    /// The predicate of the struct `struct_id`, which is defined right
+    /// after it.
+    pub fn struct_pred(&self, struct_id: DefId) -> DefId {
+        let pred = DefId {
+            id: struct_id.id + 1,
+        };
+        debug_assert!(
+            matches!(self.get(pred), Def::StructPred(p) if p.struct_id == struct_id),
+            "{:?} is not a struct",
+            struct_id
+        );
+        pred
+    }
+
+    pub fn module_funs(&self) -> impl Iterator<Item = (DefId, &Ax<LocTy, FunDef<LocTy>>)> {
+        self.funs().filter(|(_, f)| f.origin == Origin::Module)
+    }
+}
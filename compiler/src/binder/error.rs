@@ -0,0 +1,102 @@
+use crate::{parser::syntax, util::ax::Ax};
+
+#[derive(Debug)]
+pub enum Error<L> {
+    UnknownFunction(UnknownFunctionError<L>),
+    UnknownIdentifier(UnknownIdentifierError<L>),
+    WrongArity(WrongArityError<L>),
+    DuplicateDefinition(DuplicateDefinitionError<L>),
+    NonExhaustiveMatch(NonExhaustiveMatchError<L>),
+    NotAVariant(NotAVariantError<L>),
+    DuplicateArm(DuplicateArmError<L>),
+}
+
+#[derive(Debug)]
+pub struct UnknownFunctionError<L> {
+    pub identifier: Ax<L, syntax::Ident>,
+}
+
+impl<L> From<UnknownFunctionError<L>> for Error<L> {
+    fn from(e: UnknownFunctionError<L>) -> Self {
+        Error::UnknownFunction(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownIdentifierError<L> {
+    pub identifier: Ax<L, syntax::Ident>,
+}
+
+impl<L> From<UnknownIdentifierError<L>> for Error<L> {
+    fn from(e: UnknownIdentifierError<L>) -> Self {
+        Error::UnknownIdentifier(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct WrongArityError<L> {
+    pub call: Ax<L, syntax::Ident>,
+    pub expected: usize,
+    pub actual: usize,
+    /// Location of the called function, if it is defined in the source.
+    pub definition_loc: Option<L>,
+    /// Documentation of the called function.
+    pub definition_doc: Option<String>,
+}
+
+impl<L> From<WrongArityError<L>> for Error<L> {
+    fn from(e: WrongArityError<L>) -> Self {
+        Error::WrongArity(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct DuplicateDefinitionError<L> {
+    pub error: Ax<L, syntax::Ident>,
+    pub original_loc: L,
+}
+
+impl<L> From<DuplicateDefinitionError<L>> for Error<L> {
+    fn from(e: DuplicateDefinitionError<L>) -> Self {
+        Error::DuplicateDefinition(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct NonExhaustiveMatchError<L> {
+    pub match_loc: L,
+    pub type_name: syntax::Ident,
+    /// The first variant without an arm, located at its definition.
+    pub missing: Ax<L, syntax::Ident>,
+}
+
+impl<L> From<NonExhaustiveMatchError<L>> for Error<L> {
+    fn from(e: NonExhaustiveMatchError<L>) -> Self {
+        Error::NonExhaustiveMatch(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct NotAVariantError<L> {
+    pub pattern: Ax<L, syntax::Ident>,
+    /// The type the previous arms match on, if any.
+    pub type_name: Option<syntax::Ident>,
+}
+
+impl<L> From<NotAVariantError<L>> for Error<L> {
+    fn from(e: NotAVariantError<L>) -> Self {
+        Error::NotAVariant(e)
+    }
+}
+
+#[derive(Debug)]
+pub struct DuplicateArmError<L> {
+    pub error: Ax<L, syntax::Ident>,
+    pub original_loc: L,
+}
+
+impl<L> From<DuplicateArmError<L>> for Error<L> {
+    fn from(e: DuplicateArmError<L>) -> Self {
+        Error::DuplicateArm(e)
+    }
+}
@@ -0,0 +1,8 @@
+pub mod bound;
+
+#[allow(clippy::module_inception)]
+mod binder;
+pub use binder::*;
+
+mod error;
+pub use error::*;
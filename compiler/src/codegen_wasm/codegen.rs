@@ -0,0 +1,641 @@
+use std::collections::{BTreeMap, HashMap};
+
+use wasm_encoder::{BlockType, HeapType, Instruction, ValType};
+
+use super::fragment::{self, FunRef, Instr};
+use super::prims;
+use super::rts::{
+    array_val_type, object_val_type, struct_tag, struct_type_id, Helper, ARRAY_TYPE_ID,
+    CLOSURE_FUN_TYPE_ID, CLOSURE_TYPE_ID, ELEMENTS_TYPE_ID, OBJECT_TAG_CLOSURE, OBJECT_TYPE_ID,
+    STRING_TYPE_ID,
+};
+use crate::binder::bound::{DefId, Origin, Prim};
+use crate::parser::syntax;
+use crate::renamer::{plain, LENGTH};
+use crate::simplifier::simple;
+
+pub fn run<LocTy>(input: &simple::Module<LocTy>) -> fragment::Module {
+    let structs = Structs::new(input);
+    let mut funs = vec![];
+
+    for (id, def) in input.iter() {
+        match def {
+            simple::Def::Fun(fun) => {
+                funs.push(fragment::FunDecl {
+                    id: FunRef::Def(id),
+                    name: fun.name.v.0.clone(),
+                    export: false,
+                    implementation: codegen_function(&structs, &fun.v),
+                });
+                if fun.origin == Origin::Module {
+                    funs.push(export_wrapper(id, &fun.name.v.0, fun.arity()));
+                }
+            }
+            simple::Def::Prim(prim) => {
+                funs.push(fragment::FunDecl {
+                    id: FunRef::Def(id),
+                    name: prim.name().to_string(),
+                    export: false,
+                    implementation: prims::prim(*prim),
+                });
+            }
+            simple::Def::Struct(def) => {
+                funs.push(fragment::FunDecl {
+                    id: FunRef::Def(id),
+                    name: def.name.v.0.clone(),
+                    export: false,
+                    implementation: constructor(structs.ordinals[&id], def.fields.v.len()),
+                });
+            }
+            simple::Def::StructPred(pred) => {
+                funs.push(fragment::FunDecl {
+                    id: FunRef::Def(id),
+                    name: pred.name.0.clone(),
+                    export: false,
+                    implementation: prims::has_tag(struct_tag(structs.ordinals[&pred.struct_id])),
+                });
+            }
+            simple::Def::TypePred(pred) => {
+                let tags = pred
+                    .variants
+                    .iter()
+                    .map(|variant| struct_tag(structs.ordinals[variant]))
+                    .collect::<Vec<_>>();
+                funs.push(fragment::FunDecl {
+                    id: FunRef::Def(id),
+                    name: def.name().0,
+                    export: false,
+                    implementation: prims::has_any_tag(&tags),
+                });
+            }
+        }
+    }
+    for (id, captures) in closures(input) {
+        let def = input.get(id);
+        funs.push(closure_entry(
+            id,
+            &def.name().0,
+            def.arity() - captures,
+            captures,
+        ));
+    }
+    funs.push(fragment::FunDecl {
+        id: FunRef::StructsEqual,
+        name: "__structs_equal".to_string(),
+        export: false,
+        implementation: structs_equal(&structs),
+    });
+
+    fragment::Module {
+        funs,
+        structs: structs.fields.iter().map(|f| f.len() as u32).collect(),
+    }
+}
+
+/// The structs of the module, numbered in the order they are defined.
+struct Structs {
+    ordinals: HashMap<DefId, u32>,
+    /// The fields of each struct, by ordinal.
+    fields: Vec<Vec<syntax::Ident>>,
+    /// The primitive `.length` falls back to on other values.
+    length: DefId,
+}
+
+impl Structs {
+    fn new<LocTy>(input: &simple::Module<LocTy>) -> Structs {
+        let mut structs = Structs {
+            ordinals: HashMap::new(),
+            fields: vec![],
+            length: input
+                .iter()
+                .find(|(_, def)| matches!(def, simple::Def::Prim(Prim::Length)))
+                .map(|(id, _)| id)
+                .expect("every primitive is defined"),
+        };
+        for (id, def) in input.iter() {
+            if let simple::Def::Struct(def) = def {
+                structs.ordinals.insert(id, structs.fields.len() as u32);
+                structs
+                    .fields
+                    .push(def.fields.v.iter().map(|f| f.v.clone()).collect());
+            }
+        }
+        structs
+    }
+
+    /// The ordinal and the position of the field of every struct declaring
+    /// `name`.
+    fn declaring(&self, name: &syntax::Ident) -> Vec<(u32, u32)> {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter_map(|(ordinal, fields)| {
+                let ix = fields.iter().position(|field| field == name)?;
+                Some((ordinal as u32, ix as u32))
+            })
+            .collect()
+    }
+}
+
+fn constructor(ordinal: u32, arity: usize) -> fragment::FunImpl {
+    let mut body = vec![
+        Instr::Raw(Instruction::I32Const(struct_tag(ordinal))),
+        Instr::Raw(Instruction::I32Const(0)),
+    ];
+    for ix in 0..arity {
+        body.push(Instr::Raw(Instruction::LocalGet(ix as u32)));
+    }
+    body.push(Instr::Raw(Instruction::StructNew(struct_type_id(ordinal))));
+    body.push(Instr::Raw(Instruction::StructNew(OBJECT_TYPE_ID)));
+
+    fragment::FunImpl {
+        params: vec![object_val_type(); arity],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body,
+    }
+}
+
+/// Pushes the struct in `local`, cast to the type of struct `ordinal`.
+fn struct_ref(local: u32, ordinal: u32) -> [Instr; 3] {
+    [
+        Instr::Raw(Instruction::LocalGet(local)),
+        Instr::Raw(Instruction::StructGet {
+            struct_type_index: OBJECT_TYPE_ID,
+            field_index: 2,
+        }),
+        Instr::Raw(Instruction::RefCastNonNull(HeapType::Concrete(
+            struct_type_id(ordinal),
+        ))),
+    ]
+}
+
+/// Runs `then` when the object in `local` is a struct `ordinal`.
+fn if_struct(
+    instrs: &mut Vec<Instr>,
+    local: u32,
+    ordinal: u32,
+    then: impl FnOnce(&mut Vec<Instr>),
+) {
+    instrs.push(Instr::Raw(Instruction::LocalGet(local)));
+    instrs.push(Instr::Raw(Instruction::StructGet {
+        struct_type_index: OBJECT_TYPE_ID,
+        field_index: 0,
+    }));
+    instrs.push(Instr::Raw(Instruction::I32Const(struct_tag(ordinal))));
+    instrs.push(Instr::Raw(Instruction::I32Eq));
+    instrs.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    then(instrs);
+    instrs.push(Instr::Raw(Instruction::End));
+}
+
+/// Compares the fields of the structs in locals 0 and 1, which have the same
+/// tag, with the pairs seen in local 2.
+fn structs_equal(structs: &Structs) -> fragment::FunImpl {
+    let mut body = vec![];
+    for (ordinal, fields) in structs.fields.iter().enumerate() {
+        let ordinal = ordinal as u32;
+        if_struct(&mut body, 0, ordinal, |body| {
+            for ix in 0..fields.len() as u32 {
+                for local in [0, 1] {
+                    body.extend(struct_ref(local, ordinal));
+                    body.push(Instr::Raw(Instruction::StructGet {
+                        struct_type_index: struct_type_id(ordinal),
+                        field_index: ix,
+                    }));
+                }
+                body.push(Instr::Raw(Instruction::LocalGet(2)));
+                body.push(Instr::Call(FunRef::Helper(Helper::EqualSeen)));
+                body.push(Instr::Raw(Instruction::I32Eqz));
+                body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+                body.push(Instr::Raw(Instruction::I32Const(0)));
+                body.push(Instr::Raw(Instruction::Return));
+                body.push(Instr::Raw(Instruction::End));
+            }
+            body.push(Instr::Raw(Instruction::I32Const(1)));
+            body.push(Instr::Raw(Instruction::Return));
+        });
+    }
+    body.push(Instr::Raw(Instruction::Unreachable));
+
+    fragment::FunImpl {
+        params: vec![object_val_type(), object_val_type(), array_val_type()],
+        results: vec![ValType::I32],
+        locals: vec![],
+        body,
+    }
+}
+
+/// Functions of the module are exported through a wrapper that boxes the
+/// arguments and unboxes the result, so that hosts can call them with numbers.
+/// Booleans are returned as 0 or 1.
+fn export_wrapper(id: DefId, name: &str, arity: usize) -> fragment::FunDecl {
+    let mut body = vec![];
+    for ix in 0..arity {
+        body.push(Instr::Raw(Instruction::LocalGet(ix as u32)));
+        body.push(Instr::Call(FunRef::Helper(Helper::PackI32)));
+    }
+    body.push(Instr::Call(FunRef::Def(id)));
+    body.push(Instr::Call(FunRef::Helper(Helper::UnpackResult)));
+
+    fragment::FunDecl {
+        id: FunRef::Export(id),
+        name: name.to_string(),
+        export: true,
+        implementation: fragment::FunImpl {
+            params: vec![ValType::I32; arity],
+            results: vec![ValType::I32],
+            locals: vec![],
+            body,
+        },
+    }
+}
+
+/// The functions closures are built of, with the number of values they
+/// capture.
+fn closures<LocTy>(input: &simple::Module<LocTy>) -> BTreeMap<DefId, usize> {
+    fn walk(stmts: &[simple::FunStmt], out: &mut BTreeMap<DefId, usize>) {
+        for stmt in stmts {
+            match stmt {
+                simple::FunStmt::Assignment(a) => {
+                    if let simple::AssignmentValue::Closure(closure) = &a.v.value {
+                        out.insert(closure.v.fun, closure.v.captures.len());
+                    }
+                }
+                simple::FunStmt::Loop(l) => walk(&l.v.body.v, out),
+                simple::FunStmt::If(i) => {
+                    walk(&i.then.v, out);
+                    if let Some(else_) = &i.else_ {
+                        walk(&else_.v, out);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut out = BTreeMap::new();
+    for (_, fun) in input.funs() {
+        walk(&fun.implementation.body.v, &mut out);
+    }
+    out
+}
+
+/// Calls `id` with the captures of the closure in local 0, then the
+/// arguments in the array in local 1, trapping unless there are `arity` of
+/// them.
+fn closure_entry(id: DefId, name: &str, arity: usize, captures: usize) -> fragment::FunDecl {
+    let mut body = vec![
+        Instr::Raw(Instruction::LocalGet(1)),
+        Instr::Raw(Instruction::StructGet {
+            struct_type_index: ARRAY_TYPE_ID,
+            field_index: 0,
+        }),
+        Instr::Raw(Instruction::I32Const(arity as i32)),
+        Instr::Raw(Instruction::I32Ne),
+        Instr::Raw(Instruction::If(BlockType::Empty)),
+        Instr::Raw(Instruction::Unreachable),
+        Instr::Raw(Instruction::End),
+    ];
+    for ix in 0..captures {
+        body.push(Instr::Raw(Instruction::LocalGet(0)));
+        body.push(Instr::Call(FunRef::Helper(Helper::UnpackClosure)));
+        body.push(Instr::Raw(Instruction::StructGet {
+            struct_type_index: CLOSURE_TYPE_ID,
+            field_index: 1,
+        }));
+        body.push(Instr::Raw(Instruction::I32Const(ix as i32)));
+        body.push(Instr::Call(FunRef::Helper(Helper::ArrayGet)));
+    }
+    for ix in 0..arity {
+        body.push(Instr::Raw(Instruction::LocalGet(1)));
+        body.push(Instr::Raw(Instruction::I32Const(ix as i32)));
+        body.push(Instr::Call(FunRef::Helper(Helper::ArrayGet)));
+    }
+    body.push(Instr::Call(FunRef::Def(id)));
+
+    fragment::FunDecl {
+        id: FunRef::Closure(id),
+        name: format!("{}/closure", name),
+        export: false,
+        implementation: fragment::FunImpl {
+            params: vec![object_val_type(), array_val_type()],
+            results: vec![object_val_type()],
+            locals: vec![],
+            body,
+        },
+    }
+}
+
+fn codegen_function<LocTy>(structs: &Structs, input: &simple::FunDecl<LocTy>) -> fragment::FunImpl {
+    let mut instrs = vec![];
+    let mut state = CodegenState::new(structs);
+
+    let implementation = &input.implementation;
+    for param in implementation.parameters.v.iter() {
+        state.register_param(&param.v);
+    }
+
+    for stmt in implementation.body.v.iter() {
+        codegen_statement(&mut state, &mut instrs, stmt);
+    }
+
+    // Falling off the end means a missing return.
+    instrs.push(Instr::Raw(Instruction::Unreachable));
+
+    fragment::FunImpl {
+        params: vec![object_val_type(); state.params.len()],
+        results: vec![object_val_type()],
+        locals: vec![object_val_type(); state.locals.len() + state.single_uses.len()],
+        body: instrs,
+    }
+}
+
+fn codegen_statements(
+    state: &mut CodegenState,
+    instrs: &mut Vec<Instr>,
+    stmts: &[simple::FunStmt],
+) {
+    for stmt in stmts.iter() {
+        codegen_statement(state, instrs, stmt);
+    }
+}
+
+fn codegen_statement(state: &mut CodegenState, instrs: &mut Vec<Instr>, stmt: &simple::FunStmt) {
+    match stmt {
+        simple::FunStmt::Assignment(assignment) => {
+            let simple::Assignment { target, value } = &assignment.v;
+
+            match value {
+                simple::AssignmentValue::LitNum(n) => {
+                    instrs.push(Instr::Raw(Instruction::I32Const(n.v.0)));
+                    instrs.push(Instr::Call(FunRef::Helper(Helper::PackI32)));
+                }
+                simple::AssignmentValue::LitBool(b) => {
+                    instrs.push(Instr::Raw(Instruction::I32Const(b.v.0 as i32)));
+                    instrs.push(Instr::Call(FunRef::Helper(Helper::PackBool)));
+                }
+                simple::AssignmentValue::LitStr(s) => {
+                    let code_points = s.v.0.chars().map(|c| c as i32).collect::<Vec<_>>();
+                    for code_point in code_points.iter() {
+                        instrs.push(Instr::Raw(Instruction::I32Const(*code_point)));
+                    }
+                    instrs.push(Instr::Raw(Instruction::ArrayNewFixed {
+                        array_type_index: STRING_TYPE_ID,
+                        array_size: code_points.len() as u32,
+                    }));
+                    instrs.push(Instr::Call(FunRef::Helper(Helper::PackString)));
+                }
+                simple::AssignmentValue::Array(items) => {
+                    codegen_items(state, instrs, &items.v);
+                    instrs.push(Instr::Call(FunRef::Helper(Helper::PackArray)));
+                }
+                simple::AssignmentValue::Index(index) => {
+                    codegen_index(state, instrs, &index.v);
+                    instrs.push(Instr::Call(FunRef::Helper(Helper::ArrayGet)));
+                }
+                simple::AssignmentValue::Field(field) => {
+                    codegen_field(state, instrs, &field.v);
+                }
+                simple::AssignmentValue::Ident(id) => {
+                    instrs.push(Instr::Raw(Instruction::LocalGet(state.resolve(id))));
+                }
+                simple::AssignmentValue::Call(call) => {
+                    for arg in call.v.arguments.v.iter() {
+                        instrs.push(Instr::Raw(Instruction::LocalGet(state.resolve(arg))));
+                    }
+                    instrs.push(Instr::Call(FunRef::Def(call.v.fun_name.v)));
+                }
+                simple::AssignmentValue::CallRef(call) => {
+                    let fun = state.resolve(&call.v.fun);
+                    instrs.push(Instr::Raw(Instruction::LocalGet(fun)));
+                    codegen_items(state, instrs, &call.v.arguments.v);
+                    // Values other than closures trap
+                    instrs.push(Instr::Raw(Instruction::LocalGet(fun)));
+                    instrs.push(Instr::Call(FunRef::Helper(Helper::UnpackClosure)));
+                    instrs.push(Instr::Raw(Instruction::StructGet {
+                        struct_type_index: CLOSURE_TYPE_ID,
+                        field_index: 0,
+                    }));
+                    instrs.push(Instr::Raw(Instruction::RefCastNonNull(HeapType::Concrete(
+                        CLOSURE_FUN_TYPE_ID,
+                    ))));
+                    instrs.push(Instr::Raw(Instruction::CallRef(CLOSURE_FUN_TYPE_ID)));
+                }
+                simple::AssignmentValue::Closure(closure) => {
+                    instrs.push(Instr::Raw(Instruction::I32Const(OBJECT_TAG_CLOSURE)));
+                    instrs.push(Instr::Raw(Instruction::I32Const(closure.v.fun.id as i32)));
+                    instrs.push(Instr::RefFunc(FunRef::Closure(closure.v.fun)));
+                    codegen_items(state, instrs, &closure.v.captures);
+                    instrs.push(Instr::Raw(Instruction::StructNew(CLOSURE_TYPE_ID)));
+                    instrs.push(Instr::Raw(Instruction::StructNew(OBJECT_TYPE_ID)));
+                }
+            }
+
+            instrs.push(Instr::Raw(Instruction::LocalSet(state.resolve(target))));
+        }
+        simple::FunStmt::SetIndex(set_index) => {
+            codegen_index(state, instrs, &set_index.v.target);
+            instrs.push(Instr::Raw(Instruction::LocalGet(
+                state.resolve(&set_index.v.value),
+            )));
+            instrs.push(Instr::Call(FunRef::Helper(Helper::ArraySet)));
+        }
+        simple::FunStmt::SetField(set_field) => {
+            codegen_set_field(state, instrs, &set_field.v);
+        }
+        simple::FunStmt::Return(id) => {
+            instrs.push(Instr::Raw(Instruction::LocalGet(state.resolve(id))));
+            instrs.push(Instr::Raw(Instruction::Return));
+        }
+        simple::FunStmt::If(if_) => {
+            // Conditions other than booleans trap.
+            instrs.push(Instr::Raw(Instruction::LocalGet(
+                state.resolve(&if_.condition),
+            )));
+            instrs.push(Instr::Call(FunRef::Helper(Helper::UnpackBool)));
+            instrs.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+
+            state.with_block(|state| {
+                codegen_statements(state, instrs, &if_.then.v);
+                if let Some(else_) = &if_.else_ {
+                    instrs.push(Instr::Raw(Instruction::Else));
+                    codegen_statements(state, instrs, &else_.v);
+                }
+            });
+
+            instrs.push(Instr::Raw(Instruction::End));
+        }
+        simple::FunStmt::Loop(loop_) => {
+            // A `block` to break out of around a `loop` to jump back to.
+            instrs.push(Instr::Raw(Instruction::Block(BlockType::Empty)));
+            instrs.push(Instr::Raw(Instruction::Loop(BlockType::Empty)));
+
+            state.with_loop(|state| {
+                codegen_statements(state, instrs, &loop_.v.body.v);
+            });
+
+            instrs.push(Instr::Raw(Instruction::Br(0)));
+            instrs.push(Instr::Raw(Instruction::End));
+            instrs.push(Instr::Raw(Instruction::End));
+        }
+        simple::FunStmt::Break() => {
+            let depth = state
+                .loop_depths
+                .last()
+                .expect("break is only allowed in loops");
+            instrs.push(Instr::Raw(Instruction::Br(depth + 1)));
+        }
+        simple::FunStmt::Continue() => {
+            let depth = state
+                .loop_depths
+                .last()
+                .expect("continue is only allowed in loops");
+            instrs.push(Instr::Raw(Instruction::Br(*depth)));
+        }
+        simple::FunStmt::NoMatch(_) => {
+            instrs.push(Instr::Raw(Instruction::Unreachable));
+        }
+        // Invariants are only checked by the interpreter.
+        simple::FunStmt::Inv(_) | simple::FunStmt::Nop => {}
+    }
+}
+
+/// Pushes a new array of `items`, unpacked.
+fn codegen_items(state: &mut CodegenState, instrs: &mut Vec<Instr>, items: &[simple::Ident]) {
+    instrs.push(Instr::Raw(Instruction::I32Const(items.len() as i32)));
+    for item in items.iter() {
+        instrs.push(Instr::Raw(Instruction::LocalGet(state.resolve(item))));
+    }
+    instrs.push(Instr::Raw(Instruction::ArrayNewFixed {
+        array_type_index: ELEMENTS_TYPE_ID,
+        array_size: items.len() as u32,
+    }));
+    instrs.push(Instr::Raw(Instruction::StructNew(ARRAY_TYPE_ID)));
+}
+
+/// Pushes the unpacked array and index, trapping on other values.
+fn codegen_index(state: &mut CodegenState, instrs: &mut Vec<Instr>, index: &simple::Index) {
+    instrs.push(Instr::Raw(Instruction::LocalGet(
+        state.resolve(&index.array),
+    )));
+    instrs.push(Instr::Call(FunRef::Helper(Helper::UnpackArray)));
+    instrs.push(Instr::Raw(Instruction::LocalGet(
+        state.resolve(&index.index),
+    )));
+    instrs.push(Instr::Call(FunRef::Helper(Helper::UnpackI32)));
+}
+
+/// Pushes the field of the struct declaring it, or the length of a string or
+/// an array for `length`, trapping on other values.
+fn codegen_field(state: &mut CodegenState, instrs: &mut Vec<Instr>, field: &simple::Field) {
+    let value = state.resolve(&field.value);
+    instrs.push(Instr::Raw(Instruction::Block(BlockType::Result(
+        object_val_type(),
+    ))));
+    for (ordinal, ix) in state.structs.declaring(&field.name) {
+        if_struct(instrs, value, ordinal, |instrs| {
+            instrs.extend(struct_ref(value, ordinal));
+            instrs.push(Instr::Raw(Instruction::StructGet {
+                struct_type_index: struct_type_id(ordinal),
+                field_index: ix,
+            }));
+            instrs.push(Instr::Raw(Instruction::Br(1)));
+        });
+    }
+    if field.name.0 == LENGTH {
+        instrs.push(Instr::Raw(Instruction::LocalGet(value)));
+        instrs.push(Instr::Call(FunRef::Def(state.structs.length)));
+    } else {
+        instrs.push(Instr::Raw(Instruction::Unreachable));
+    }
+    instrs.push(Instr::Raw(Instruction::End));
+}
+
+/// Replaces the field of the struct declaring it, trapping on other values.
+fn codegen_set_field(state: &mut CodegenState, instrs: &mut Vec<Instr>, set: &simple::SetField) {
+    let target = state.resolve(&set.target.value);
+    let value = state.resolve(&set.value);
+    instrs.push(Instr::Raw(Instruction::Block(BlockType::Empty)));
+    for (ordinal, ix) in state.structs.declaring(&set.target.name) {
+        if_struct(instrs, target, ordinal, |instrs| {
+            instrs.extend(struct_ref(target, ordinal));
+            instrs.push(Instr::Raw(Instruction::LocalGet(value)));
+            instrs.push(Instr::Raw(Instruction::StructSet {
+                struct_type_index: struct_type_id(ordinal),
+                field_index: ix,
+            }));
+            instrs.push(Instr::Raw(Instruction::Br(1)));
+        });
+    }
+    instrs.push(Instr::Raw(Instruction::Unreachable));
+    instrs.push(Instr::Raw(Instruction::End));
+}
+
+// State
+
+struct CodegenState<'t> {
+    structs: &'t Structs,
+    params: HashMap<plain::LocalIdent, u32>,
+    locals: HashMap<plain::LocalIdent, u32>,
+    single_uses: HashMap<simple::SingleUseIdent, u32>,
+
+    /// For each enclosing loop, the number of blocks opened inside it.
+    loop_depths: Vec<u32>,
+}
+
+impl<'t> CodegenState<'t> {
+    fn new(structs: &'t Structs) -> CodegenState<'t> {
+        CodegenState {
+            structs,
+            params: HashMap::new(),
+            locals: HashMap::new(),
+            single_uses: HashMap::new(),
+            loop_depths: vec![],
+        }
+    }
+
+    fn register_param(&mut self, param: &plain::LocalIdent) {
+        assert!(
+            self.locals.is_empty() && self.single_uses.is_empty(),
+            "parameters are registered before any local"
+        );
+        self.params.insert(*param, self.params.len() as u32);
+    }
+
+    fn next_index(&self) -> u32 {
+        (self.params.len() + self.locals.len() + self.single_uses.len()) as u32
+    }
+
+    fn resolve(&mut self, id: &simple::Ident) -> u32 {
+        match id {
+            simple::Ident::Local(id) => {
+                if let Some(ix) = self.params.get(&id.v) {
+                    return *ix;
+                }
+                let ix = self.next_index();
+                *self.locals.entry(id.v).or_insert(ix)
+            }
+            simple::Ident::SingleUse(id) => {
+                let ix = self.next_index();
+                *self.single_uses.entry(id.v).or_insert(ix)
+            }
+        }
+    }
+
+    fn with_loop(&mut self, f: impl FnOnce(&mut Self)) {
+        self.loop_depths.push(0);
+        f(self);
+        self.loop_depths.pop();
+    }
+
+    fn with_block(&mut self, f: impl FnOnce(&mut Self)) {
+        if let Some(depth) = self.loop_depths.last_mut() {
+            *depth += 1;
+        }
+        f(self);
+        if let Some(depth) = self.loop_depths.last_mut() {
+            *depth -= 1;
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use sexpr_derive::SExpr;
+
+use super::rts::Helper;
+use crate::binder::bound::DefId;
+
+/// Functions compiled in isolation, calling each other by reference.
+#[derive(Debug, Clone, SExpr)]
+pub struct Module {
+    pub funs: Vec<FunDecl>,
+    /// The number of fields of each struct, in the order of their types.
+    pub structs: Vec<u32>,
+}
+
+#[derive(Debug, Clone, SExpr)]
+pub struct FunDecl {
+    pub id: FunRef,
+    pub name: String,
+    pub export: bool,
+    pub implementation: FunImpl,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SExpr)]
+pub enum FunRef {
+    /// The function or primitive defined as `DefId`.
+    Def(DefId),
+    /// The exported wrapper of `DefId`, taking and returning plain numbers.
+    Export(DefId),
+    /// The function closures of `DefId` call, of type `CLOSURE_FUN_TYPE_ID`.
+    Closure(DefId),
+    Helper(Helper),
+    /// Compares two structs with the same tag field by field, generated for
+    /// the structs of the module.
+    StructsEqual,
+}
+
+pub type FunImpl = FunImpl_<Instr>;
+
+#[derive(Debug, Clone)]
+pub struct FunImpl_<InstrTy> {
+    pub params: Vec<wasm_encoder::ValType>,
+    pub results: Vec<wasm_encoder::ValType>,
+    /// Locals besides the parameters.
+    pub locals: Vec<wasm_encoder::ValType>,
+    pub body: Vec<InstrTy>,
+}
+
+fn val_types(name: &str, tys: &[wasm_encoder::ValType]) -> sexpr::SExprTerm {
+    let tys = tys.iter().map(val_type).collect::<Vec<_>>();
+    sexpr::call(name, &tys)
+}
+
+/// Prints value types like the text format does.
+fn val_type(ty: &wasm_encoder::ValType) -> sexpr::SExprTerm {
+    match ty {
+        wasm_encoder::ValType::Ref(r) => {
+            let heap_type = match r.heap_type {
+                wasm_encoder::HeapType::Concrete(ix) => sexpr::number(ix),
+                other => sexpr::symbol(&format!("{:?}", other).to_lowercase()),
+            };
+            match r.nullable {
+                true => sexpr::list([sexpr::symbol("ref"), sexpr::symbol("null"), heap_type]),
+                false => sexpr::list([sexpr::symbol("ref"), heap_type]),
+            }
+        }
+        other => sexpr::symbol(&format!("{:?}", other).to_lowercase()),
+    }
+}
+
+impl<InstrTy: sexpr::SExpr> sexpr::SExpr for FunImpl_<InstrTy> {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::list([
+            sexpr::symbol("FunImpl"),
+            val_types("params", &self.params),
+            val_types("results", &self.results),
+            val_types("locals", &self.locals),
+            sexpr::call("body", &self.body),
+        ])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Instr {
+    Call(FunRef),
+    /// A reference to the function, to be called with `call_ref`.
+    RefFunc(FunRef),
+    Raw(wasm_encoder::Instruction<'static>),
+}
+
+impl sexpr::SExpr for Instr {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        match self {
+            Instr::Call(fun) => sexpr::call("call", &[fun]),
+            Instr::RefFunc(fun) => sexpr::call("ref.func", &[fun]),
+            Instr::Raw(instr) => sexpr::symbol(&format!("{:?}", instr)),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use sexpr_derive::SExpr;
+
+use super::fragment;
+
+/// Functions in their final order, calling each other by index.
+#[derive(Debug, Clone, SExpr)]
+pub struct Module {
+    pub funs: Vec<FunDecl>,
+    /// The number of fields of each struct, in the order of their types.
+    pub structs: Vec<u32>,
+    /// The functions referenced by `ref.func`, which have to be declared.
+    pub referenced: Vec<u32>,
+}
+
+#[derive(Debug, Clone, SExpr)]
+pub struct FunDecl {
+    pub name: String,
+    pub export: bool,
+    pub implementation: FunImpl,
+}
+
+pub type FunImpl = fragment::FunImpl_<Instr>;
+
+#[derive(Debug, Clone)]
+pub struct Instr(pub wasm_encoder::Instruction<'static>);
+
+impl sexpr::SExpr for Instr {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::symbol(&format!("{:?}", self.0))
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use super::fragment::{self, FunRef};
+use super::linked;
+use super::rts::Helper;
+
+/// Assigns an index to every function reachable from the exports, and
+/// resolves the calls between them.
+pub fn link(input: &fragment::Module) -> linked::Module {
+    let helpers = Helper::ALL.iter().map(|h| h.fragment()).collect::<Vec<_>>();
+
+    let mut env = LinkerEnv {
+        available: input
+            .funs
+            .iter()
+            .chain(helpers.iter())
+            .map(|fun| (fun.id, fun))
+            .collect(),
+        ixs: HashMap::new(),
+        funs: vec![],
+        referenced: vec![],
+    };
+
+    for fun in input.funs.iter().filter(|fun| fun.export) {
+        env.resolve(fun.id);
+    }
+
+    linked::Module {
+        funs: env
+            .funs
+            .into_iter()
+            .map(|fun| fun.expect("every function is linked"))
+            .collect(),
+        structs: input.structs.clone(),
+        referenced: env.referenced,
+    }
+}
+
+struct LinkerEnv<'t> {
+    available: HashMap<FunRef, &'t fragment::FunDecl>,
+    ixs: HashMap<FunRef, u32>,
+    /// `None` while the function is being linked, so that recursive calls
+    /// can refer to it.
+    funs: Vec<Option<linked::FunDecl>>,
+    referenced: Vec<u32>,
+}
+
+impl<'t> LinkerEnv<'t> {
+    fn resolve(&mut self, fun_ref: FunRef) -> u32 {
+        if let Some(ix) = self.ixs.get(&fun_ref) {
+            return *ix;
+        }
+
+        let fun = *self
+            .available
+            .get(&fun_ref)
+            .unwrap_or_else(|| panic!("No fragment found for {:?}", fun_ref));
+
+        let ix = self.funs.len() as u32;
+        self.ixs.insert(fun_ref, ix);
+        self.funs.push(None);
+
+        let linked = self.link_function(fun);
+        self.funs[ix as usize] = Some(linked);
+        ix
+    }
+
+    fn link_function(&mut self, fun: &fragment::FunDecl) -> linked::FunDecl {
+        let implementation = &fun.implementation;
+        let body = implementation
+            .body
+            .iter()
+            .map(|instr| match instr {
+                fragment::Instr::Call(callee) => {
+                    linked::Instr(wasm_encoder::Instruction::Call(self.resolve(*callee)))
+                }
+                fragment::Instr::RefFunc(callee) => {
+                    let ix = self.resolve(*callee);
+                    if !self.referenced.contains(&ix) {
+                        self.referenced.push(ix);
+                    }
+                    linked::Instr(wasm_encoder::Instruction::RefFunc(ix))
+                }
+                fragment::Instr::Raw(instr) => linked::Instr(instr.clone()),
+            })
+            .collect();
+
+        linked::FunDecl {
+            name: fun.name.clone(),
+            export: fun.export,
+            implementation: linked::FunImpl {
+                params: implementation.params.clone(),
+                results: implementation.results.clone(),
+                locals: implementation.locals.clone(),
+                body,
+            },
+        }
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+
+use super::linked;
+use super::rts::{
+    array_fields, closure_fields, closure_fun_type, elements_field, object_fields, string_field,
+    struct_fields, struct_type_id, ARRAY_TYPE_ID, CLOSURE_FUN_TYPE_ID, CLOSURE_TYPE_ID,
+    ELEMENTS_TYPE_ID, OBJECT_TYPE_ID, STRING_TYPE_ID,
+};
+use super::types::WasmBytes;
+
+pub fn mk_wasm(module: &linked::Module) -> WasmBytes {
+    let mut env = MkWasmEnv::new();
+
+    let obj_type = env.get_type_ix(TypeKind::Struct(object_fields()));
+    assert!(obj_type == OBJECT_TYPE_ID);
+    let string_type = env.get_type_ix(TypeKind::Array(string_field()));
+    assert!(string_type == STRING_TYPE_ID);
+    let elements_type = env.get_type_ix(TypeKind::Array(elements_field()));
+    assert!(elements_type == ELEMENTS_TYPE_ID);
+    let array_type = env.get_type_ix(TypeKind::Struct(array_fields()));
+    assert!(array_type == ARRAY_TYPE_ID);
+    let closure_type = env.get_type_ix(TypeKind::Struct(closure_fields()));
+    assert!(closure_type == CLOSURE_TYPE_ID);
+    let (params, results) = closure_fun_type();
+    let closure_fun_type = env.get_type_ix(TypeKind::Func(params, results));
+    assert!(closure_fun_type == CLOSURE_FUN_TYPE_ID);
+    for (ordinal, count) in module.structs.iter().enumerate() {
+        let struct_type = env.add_type(TypeKind::Struct(struct_fields(*count)));
+        assert!(struct_type == struct_type_id(ordinal as u32));
+    }
+
+    let mut exports = wasm_encoder::ExportSection::new();
+    for fun in module.funs.iter() {
+        let implementation = &fun.implementation;
+        let type_ix = env.get_type_ix(TypeKind::Func(
+            implementation.params.clone(),
+            implementation.results.clone(),
+        ));
+        let fun_ix = env.add_func(type_ix, implementation);
+
+        if fun.export {
+            exports.export(&fun.name, wasm_encoder::ExportKind::Func, fun_ix);
+        }
+    }
+
+    // Functions can only be referenced once declared
+    let mut elements = wasm_encoder::ElementSection::new();
+    elements.declared(wasm_encoder::Elements::Functions(&module.referenced));
+    let has_elements = !module.referenced.is_empty();
+
+    // Build the module
+    let mut module = wasm_encoder::Module::new();
+    module.section(&env.type_section);
+    module.section(&env.function_section);
+    module.section(&exports);
+    if has_elements {
+        module.section(&elements);
+    }
+    module.section(&env.code_section);
+
+    WasmBytes {
+        bytes: module.finish(),
+    }
+}
+
+struct MkWasmEnv {
+    type_map: HashMap<TypeKind, u32>,
+    type_section: wasm_encoder::TypeSection,
+    function_section: wasm_encoder::FunctionSection,
+    code_section: wasm_encoder::CodeSection,
+}
+
+impl MkWasmEnv {
+    fn new() -> MkWasmEnv {
+        MkWasmEnv {
+            type_map: HashMap::new(),
+            type_section: wasm_encoder::TypeSection::new(),
+            function_section: wasm_encoder::FunctionSection::new(),
+            code_section: wasm_encoder::CodeSection::new(),
+        }
+    }
+
+    fn get_type_ix(&mut self, ty: TypeKind) -> u32 {
+        if let Some(existing) = self.type_map.get(&ty) {
+            return *existing;
+        }
+
+        let ix = self.add_type(ty.clone());
+        self.type_map.insert(ty, ix);
+        ix
+    }
+
+    /// Adds a type even if an identical one exists, structs with the same
+    /// number of fields have a type each.
+    fn add_type(&mut self, ty: TypeKind) -> u32 {
+        match ty {
+            TypeKind::Func(ref params, ref results) => {
+                self.type_section
+                    .function(params.iter().copied(), results.iter().copied());
+            }
+            TypeKind::Struct(ref fields) => {
+                self.type_section.struct_(fields.iter().copied());
+            }
+            TypeKind::Array(ref field) => {
+                self.type_section.array(&field.element_type, field.mutable);
+            }
+        }
+        self.type_section.len() - 1
+    }
+
+    fn add_func(&mut self, type_ix: u32, implementation: &linked::FunImpl) -> u32 {
+        let mut f =
+            wasm_encoder::Function::new_with_locals_types(implementation.locals.iter().copied());
+        for instr in implementation.body.iter() {
+            f.instruction(&instr.0);
+        }
+        f.instruction(&wasm_encoder::Instruction::End);
+        self.function_section.function(type_ix);
+        self.code_section.function(&f);
+
+        self.function_section.len() - 1
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum TypeKind {
+    Func(Vec<wasm_encoder::ValType>, Vec<wasm_encoder::ValType>),
+    Struct(Vec<wasm_encoder::FieldType>),
+    Array(wasm_encoder::FieldType),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen_wasm::{link, run};
+    use crate::interpreter::{self, InterpretOptions, KediValue};
+    use crate::runner::runner;
+
+    fn compile(src: &str) -> (linked::Module, WasmBytes) {
+        let out = runner(src).unwrap();
+        let linked = link(&run(&out.simple));
+        let wasm = mk_wasm(&linked);
+        (linked, wasm)
+    }
+
+    fn validate(wasm: &WasmBytes) {
+        let mut validator = wasmparser::Validator::new_with_features(
+            wasmparser::WasmFeatures::default()
+                | wasmparser::WasmFeatures::GC
+                | wasmparser::WasmFeatures::FUNCTION_REFERENCES,
+        );
+        validator.validate_all(&wasm.bytes).unwrap();
+    }
+
+    #[test]
+    fn loops_and_branches_validate() {
+        let (_, wasm) = compile(
+            "
+            fn fibonacci nth {
+                let ret = 0;
+                let prev = 1;
+
+                let i = 1;
+                while lte? i nth {
+                    let temp = ret;
+                    ret = add ret prev;
+                    prev = temp;
+                    i = add i 1;
+                    if gt? i 100 {
+                        return i;
+                    }
+                }
+
+                return ret;
+            }",
+        );
+        validate(&wasm);
+
+        let wat = wasm.to_wat().unwrap().text;
+        assert!(wat.contains("(export \"fibonacci\""));
+    }
+
+    #[test]
+    fn booleans_validate() {
+        let (_, wasm) = compile(
+            "
+            fn f x {
+                let b = x == 0 or not bool? x and true != false;
+                if b { return number? x; }
+                return false;
+            }",
+        );
+        validate(&wasm);
+    }
+
+    #[test]
+    fn strings_validate() {
+        let (_, wasm) = compile(
+            r#"
+            fn f s {
+                let t = concat s "\"ç\"";
+                let u = slice t 1 (length s);
+                if string? u and compare s u < 0 { return u == "x"; }
+                return t;
+            }"#,
+        );
+        validate(&wasm);
+    }
+
+    #[test]
+    fn arrays_validate() {
+        let (_, wasm) = compile(
+            "
+            fn f n {
+                let a = [n, [1, 2], \"x\"];
+                a[0] = a.length;
+                let b = push ([]) a[1];
+                if array? b and a == b { return a[1][0]; }
+                return [];
+            }",
+        );
+        validate(&wasm);
+    }
+
+    #[test]
+    fn structs_validate() {
+        let (linked, wasm) = compile(
+            "
+            struct Point { x, y }
+            struct Pair { y, x }
+            struct Unit {}
+            fn f a {
+                let p = Point a ([1]);
+                p.x = p.y.length;
+                if Point? p and p != Pair 1 2 and not Unit? p { return p.x; }
+                return p.length;
+            }",
+        );
+        validate(&wasm);
+        assert_eq!(linked.structs, vec![2, 2, 0]);
+
+        // Structs with as many fields have types of their own.
+        let wat = wasm.to_wat().unwrap().text;
+        assert_eq!(wat.matches("(type (;6;) (struct").count(), 1);
+        assert_eq!(wat.matches("(type (;7;) (struct").count(), 1);
+    }
+
+    #[test]
+    fn sum_types_validate() {
+        let (linked, wasm) = compile(
+            "
+            type Tree = Leaf | Node left value right;
+            fn sum t {
+                match t {
+                    Leaf => { return 0; }
+                    Node l v r => { return sum l + v + sum r; }
+                }
+            }
+            fn f a { if Tree? a { return sum (Node Leaf a Leaf); } return 0; }",
+        );
+        validate(&wasm);
+        assert_eq!(linked.structs, vec![0, 3]);
+    }
+
+    #[test]
+    fn jumps_validate() {
+        let (_, wasm) = compile(
+            "
+            fn f n {
+                let s = 0;
+                for i in range(0, n) {
+                    if i == 2 { continue; }
+                    while true { if s > 10 { break; } s = s + i; }
+                    if s > 100 { break; }
+                }
+                return s;
+            }",
+        );
+        validate(&wasm);
+    }
+
+    #[test]
+    fn closures_validate() {
+        let (_, wasm) = compile(
+            "
+            fn twice f x { return f (f x); }
+            fn f n {
+                let k = |x| { return x + n; };
+                if k == number? { return 0; }
+                return twice k (twice |x| { return k x; } 1);
+            }",
+        );
+        validate(&wasm);
+
+        let wat = wasm.to_wat().unwrap().text;
+        assert!(wat.contains("(elem (;0;) declare func"));
+    }
+
+    #[test]
+    fn recursive_functions_are_linked_once() {
+        let (linked, wasm) = compile(
+            "
+            fn even n { if eq? n 0 { return 1; } let m = add n 1; let r = odd m; return r; }
+            fn odd n { if eq? n 0 { return 0; } let m = add n 1; let r = even m; return r; }",
+        );
+        validate(&wasm);
+
+        let names = linked
+            .funs
+            .iter()
+            .map(|fun| fun.name.as_str())
+            .collect::<Vec<_>>();
+        for name in ["even", "odd"] {
+            // The wrapper and the function itself.
+            assert_eq!(names.iter().filter(|n| **n == name).count(), 2);
+        }
+        assert_eq!(linked.funs.iter().filter(|fun| fun.export).count(), 2);
+    }
+
+    const CYCLES: &str = "
+        struct Box { v }
+        fn arrays n {
+            let a = [n]; a = push a a;
+            let b = [n]; b = push b b;
+            let c = [n]; c = push c c; c = push c 1;
+            return a == b and a != c;
+        }
+        fn nested n {
+            let a = [n]; a = push a a;
+            let b = [n]; let c = [n, b]; b = push b c;
+            return a == b;
+        }
+        fn boxes n {
+            let a = Box n; a.v = a;
+            let b = Box n; b.v = b;
+            let c = Box n; c.v = b;
+            return a == b and a == c and a != Box 0;
+        }
+        fn differ n {
+            let a = [n]; a = push a a;
+            let b = [add n 1]; b = push b b;
+            return a == b;
+        }";
+
+    #[test]
+    fn cyclic_equality_validates() {
+        let (_, wasm) = compile(CYCLES);
+        validate(&wasm);
+    }
+
+    /// The result the wasm wrapper of a function returns for `value`.
+    fn as_i32(value: &KediValue) -> i32 {
+        match value {
+            KediValue::KediNum(n) => i32::try_from(n).unwrap(),
+            KediValue::KediBool(b) => *b as i32,
+            other => panic!("no i32 for {}", other),
+        }
+    }
+
+    #[test]
+    fn wasm_agrees_with_the_interpreter() {
+        let out = runner(CYCLES).unwrap();
+        let (_, wasm) = compile(CYCLES);
+        for fun in ["arrays", "nested", "boxes", "differ"] {
+            for n in [0, 7] {
+                let expected = interpreter::run(
+                    InterpretOptions::default(),
+                    &out.simple,
+                    fun,
+                    vec![KediValue::num(n)],
+                )
+                .unwrap();
+                let actual = wasm_exec::execute_wasm(&wasm.bytes, fun, &[n]);
+                assert_eq!(
+                    actual,
+                    wasm_exec::ExecuteWasmResult::Ok(as_i32(&expected.value)),
+                    "{} {}",
+                    fun,
+                    n
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+mod codegen;
+pub mod fragment;
+pub mod linked;
+mod linker;
+mod mk_wasm;
+mod prims;
+pub mod rts;
+mod types;
+
+pub use codegen::run;
+pub use linker::link;
+pub use mk_wasm::mk_wasm;
+pub use types::*;
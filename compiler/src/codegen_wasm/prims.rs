@@ -0,0 +1,251 @@
+use wasm_encoder::{BlockType, Instruction, ValType};
+
+use super::fragment::{FunImpl, FunRef, Instr};
+use super::rts::{
+    object_val_type, string_val_type, Helper, ARRAY_TYPE_ID, OBJECT_TAG_ARRAY, OBJECT_TAG_BOOL,
+    OBJECT_TAG_I32, OBJECT_TAG_STRING, OBJECT_TYPE_ID, STRING_TYPE_ID,
+};
+use crate::binder::bound::Prim;
+
+pub fn prim(prim: Prim) -> FunImpl {
+    match prim {
+        Prim::Gt => i32_binop(Instruction::I32GtS, Helper::PackBool),
+        Prim::Lt => i32_binop(Instruction::I32LtS, Helper::PackBool),
+        Prim::Gte => i32_binop(Instruction::I32GeS, Helper::PackBool),
+        Prim::Lte => i32_binop(Instruction::I32LeS, Helper::PackBool),
+        Prim::Eq => eq(),
+        Prim::Add => i32_binop(Instruction::I32Add, Helper::PackI32),
+        Prim::Sub => i32_binop(Instruction::I32Sub, Helper::PackI32),
+        Prim::Mul => i32_binop(Instruction::I32Mul, Helper::PackI32),
+        // Both trap on a zero divisor.
+        Prim::Div => i32_binop(Instruction::I32DivS, Helper::PackI32),
+        Prim::Mod => i32_binop(Instruction::I32RemS, Helper::PackI32),
+        Prim::IsNumber => has_tag(OBJECT_TAG_I32),
+        Prim::IsBool => has_tag(OBJECT_TAG_BOOL),
+        Prim::IsString => has_tag(OBJECT_TAG_STRING),
+        Prim::Concat => concat(),
+        Prim::Length => length(),
+        Prim::Slice => slice(),
+        Prim::Compare => compare(),
+        Prim::IsArray => has_tag(OBJECT_TAG_ARRAY),
+        Prim::Push => push(),
+    }
+}
+
+fn get(local: u32) -> Instr {
+    Instr::Raw(Instruction::LocalGet(local))
+}
+
+fn set(local: u32) -> Instr {
+    Instr::Raw(Instruction::LocalSet(local))
+}
+
+fn call(helper: Helper) -> Instr {
+    Instr::Call(FunRef::Helper(helper))
+}
+
+fn field(local: u32, field_index: u32) -> [Instr; 2] {
+    [
+        Instr::Raw(Instruction::LocalGet(local)),
+        Instr::Raw(Instruction::StructGet {
+            struct_type_index: OBJECT_TYPE_ID,
+            field_index,
+        }),
+    ]
+}
+
+fn eq() -> FunImpl {
+    FunImpl {
+        params: vec![object_val_type(), object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body: vec![get(0), get(1), call(Helper::Equal), call(Helper::PackBool)],
+    }
+}
+
+/// Whether the tag of the object is one of `tags`.
+pub fn has_any_tag(tags: &[i32]) -> FunImpl {
+    let mut body = vec![Instr::Raw(Instruction::I32Const(0))];
+    for tag in tags {
+        body.extend(field(0, 0));
+        body.push(Instr::Raw(Instruction::I32Const(*tag)));
+        body.push(Instr::Raw(Instruction::I32Eq));
+        body.push(Instr::Raw(Instruction::I32Or));
+    }
+    body.push(Instr::Call(FunRef::Helper(Helper::PackBool)));
+
+    FunImpl {
+        params: vec![object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body,
+    }
+}
+
+pub fn has_tag(tag: i32) -> FunImpl {
+    let mut body = Vec::from(field(0, 0));
+    body.push(Instr::Raw(Instruction::I32Const(tag)));
+    body.push(Instr::Raw(Instruction::I32Eq));
+    body.push(Instr::Call(FunRef::Helper(Helper::PackBool)));
+
+    FunImpl {
+        params: vec![object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body,
+    }
+}
+
+/// Unpacks both numbers, and packs the result with `pack`.
+fn i32_binop(instr: Instruction<'static>, pack: Helper) -> FunImpl {
+    FunImpl {
+        params: vec![object_val_type(), object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body: vec![
+            Instr::Raw(Instruction::LocalGet(0)),
+            Instr::Call(FunRef::Helper(Helper::UnpackI32)),
+            Instr::Raw(Instruction::LocalGet(1)),
+            Instr::Call(FunRef::Helper(Helper::UnpackI32)),
+            Instr::Raw(instr),
+            Instr::Call(FunRef::Helper(pack)),
+        ],
+    }
+}
+
+fn concat() -> FunImpl {
+    let (l, r, result) = (2, 3, 4);
+    let mut body = vec![];
+    body.extend([get(0), call(Helper::UnpackString), set(l)]);
+    body.extend([get(1), call(Helper::UnpackString), set(r)]);
+
+    body.extend([get(l), Instr::Raw(Instruction::ArrayLen)]);
+    body.extend([get(r), Instr::Raw(Instruction::ArrayLen)]);
+    body.push(Instr::Raw(Instruction::I32Add));
+    body.push(Instr::Raw(Instruction::ArrayNewDefault(STRING_TYPE_ID)));
+    body.push(set(result));
+
+    // Copy l to the start, and r after it
+    for (string, offset) in [(l, None), (r, Some(l))] {
+        body.push(get(result));
+        match offset {
+            Some(offset) => body.extend([get(offset), Instr::Raw(Instruction::ArrayLen)]),
+            None => body.push(Instr::Raw(Instruction::I32Const(0))),
+        }
+        body.extend([get(string), Instr::Raw(Instruction::I32Const(0))]);
+        body.extend([get(string), Instr::Raw(Instruction::ArrayLen)]);
+        body.push(Instr::Raw(Instruction::ArrayCopy {
+            array_type_index_dst: STRING_TYPE_ID,
+            array_type_index_src: STRING_TYPE_ID,
+        }));
+    }
+
+    body.extend([get(result), call(Helper::PackString)]);
+
+    FunImpl {
+        params: vec![object_val_type(), object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![string_val_type(); 3],
+        body,
+    }
+}
+
+/// The length of an array, or of a string otherwise.
+fn length() -> FunImpl {
+    let mut body = Vec::from(field(0, 0));
+    body.push(Instr::Raw(Instruction::I32Const(OBJECT_TAG_ARRAY)));
+    body.push(Instr::Raw(Instruction::I32Eq));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend([get(0), call(Helper::UnpackArray)]);
+    body.push(Instr::Raw(Instruction::StructGet {
+        struct_type_index: ARRAY_TYPE_ID,
+        field_index: 0,
+    }));
+    body.extend([call(Helper::PackI32), Instr::Raw(Instruction::Return)]);
+    body.push(Instr::Raw(Instruction::End));
+
+    body.extend([get(0), call(Helper::UnpackString)]);
+    body.push(Instr::Raw(Instruction::ArrayLen));
+    body.push(call(Helper::PackI32));
+
+    FunImpl {
+        params: vec![object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body,
+    }
+}
+
+fn push() -> FunImpl {
+    FunImpl {
+        params: vec![object_val_type(), object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body: vec![
+            get(0),
+            call(Helper::UnpackArray),
+            get(1),
+            call(Helper::ArrayPush),
+            get(0),
+        ],
+    }
+}
+
+/// Traps unless 0 <= start <= end <= length, like the interpreter fails.
+fn slice() -> FunImpl {
+    let (string, start, end, result) = (3, 4, 5, 6);
+    let mut body = vec![];
+    body.extend([get(0), call(Helper::UnpackString), set(string)]);
+    body.extend([get(1), call(Helper::UnpackI32), set(start)]);
+    body.extend([get(2), call(Helper::UnpackI32), set(end)]);
+
+    // Unsigned comparisons also catch negative indices
+    body.extend([get(start), get(end), Instr::Raw(Instruction::I32GtU)]);
+    body.extend([get(end), get(string), Instr::Raw(Instruction::ArrayLen)]);
+    body.push(Instr::Raw(Instruction::I32GtU));
+    body.push(Instr::Raw(Instruction::I32Or));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.push(Instr::Raw(Instruction::Unreachable));
+    body.push(Instr::Raw(Instruction::End));
+
+    body.extend([get(end), get(start), Instr::Raw(Instruction::I32Sub)]);
+    body.push(Instr::Raw(Instruction::ArrayNewDefault(STRING_TYPE_ID)));
+    body.push(set(result));
+    body.extend([get(result), Instr::Raw(Instruction::I32Const(0))]);
+    body.extend([get(string), get(start)]);
+    body.extend([get(end), get(start), Instr::Raw(Instruction::I32Sub)]);
+    body.push(Instr::Raw(Instruction::ArrayCopy {
+        array_type_index_dst: STRING_TYPE_ID,
+        array_type_index_src: STRING_TYPE_ID,
+    }));
+
+    body.extend([get(result), call(Helper::PackString)]);
+
+    FunImpl {
+        params: vec![object_val_type(); 3],
+        results: vec![object_val_type()],
+        locals: vec![
+            string_val_type(),
+            ValType::I32,
+            ValType::I32,
+            string_val_type(),
+        ],
+        body,
+    }
+}
+
+fn compare() -> FunImpl {
+    FunImpl {
+        params: vec![object_val_type(), object_val_type()],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body: vec![
+            get(0),
+            call(Helper::UnpackString),
+            get(1),
+            call(Helper::UnpackString),
+            call(Helper::CompareStrings),
+            call(Helper::PackI32),
+        ],
+    }
+}
@@ -0,0 +1,764 @@
+use sexpr_derive::SExpr;
+use wasm_encoder::{BlockType, HeapType, Instruction, RefType, ValType};
+
+use super::fragment::{FunDecl, FunImpl, FunRef, Instr};
+
+// Every kedi value is boxed in an object, a struct of a tag and a payload.
+// Numbers and booleans keep their payload in an i32, other values in a
+// reference.
+
+pub static OBJECT_TYPE_ID: u32 = 0;
+
+/// Strings are arrays of code points, mutable so that they can be built with
+/// `array.copy`.
+pub static STRING_TYPE_ID: u32 = 1;
+
+/// The items of an array, with room to grow past its length.
+pub static ELEMENTS_TYPE_ID: u32 = 2;
+
+/// Arrays are a struct of a length and the elements, replaced by larger ones
+/// as items are pushed.
+pub static ARRAY_TYPE_ID: u32 = 3;
+
+/// Closures are a struct of the function to call and an array of the values
+/// it captured.
+pub static CLOSURE_TYPE_ID: u32 = 4;
+
+/// The type of the functions closures call, which take the closure and an
+/// array of the arguments, so that closures of any arity share it.
+pub static CLOSURE_FUN_TYPE_ID: u32 = 5;
+
+/// Each struct has a type of its own after the fixed ones, with an object per
+/// field.
+pub static STRUCT_TYPE_BASE: u32 = 6;
+
+pub static OBJECT_TAG_I32: i32 = 1;
+
+/// Booleans have a payload of 0 or 1.
+pub static OBJECT_TAG_BOOL: i32 = 2;
+
+pub static OBJECT_TAG_STRING: i32 = 3;
+
+pub static OBJECT_TAG_ARRAY: i32 = 4;
+
+/// Closures have the `DefId` of their function as payload, so that they can
+/// be compared.
+pub static OBJECT_TAG_CLOSURE: i32 = 5;
+
+/// Structs are tagged with this plus the position of their definition among
+/// the structs of the module, their payload is unused.
+pub static OBJECT_TAG_STRUCT: i32 = 6;
+
+pub fn struct_type_id(ordinal: u32) -> u32 {
+    STRUCT_TYPE_BASE + ordinal
+}
+
+pub fn struct_tag(ordinal: u32) -> i32 {
+    OBJECT_TAG_STRUCT + ordinal as i32
+}
+
+/// Objects are nullable so that locals do not need to be initialised, the
+/// null reference never escapes the generated code.
+pub fn object_val_type() -> ValType {
+    ValType::Ref(RefType {
+        nullable: true,
+        heap_type: HeapType::Concrete(OBJECT_TYPE_ID),
+    })
+}
+
+pub fn string_val_type() -> ValType {
+    ValType::Ref(RefType {
+        nullable: true,
+        heap_type: HeapType::Concrete(STRING_TYPE_ID),
+    })
+}
+
+pub fn elements_val_type() -> ValType {
+    ValType::Ref(RefType {
+        nullable: true,
+        heap_type: HeapType::Concrete(ELEMENTS_TYPE_ID),
+    })
+}
+
+pub fn array_val_type() -> ValType {
+    ValType::Ref(RefType {
+        nullable: true,
+        heap_type: HeapType::Concrete(ARRAY_TYPE_ID),
+    })
+}
+
+pub fn closure_val_type() -> ValType {
+    ValType::Ref(RefType {
+        nullable: true,
+        heap_type: HeapType::Concrete(CLOSURE_TYPE_ID),
+    })
+}
+
+pub fn object_fields() -> Vec<wasm_encoder::FieldType> {
+    vec![
+        // Tag
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::Val(ValType::I32),
+            mutable: false,
+        },
+        // Value
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::Val(ValType::I32),
+            mutable: false,
+        },
+        // Reference
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::Val(ValType::Ref(RefType::ANYREF)),
+            mutable: false,
+        },
+    ]
+}
+
+pub fn string_field() -> wasm_encoder::FieldType {
+    wasm_encoder::FieldType {
+        element_type: wasm_encoder::StorageType::Val(ValType::I32),
+        mutable: true,
+    }
+}
+
+pub fn elements_field() -> wasm_encoder::FieldType {
+    wasm_encoder::FieldType {
+        element_type: wasm_encoder::StorageType::Val(object_val_type()),
+        mutable: true,
+    }
+}
+
+pub fn array_fields() -> Vec<wasm_encoder::FieldType> {
+    vec![
+        // Length
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::Val(ValType::I32),
+            mutable: true,
+        },
+        // Elements
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::Val(elements_val_type()),
+            mutable: true,
+        },
+    ]
+}
+
+pub fn closure_fields() -> Vec<wasm_encoder::FieldType> {
+    vec![
+        // Function
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::Val(ValType::Ref(RefType::FUNCREF)),
+            mutable: false,
+        },
+        // Captures
+        wasm_encoder::FieldType {
+            element_type: wasm_encoder::StorageType::Val(array_val_type()),
+            mutable: false,
+        },
+    ]
+}
+
+/// The parameters and results of `CLOSURE_FUN_TYPE_ID`.
+pub fn closure_fun_type() -> (Vec<ValType>, Vec<ValType>) {
+    (
+        vec![object_val_type(), array_val_type()],
+        vec![object_val_type()],
+    )
+}
+
+pub fn struct_fields(count: u32) -> Vec<wasm_encoder::FieldType> {
+    let field = wasm_encoder::FieldType {
+        element_type: wasm_encoder::StorageType::Val(object_val_type()),
+        mutable: true,
+    };
+    vec![field; count as usize]
+}
+
+/// Functions of the runtime, linked in when the generated code calls them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, SExpr)]
+pub enum Helper {
+    PackI32,
+    UnpackI32,
+    PackBool,
+    UnpackBool,
+    /// Unwraps a number, or a boolean as 0 or 1, for the host.
+    UnpackResult,
+    PackString,
+    UnpackString,
+    /// -1, 0 or 1 as a string sorts before, with or after another.
+    CompareStrings,
+    PackArray,
+    UnpackArray,
+    /// The item at an index, trapping when it is out of bounds.
+    ArrayGet,
+    /// Replaces the item at an index, trapping when it is out of bounds.
+    ArraySet,
+    /// Adds an item at the end, doubling the capacity when it is full.
+    ArrayPush,
+    UnpackClosure,
+    /// 1 when two values are equal, comparing strings and arrays by items,
+    /// structs by fields, and closures by function and captures.
+    Equal,
+    /// `Equal`, taking the pairs of arrays and structs in an array of
+    /// objects to be equal, so that comparing cyclic values ends.
+    EqualSeen,
+}
+
+impl Helper {
+    pub const ALL: &'static [Helper] = &[
+        Helper::PackI32,
+        Helper::UnpackI32,
+        Helper::PackBool,
+        Helper::UnpackBool,
+        Helper::UnpackResult,
+        Helper::PackString,
+        Helper::UnpackString,
+        Helper::CompareStrings,
+        Helper::PackArray,
+        Helper::UnpackArray,
+        Helper::ArrayGet,
+        Helper::ArraySet,
+        Helper::ArrayPush,
+        Helper::UnpackClosure,
+        Helper::Equal,
+        Helper::EqualSeen,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Helper::PackI32 => "__rts_pack_i32",
+            Helper::UnpackI32 => "__rts_unpack_i32",
+            Helper::PackBool => "__rts_pack_bool",
+            Helper::UnpackBool => "__rts_unpack_bool",
+            Helper::UnpackResult => "__rts_unpack_result",
+            Helper::PackString => "__rts_pack_string",
+            Helper::UnpackString => "__rts_unpack_string",
+            Helper::CompareStrings => "__rts_compare_strings",
+            Helper::PackArray => "__rts_pack_array",
+            Helper::UnpackArray => "__rts_unpack_array",
+            Helper::ArrayGet => "__rts_array_get",
+            Helper::ArraySet => "__rts_array_set",
+            Helper::ArrayPush => "__rts_array_push",
+            Helper::UnpackClosure => "__rts_unpack_closure",
+            Helper::Equal => "__rts_equal",
+            Helper::EqualSeen => "__rts_equal_seen",
+        }
+    }
+
+    pub fn fragment(&self) -> FunDecl {
+        let implementation = match self {
+            Helper::PackI32 => pack(OBJECT_TAG_I32),
+            Helper::UnpackI32 => unpack(Some(OBJECT_TAG_I32)),
+            Helper::PackBool => pack(OBJECT_TAG_BOOL),
+            Helper::UnpackBool => unpack(Some(OBJECT_TAG_BOOL)),
+            Helper::UnpackResult => unpack(None),
+            Helper::PackString => pack_ref(OBJECT_TAG_STRING, string_val_type()),
+            Helper::UnpackString => unpack_ref(OBJECT_TAG_STRING, STRING_TYPE_ID),
+            Helper::CompareStrings => compare_strings(),
+            Helper::PackArray => pack_ref(OBJECT_TAG_ARRAY, array_val_type()),
+            Helper::UnpackArray => unpack_ref(OBJECT_TAG_ARRAY, ARRAY_TYPE_ID),
+            Helper::ArrayGet => array_get(),
+            Helper::ArraySet => array_set(),
+            Helper::ArrayPush => array_push(),
+            Helper::UnpackClosure => unpack_ref(OBJECT_TAG_CLOSURE, CLOSURE_TYPE_ID),
+            Helper::Equal => equal(),
+            Helper::EqualSeen => equal_seen(),
+        };
+
+        FunDecl {
+            id: FunRef::Helper(*self),
+            name: self.name().to_string(),
+            export: false,
+            implementation,
+        }
+    }
+}
+
+fn pack(tag: i32) -> FunImpl {
+    FunImpl {
+        params: vec![ValType::I32],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body: vec![
+            Instr::Raw(Instruction::I32Const(tag)),
+            Instr::Raw(Instruction::LocalGet(0)),
+            Instr::Raw(Instruction::RefNull(HeapType::Abstract {
+                shared: false,
+                ty: wasm_encoder::AbstractHeapType::Any,
+            })),
+            Instr::Raw(Instruction::StructNew(OBJECT_TYPE_ID)),
+        ],
+    }
+}
+
+/// Traps unless the object in local 0 has the tag `tag`.
+fn check_tag(tag: i32) -> Vec<Instr> {
+    vec![
+        Instr::Raw(Instruction::LocalGet(0)),
+        Instr::Raw(Instruction::StructGet {
+            struct_type_index: OBJECT_TYPE_ID,
+            field_index: 0,
+        }),
+        Instr::Raw(Instruction::I32Const(tag)),
+        Instr::Raw(Instruction::I32Ne),
+        Instr::Raw(Instruction::If(BlockType::Empty)),
+        Instr::Raw(Instruction::Unreachable),
+        Instr::Raw(Instruction::End),
+    ]
+}
+
+/// Unwraps the payload of an object, trapping unless it has the tag `tag`
+/// when given.
+fn unpack(tag: Option<i32>) -> FunImpl {
+    let mut body = tag.map(check_tag).unwrap_or_default();
+    // Unwrap the value
+    body.extend([
+        Instr::Raw(Instruction::LocalGet(0)),
+        Instr::Raw(Instruction::StructGet {
+            struct_type_index: OBJECT_TYPE_ID,
+            field_index: 1,
+        }),
+    ]);
+
+    FunImpl {
+        params: vec![object_val_type()],
+        results: vec![ValType::I32],
+        locals: vec![],
+        body,
+    }
+}
+
+/// Boxes the reference in local 0, of type `ty`, with the tag `tag`.
+fn pack_ref(tag: i32, ty: ValType) -> FunImpl {
+    FunImpl {
+        params: vec![ty],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body: vec![
+            Instr::Raw(Instruction::I32Const(tag)),
+            Instr::Raw(Instruction::I32Const(0)),
+            Instr::Raw(Instruction::LocalGet(0)),
+            Instr::Raw(Instruction::StructNew(OBJECT_TYPE_ID)),
+        ],
+    }
+}
+
+/// Unwraps the reference of an object, trapping unless it has the tag `tag`.
+fn unpack_ref(tag: i32, type_id: u32) -> FunImpl {
+    let mut body = check_tag(tag);
+    body.extend([
+        Instr::Raw(Instruction::LocalGet(0)),
+        Instr::Raw(Instruction::StructGet {
+            struct_type_index: OBJECT_TYPE_ID,
+            field_index: 2,
+        }),
+        Instr::Raw(Instruction::RefCastNonNull(HeapType::Concrete(type_id))),
+    ]);
+
+    FunImpl {
+        params: vec![object_val_type()],
+        results: vec![ValType::Ref(RefType {
+            nullable: true,
+            heap_type: HeapType::Concrete(type_id),
+        })],
+        locals: vec![],
+        body,
+    }
+}
+
+/// Compares the strings in locals 0 and 1 code point by code point, and by
+/// their lengths when one is a prefix of the other.
+fn compare_strings() -> FunImpl {
+    let (l, r, ix, len, x, y) = (0, 1, 2, 3, 4, 5);
+    let get = |local| Instr::Raw(Instruction::LocalGet(local));
+    let set = |local| Instr::Raw(Instruction::LocalSet(local));
+    let array_len = |local| [get(local), Instr::Raw(Instruction::ArrayLen)];
+    // (a > b) - (a < b), for the two values pushed by `push`.
+    let sign = |push: &dyn Fn() -> Vec<Instr>| {
+        let mut instrs = push();
+        instrs.push(Instr::Raw(Instruction::I32GtU));
+        instrs.extend(push());
+        instrs.push(Instr::Raw(Instruction::I32LtU));
+        instrs.push(Instr::Raw(Instruction::I32Sub));
+        instrs
+    };
+    let lengths = || [array_len(l), array_len(r)].concat();
+    let code_points = || vec![get(x), get(y)];
+
+    let mut body = vec![];
+    // The length of the shorter string
+    body.extend(lengths());
+    body.extend(lengths());
+    body.push(Instr::Raw(Instruction::I32LtU));
+    body.push(Instr::Raw(Instruction::Select));
+    body.push(set(len));
+
+    body.push(Instr::Raw(Instruction::Block(BlockType::Empty)));
+    body.push(Instr::Raw(Instruction::Loop(BlockType::Empty)));
+    body.extend([get(ix), get(len), Instr::Raw(Instruction::I32GeU)]);
+    body.push(Instr::Raw(Instruction::BrIf(1)));
+    for (string, code_point) in [(l, x), (r, y)] {
+        body.extend([get(string), get(ix)]);
+        body.push(Instr::Raw(Instruction::ArrayGet(STRING_TYPE_ID)));
+        body.push(set(code_point));
+    }
+    // The first difference decides
+    body.extend([get(x), get(y), Instr::Raw(Instruction::I32Ne)]);
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend(sign(&code_points));
+    body.push(Instr::Raw(Instruction::Return));
+    body.push(Instr::Raw(Instruction::End));
+    body.extend([get(ix), Instr::Raw(Instruction::I32Const(1))]);
+    body.extend([Instr::Raw(Instruction::I32Add), set(ix)]);
+    body.push(Instr::Raw(Instruction::Br(0)));
+    body.push(Instr::Raw(Instruction::End));
+    body.push(Instr::Raw(Instruction::End));
+
+    // Otherwise the shorter string comes first
+    body.extend(sign(&lengths));
+
+    FunImpl {
+        params: vec![string_val_type(), string_val_type()],
+        results: vec![ValType::I32],
+        locals: vec![ValType::I32; 4],
+        body,
+    }
+}
+
+fn array_field(local: u32, field_index: u32) -> [Instr; 2] {
+    [
+        Instr::Raw(Instruction::LocalGet(local)),
+        Instr::Raw(Instruction::StructGet {
+            struct_type_index: ARRAY_TYPE_ID,
+            field_index,
+        }),
+    ]
+}
+
+/// Traps unless the index in local 1 is below the length of the array in
+/// local 0. The comparison is unsigned to also catch negative indices.
+fn check_bounds() -> Vec<Instr> {
+    let mut body = vec![Instr::Raw(Instruction::LocalGet(1))];
+    body.extend(array_field(0, 0));
+    body.extend([
+        Instr::Raw(Instruction::I32GeU),
+        Instr::Raw(Instruction::If(BlockType::Empty)),
+        Instr::Raw(Instruction::Unreachable),
+        Instr::Raw(Instruction::End),
+    ]);
+    body
+}
+
+fn array_get() -> FunImpl {
+    let mut body = check_bounds();
+    body.extend(array_field(0, 1));
+    body.extend([
+        Instr::Raw(Instruction::LocalGet(1)),
+        Instr::Raw(Instruction::ArrayGet(ELEMENTS_TYPE_ID)),
+    ]);
+
+    FunImpl {
+        params: vec![array_val_type(), ValType::I32],
+        results: vec![object_val_type()],
+        locals: vec![],
+        body,
+    }
+}
+
+fn array_set() -> FunImpl {
+    let mut body = check_bounds();
+    body.extend(array_field(0, 1));
+    body.extend([
+        Instr::Raw(Instruction::LocalGet(1)),
+        Instr::Raw(Instruction::LocalGet(2)),
+        Instr::Raw(Instruction::ArraySet(ELEMENTS_TYPE_ID)),
+    ]);
+
+    FunImpl {
+        params: vec![array_val_type(), ValType::I32, object_val_type()],
+        results: vec![],
+        locals: vec![],
+        body,
+    }
+}
+
+fn array_push() -> FunImpl {
+    let (array, item, length, elements) = (0, 1, 2, 3);
+    let get = |local| Instr::Raw(Instruction::LocalGet(local));
+    let set = |local| Instr::Raw(Instruction::LocalSet(local));
+
+    let mut body = vec![];
+    body.extend(array_field(array, 0));
+    body.push(set(length));
+
+    // Move the items to elements twice as large, or of one item, when full
+    body.extend([get(length), Instr::Raw(Instruction::I32Eqz)]);
+    body.extend(array_field(array, 1));
+    body.extend([Instr::Raw(Instruction::ArrayLen), get(length)]);
+    body.push(Instr::Raw(Instruction::I32Eq));
+    body.push(Instr::Raw(Instruction::I32Or));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend([get(length), Instr::Raw(Instruction::I32Const(1))]);
+    body.push(Instr::Raw(Instruction::I32Shl));
+    body.extend([Instr::Raw(Instruction::I32Const(1))]);
+    body.push(Instr::Raw(Instruction::I32Or));
+    body.push(Instr::Raw(Instruction::ArrayNewDefault(ELEMENTS_TYPE_ID)));
+    body.push(set(elements));
+    body.extend([get(elements), Instr::Raw(Instruction::I32Const(0))]);
+    body.extend(array_field(array, 1));
+    body.extend([Instr::Raw(Instruction::I32Const(0)), get(length)]);
+    body.push(Instr::Raw(Instruction::ArrayCopy {
+        array_type_index_dst: ELEMENTS_TYPE_ID,
+        array_type_index_src: ELEMENTS_TYPE_ID,
+    }));
+    body.extend([get(array), get(elements)]);
+    body.push(Instr::Raw(Instruction::StructSet {
+        struct_type_index: ARRAY_TYPE_ID,
+        field_index: 1,
+    }));
+    body.push(Instr::Raw(Instruction::End));
+
+    body.extend(array_field(array, 1));
+    body.extend([get(length), get(item)]);
+    body.push(Instr::Raw(Instruction::ArraySet(ELEMENTS_TYPE_ID)));
+    body.extend([
+        get(array),
+        get(length),
+        Instr::Raw(Instruction::I32Const(1)),
+    ]);
+    body.push(Instr::Raw(Instruction::I32Add));
+    body.push(Instr::Raw(Instruction::StructSet {
+        struct_type_index: ARRAY_TYPE_ID,
+        field_index: 0,
+    }));
+
+    FunImpl {
+        params: vec![array_val_type(), object_val_type()],
+        results: vec![],
+        locals: vec![ValType::I32, elements_val_type()],
+        body,
+    }
+}
+
+/// Compares two values with no pairs seen yet.
+fn equal() -> FunImpl {
+    FunImpl {
+        params: vec![object_val_type(), object_val_type()],
+        results: vec![ValType::I32],
+        locals: vec![],
+        body: vec![
+            Instr::Raw(Instruction::LocalGet(0)),
+            Instr::Raw(Instruction::LocalGet(1)),
+            Instr::Raw(Instruction::I32Const(0)),
+            Instr::Raw(Instruction::I32Const(0)),
+            Instr::Raw(Instruction::ArrayNewDefault(ELEMENTS_TYPE_ID)),
+            Instr::Raw(Instruction::StructNew(ARRAY_TYPE_ID)),
+            Instr::Call(FunRef::Helper(Helper::EqualSeen)),
+        ],
+    }
+}
+
+/// Values are equal when they have the same tag and the same payload, the
+/// same code points for strings, equal items for arrays, equal fields for
+/// structs, or equal captures for closures of the same function.
+///
+/// Like the interpreter, the same array or struct is equal to itself, and
+/// pairs of arrays or structs already seen are taken to be equal. Pairs are
+/// never removed from `seen`: a comparison stops at the first difference,
+/// so the pairs left are either still being compared or found equal.
+fn equal_seen() -> FunImpl {
+    let (l, r, seen, ix, length) = (0, 1, 2, 3, 4);
+    let (left, right, pair) = (5, 6, 7);
+    let get = |local| Instr::Raw(Instruction::LocalGet(local));
+    let set = |local| Instr::Raw(Instruction::LocalSet(local));
+    let field = |local, field_index| {
+        [
+            Instr::Raw(Instruction::LocalGet(local)),
+            Instr::Raw(Instruction::StructGet {
+                struct_type_index: OBJECT_TYPE_ID,
+                field_index,
+            }),
+        ]
+    };
+    let tag = |local| field(local, 0);
+    let returns = |value| {
+        [
+            Instr::Raw(Instruction::I32Const(value)),
+            Instr::Raw(Instruction::Return),
+        ]
+    };
+    // What the array or struct of an object is, comparable with `ref.eq`
+    let identity = || {
+        [
+            Instr::Raw(Instruction::StructGet {
+                struct_type_index: OBJECT_TYPE_ID,
+                field_index: 2,
+            }),
+            Instr::Raw(Instruction::RefCastNullable(HeapType::Abstract {
+                shared: false,
+                ty: wasm_encoder::AbstractHeapType::Eq,
+            })),
+        ]
+    };
+    // Returns 1 for the same array or struct, or for a pair seen before,
+    // otherwise adds the pair to `seen`
+    let seen_before = || {
+        let mut body = vec![get(l)];
+        body.extend(identity());
+        body.push(get(r));
+        body.extend(identity());
+        body.push(Instr::Raw(Instruction::RefEq));
+        body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+        body.extend(returns(1));
+        body.push(Instr::Raw(Instruction::End));
+
+        body.push(Instr::Raw(Instruction::Block(BlockType::Empty)));
+        body.push(Instr::Raw(Instruction::Loop(BlockType::Empty)));
+        body.push(get(pair));
+        body.extend(array_field(seen, 0));
+        body.push(Instr::Raw(Instruction::I32GeU));
+        body.push(Instr::Raw(Instruction::BrIf(1)));
+        for (offset, object) in [(0, l), (1, r)] {
+            body.extend(array_field(seen, 1));
+            body.extend([get(pair), Instr::Raw(Instruction::I32Const(offset))]);
+            body.push(Instr::Raw(Instruction::I32Add));
+            body.push(Instr::Raw(Instruction::ArrayGet(ELEMENTS_TYPE_ID)));
+            body.extend(identity());
+            body.push(get(object));
+            body.extend(identity());
+            body.push(Instr::Raw(Instruction::RefEq));
+        }
+        body.push(Instr::Raw(Instruction::I32And));
+        body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+        body.extend(returns(1));
+        body.push(Instr::Raw(Instruction::End));
+        body.extend([get(pair), Instr::Raw(Instruction::I32Const(2))]);
+        body.extend([Instr::Raw(Instruction::I32Add), set(pair)]);
+        body.push(Instr::Raw(Instruction::Br(0)));
+        body.push(Instr::Raw(Instruction::End));
+        body.push(Instr::Raw(Instruction::End));
+
+        for object in [l, r] {
+            body.extend([get(seen), get(object)]);
+            body.push(Instr::Call(FunRef::Helper(Helper::ArrayPush)));
+        }
+        body
+    };
+
+    let mut body = vec![];
+    body.extend(tag(l));
+    body.extend(tag(r));
+    body.push(Instr::Raw(Instruction::I32Ne));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend(returns(0));
+    body.push(Instr::Raw(Instruction::End));
+
+    body.extend(tag(l));
+    body.push(Instr::Raw(Instruction::I32Const(OBJECT_TAG_STRING)));
+    body.push(Instr::Raw(Instruction::I32Eq));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend([get(l), Instr::Call(FunRef::Helper(Helper::UnpackString))]);
+    body.extend([get(r), Instr::Call(FunRef::Helper(Helper::UnpackString))]);
+    body.push(Instr::Call(FunRef::Helper(Helper::CompareStrings)));
+    body.push(Instr::Raw(Instruction::I32Eqz));
+    body.push(Instr::Raw(Instruction::Return));
+    body.push(Instr::Raw(Instruction::End));
+
+    // The arrays in `left` and `right` compare item by item
+    let items_equal = || {
+        let mut body = vec![];
+        body.extend(array_field(left, 0));
+        body.push(set(length));
+        body.push(get(length));
+        body.extend(array_field(right, 0));
+        body.push(Instr::Raw(Instruction::I32Ne));
+        body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+        body.extend(returns(0));
+        body.push(Instr::Raw(Instruction::End));
+        body.push(Instr::Raw(Instruction::Block(BlockType::Empty)));
+        body.push(Instr::Raw(Instruction::Loop(BlockType::Empty)));
+        body.extend([get(ix), get(length), Instr::Raw(Instruction::I32GeU)]);
+        body.push(Instr::Raw(Instruction::BrIf(1)));
+        for array in [left, right] {
+            body.extend([get(array), get(ix)]);
+            body.push(Instr::Call(FunRef::Helper(Helper::ArrayGet)));
+        }
+        body.push(get(seen));
+        body.push(Instr::Call(FunRef::Helper(Helper::EqualSeen)));
+        body.push(Instr::Raw(Instruction::I32Eqz));
+        body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+        body.extend(returns(0));
+        body.push(Instr::Raw(Instruction::End));
+        body.extend([get(ix), Instr::Raw(Instruction::I32Const(1))]);
+        body.extend([Instr::Raw(Instruction::I32Add), set(ix)]);
+        body.push(Instr::Raw(Instruction::Br(0)));
+        body.push(Instr::Raw(Instruction::End));
+        body.push(Instr::Raw(Instruction::End));
+        body.extend(returns(1));
+        body
+    };
+
+    body.extend(tag(l));
+    body.push(Instr::Raw(Instruction::I32Const(OBJECT_TAG_ARRAY)));
+    body.push(Instr::Raw(Instruction::I32Eq));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend(seen_before());
+    body.extend([get(l), Instr::Call(FunRef::Helper(Helper::UnpackArray))]);
+    body.push(set(left));
+    body.extend([get(r), Instr::Call(FunRef::Helper(Helper::UnpackArray))]);
+    body.push(set(right));
+    body.extend(items_equal());
+    body.push(Instr::Raw(Instruction::End));
+
+    // Closures of the same function compare their captures
+    body.extend(tag(l));
+    body.push(Instr::Raw(Instruction::I32Const(OBJECT_TAG_CLOSURE)));
+    body.push(Instr::Raw(Instruction::I32Eq));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend(field(l, 1));
+    body.extend(field(r, 1));
+    body.push(Instr::Raw(Instruction::I32Ne));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend(returns(0));
+    body.push(Instr::Raw(Instruction::End));
+    for (object, array) in [(l, left), (r, right)] {
+        body.extend([
+            get(object),
+            Instr::Call(FunRef::Helper(Helper::UnpackClosure)),
+        ]);
+        body.push(Instr::Raw(Instruction::StructGet {
+            struct_type_index: CLOSURE_TYPE_ID,
+            field_index: 1,
+        }));
+        body.push(set(array));
+    }
+    body.extend(items_equal());
+    body.push(Instr::Raw(Instruction::End));
+
+    body.extend(tag(l));
+    body.push(Instr::Raw(Instruction::I32Const(OBJECT_TAG_STRUCT)));
+    body.push(Instr::Raw(Instruction::I32GeS));
+    body.push(Instr::Raw(Instruction::If(BlockType::Empty)));
+    body.extend(seen_before());
+    body.extend([get(l), get(r), get(seen), Instr::Call(FunRef::StructsEqual)]);
+    body.push(Instr::Raw(Instruction::Return));
+    body.push(Instr::Raw(Instruction::End));
+
+    // Numbers and booleans compare their payloads
+    body.extend(field(l, 1));
+    body.extend(field(r, 1));
+    body.push(Instr::Raw(Instruction::I32Eq));
+
+    FunImpl {
+        params: vec![object_val_type(), object_val_type(), array_val_type()],
+        results: vec![ValType::I32],
+        locals: vec![
+            ValType::I32,
+            ValType::I32,
+            array_val_type(),
+            array_val_type(),
+            ValType::I32,
+        ],
+        body,
+    }
+}
@@ -1,18 +1,48 @@
-use crate::parser;
+use crate::util::loc::SrcLoc;
+use crate::{binder, interpreter, parser, renamer, simplifier, verify};
 
 #[derive(Debug)]
 pub enum Error {
     Parser(parser::Error),
+    Renamer(renamer::Error<SrcLoc>),
+    Binder(binder::Error<SrcLoc>),
+    Simplifier(simplifier::Error<SrcLoc>),
+    Verify(verify::Error<SrcLoc>),
+    Interpret(interpreter::InterpretError<SrcLoc>),
 }
 
-// impl From<parser::Error> for Error {
-//     fn from(e: parser::Error) -> Self {
-//         Error::Parser(e)
-//     }
-// }
+impl From<parser::Error> for Error {
+    fn from(e: parser::Error) -> Self {
+        Error::Parser(e)
+    }
+}
+
+impl From<renamer::Error<SrcLoc>> for Error {
+    fn from(e: renamer::Error<SrcLoc>) -> Self {
+        Error::Renamer(e)
+    }
+}
+
+impl From<binder::Error<SrcLoc>> for Error {
+    fn from(e: binder::Error<SrcLoc>) -> Self {
+        Error::Binder(e)
+    }
+}
 
-// impl From<renamer::Error> for Error {
-//     fn from(e: renamer::Error) -> Self {
-//         Error::Renamer(e)
-//     }
-// }
+impl From<simplifier::Error<SrcLoc>> for Error {
+    fn from(e: simplifier::Error<SrcLoc>) -> Self {
+        Error::Simplifier(e)
+    }
+}
+
+impl From<verify::Error<SrcLoc>> for Error {
+    fn from(e: verify::Error<SrcLoc>) -> Self {
+        Error::Verify(e)
+    }
+}
+
+impl From<interpreter::InterpretError<SrcLoc>> for Error {
+    fn from(e: interpreter::InterpretError<SrcLoc>) -> Self {
+        Error::Interpret(e)
+    }
+}
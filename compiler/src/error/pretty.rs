@@ -1,57 +1,350 @@
 use miette;
 
 use super::core;
-use crate::parser;
 use crate::util::loc;
+use crate::{binder, parser, renamer, simplifier, verify};
 
 pub fn annotate_error<T: Into<core::Error>>(error: T, src: String) -> miette::Report {
-    let diagnostic: miette::MietteDiagnostic = match error.into() {
+    miette::Report::new(to_diagnostic(error)).with_source_code(src)
+}
+
+/// The message, help and labelled spans of an error, for callers that show
+/// them against something other than the whole source.
+pub fn to_diagnostic<T: Into<core::Error>>(error: T) -> miette::MietteDiagnostic {
+    match error.into() {
         core::Error::Parser(p) => match p {
-            parser::Error::ParseFailed(err) => miette::MietteDiagnostic {
+            parser::Error::ParseFailed(errs) => match errs.as_slice() {
+                [err] => miette::MietteDiagnostic {
+                    severity: Some(miette::Severity::Error),
+                    code: None,
+                    message: err.msg.clone(),
+                    help: None,
+                    url: None,
+                    labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                        None, err.span,
+                    )]),
+                },
+                _ => miette::MietteDiagnostic {
+                    severity: Some(miette::Severity::Error),
+                    code: None,
+                    message: format!("{} syntax errors", errs.len()),
+                    help: None,
+                    url: None,
+                    labels: Some(
+                        errs.iter()
+                            .enumerate()
+                            .map(|(ix, err)| match ix {
+                                0 => miette::LabeledSpan::new_primary_with_span(
+                                    Some(err.msg.clone()),
+                                    err.span,
+                                ),
+                                _ => miette::LabeledSpan::new_with_span(
+                                    Some(err.msg.clone()),
+                                    err.span,
+                                ),
+                            })
+                            .collect(),
+                    ),
+                },
+            },
+        },
+        core::Error::Renamer(e) => match e {
+            renamer::Error::IdentifierNotFound(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: "Identifier not found".to_string(),
+                help: None,
+                url: None,
+                labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                    Some("Not defined.".to_string()),
+                    err.identifier.a,
+                )]),
+            },
+            renamer::Error::DuplicateIdentifier(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: "Duplicate identifier".to_string(),
+                help: None,
+                url: None,
+                labels: Some(vec![
+                    miette::LabeledSpan::new_primary_with_span(
+                        Some("[ERR] Duplicate identifier.".to_string()),
+                        err.error.a,
+                    ),
+                    miette::LabeledSpan::new_with_span(
+                        Some("Previously defined at.".to_string()),
+                        err.original_loc,
+                    ),
+                ]),
+            },
+            renamer::Error::UnknownField(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("Unknown field `{}`", err.field.v.0),
+                help: Some(
+                    "No struct declares it, and strings and arrays only have `length`.".to_string(),
+                ),
+                url: None,
+                labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                    None,
+                    err.field.a,
+                )]),
+            },
+            renamer::Error::AssignToCapture(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("Can not assign to captured variable `{}`", err.assignment.v.0),
+                help: Some(
+                    "Lambdas get a copy of the variables they use, so the assignment would be lost."
+                        .to_string(),
+                ),
+                url: None,
+                labels: Some(vec![
+                    miette::LabeledSpan::new_primary_with_span(None, err.assignment.a),
+                    miette::LabeledSpan::new_with_span(
+                        Some("Captured by this lambda.".to_string()),
+                        err.lambda_loc,
+                    ),
+                ]),
+            },
+            renamer::Error::JumpOutsideLoop(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("`{}` outside of a loop", err.jump.v.keyword()),
+                help: None,
+                url: None,
+                labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                    None,
+                    err.jump.a,
+                )]),
+            },
+            renamer::Error::ReservedIdentifier(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("`{}` can not be a parameter", err.identifier.v.0),
+                help: Some("Predicates use `result` for the return value.".to_string()),
+                url: None,
+                labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                    None,
+                    err.identifier.a,
+                )]),
+            },
+        },
+        core::Error::Binder(e) => match e {
+            binder::Error::UnknownFunction(err) => miette::MietteDiagnostic {
                 severity: Some(miette::Severity::Error),
                 code: None,
-                message: err.msg.clone(),
+                message: format!("Unknown function `{}`", err.identifier.v.0),
                 help: None,
                 url: None,
                 labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
-                    None, err.span,
+                    Some("Not defined.".to_string()),
+                    err.identifier.a,
                 )]),
             },
+            binder::Error::UnknownIdentifier(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("Unknown identifier `{}`", err.identifier.v.0),
+                help: None,
+                url: None,
+                labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                    Some("Not defined.".to_string()),
+                    err.identifier.a,
+                )]),
+            },
+            binder::Error::WrongArity(err) => {
+                let mut labels = vec![miette::LabeledSpan::new_primary_with_span(
+                    Some(format!("Called with {} arguments.", err.actual)),
+                    err.call.a,
+                )];
+                if let Some(loc @ loc::SrcLoc::Known(_)) = err.definition_loc {
+                    labels.push(miette::LabeledSpan::new_with_span(
+                        Some("Defined here.".to_string()),
+                        loc,
+                    ));
+                }
+                miette::MietteDiagnostic {
+                    severity: Some(miette::Severity::Error),
+                    code: None,
+                    message: format!(
+                        "Function `{}` expects {} arguments, but got {}",
+                        err.call.v.0, err.expected, err.actual
+                    ),
+                    help: err.definition_doc.clone(),
+                    url: None,
+                    labels: Some(labels),
+                }
+            }
+            binder::Error::DuplicateDefinition(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("Duplicate definition of `{}`", err.error.v.0),
+                help: None,
+                url: None,
+                labels: Some(vec![
+                    miette::LabeledSpan::new_primary_with_span(
+                        Some("[ERR] Duplicate definition.".to_string()),
+                        err.error.a,
+                    ),
+                    miette::LabeledSpan::new_with_span(
+                        Some("Previously defined at.".to_string()),
+                        err.original_loc,
+                    ),
+                ]),
+            },
+            binder::Error::NonExhaustiveMatch(err) => {
+                let mut labels = vec![miette::LabeledSpan::new_with_span(
+                    Some(format!("No arm for `{}`.", err.missing.v.0)),
+                    err.match_loc,
+                )];
+                if let loc @ loc::SrcLoc::Known(_) = err.missing.a {
+                    labels.insert(
+                        0,
+                        miette::LabeledSpan::new_primary_with_span(
+                            Some("[ERR] Not matched.".to_string()),
+                            loc,
+                        ),
+                    );
+                }
+                miette::MietteDiagnostic {
+                    severity: Some(miette::Severity::Error),
+                    code: None,
+                    message: format!(
+                        "Non-exhaustive match on `{}`, `{}` is missing",
+                        err.type_name.0, err.missing.v.0
+                    ),
+                    help: None,
+                    url: None,
+                    labels: Some(labels),
+                }
+            }
+            binder::Error::NotAVariant(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: match err.type_name {
+                    Some(type_name) => {
+                        format!(
+                            "`{}` is not a variant of `{}`",
+                            err.pattern.v.0, type_name.0
+                        )
+                    }
+                    None => format!("`{}` is not a variant", err.pattern.v.0),
+                },
+                help: None,
+                url: None,
+                labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                    None,
+                    err.pattern.a,
+                )]),
+            },
+            binder::Error::DuplicateArm(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("Duplicate arm for `{}`", err.error.v.0),
+                help: None,
+                url: None,
+                labels: Some(vec![
+                    miette::LabeledSpan::new_primary_with_span(
+                        Some("[ERR] Duplicate arm.".to_string()),
+                        err.error.a,
+                    ),
+                    miette::LabeledSpan::new_with_span(
+                        Some("Previously matched at.".to_string()),
+                        err.original_loc,
+                    ),
+                ]),
+            },
+        },
+        core::Error::Simplifier(e) => match e {
+            simplifier::Error::Unsupported(err) => miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: format!("Unsupported: {}", err.construct),
+                help: None,
+                url: None,
+                labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
+                    None, err.loc,
+                )]),
+            },
+        },
+        core::Error::Verify(e) => match e {
+            verify::Error::ContractViolation(err) => {
+                let call = err.counterexample.call(&err.function.v);
+
+                let mut help = format!("Minimized counterexample: `{}`", call);
+                if !err.counterexample.shrinks.is_empty() {
+                    help.push_str(&format!(
+                        "\nShrunk from `{}` in {} steps:",
+                        err.counterexample.original_call(&err.function.v),
+                        err.counterexample.shrinks.len()
+                    ));
+                    for step in &err.counterexample.shrinks {
+                        help.push_str(&format!(
+                            "\n  argument {}: {} -> {}",
+                            step.arg + 1,
+                            step.from,
+                            step.to
+                        ));
+                    }
+                }
+
+                let (message, label) = match &err.counterexample.failure {
+                    verify::Failure::Predicate { loc, result, .. } => (
+                        format!("`{}` returned {}, violating its contract", call, result),
+                        miette::LabeledSpan::new_primary_with_span(
+                            Some("Does not hold.".to_string()),
+                            loc.unwrap_or(loc::SrcLoc::Unknown),
+                        ),
+                    ),
+                    verify::Failure::Error(e) => (
+                        format!("`{}` failed: {}", call, e.kind),
+                        miette::LabeledSpan::new_primary_with_span(
+                            Some(e.kind.to_string()),
+                            e.loc.unwrap_or(loc::SrcLoc::Unknown),
+                        ),
+                    ),
+                };
+                miette::MietteDiagnostic {
+                    severity: Some(miette::Severity::Error),
+                    code: None,
+                    message,
+                    help: Some(help),
+                    url: None,
+                    labels: Some(vec![
+                        label,
+                        miette::LabeledSpan::new_with_span(
+                            Some("In this function.".to_string()),
+                            err.function.a,
+                        ),
+                    ]),
+                }
+            }
         },
-    };
-    //     core::Error::Renamer(e) => match e {
-    //         renamer::Error::IdentifierNotFound(err) => miette::MietteDiagnostic {
-    //             severity: Some(miette::Severity::Error),
-    //             code: None,
-    //             message: "Identifier not found".to_string(),
-    //             help: None,
-    //             url: None,
-    //             labels: Some(vec![miette::LabeledSpan::new_primary_with_span(
-    //                 Some("Defined here.".to_string()),
-    //                 err.identifier.location,
-    //             )]),
-    //         },
-    //         renamer::Error::DuplicateIdentifier(err) => miette::MietteDiagnostic {
-    //             severity: Some(miette::Severity::Error),
-    //             code: None,
-    //             message: "Duplicate identifier".to_string(),
-    //             help: None,
-    //             url: None,
-    //             labels: Some(vec![
-    //                 miette::LabeledSpan::new_primary_with_span(
-    //                     Some("[ERR] Duplicate identifier.".to_string()),
-    //                     err.error.location,
-    //                 ),
-    //                 miette::LabeledSpan::new_with_span(
-    //                     Some("Previously defined at.".to_string()),
-    //                     err.original_loc,
-    //                 ),
-    //             ]),
-    //         },
-    //     },
-    // };
+        core::Error::Interpret(e) => {
+            let mut help = format!("After {} steps.", e.fuel_used);
+            if !e.stack.is_empty() {
+                help.push_str("\nCall stack:");
+                for frame in e.stack.iter().rev() {
+                    help.push_str(&format!("\n  in `{}`", frame.function.0));
+                }
+            }
 
-    miette::Report::new(diagnostic).with_source_code(src)
+            miette::MietteDiagnostic {
+                severity: Some(miette::Severity::Error),
+                code: None,
+                message: e.kind.to_string(),
+                help: Some(help),
+                url: None,
+                labels: match e.loc {
+                    Some(loc @ loc::SrcLoc::Known(_)) => {
+                        Some(vec![miette::LabeledSpan::new_primary_with_span(None, loc)])
+                    }
+                    _ => None,
+                },
+            }
+        }
+    }
 }
 
 impl From<loc::Span> for miette::SourceSpan {
@@ -0,0 +1,129 @@
+use std::ops::Range;
+
+use crate::util::loc::Span;
+
+/// A `//` comment, with its slashes and without the line break.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+    /// The end of the code before the comment on its line, `None` if the
+    /// comment starts its line.
+    pub after: Option<usize>,
+}
+
+/// Finds the comments of `source` in order. The parser skips them, so they
+/// are found again here, stepping over string literals. Doc comments are
+/// left out, as the parser keeps them.
+pub fn comments(source: &str) -> Vec<Comment> {
+    segments(source)
+        .into_iter()
+        .filter(|(kind, _)| *kind == Segment::Comment)
+        .filter_map(|(_, range)| {
+            let text = source[range.clone()].trim_end();
+            let line_start = source[..range.start].rfind('\n').map_or(0, |ix| ix + 1);
+            let before = source[line_start..range.start].trim_end();
+            (!text.starts_with("///")).then(|| Comment {
+                span: Span::from_offset_len(range.start, text.len()),
+                text: text.to_string(),
+                after: (!before.trim_start().is_empty()).then(|| line_start + before.len()),
+            })
+        })
+        .collect()
+}
+
+/// What a part of the source is, as far as the parser's tokens go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Segment {
+    Code,
+    /// A string literal with its quotes, or an unterminated one up to the
+    /// end of the source.
+    String,
+    /// A `//` comment, without the line break.
+    Comment,
+}
+
+/// Splits `source` into code, string literals and comments, in order.
+pub fn segments(source: &str) -> Vec<(Segment, Range<usize>)> {
+    let mut ret = vec![];
+    let mut code_start = 0;
+    let mut pos = 0;
+    while let Some(ix) = source[pos..].find(['"', '/']) {
+        let start = pos + ix;
+        let rest = &source[start..];
+        let (kind, end) = if let Some(string) = rest.strip_prefix('"') {
+            // Up to the closing quote, or the end of an unterminated string.
+            let end = string_len(string).map_or(source.len(), |len| start + len + 2);
+            (Segment::String, end)
+        } else if rest.starts_with("//") {
+            let end = start + rest.find(['\n', '\r']).unwrap_or(rest.len());
+            (Segment::Comment, end)
+        } else {
+            pos = start + 1;
+            continue;
+        };
+        if code_start < start {
+            ret.push((Segment::Code, code_start..start));
+        }
+        ret.push((kind, start..end));
+        code_start = end;
+        pos = end;
+    }
+    if code_start < source.len() {
+        ret.push((Segment::Code, code_start..source.len()));
+    }
+    ret
+}
+
+/// The length of the string literal starting `rest`, up to its closing quote.
+fn string_len(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices();
+    while let Some((ix, c)) = chars.next() {
+        match c {
+            '"' => return Some(ix),
+            '\\' => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comments_in_strings_are_skipped() {
+        let src = "// a\nfn f { return \"// b\\\"//\"; } // c  \n/// d\n//";
+        let texts = comments(src)
+            .into_iter()
+            .map(|c| c.text)
+            .collect::<Vec<_>>();
+        assert_eq!(texts, vec!["// a", "// c", "//"]);
+        assert_eq!(comments(src)[1].span, Span::from_offset_len(33, 4));
+        assert_eq!(comments(src)[0].after, None);
+        assert_eq!(comments(src)[1].after, Some(32));
+    }
+
+    #[test]
+    fn segments_cover_the_source() {
+        let src = "f { \"{\\\"\" // }\n} \"open";
+        let kinds = segments(src)
+            .into_iter()
+            .map(|(kind, range)| (kind, &src[range]))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (Segment::Code, "f { "),
+                (Segment::String, "\"{\\\"\""),
+                (Segment::Code, " "),
+                (Segment::Comment, "// }"),
+                (Segment::Code, "\n} "),
+                (Segment::String, "\"open"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,787 @@
+use pretty::RcDoc;
+
+use super::comments::{comments, Comment};
+use crate::parser::{self, syntax};
+use crate::util::ax::Ax;
+use crate::util::loc::{Span, SrcLoc};
+
+const WIDTH: usize = 80;
+const INDENT: isize = 4;
+
+type Doc = RcDoc<'static, ()>;
+
+/// Writes a doc comment back as `///` lines.
+pub fn doc_comment(doc: &str) -> String {
+    doc.split('\n')
+        .map(|line| match line {
+            "" => "///".to_string(),
+            line => format!("/// {}", line),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A line of output, and whether a blank line comes before it.
+type Line = (bool, Doc);
+
+type Block = Ax<SrcLoc, Vec<syntax::FunStmt<SrcLoc>>>;
+
+/// Formats a module, keeping its comments and the blank lines between
+/// statements. Fails on modules with syntax errors.
+pub fn format(source: &str) -> Result<String, parser::Error> {
+    let module = parser::parse(source)?;
+    let mut formatter = Formatter {
+        source,
+        comments: comments(source),
+        next: 0,
+    };
+    let lines = formatter.lines(
+        &module.statements.v,
+        source.len(),
+        true,
+        |stmt| match stmt {
+            syntax::TopLevelStmt::FunDef(def) => span(def.a),
+            syntax::TopLevelStmt::StructDef(def) => span(def.a),
+            syntax::TopLevelStmt::TypeDef(def) => span(def.a),
+        },
+        |f, stmt| match stmt {
+            syntax::TopLevelStmt::FunDef(def) => f.fun_def(def),
+            syntax::TopLevelStmt::StructDef(def) => f.struct_def(def),
+            syntax::TopLevelStmt::TypeDef(def) => f.type_def(&def.v),
+        },
+    );
+
+    match lines.is_empty() {
+        true => Ok(String::new()),
+        false => Ok(format!("{}\n", join(lines).pretty(WIDTH))),
+    }
+}
+
+struct Formatter<'s> {
+    source: &'s str,
+    comments: Vec<Comment>,
+    /// The first comment that is not printed yet.
+    next: usize,
+}
+
+impl Formatter<'_> {
+    /// Lays out `items` one per line, with the comments before `end` in
+    /// between. Consecutive items are separated by a blank line if
+    /// `blank_between` is set, otherwise blank lines are kept from the source.
+    fn lines<T>(
+        &mut self,
+        items: &[T],
+        end: usize,
+        blank_between: bool,
+        span_of: impl Fn(&T) -> Span,
+        print: impl Fn(&mut Self, &T) -> Doc,
+    ) -> Vec<Line> {
+        let mut lines = vec![];
+        // Where the previous line ends in the source, and if it is an item.
+        let mut prev = None;
+        for item in items {
+            let span = span_of(item);
+            lines.extend(self.comment_lines(span.start.0, &mut prev));
+
+            let blank = match prev {
+                Some((_, true)) if blank_between => true,
+                Some((prev_end, _)) => self.blank_between(prev_end, span.start.0),
+                None => false,
+            };
+            let doc = print(self, item);
+            let end = span.start.0 + span.length;
+            // Comments inside the item that were not printed with it follow
+            // it, each on its own line.
+            let inner = self.comment_lines(end, &mut None);
+            let trailing =
+                self.comment_after(end, |between| between.chars().all(|c| c == ',' || c == ';'));
+            let item_end = trailing
+                .as_ref()
+                .map_or(end, |comment| comment.span.start.0 + comment.span.length);
+            lines.push((blank, doc.append(trailing_doc(trailing))));
+            lines.extend(inner);
+            prev = Some((item_end, true));
+        }
+        lines.extend(self.comment_lines(end, &mut prev));
+        lines
+    }
+
+    /// The comments left before `pos`, each on its own line.
+    fn comment_lines(&mut self, pos: usize, prev: &mut Option<(usize, bool)>) -> Vec<Line> {
+        let mut lines = vec![];
+        while let Some(comment) = self.comments.get(self.next) {
+            let start = comment.span.start.0;
+            if start >= pos {
+                break;
+            }
+            let blank = prev.is_some_and(|(prev_end, _)| self.blank_between(prev_end, start));
+            lines.push((blank, RcDoc::text(comment.text.clone())));
+            *prev = Some((start + comment.span.length, false));
+            self.next += 1;
+        }
+        lines
+    }
+
+    /// The next comment, if it follows the code ending at `end` on the same
+    /// line, with only tokens `between` accepts in between. Comments are
+    /// attached to the last token before them on their line.
+    fn comment_after(&mut self, end: usize, between: impl Fn(&str) -> bool) -> Option<Comment> {
+        let comment = self.comments.get(self.next)?;
+        let after = comment.after?;
+        let tokens = self.source.get(end..after)?;
+        if !between(&tokens.split_whitespace().collect::<String>()) {
+            return None;
+        }
+        self.next += 1;
+        Some(comment.clone())
+    }
+
+    fn blank_between(&self, start: usize, end: usize) -> bool {
+        start < end && self.source[start..end].matches('\n').count() > 1
+    }
+
+    /// The doc comment, then the comments between it and the keyword and
+    /// name of a definition.
+    fn header(
+        &mut self,
+        doc: &Option<String>,
+        keyword: &str,
+        name: &Ax<SrcLoc, syntax::Ident>,
+    ) -> Doc {
+        let mut lines = match doc {
+            Some(doc) => doc_comment(doc)
+                .split('\n')
+                .map(|line| (false, RcDoc::text(line.to_string())))
+                .collect(),
+            None => vec![],
+        };
+        lines.extend(self.comment_lines(span(name.a).start.0, &mut None));
+        lines.push((false, RcDoc::text(format!("{} ", keyword))));
+        join(lines).append(RcDoc::text(name.v.0.clone()))
+    }
+
+    fn fun_def(&mut self, def: &syntax::FunDef<SrcLoc>) -> Doc {
+        let header = def
+            .params
+            .v
+            .iter()
+            .fold(self.header(&def.doc, "fn", &def.name), |doc, param| {
+                doc.append(" ").append(RcDoc::text(param.v.0.clone()))
+            });
+        if def.preds.v.is_empty() {
+            return header.append(" ").append(self.block(&def.body));
+        }
+
+        // Predicates go on their own lines, and the body starts below them.
+        let preds = self.lines(
+            &def.preds.v,
+            span(def.body.a).start.0,
+            false,
+            expr_span,
+            |f, pred| RcDoc::text(": ").append(f.expr(pred)),
+        );
+        header
+            .append(RcDoc::hardline().append(join(preds)).nest(INDENT))
+            .append(RcDoc::hardline())
+            .append(self.block(&def.body))
+    }
+
+    /// A struct with a field per line, each followed by a comma.
+    fn struct_def(&mut self, def: &Ax<SrcLoc, syntax::StructDef<SrcLoc>>) -> Doc {
+        let header = self.header(&def.v.doc, "struct", &def.v.name);
+        let def_span = span(def.a);
+        let lines = self.lines(
+            &def.v.fields.v,
+            def_span.start.0 + def_span.length,
+            false,
+            |field| span(field.a),
+            |_, field| RcDoc::text(field.v.0.clone()).append(","),
+        );
+        if lines.is_empty() {
+            return header.append(" {}");
+        }
+        header
+            .append(" {")
+            .append(RcDoc::hardline().append(join(lines)).nest(INDENT))
+            .append(RcDoc::hardline())
+            .append("}")
+    }
+
+    /// A type on one line, or with a variant per line if it does not fit.
+    fn type_def(&mut self, def: &syntax::TypeDef<SrcLoc>) -> Doc {
+        let header = self.header(&def.doc, "type", &def.name);
+        let variants = RcDoc::intersperse(
+            def.variants
+                .v
+                .iter()
+                .map(|variant| words(&variant.v.name, &variant.v.fields.v)),
+            RcDoc::line().append("| "),
+        );
+        header.append(
+            RcDoc::text(" =")
+                .append(RcDoc::line().append(variants).nest(INDENT))
+                .append(";")
+                .group(),
+        )
+    }
+
+    /// A match with an arm per line.
+    fn match_stmt(&mut self, match_: &Ax<SrcLoc, syntax::Match<SrcLoc>>) -> Doc {
+        let match_span = span(match_.a);
+        let arms = self.lines(
+            &match_.v.arms.v,
+            match_span.start.0 + match_span.length,
+            false,
+            |arm| span(arm.a),
+            |f, arm| {
+                words(&arm.v.variant, &arm.v.bindings.v)
+                    .append(" => ")
+                    .append(f.block(&arm.v.body))
+            },
+        );
+        RcDoc::text("match ")
+            .append(self.expr(&match_.v.value))
+            .append(" {")
+            .append(RcDoc::hardline().append(join(arms)).nest(INDENT))
+            .append(RcDoc::hardline())
+            .append("}")
+    }
+
+    fn block(&mut self, block: &Block) -> Doc {
+        let block_span = span(block.a);
+        let lines = self.lines(
+            &block.v,
+            block_span.start.0 + block_span.length,
+            false,
+            stmt_span,
+            Self::fun_stmt,
+        );
+        if lines.is_empty() {
+            return RcDoc::text("{}");
+        }
+        RcDoc::text("{")
+            .append(RcDoc::hardline().append(join(lines)).nest(INDENT))
+            .append(RcDoc::hardline())
+            .append("}")
+    }
+
+    fn fun_stmt(&mut self, stmt: &syntax::FunStmt<SrcLoc>) -> Doc {
+        match stmt {
+            syntax::FunStmt::Return(ret) => RcDoc::text("return ")
+                .append(self.expr(&ret.v.0))
+                .append(";"),
+            syntax::FunStmt::Inv(inv) => RcDoc::text("invariant ")
+                .append(self.expr(&inv.v.value.v))
+                .append(";"),
+            syntax::FunStmt::LetDecl(decl) => RcDoc::text("let ")
+                .append(RcDoc::text(decl.v.name.v.0.clone()))
+                .append(" = ")
+                .append(self.expr(&decl.v.value))
+                .append(";"),
+            syntax::FunStmt::Assignment(assign) => RcDoc::text(assign.v.name.v.0.clone())
+                .append(" = ")
+                .append(self.expr(&assign.v.value))
+                .append(";"),
+            syntax::FunStmt::IndexAssignment(assign) => self
+                .index(&assign.v.target)
+                .append(" = ")
+                .append(self.expr(&assign.v.value))
+                .append(";"),
+            syntax::FunStmt::FieldAssignment(assign) => self
+                .field(&assign.v.target)
+                .append(" = ")
+                .append(self.expr(&assign.v.value))
+                .append(";"),
+            syntax::FunStmt::While(while_) => RcDoc::text("while ")
+                .append(self.expr(&while_.v.condition))
+                .append(" ")
+                .append(self.block(&while_.v.body)),
+            syntax::FunStmt::For(for_) => RcDoc::text("for ")
+                .append(RcDoc::text(for_.v.var.v.0.clone()))
+                .append(" in range(")
+                .append(self.expr(&for_.v.range.v.start))
+                .append(", ")
+                .append(self.expr(&for_.v.range.v.end))
+                .append(") ")
+                .append(self.block(&for_.v.body)),
+            syntax::FunStmt::Jump(jump) => RcDoc::text(jump.v.keyword()).append(";"),
+            syntax::FunStmt::If(if_) => {
+                let doc = RcDoc::text("if ")
+                    .append(self.expr(&if_.v.condition))
+                    .append(" ")
+                    .append(self.block(&if_.v.then));
+                match &if_.v.else_ {
+                    Some(else_) => doc.append(" else ").append(self.block(else_)),
+                    None => doc,
+                }
+            }
+            syntax::FunStmt::Match(match_) => self.match_stmt(match_),
+        }
+    }
+}
+
+/// A comment at the end of a line.
+fn trailing_doc(comment: Option<Comment>) -> Doc {
+    match comment {
+        Some(comment) => RcDoc::text(" ").append(RcDoc::text(comment.text)),
+        None => RcDoc::nil(),
+    }
+}
+
+/// A name followed by the names it binds, like a variant and its fields.
+fn words(name: &Ax<SrcLoc, syntax::Ident>, names: &[Ax<SrcLoc, syntax::Ident>]) -> Doc {
+    names
+        .iter()
+        .fold(RcDoc::text(name.v.0.clone()), |doc, name| {
+            doc.append(" ").append(RcDoc::text(name.v.0.clone()))
+        })
+}
+
+/// Joins lines, turning the blank line markers into empty lines.
+fn join(lines: Vec<Line>) -> Doc {
+    let mut lines = lines.into_iter();
+    let first = lines.next().map_or(RcDoc::nil(), |(_, doc)| doc);
+    lines.fold(first, |doc, (blank, line)| {
+        // A text newline, so the empty line is not indented.
+        let doc = match blank {
+            true => doc.append("\n"),
+            false => doc,
+        };
+        doc.append(RcDoc::hardline()).append(line)
+    })
+}
+
+// Expressions are printed by the formatter too, as the bodies of lambdas
+// have comments.
+impl Formatter<'_> {
+    fn expr(&mut self, expr: &syntax::Expr<SrcLoc>) -> Doc {
+        match expr {
+            syntax::Expr::Array(items) => self.array(&items.v),
+            syntax::Expr::FunCall(call) => self.fun_call(call),
+            syntax::Expr::BinOp(op) => self.bin_op(op),
+            syntax::Expr::Not(not) => self.not_expr(not),
+            syntax::Expr::Lambda(lambda) => self.lambda(&lambda.v),
+            expr => self.simple_expr(expr),
+        }
+    }
+
+    /// An argument of a call, in parentheses unless it is atomic.
+    fn simple_expr(&mut self, expr: &syntax::Expr<SrcLoc>) -> Doc {
+        let parens = |doc: Doc| RcDoc::text("(").append(doc).append(")");
+        match expr {
+            syntax::Expr::LitNum(n) => RcDoc::as_string(n.v.0),
+            syntax::Expr::LitStr(s) => RcDoc::text(syntax::quote(&s.v.0)),
+            syntax::Expr::LitBool(b) => RcDoc::as_string(b.v.0),
+            syntax::Expr::Ident(ident) => RcDoc::text(ident.v.0.clone()),
+            syntax::Expr::Array(items) => parens(self.array(&items.v)),
+            syntax::Expr::Index(ix) => self.index(ix),
+            syntax::Expr::Field(f) => self.field(f),
+            syntax::Expr::FunCall(call) => parens(self.fun_call(call)),
+            syntax::Expr::BinOp(op) => parens(self.bin_op(op)),
+            syntax::Expr::Not(not) => parens(self.not_expr(not)),
+            syntax::Expr::Lambda(lambda) => parens(self.lambda(&lambda.v)),
+        }
+    }
+
+    fn index(&mut self, index: &syntax::Index<SrcLoc>) -> Doc {
+        self.simple_expr(&index.array)
+            .append("[")
+            .append(self.expr(&index.index.v))
+            .append("]")
+    }
+
+    fn field(&mut self, field: &syntax::Field<SrcLoc>) -> Doc {
+        self.simple_expr(&field.expr)
+            .append(".")
+            .append(RcDoc::text(field.name.v.0.clone()))
+    }
+
+    /// An array literal on one line, or with an item per line if it does not
+    /// fit. Comments after items break the array.
+    fn array(&mut self, items: &[syntax::Expr<SrcLoc>]) -> Doc {
+        if items.is_empty() {
+            return RcDoc::text("[]");
+        }
+        let mut doc = RcDoc::nil();
+        let mut close = RcDoc::line_();
+        for (ix, item) in items.iter().enumerate() {
+            doc = doc.append(self.expr(item));
+            let end = expr_span(item).end();
+            let comment =
+                self.comment_after(end, |between| between.chars().all(|c| c == ',' || c == ')'));
+            let last = ix + 1 == items.len();
+            doc = match (last, comment) {
+                (false, None) => doc.append(",").append(RcDoc::line()),
+                (false, comment) => doc
+                    .append(",")
+                    .append(trailing_doc(comment))
+                    .append(RcDoc::hardline()),
+                (true, None) => doc,
+                (true, comment) => {
+                    close = RcDoc::hardline();
+                    doc.append(trailing_doc(comment))
+                }
+            };
+        }
+        RcDoc::text("[")
+            .append(RcDoc::line_().append(doc).nest(INDENT))
+            .append(close)
+            .append("]")
+            .group()
+    }
+
+    /// `not` takes comparisons without parentheses, but not `and` or `or`.
+    fn not_expr(&mut self, not: &syntax::Not<SrcLoc>) -> Doc {
+        RcDoc::text("not ").append(self.operand(&not.expr, syntax::Op::Eq.precedence(), true))
+    }
+
+    /// A comment after the left operand or the operator goes after the
+    /// operator, and the right operand on the next line.
+    fn bin_op(&mut self, op: &syntax::BinOp<SrcLoc>) -> Doc {
+        let precedence = op.op.v.precedence();
+        let lhs = self
+            .operand(&op.lhs, precedence, op.op.v.is_associative())
+            .append(" ")
+            .append(op.op.v.symbol());
+        let symbol = op.op.v.symbol();
+        let comment = self.comment_after(expr_span(&op.lhs).end(), |between| {
+            let between = between.trim_start_matches(')');
+            between.is_empty() || between == symbol
+        });
+        let rhs = self.operand(&op.rhs, precedence, false);
+        match comment {
+            Some(_) => lhs
+                .append(trailing_doc(comment))
+                .append(RcDoc::hardline().append(rhs).nest(INDENT)),
+            None => lhs.append(" ").append(rhs),
+        }
+    }
+
+    /// An operand of an operator with `precedence`, in parentheses if it
+    /// binds looser, or as tight unless `same_tier` allows it.
+    fn operand(&mut self, operand: &syntax::Expr<SrcLoc>, precedence: u8, same_tier: bool) -> Doc {
+        match operand {
+            syntax::Expr::BinOp(op)
+                if op.op.v.precedence() < precedence
+                    || (op.op.v.precedence() == precedence && !same_tier) =>
+            {
+                RcDoc::text("(").append(self.bin_op(op)).append(")")
+            }
+            syntax::Expr::Not(not) if precedence > syntax::Op::And.precedence() => {
+                RcDoc::text("(").append(self.not_expr(not)).append(")")
+            }
+            operand => self.expr(operand),
+        }
+    }
+
+    /// A call on one line, or with an argument per line if it does not fit.
+    /// Calls taking lambdas stay on one line, the bodies break instead.
+    fn fun_call(&mut self, call: &syntax::FunCall<SrcLoc>) -> Doc {
+        let name = RcDoc::text(call.name.v.0.clone());
+        if call
+            .args
+            .v
+            .iter()
+            .any(|arg| matches!(arg, syntax::Expr::Lambda(_)))
+        {
+            return call.args.v.iter().fold(name, |doc, arg| {
+                let arg = match arg {
+                    syntax::Expr::Lambda(lambda) => self.lambda(&lambda.v),
+                    arg => self.simple_expr(arg),
+                };
+                doc.append(" ").append(arg)
+            });
+        }
+
+        // A comment after the name or an argument, but the last, breaks the
+        // call after it.
+        let mut args = RcDoc::nil();
+        let mut end = span(call.name.a).end();
+        for arg in &call.args.v {
+            let comment = self.comment_after(end, |between| between.chars().all(|c| c == ')'));
+            args = match comment {
+                Some(_) => args.append(trailing_doc(comment)).append(RcDoc::hardline()),
+                None => args.append(RcDoc::line()),
+            };
+            args = args.append(self.simple_expr(arg));
+            end = expr_span(arg).end();
+        }
+        name.append(args.nest(INDENT)).group()
+    }
+
+    fn lambda(&mut self, lambda: &syntax::Lambda<SrcLoc>) -> Doc {
+        let params = lambda
+            .params
+            .v
+            .iter()
+            .map(|param| RcDoc::text(param.v.0.clone()));
+        RcDoc::text("|")
+            .append(RcDoc::intersperse(params, " "))
+            .append("| ")
+            .append(self.block(&lambda.body))
+    }
+}
+
+fn span(loc: SrcLoc) -> Span {
+    match loc {
+        SrcLoc::Known(span) => span,
+        SrcLoc::Unknown => unreachable!("parsed syntax has locations"),
+    }
+}
+
+fn expr_span(expr: &syntax::Expr<SrcLoc>) -> Span {
+    span(expr.loc())
+}
+
+fn stmt_span(stmt: &syntax::FunStmt<SrcLoc>) -> Span {
+    span(match stmt {
+        syntax::FunStmt::Return(s) => s.a,
+        syntax::FunStmt::Inv(s) => s.a,
+        syntax::FunStmt::LetDecl(s) => s.a,
+        syntax::FunStmt::While(s) => s.a,
+        syntax::FunStmt::For(s) => s.a,
+        syntax::FunStmt::Jump(s) => s.a,
+        syntax::FunStmt::Assignment(s) => s.a,
+        syntax::FunStmt::IndexAssignment(s) => s.a,
+        syntax::FunStmt::FieldAssignment(s) => s.a,
+        syntax::FunStmt::If(s) => s.a,
+        syntax::FunStmt::Match(s) => s.a,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sexpr::SExpr;
+
+    const MESSY: &str = "// Sums up to n.
+fn   sum n :gt? n 0
+  : gt? result   0 // positive
+{ let i = 0;   let r = 0;
+
+  while lt? i n { i = add i 1;
+  // keep going
+  r = add r i; }
+  if gt? r 100 { return r; } else {}
+    return (add r 0); }
+///   The identity.
+///
+// Not a doc.
+fn id x { return x; }
+// The end.
+";
+
+    const FORMATTED: &str = "// Sums up to n.
+fn sum n
+    : gt? n 0
+    : gt? result 0 // positive
+{
+    let i = 0;
+    let r = 0;
+
+    while lt? i n {
+        i = add i 1;
+        // keep going
+        r = add r i;
+    }
+    if gt? r 100 {
+        return r;
+    } else {}
+    return add r 0;
+}
+
+///   The identity.
+///
+// Not a doc.
+fn id x {
+    return x;
+}
+// The end.
+";
+
+    #[test]
+    fn formats_and_keeps_comments() {
+        assert_eq!(format(MESSY).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn formatting_is_idempotent_and_keeps_the_ast() {
+        let formatted = format(MESSY).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        assert_eq!(
+            parser::parse(&formatted).unwrap().to_sexpr().to_string(),
+            parser::parse(MESSY).unwrap().to_sexpr().to_string()
+        );
+    }
+
+    #[test]
+    fn operators_keep_only_needed_parentheses() {
+        let src = "fn f a b : (result > a) or (a == (b)) and b != 0 {
+            return ((a - b) - (a - b)) * (a + (b % 2)) / add (a * b) 1;
+        }";
+        let formatted = format(src).unwrap();
+        assert_eq!(
+            formatted,
+            "fn f a b
+    : result > a or a == b and b != 0
+{
+    return (a - b - (a - b)) * (a + b % 2) / add (a * b) 1;
+}
+"
+        );
+        assert_eq!(
+            parser::parse(&formatted).unwrap().to_sexpr().to_string(),
+            parser::parse(src).unwrap().to_sexpr().to_string()
+        );
+    }
+
+    #[test]
+    fn not_keeps_only_needed_parentheses() {
+        let src = "fn f a : not (a == 1) and (not a) or f (not true) { return (not a) == false; }";
+        let formatted = format(src).unwrap();
+        assert_eq!(
+            formatted,
+            "fn f a
+    : not a == 1 and not a or f (not true)
+{
+    return (not a) == false;
+}
+"
+        );
+        assert_eq!(
+            parser::parse(&formatted).unwrap().to_sexpr().to_string(),
+            parser::parse(src).unwrap().to_sexpr().to_string()
+        );
+    }
+
+    #[test]
+    fn strings_keep_their_escapes() {
+        let src = "fn f { return concat \"a\\\"b\" \"\\t\\\\\"; }\n";
+        assert_eq!(
+            format(src).unwrap(),
+            "fn f {\n    return concat \"a\\\"b\" \"\\t\\\\\";\n}\n"
+        );
+    }
+
+    #[test]
+    fn arrays_keep_their_parentheses() {
+        let src = "fn f a { a [ 0 ] = g ([1,2]) a[0].length; return [ ]; }\n";
+        assert_eq!(
+            format(src).unwrap(),
+            "fn f a {\n    a[0] = g ([1, 2]) a[0].length;\n    return [];\n}\n"
+        );
+    }
+
+    #[test]
+    fn structs_have_a_field_per_line() {
+        let src = "/// A point.\nstruct Point { x, // across\n y }\nstruct Unit {}\n\
+                   fn f p { p.x = p.y; return p; }\n";
+        assert_eq!(
+            format(src).unwrap(),
+            "/// A point.\nstruct Point {\n    x, // across\n    y,\n}\n\nstruct Unit {}\n\n\
+             fn f p {\n    p.x = p.y;\n    return p;\n}\n"
+        );
+    }
+
+    #[test]
+    fn types_and_matches() {
+        let src = "/// A tree.\ntype Tree = | Leaf |Node  left right;\n\
+                   fn f t { match t { Leaf => { return 0; }\n // empty\n Node l r => {} } }\n";
+        assert_eq!(
+            format(src).unwrap(),
+            "/// A tree.\ntype Tree = Leaf | Node left right;\n\n\
+             fn f t {\n    match t {\n        Leaf => {\n            return 0;\n        }\n        // empty\n\
+             \x20       Node l r => {}\n    }\n}\n"
+        );
+
+        let long = format!("type T = {};\n", ["Variant field"; 6].join(" | "));
+        let formatted = format(&long).unwrap();
+        assert!(formatted.starts_with("type T =\n    Variant field\n    | Variant field\n"));
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn lambdas_are_passed_as_blocks() {
+        let src = "fn f a { return map a |x|{ // double\n return x*2; }; }\n";
+        let formatted = format(src).unwrap();
+        assert_eq!(
+            formatted,
+            "fn f a {\n    return map a |x| {\n        // double\n        return x * 2;\n    };\n}\n"
+        );
+        assert_eq!(
+            format("fn f { return (|| { return 1; }); }").unwrap(),
+            "fn f {\n    return || {\n        return 1;\n    };\n}\n"
+        );
+    }
+
+    #[test]
+    fn for_loops_and_jumps() {
+        let src = "fn f n { for i in range ( 0,n+1 ) { if i>2 { break ; } continue; } return n; }";
+        let formatted = format(src).unwrap();
+        assert_eq!(
+            formatted,
+            "fn f n {\n    for i in range(0, n + 1) {\n        if i > 2 {\n            break;\n        }\n        continue;\n    }\n    return n;\n}\n"
+        );
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn invariants_are_statements() {
+        let src = "fn f n { while n>0 { invariant n>=0 ; n = n-1; } return n; }";
+        assert_eq!(
+            format(src).unwrap(),
+            "fn f n {\n    while n > 0 {\n        invariant n >= 0;\n        n = n - 1;\n    }\n    return n;\n}\n"
+        );
+    }
+
+    #[test]
+    fn comments_stay_after_the_token_before_them() {
+        let src = "fn f n : gt? n 0 { let x = 1; // A\nreturn x; }\n";
+        assert_eq!(
+            format(src).unwrap(),
+            "fn f n\n    : gt? n 0\n{\n    let x = 1; // A\n    return x;\n}\n"
+        );
+    }
+
+    #[test]
+    fn comments_in_expressions_break_them() {
+        let src = "fn f a { let x = add 1 // one\n 2; let y = [1, // one\n 2, 3];\n\
+                   let z = a // minus\n - 1; let v = a[ // index\n 0]; return [x, y // y\n]; }\n";
+        let formatted = format(src).unwrap();
+        assert_eq!(
+            formatted,
+            "fn f a {
+    let x = add
+        1 // one
+        2;
+    let y = [
+        1, // one
+        2,
+        3
+    ];
+    let z = a - // minus
+        1;
+    let v = a[0];
+    // index
+    return [
+        x,
+        y // y
+    ];
+}
+"
+        );
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        assert_eq!(
+            parser::parse(&formatted).unwrap().to_sexpr().to_string(),
+            parser::parse(src).unwrap().to_sexpr().to_string()
+        );
+    }
+
+    #[test]
+    fn long_calls_are_broken() {
+        let src = format!("fn f x {{ return g {}; }}", "argument ".repeat(10));
+        let formatted = format(&src).unwrap();
+        assert!(formatted.contains("return g\n        argument\n"));
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+}
@@ -0,0 +1,6 @@
+mod comments;
+pub use comments::*;
+
+#[allow(clippy::module_inception)]
+mod formatter;
+pub use formatter::*;
@@ -0,0 +1,578 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use crate::binder::bound::{self, DefId};
+use crate::renamer::plain;
+
+/// Bumped whenever the hashed representation changes, so that hashes from
+/// older compilers never match.
+const VERSION: &str = "kedi-hash-v1";
+
+/// A content-addressable hash of a function.
+///
+/// It covers the parameters, predicates and body of the function, and the
+/// hashes of every global it references, but not its name or the source
+/// locations. Locals are already numbered by the renamer, so renaming a
+/// variable or moving a function around does not change its hash.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FunHash(pub [u8; 32]);
+
+impl FunHash {
+    /// The first 12 hex digits, enough to tell functions apart at a glance.
+    pub fn short(&self) -> String {
+        self.to_string()[..12].to_string()
+    }
+
+    pub fn from_hex(s: &str) -> Option<FunHash> {
+        if s.len() != 64 || !s.is_ascii() {
+            return None;
+        }
+        let mut bytes = [0; 32];
+        for (ix, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[ix * 2..ix * 2 + 2], 16).ok()?;
+        }
+        Some(FunHash(bytes))
+    }
+}
+
+impl fmt::Display for FunHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for FunHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FunHash({})", self)
+    }
+}
+
+/// Hashes every definition in the module.
+///
+/// Mutually recursive functions can not include each other's hash, so they
+/// are hashed together: references within a group of mutually recursive
+/// functions are replaced by the position of the target in the group.
+pub fn hash_module<L>(module: &bound::Module<L>) -> HashMap<DefId, FunHash> {
+    let deps = module
+        .iter()
+        .map(|(_, def)| match def {
+            bound::Def::Fun(f) => references(&f.v.implementation),
+            bound::Def::StructPred(p) => BTreeSet::from([p.struct_id]),
+            bound::Def::TypePred(p) => p.variants.iter().copied().collect(),
+            bound::Def::Prim(_) | bound::Def::Struct(_) => BTreeSet::new(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut hashes = HashMap::new();
+
+    // Components come callees first, so every reference leaving the
+    // component is already hashed.
+    for component in strongly_connected_components(&deps) {
+        let placeholder = |_: DefId| GlobalRef::Recursive(0);
+        let mut members = component
+            .iter()
+            .map(|id| (hash_def(module, *id, &hashes, &placeholder), *id))
+            .collect::<Vec<_>>();
+        members.sort_by(|(a, a_id), (b, b_id)| {
+            a.cmp(b)
+                .then_with(|| module.get(*a_id).name().0.cmp(&module.get(*b_id).name().0))
+        });
+
+        let positions = members
+            .iter()
+            .enumerate()
+            .map(|(ix, (_, id))| (*id, ix as u32))
+            .collect::<HashMap<_, _>>();
+        let in_group = |id: DefId| GlobalRef::Recursive(positions[&id]);
+
+        let mut group = Sha256::new();
+        group.update(VERSION);
+        for (_, id) in &members {
+            group.update(hash_def(module, *id, &hashes, &in_group).0);
+        }
+        let group = group.finalize();
+
+        for (_, id) in &members {
+            let mut hasher = Sha256::new();
+            hasher.update(group);
+            hasher.update(positions[id].to_le_bytes());
+            hashes.insert(*id, FunHash(hasher.finalize().into()));
+        }
+    }
+
+    hashes
+}
+
+/// The definitions referenced by a function, from its predicates or body.
+pub fn references<L>(fun: &bound::FunImpl<L>) -> BTreeSet<DefId> {
+    fn expr<L>(e: &bound::Expr<L>, out: &mut BTreeSet<DefId>) {
+        match e {
+            plain::Expr::LitNum(_) | plain::Expr::LitStr(_) | plain::Expr::LitBool(_) => {}
+            plain::Expr::Ident(i) => ident(i, out),
+            plain::Expr::Array(items) => items.v.iter().for_each(|item| expr(item, out)),
+            plain::Expr::Index(index) => {
+                expr(&index.array, out);
+                expr(&index.index.v, out);
+            }
+            plain::Expr::Field(field) => expr(&field.expr, out),
+            plain::Expr::FunCall(call) => {
+                ident(&call.name, out);
+                call.args.v.iter().for_each(|arg| expr(arg, out));
+            }
+            plain::Expr::Logic(logic) => logic.args.v.iter().for_each(|arg| expr(arg, out)),
+            plain::Expr::Lambda(lambda) => block(&lambda.v.body.v, out),
+        }
+    }
+
+    fn ident<L>(i: &bound::Ident<L>, out: &mut BTreeSet<DefId>) {
+        if let bound::Ident::Global(id) = i {
+            out.insert(id.v);
+        }
+    }
+
+    fn block<L>(stmts: &[bound::FunStmt<L>], out: &mut BTreeSet<DefId>) {
+        for stmt in stmts {
+            match stmt {
+                plain::FunStmt::Return(r) => expr(&r.v.0, out),
+                plain::FunStmt::Inv(i) => expr(&i.v, out),
+                plain::FunStmt::LetDecl(d) => expr(&d.v.value, out),
+                plain::FunStmt::Assignment(a) => expr(&a.v.value, out),
+                plain::FunStmt::IndexAssignment(a) => {
+                    expr(&a.v.target.array, out);
+                    expr(&a.v.target.index.v, out);
+                    expr(&a.v.value, out);
+                }
+                plain::FunStmt::FieldAssignment(a) => {
+                    expr(&a.v.target.expr, out);
+                    expr(&a.v.value, out);
+                }
+                plain::FunStmt::While(w) => {
+                    expr(&w.v.condition, out);
+                    block(&w.v.body.v, out);
+                }
+                plain::FunStmt::For(f) => {
+                    expr(&f.v.start, out);
+                    expr(&f.v.end, out);
+                    expr(&f.v.condition, out);
+                    expr(&f.v.step, out);
+                    block(&f.v.body.v, out);
+                }
+                plain::FunStmt::Jump(_) => {}
+                plain::FunStmt::If(i) => {
+                    expr(&i.v.condition, out);
+                    block(&i.v.then.v, out);
+                    if let Some(else_) = &i.v.else_ {
+                        block(&else_.v, out);
+                    }
+                }
+                plain::FunStmt::Match(m) => {
+                    expr(&m.v.value, out);
+                    for arm in &m.v.arms.v {
+                        ident(&arm.variant, out);
+                        block(&arm.body.v, out);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut out = BTreeSet::new();
+    fun.preds.v.iter().for_each(|p| expr(p, &mut out));
+    block(&fun.body.v, &mut out);
+    out
+}
+
+/// Tarjan's algorithm, returning the components in reverse topological
+/// order of the dependency graph.
+fn strongly_connected_components(deps: &[BTreeSet<DefId>]) -> Vec<Vec<DefId>> {
+    struct State<'t> {
+        deps: &'t [BTreeSet<DefId>],
+        next_index: usize,
+        index: Vec<Option<usize>>,
+        lowlink: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        components: Vec<Vec<DefId>>,
+    }
+
+    fn visit(st: &mut State, v: usize) {
+        st.index[v] = Some(st.next_index);
+        st.lowlink[v] = st.next_index;
+        st.next_index += 1;
+        st.stack.push(v);
+        st.on_stack[v] = true;
+
+        for w in st.deps[v].iter().map(|d| d.id as usize) {
+            match st.index[w] {
+                None => {
+                    visit(st, w);
+                    st.lowlink[v] = st.lowlink[v].min(st.lowlink[w]);
+                }
+                Some(ix) if st.on_stack[w] => st.lowlink[v] = st.lowlink[v].min(ix),
+                Some(_) => {}
+            }
+        }
+
+        if Some(st.lowlink[v]) == st.index[v] {
+            let mut component = vec![];
+            loop {
+                let w = st.stack.pop().unwrap();
+                st.on_stack[w] = false;
+                component.push(DefId { id: w as u32 });
+                if w == v {
+                    break;
+                }
+            }
+            st.components.push(component);
+        }
+    }
+
+    let n = deps.len();
+    let mut st = State {
+        deps,
+        next_index: 0,
+        index: vec![None; n],
+        lowlink: vec![0; n],
+        on_stack: vec![false; n],
+        stack: vec![],
+        components: vec![],
+    };
+    for v in 0..n {
+        if st.index[v].is_none() {
+            visit(&mut st, v);
+        }
+    }
+    st.components
+}
+
+enum GlobalRef {
+    /// A definition outside the group being hashed.
+    Hashed(FunHash),
+    /// The definition at this position within the group being hashed.
+    Recursive(u32),
+}
+
+fn hash_def<L>(
+    module: &bound::Module<L>,
+    id: DefId,
+    hashes: &HashMap<DefId, FunHash>,
+    in_group: &dyn Fn(DefId) -> GlobalRef,
+) -> FunHash {
+    let mut hasher = StructHasher {
+        sha: Sha256::new(),
+        resolve: &|target| match hashes.get(&target) {
+            Some(hash) => GlobalRef::Hashed(*hash),
+            None => in_group(target),
+        },
+    };
+    hasher.str(VERSION);
+
+    match module.get(id) {
+        bound::Def::Prim(prim) => {
+            hasher.str("prim");
+            hasher.str(prim.name());
+        }
+        bound::Def::Fun(fun) => {
+            let fun = &fun.v.implementation;
+            hasher.str("fun");
+            hasher.len(fun.params.v.len());
+            for param in &fun.params.v {
+                hasher.u32(param.v.id);
+            }
+            hasher.u32(fun.result.v.id);
+            hasher.len(fun.preds.v.len());
+            for pred in &fun.preds.v {
+                hasher.expr(pred);
+            }
+            hasher.block(&fun.body.v);
+        }
+        // Structs with the same fields are still told apart by their
+        // predicates, so the name is part of the hash.
+        bound::Def::Struct(def) => {
+            hasher.str("struct");
+            hasher.str(&def.v.name.v.0);
+            hasher.len(def.v.fields.v.len());
+            for field in &def.v.fields.v {
+                hasher.str(&field.v.0);
+            }
+        }
+        bound::Def::StructPred(pred) => {
+            hasher.str("struct-pred");
+            hasher.global(pred.struct_id);
+        }
+        bound::Def::TypePred(pred) => {
+            hasher.str("type-pred");
+            hasher.len(pred.variants.len());
+            for variant in &pred.variants {
+                hasher.global(*variant);
+            }
+        }
+    }
+
+    FunHash(hasher.sha.finalize().into())
+}
+
+/// Feeds the structure of a function into a hash, leaving out locations.
+/// Every node starts with a distinct label and every sequence with its
+/// length, so different trees never produce the same stream.
+struct StructHasher<'t> {
+    sha: Sha256,
+    resolve: &'t dyn Fn(DefId) -> GlobalRef,
+}
+
+impl<'t> StructHasher<'t> {
+    fn u32(&mut self, n: u32) {
+        self.sha.update(n.to_le_bytes());
+    }
+
+    fn len(&mut self, n: usize) {
+        self.sha.update((n as u64).to_le_bytes());
+    }
+
+    fn str(&mut self, s: &str) {
+        self.len(s.len());
+        self.sha.update(s);
+    }
+
+    fn block<L>(&mut self, stmts: &[bound::FunStmt<L>]) {
+        self.len(stmts.len());
+        for stmt in stmts {
+            match stmt {
+                plain::FunStmt::Return(r) => {
+                    self.str("return");
+                    self.expr(&r.v.0);
+                }
+                plain::FunStmt::Inv(i) => {
+                    self.str("inv");
+                    self.expr(&i.v);
+                }
+                plain::FunStmt::LetDecl(d) => {
+                    self.str("let");
+                    self.u32(d.v.name.v.id);
+                    self.expr(&d.v.value);
+                }
+                plain::FunStmt::Assignment(a) => {
+                    self.str("assign");
+                    self.u32(a.v.id.v.id);
+                    self.expr(&a.v.value);
+                }
+                plain::FunStmt::IndexAssignment(a) => {
+                    self.str("assign-index");
+                    self.expr(&a.v.target.array);
+                    self.expr(&a.v.target.index.v);
+                    self.expr(&a.v.value);
+                }
+                plain::FunStmt::FieldAssignment(a) => {
+                    self.str("assign-field");
+                    self.expr(&a.v.target.expr);
+                    self.str(&a.v.target.name.v.0);
+                    self.expr(&a.v.value);
+                }
+                plain::FunStmt::While(w) => {
+                    self.str("while");
+                    self.expr(&w.v.condition);
+                    self.block(&w.v.body.v);
+                }
+                plain::FunStmt::For(f) => {
+                    self.str("for");
+                    self.u32(f.v.var.v.id);
+                    self.expr(&f.v.start);
+                    self.expr(&f.v.end);
+                    self.u32(f.v.next.v.id);
+                    self.u32(f.v.limit.v.id);
+                    self.expr(&f.v.condition);
+                    self.expr(&f.v.step);
+                    self.block(&f.v.body.v);
+                }
+                plain::FunStmt::Jump(j) => self.str(j.v.keyword()),
+                plain::FunStmt::If(i) => {
+                    self.str("if");
+                    self.expr(&i.v.condition);
+                    self.block(&i.v.then.v);
+                    match &i.v.else_ {
+                        Some(else_) => {
+                            self.str("else");
+                            self.block(&else_.v);
+                        }
+                        None => self.str("no-else"),
+                    }
+                }
+                plain::FunStmt::Match(m) => {
+                    self.str("match");
+                    self.expr(&m.v.value);
+                    self.len(m.v.arms.v.len());
+                    for arm in &m.v.arms.v {
+                        self.str("arm");
+                        self.ident(&arm.variant);
+                        self.len(arm.bindings.v.len());
+                        for binding in &arm.bindings.v {
+                            self.u32(binding.v.id);
+                        }
+                        self.block(&arm.body.v);
+                    }
+                }
+            }
+        }
+    }
+
+    fn expr<L>(&mut self, expr: &bound::Expr<L>) {
+        match expr {
+            plain::Expr::LitNum(n) => {
+                self.str("num");
+                self.sha.update(n.v.0.to_le_bytes());
+            }
+            plain::Expr::LitStr(s) => {
+                self.str("str");
+                self.str(&s.v.0);
+            }
+            plain::Expr::LitBool(b) => {
+                self.str("bool");
+                self.sha.update([b.v.0 as u8]);
+            }
+            plain::Expr::Ident(i) => self.ident(i),
+            plain::Expr::Array(items) => {
+                self.str("array");
+                self.len(items.v.len());
+                for item in &items.v {
+                    self.expr(item);
+                }
+            }
+            plain::Expr::Index(index) => {
+                self.str("index");
+                self.expr(&index.array);
+                self.expr(&index.index.v);
+            }
+            plain::Expr::Field(field) => {
+                self.str("field");
+                self.expr(&field.expr);
+                self.str(&field.name.v.0);
+            }
+            plain::Expr::FunCall(call) => {
+                self.str("call");
+                self.ident(&call.name);
+                self.len(call.args.v.len());
+                for arg in &call.args.v {
+                    self.expr(arg);
+                }
+            }
+            plain::Expr::Logic(logic) => {
+                self.str(match logic.op.v {
+                    plain::LogicOp::And => "and",
+                    plain::LogicOp::Or => "or",
+                    plain::LogicOp::Not => "not",
+                });
+                self.len(logic.args.v.len());
+                for arg in &logic.args.v {
+                    self.expr(arg);
+                }
+            }
+            plain::Expr::Lambda(lambda) => {
+                self.str("lambda");
+                self.len(lambda.v.params.v.len());
+                for param in &lambda.v.params.v {
+                    self.u32(param.v.id);
+                }
+                self.len(lambda.v.captures.len());
+                for capture in &lambda.v.captures {
+                    self.u32(capture.id);
+                }
+                self.block(&lambda.v.body.v);
+            }
+        }
+    }
+
+    fn ident<L>(&mut self, ident: &bound::Ident<L>) {
+        match ident {
+            bound::Ident::Local(l) => {
+                self.str("local");
+                self.u32(l.v.id);
+            }
+            bound::Ident::Global(g) => self.global(g.v),
+        }
+    }
+
+    fn global(&mut self, id: DefId) {
+        match (self.resolve)(id) {
+            GlobalRef::Hashed(hash) => {
+                self.str("global");
+                self.sha.update(hash.0);
+            }
+            GlobalRef::Recursive(ix) => {
+                self.str("recursive");
+                self.u32(ix);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::runner;
+
+    fn hashes(src: &str) -> HashMap<String, FunHash> {
+        let out = runner(src).unwrap();
+        let hashes = hash_module(&out.bound);
+        out.bound
+            .module_funs()
+            .map(|(id, f)| (f.name.v.0.clone(), hashes[&id]))
+            .collect()
+    }
+
+    #[test]
+    fn ignores_names_and_locations() {
+        let a = hashes("fn f x { let y = add x 1; return y; }");
+        let b = hashes("fn g   a {\n  let b = add a 1;\n  return b;\n}");
+        assert_eq!(a["f"], b["g"]);
+    }
+
+    #[test]
+    fn depends_on_the_body_and_predicates() {
+        let base = hashes("fn f x { return add x 1; }");
+        let body = hashes("fn f x { return add x 2; }");
+        let pred = hashes("fn f x : number? x { return add x 1; }");
+        assert_ne!(base["f"], body["f"]);
+        assert_ne!(base["f"], pred["f"]);
+    }
+
+    #[test]
+    fn includes_transitive_references() {
+        let a = hashes("fn h x { return x; } fn g x { return h x; } fn f x { return g x; }");
+        let b = hashes("fn h x { return 1; } fn g x { return h x; } fn f x { return g x; }");
+        assert_ne!(a["h"], b["h"]);
+        assert_ne!(a["g"], b["g"]);
+        assert_ne!(a["f"], b["f"]);
+
+        // Unrelated functions are not affected.
+        let c = hashes("fn h x { return 1; } fn g x { return h x; } fn f x { return add x 1; }");
+        let d = hashes("fn h x { return 2; } fn g x { return h x; } fn f x { return add x 1; }");
+        assert_eq!(c["f"], d["f"]);
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let src = "
+            fn even? n { if eq? n 0 { return 1; } return odd? n; }
+            fn odd? n { if eq? n 0 { return 0; } return even? n; }";
+        let a = hashes(src);
+        assert_ne!(a["even?"], a["odd?"]);
+        assert_eq!(a, hashes(src));
+
+        let b = hashes(&src.replace("return 0;", "return 2;"));
+        assert_ne!(a["even?"], b["even?"]);
+        assert_ne!(a["odd?"], b["odd?"]);
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let hash = hashes("fn f x { return x; }")["f"];
+        assert_eq!(FunHash::from_hex(&hash.to_string()), Some(hash));
+        assert_eq!(hash.short().len(), 12);
+        assert_eq!(FunHash::from_hex("not a hash"), None);
+    }
+}
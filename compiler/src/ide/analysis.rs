@@ -0,0 +1,644 @@
+use std::collections::HashMap;
+
+use crate::binder::{self, bound};
+use crate::error::{to_diagnostic, Error};
+use crate::formatter::doc_comment;
+use crate::parser::{self, syntax};
+use crate::renamer::{self, plain};
+use crate::simplifier;
+use crate::util::loc::{Span, SrcLoc};
+
+/// An error found in a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+    /// Other locations relevant to the error, with an explanation.
+    pub related: Vec<(Span, String)>,
+}
+
+/// What a top-level definition defines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Struct,
+    Type,
+}
+
+/// A top-level definition of a document.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The whole definition.
+    pub span: Span,
+    /// The name of the definition.
+    pub name_span: Span,
+}
+
+/// What an identifier refers to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Target {
+    Global(bound::DefId),
+    /// A local of the function `DefId`.
+    Local(bound::DefId, plain::LocalIdent),
+}
+
+/// The result of compiling a document, answering the queries of an editor.
+/// Offsets and spans are in bytes.
+pub struct Analysis {
+    source: String,
+    diagnostics: Vec<Diagnostic>,
+    syntax: Option<syntax::Module<SrcLoc>>,
+    bound: Option<bound::Module<SrcLoc>>,
+    /// Every identifier in the document with what it refers to.
+    occurrences: Vec<(Span, Target)>,
+    definitions: HashMap<Target, Span>,
+}
+
+impl Analysis {
+    pub fn new(source: &str) -> Analysis {
+        let mut analysis = Analysis {
+            source: source.to_string(),
+            diagnostics: vec![],
+            syntax: None,
+            bound: None,
+            occurrences: vec![],
+            definitions: HashMap::new(),
+        };
+
+        // Run the phases one by one, keeping the results that are available.
+        // With syntax errors, the later phases run on what could be parsed,
+        // but only the syntax errors are reported.
+        let (syntax, parse_errors) = parser::parse_partial(source);
+        let parsed = parse_errors.is_empty();
+        for error in parse_errors {
+            let error = parser::Error::ParseFailed(vec![error]);
+            analysis.diagnostics.push(diagnostic_of(error.into()));
+        }
+
+        // The renamer and the binder stop at the first error, so the
+        // definition with it is stubbed out, or left out, and the rest bound
+        // again. That reports every definition with an error, and keeps the
+        // others navigable.
+        let mut module = syntax.clone();
+        analysis.syntax = Some(syntax);
+        let bound = loop {
+            let bound = renamer::rename(&module)
+                .map_err(Error::from)
+                .and_then(|plain| {
+                    binder::bind(&plain, &binder::prelude(SrcLoc::Unknown)).map_err(Error::from)
+                });
+            match bound {
+                Ok(bound) => break Some(bound),
+                Err(e) => {
+                    let diagnostic = diagnostic_of(e);
+                    let left_out = leave_out(&mut module, &diagnostic);
+                    if parsed {
+                        analysis.diagnostics.push(diagnostic);
+                    }
+                    if !left_out {
+                        break None;
+                    }
+                }
+            }
+        };
+        let Some(bound) = bound else {
+            return analysis;
+        };
+        if analysis.diagnostics.is_empty() {
+            if let Err(e) = simplifier::simplify(&bound) {
+                analysis.diagnostics.push(diagnostic_of(e.into()));
+            }
+        }
+
+        analysis.bound = Some(bound);
+        analysis.index();
+        analysis
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The functions of the document that could be parsed.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let Some(syntax) = &self.syntax else {
+            return vec![];
+        };
+        syntax
+            .statements
+            .v
+            .iter()
+            .filter_map(|stmt| match stmt {
+                syntax::TopLevelStmt::FunDef(f) => Some(Symbol {
+                    name: f.name.v.0.clone(),
+                    kind: SymbolKind::Function,
+                    span: known(f.a)?,
+                    name_span: known(f.name.a)?,
+                }),
+                syntax::TopLevelStmt::StructDef(s) => Some(Symbol {
+                    name: s.name.v.0.clone(),
+                    kind: SymbolKind::Struct,
+                    span: known(s.a)?,
+                    name_span: known(s.name.a)?,
+                }),
+                syntax::TopLevelStmt::TypeDef(t) => Some(Symbol {
+                    name: t.name.v.0.clone(),
+                    kind: SymbolKind::Type,
+                    span: known(t.a)?,
+                    name_span: known(t.name.a)?,
+                }),
+            })
+            .collect()
+    }
+
+    /// Where the identifier at `offset` is defined.
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        let target = self.target_at(offset)?;
+        self.definitions.get(&target).copied()
+    }
+
+    /// Every occurrence of the identifier at `offset`, its definition included.
+    pub fn references(&self, offset: usize) -> Vec<Span> {
+        match self.target_at(offset) {
+            Some(target) => self
+                .occurrences
+                .iter()
+                .filter(|(_, t)| *t == target)
+                .map(|(span, _)| *span)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// The signature and the predicates of the function at `offset`, the
+    /// fields of the struct, or the variants of the type.
+    pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+        let (span, target) = self.occurrence_at(offset)?;
+        let Target::Global(id) = target else {
+            return None;
+        };
+        let bound = self.bound.as_ref()?;
+        let fun = match bound.get(id) {
+            bound::Def::Fun(fun) => fun,
+            bound::Def::Struct(_) => return Some((span, struct_hover(bound, id)?)),
+            bound::Def::StructPred(pred) => {
+                return Some((span, struct_hover(bound, pred.struct_id)?))
+            }
+            bound::Def::TypePred(pred) => return Some((span, type_signature(&pred.def.v))),
+            bound::Def::Prim(_) => return None,
+        };
+
+        let mut text = match &fun.doc {
+            Some(doc) => format!("{}\n", doc_comment(doc)),
+            None => String::new(),
+        };
+        text.push_str(&format!("fn {}", fun.name.v.0));
+        for param in fun.implementation.params.v.iter() {
+            if let Some(name) = self.text(param.a) {
+                text.push(' ');
+                text.push_str(name);
+            }
+        }
+        for pred in fun.implementation.preds.v.iter() {
+            if let Some(pred) = self.text(expr_loc(pred)) {
+                text.push_str("\n  : ");
+                text.push_str(pred);
+            }
+        }
+        Some((span, text))
+    }
+
+    fn occurrence_at(&self, offset: usize) -> Option<(Span, Target)> {
+        self.occurrences
+            .iter()
+            .find(|(span, _)| span.start.0 <= offset && offset <= span.start.0 + span.length)
+            .copied()
+    }
+
+    fn target_at(&self, offset: usize) -> Option<Target> {
+        self.occurrence_at(offset).map(|(_, target)| target)
+    }
+
+    fn text(&self, loc: SrcLoc) -> Option<&str> {
+        let span = known(loc)?;
+        self.source.get(span.start.0..span.start.0 + span.length)
+    }
+
+    fn index(&mut self) {
+        let Some(bound) = self.bound.take() else {
+            return;
+        };
+
+        for (id, def) in bound.iter() {
+            match def {
+                bound::Def::Struct(def) => self.define(def.name.a, Target::Global(id)),
+                bound::Def::StructPred(pred) => {
+                    if let bound::Def::Struct(def) = bound.get(pred.struct_id) {
+                        self.define(def.name.a, Target::Global(id));
+                    }
+                }
+                bound::Def::TypePred(pred) => self.define(pred.def.v.name.a, Target::Global(id)),
+                bound::Def::Fun(_) | bound::Def::Prim(_) => {}
+            }
+        }
+
+        for (id, fun) in bound.module_funs() {
+            self.define(fun.name.a, Target::Global(id));
+
+            let implementation = &fun.implementation;
+            for param in implementation.params.v.iter() {
+                self.define(param.a, Target::Local(id, param.v));
+            }
+            for pred in implementation.preds.v.iter() {
+                self.index_expr(id, pred);
+            }
+            self.index_stmts(id, &implementation.body.v);
+        }
+
+        self.bound = Some(bound);
+    }
+
+    fn occurrence(&mut self, loc: SrcLoc, target: Target) {
+        if let Some(span) = known(loc) {
+            self.occurrences.push((span, target));
+        }
+    }
+
+    /// Records a binding, only the first one of a local is its definition.
+    fn define(&mut self, loc: SrcLoc, target: Target) {
+        if let Some(span) = known(loc) {
+            self.definitions.entry(target).or_insert(span);
+        }
+        self.occurrence(loc, target);
+    }
+
+    fn index_stmts(&mut self, fun: bound::DefId, stmts: &[bound::FunStmt<SrcLoc>]) {
+        for stmt in stmts {
+            match stmt {
+                plain::FunStmt::Return(ret) => self.index_expr(fun, &ret.v.0),
+                plain::FunStmt::Inv(inv) => self.index_expr(fun, &inv.v),
+                plain::FunStmt::LetDecl(decl) => {
+                    self.index_expr(fun, &decl.v.value);
+                    self.define(decl.v.name.a, Target::Local(fun, decl.v.name.v));
+                }
+                plain::FunStmt::Assignment(assignment) => {
+                    self.index_expr(fun, &assignment.v.value);
+                    let id = &assignment.v.id;
+                    self.occurrence(id.a, Target::Local(fun, id.v));
+                }
+                plain::FunStmt::IndexAssignment(assignment) => {
+                    self.index_expr(fun, &assignment.v.target.array);
+                    self.index_expr(fun, &assignment.v.target.index.v);
+                    self.index_expr(fun, &assignment.v.value);
+                }
+                plain::FunStmt::FieldAssignment(assignment) => {
+                    self.index_expr(fun, &assignment.v.target.expr);
+                    self.index_expr(fun, &assignment.v.value);
+                }
+                plain::FunStmt::While(while_) => {
+                    self.index_expr(fun, &while_.v.condition);
+                    self.index_stmts(fun, &while_.v.body.v);
+                }
+                // The comparison and the increment are not in the source.
+                plain::FunStmt::For(for_) => {
+                    self.index_expr(fun, &for_.v.start);
+                    self.index_expr(fun, &for_.v.end);
+                    self.define(for_.v.var.a, Target::Local(fun, for_.v.var.v));
+                    self.index_stmts(fun, &for_.v.body.v);
+                }
+                plain::FunStmt::Jump(_) => {}
+                plain::FunStmt::If(if_) => {
+                    self.index_expr(fun, &if_.v.condition);
+                    self.index_stmts(fun, &if_.v.then.v);
+                    if let Some(else_) = &if_.v.else_ {
+                        self.index_stmts(fun, &else_.v);
+                    }
+                }
+                plain::FunStmt::Match(match_) => {
+                    self.index_expr(fun, &match_.v.value);
+                    for arm in match_.v.arms.v.iter() {
+                        self.index_ident(fun, &arm.variant);
+                        for binding in arm.bindings.v.iter() {
+                            self.define(binding.a, Target::Local(fun, binding.v));
+                        }
+                        self.index_stmts(fun, &arm.body.v);
+                    }
+                }
+            }
+        }
+    }
+
+    fn index_expr(&mut self, fun: bound::DefId, expr: &bound::Expr<SrcLoc>) {
+        match expr {
+            plain::Expr::LitNum(_) | plain::Expr::LitStr(_) | plain::Expr::LitBool(_) => {}
+            plain::Expr::Ident(ident) => self.index_ident(fun, ident),
+            plain::Expr::Array(items) => {
+                for item in items.v.iter() {
+                    self.index_expr(fun, item);
+                }
+            }
+            plain::Expr::Index(index) => {
+                self.index_expr(fun, &index.array);
+                self.index_expr(fun, &index.index.v);
+            }
+            plain::Expr::Field(field) => self.index_expr(fun, &field.expr),
+            plain::Expr::FunCall(call) => {
+                self.index_ident(fun, &call.name);
+                for arg in call.args.v.iter() {
+                    self.index_expr(fun, arg);
+                }
+            }
+            plain::Expr::Logic(logic) => {
+                for arg in logic.args.v.iter() {
+                    self.index_expr(fun, arg);
+                }
+            }
+            // Lambdas share the locals of the function they are in.
+            plain::Expr::Lambda(lambda) => {
+                for param in lambda.v.params.v.iter() {
+                    self.define(param.a, Target::Local(fun, param.v));
+                }
+                self.index_stmts(fun, &lambda.v.body.v);
+            }
+        }
+    }
+
+    fn index_ident(&mut self, fun: bound::DefId, ident: &bound::Ident<SrcLoc>) {
+        match ident {
+            bound::Ident::Local(local) => self.occurrence(local.a, Target::Local(fun, local.v)),
+            bound::Ident::Global(global) => self.occurrence(global.a, Target::Global(global.v)),
+        }
+    }
+}
+
+fn known(loc: SrcLoc) -> Option<Span> {
+    match loc {
+        SrcLoc::Known(span) => Some(span),
+        SrcLoc::Unknown => None,
+    }
+}
+
+fn expr_loc(expr: &bound::Expr<SrcLoc>) -> SrcLoc {
+    match expr {
+        plain::Expr::LitNum(n) => n.a,
+        plain::Expr::LitStr(s) => s.a,
+        plain::Expr::LitBool(b) => b.a,
+        plain::Expr::Ident(ident) => *ident.loc(),
+        plain::Expr::Array(items) => items.a,
+        plain::Expr::Index(index) => SrcLoc::enclosing(&expr_loc(&index.array), &index.index.a),
+        plain::Expr::Field(field) => SrcLoc::enclosing(&expr_loc(&field.expr), &field.name.a),
+        plain::Expr::FunCall(call) => SrcLoc::enclosing(call.name.loc(), &call.args.a),
+        plain::Expr::Logic(logic) => SrcLoc::enclosing(&logic.op.a, &logic.args.a),
+        plain::Expr::Lambda(lambda) => lambda.a,
+    }
+}
+
+/// The type a struct is a variant of, or the struct itself.
+fn struct_hover(bound: &bound::Module<SrcLoc>, id: bound::DefId) -> Option<String> {
+    let ty = bound.iter().find_map(|(_, def)| match def {
+        bound::Def::TypePred(pred) if pred.variants.contains(&id) => Some(pred),
+        _ => None,
+    });
+    match (ty, bound.get(id)) {
+        (Some(pred), _) => Some(type_signature(&pred.def.v)),
+        (None, bound::Def::Struct(def)) => Some(struct_signature(&def.v)),
+        (None, _) => None,
+    }
+}
+
+fn type_signature(def: &bound::TypeDef<SrcLoc>) -> String {
+    let mut text = match &def.doc {
+        Some(doc) => format!("{}\n", doc_comment(doc)),
+        None => String::new(),
+    };
+    let variants = def
+        .variants
+        .v
+        .iter()
+        .map(|variant| {
+            std::iter::once(&variant.v.name)
+                .chain(variant.v.fields.v.iter())
+                .map(|name| name.v.0.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect::<Vec<_>>();
+    text.push_str(&format!("type {} = {}", def.name.v.0, variants.join(" | ")));
+    text
+}
+
+fn struct_signature(def: &bound::StructDef<SrcLoc>) -> String {
+    let mut text = match &def.doc {
+        Some(doc) => format!("{}\n", doc_comment(doc)),
+        None => String::new(),
+    };
+    let fields = def
+        .fields
+        .v
+        .iter()
+        .map(|field| field.v.0.as_str())
+        .collect::<Vec<_>>();
+    text.push_str(&format!(
+        "struct {} {{ {} }}",
+        def.name.v.0,
+        fields.join(", ")
+    ));
+    text
+}
+
+/// The diagnostic of an error, with the message and the labels it is
+/// reported with on the command line.
+fn diagnostic_of(error: Error) -> Diagnostic {
+    let diagnostic = to_diagnostic(error);
+    let mut labels = diagnostic.labels.unwrap_or_default();
+    let primary = labels
+        .iter()
+        .position(|label| label.primary())
+        .map(|ix| labels.remove(ix))
+        .or_else(|| (!labels.is_empty()).then(|| labels.remove(0)));
+    let span = |label: &miette::LabeledSpan| Span::from_offset_len(label.offset(), label.len());
+
+    let message = match diagnostic.help {
+        Some(help) => format!("{}\n\n{}", diagnostic.message, help),
+        None => diagnostic.message,
+    };
+    Diagnostic {
+        span: primary.map_or(Span::from_offset_len(0, 0), |label| span(&label)),
+        message,
+        related: labels
+            .iter()
+            .map(|label| (span(label), label.label().unwrap_or_default().to_string()))
+            .collect(),
+    }
+}
+
+/// Stubs out the body and the predicates of the function `diagnostic` is
+/// about, or leaves out the definition it is in if there is nothing to stub.
+/// Returns whether the module changed.
+fn leave_out(module: &mut syntax::Module<SrcLoc>, diagnostic: &Diagnostic) -> bool {
+    let spans =
+        std::iter::once(diagnostic.span).chain(diagnostic.related.iter().map(|(span, _)| *span));
+    let stmt_span = |stmt: &syntax::TopLevelStmt<SrcLoc>| match stmt {
+        syntax::TopLevelStmt::FunDef(def) => known(def.a),
+        syntax::TopLevelStmt::StructDef(def) => known(def.a),
+        syntax::TopLevelStmt::TypeDef(def) => known(def.a),
+    };
+    let contains = |stmt: &syntax::TopLevelStmt<SrcLoc>, span: Span| {
+        stmt_span(stmt).is_some_and(|def| def.start.0 <= span.start.0 && span.end() <= def.end())
+    };
+
+    // Errors are about the function the first label in one is in, like a
+    // match missing a variant of a type.
+    let stmts = &mut module.statements.v;
+    let function = spans.clone().find_map(|span| {
+        stmts.iter().position(|stmt| {
+            matches!(stmt, syntax::TopLevelStmt::FunDef(_)) && contains(stmt, span)
+        })
+    });
+    let Some(ix) = function.or_else(|| {
+        stmts
+            .iter()
+            .position(|stmt| contains(stmt, diagnostic.span))
+    }) else {
+        return false;
+    };
+    match &mut stmts[ix] {
+        syntax::TopLevelStmt::FunDef(def)
+            if !def.v.preds.v.is_empty() || !def.v.body.v.is_empty() =>
+        {
+            def.v.preds.v.clear();
+            def.v.body.v.clear();
+        }
+        _ => {
+            stmts.remove(ix);
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "/// Adds one.
+///
+/// Always grows.
+fn inc n : gt? result n { let r = add n 1; return r; }
+fn twice n { let x = inc n; let y = inc x; return y; }";
+
+    fn offset(needle: &str, nth: usize) -> usize {
+        SRC.match_indices(needle).nth(nth).unwrap().0
+    }
+
+    fn spans(needle: &str) -> Vec<Span> {
+        SRC.match_indices(needle)
+            .map(|(ix, _)| Span::from_offset_len(ix, needle.len()))
+            .collect()
+    }
+
+    #[test]
+    fn definitions_and_references() {
+        let analysis = Analysis::new(SRC);
+        assert_eq!(analysis.diagnostics(), &[]);
+
+        // `inc` is called twice in `twice`.
+        let call = offset("inc", 2);
+        assert_eq!(analysis.definition(call), Some(spans("inc")[0]));
+        assert_eq!(analysis.references(call), spans("inc"));
+
+        // Locals are resolved per function.
+        let x = offset("x", 1);
+        assert_eq!(analysis.definition(x), Some(spans("x")[0]));
+        assert_eq!(analysis.references(x), spans("x"));
+        assert_eq!(analysis.references(offset("let r", 0) + 4).len(), 2);
+    }
+
+    #[test]
+    fn hover_shows_the_predicates() {
+        let analysis = Analysis::new(SRC);
+        let (span, text) = analysis.hover(offset("inc", 1)).unwrap();
+        assert_eq!(span, spans("inc")[1]);
+        assert_eq!(
+            text,
+            "/// Adds one.\n///\n/// Always grows.\nfn inc n\n  : gt? result n"
+        );
+    }
+
+    #[test]
+    fn symbols_survive_renamer_errors() {
+        let src = "fn f x { let x = 1; return x; }\nfn g y { return y; }";
+        let analysis = Analysis::new(src);
+
+        let diagnostics = analysis.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span, Span::from_offset_len(13, 1));
+        assert_eq!(diagnostics[0].related[0].0, Span::from_offset_len(5, 1));
+
+        let names = analysis
+            .symbols()
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["f", "g"]);
+    }
+
+    #[test]
+    fn every_definition_with_an_error_is_reported() {
+        let src = "type T = A | B;
+fn f t { match t { A => { return 1; } } }
+fn g x { return nope x; }
+fn h x { let y = g x; return y; }";
+        let analysis = Analysis::new(src);
+
+        let diagnostics = analysis.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].message,
+            "Non-exhaustive match on `T`, `B` is missing"
+        );
+        assert_eq!(diagnostics[0].span, Span::from_offset_len(13, 1));
+        assert_eq!(diagnostics[1].message, "Unknown function `nope`");
+
+        // The definitions without errors can still be navigated.
+        let call = src.rfind("g x").unwrap();
+        assert_eq!(
+            analysis.definition(call),
+            Some(Span::from_offset_len(src.find("g x").unwrap(), 1))
+        );
+    }
+
+    #[test]
+    fn symbols_have_kinds() {
+        let analysis = Analysis::new("struct P { x }\ntype T = A;\nfn f { return 1; }");
+        let kinds = analysis
+            .symbols()
+            .into_iter()
+            .map(|s| s.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![SymbolKind::Struct, SymbolKind::Type, SymbolKind::Function]
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let src = "fn f { return 1 }\nfn g x { let = 2; return x; }";
+        let analysis = Analysis::new(src);
+        let diagnostics = analysis.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].span, Span::from_offset_len(16, 1));
+        assert_eq!(diagnostics[1].span, Span::from_offset_len(31, 1));
+
+        // What could be parsed can still be navigated.
+        let names = analysis
+            .symbols()
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["f", "g"]);
+        assert_eq!(analysis.definition(43), Some(Span::from_offset_len(23, 1)));
+    }
+}
@@ -0,0 +1,2 @@
+mod analysis;
+pub use analysis::*;
@@ -0,0 +1,71 @@
+use std::collections::HashSet;
+
+use crate::binder::bound::DefId;
+use crate::util::loc::Tag;
+
+/// A branch taken while interpreting. `tag` identifies the `If` (by its
+/// condition) or `Loop` within the function `fun`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Branch {
+    pub fun: DefId,
+    pub tag: Tag,
+    pub edge: Edge,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Edge {
+    Then,
+    Else,
+    /// The loop exited after a number of iterations in the bucket
+    /// `2^n ..= 2^(n+1) - 1` (`0` for loops that never completed an iteration).
+    Iterations(u32),
+}
+
+impl Edge {
+    pub fn iterations(count: u64) -> Edge {
+        match count {
+            0 => Edge::Iterations(0),
+            n => Edge::Iterations(1 + n.ilog2()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    branches: HashSet<Branch>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, branch: Branch) {
+        self.branches.insert(branch);
+    }
+
+    pub fn len(&self) -> usize {
+        self.branches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.branches.is_empty()
+    }
+
+    pub fn contains(&self, branch: &Branch) -> bool {
+        self.branches.contains(branch)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Branch> {
+        self.branches.iter()
+    }
+
+    /// Adds the branches of `other`, returning how many were not covered yet.
+    pub fn merge(&mut self, other: &Coverage) -> usize {
+        other
+            .branches
+            .iter()
+            .filter(|b| self.branches.insert(**b))
+            .count()
+    }
+}
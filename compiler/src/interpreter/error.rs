@@ -0,0 +1,144 @@
+use std::fmt;
+use std::time::Duration;
+
+use num_bigint::BigInt;
+
+use super::KediValue;
+use crate::parser::syntax;
+
+/// A runtime error raised while interpreting a program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterpretError<L> {
+    pub kind: InterpretErrorKind,
+    /// Location of the statement that failed, `None` when the error is
+    /// raised before entering the program (e.g. a bad entrypoint).
+    pub loc: Option<L>,
+    /// The kedi call stack at the time of the error, outermost call first.
+    pub stack: Vec<StackFrame<L>>,
+    pub fuel_used: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InterpretErrorKind {
+    OutOfFuel {
+        limit: u64,
+    },
+    MaxDepthExceeded {
+        limit: usize,
+    },
+    Timeout {
+        limit: Duration,
+    },
+    UnknownFunction {
+        name: syntax::Ident,
+    },
+    WrongArity {
+        expected: usize,
+        actual: usize,
+    },
+    MissingReturn,
+    /// An `invariant` that does not hold, with the iteration of the
+    /// innermost loop around it, counting from 1, and the values of the
+    /// variables at that point.
+    InvariantViolated {
+        iteration: Option<u64>,
+        locals: Vec<(syntax::Ident, KediValue)>,
+    },
+    DivisionByZero,
+    /// A value of the wrong type, e.g. a number used as a condition.
+    TypeMismatch {
+        expected: &'static str,
+        actual: KediValue,
+    },
+    /// The bounds are boxed to keep the error small.
+    InvalidSlice {
+        start: Box<BigInt>,
+        end: Box<BigInt>,
+        length: usize,
+    },
+    IndexOutOfBounds {
+        index: Box<BigInt>,
+        length: usize,
+    },
+    /// A field the value does not have, including `length` on anything but
+    /// strings and arrays, and assignments to `length`.
+    MissingField {
+        field: syntax::Ident,
+        actual: KediValue,
+    },
+    /// A match on a value built by none of the variants of the type.
+    NoMatchingArm {
+        actual: KediValue,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackFrame<L> {
+    pub function: syntax::Ident,
+    /// Location of the call in the calling function, `None` for the entrypoint.
+    pub call_site: Option<L>,
+}
+
+impl fmt::Display for InterpretErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpretErrorKind::OutOfFuel { limit } => {
+                write!(f, "Out of fuel after {} steps", limit)
+            }
+            InterpretErrorKind::MaxDepthExceeded { limit } => {
+                write!(f, "Maximum recursion depth of {} exceeded", limit)
+            }
+            InterpretErrorKind::Timeout { limit } => {
+                write!(f, "Timed out after {:?}", limit)
+            }
+            InterpretErrorKind::UnknownFunction { name } => {
+                write!(f, "Unknown function `{}`", name.0)
+            }
+            InterpretErrorKind::WrongArity { expected, actual } => {
+                write!(f, "Expected {} arguments, got {}", expected, actual)
+            }
+            InterpretErrorKind::MissingReturn => {
+                write!(f, "Function finished without returning a value")
+            }
+            InterpretErrorKind::InvariantViolated { iteration, locals } => {
+                write!(f, "Invariant violated")?;
+                if let Some(iteration) = iteration {
+                    write!(f, " in iteration {}", iteration)?;
+                }
+                for (ix, (name, value)) in locals.iter().enumerate() {
+                    let sep = if ix == 0 { ", with" } else { "," };
+                    write!(f, "{} {} = {}", sep, name.0, value)?;
+                }
+                Ok(())
+            }
+            InterpretErrorKind::DivisionByZero => write!(f, "Division by zero"),
+            InterpretErrorKind::TypeMismatch { expected, actual } => write!(
+                f,
+                "Expected a {}, got the {} `{}`",
+                expected,
+                actual.type_name(),
+                actual
+            ),
+            InterpretErrorKind::InvalidSlice { start, end, length } => write!(
+                f,
+                "Can not slice from {} to {} a string of length {}",
+                start, end, length
+            ),
+            InterpretErrorKind::IndexOutOfBounds { index, length } => write!(
+                f,
+                "Index {} is out of bounds for an array of length {}",
+                index, length
+            ),
+            InterpretErrorKind::MissingField { field, actual } => write!(
+                f,
+                "The {} `{}` has no field `{}`",
+                actual.type_name(),
+                actual,
+                field.0
+            ),
+            InterpretErrorKind::NoMatchingArm { actual } => {
+                write!(f, "No arm matches the {} `{}`", actual.type_name(), actual)
+            }
+        }
+    }
+}
@@ -0,0 +1,1252 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use num_bigint::{BigInt, Sign};
+
+use super::coverage::{Branch, Coverage, Edge};
+use super::error::{InterpretError, InterpretErrorKind, StackFrame};
+use super::KediValue;
+use crate::binder::bound::{DefId, Prim};
+use crate::parser::syntax;
+use crate::renamer::plain;
+use crate::renamer::LENGTH;
+use crate::simplifier::simple;
+use crate::util::loc::{Tag, Tagged};
+
+/// Nested calls are interpreted on the host stack, so recursion is always
+/// bounded, by this many calls unless the caller asks otherwise.
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct InterpretOptions {
+    /// Maximum number of statements to execute.
+    pub fuel_limit: Option<u64>,
+    /// Maximum number of nested kedi calls. There is always one, deeper
+    /// recursion would overflow the host stack.
+    pub max_depth: usize,
+    /// Maximum wall-clock time to run for.
+    pub time_limit: Option<Duration>,
+    /// Evaluate `invariant` statements, failing on the first one that does
+    /// not hold.
+    pub check_invariants: bool,
+}
+
+impl Default for InterpretOptions {
+    fn default() -> Self {
+        InterpretOptions {
+            fuel_limit: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            time_limit: None,
+            check_invariants: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InterpretSuccess {
+    pub value: KediValue,
+    pub fuel_used: u64,
+}
+
+pub type InterpretResult<LocTy> = Result<InterpretSuccess, InterpretError<LocTy>>;
+
+pub fn run<LocTy: Clone>(
+    options: InterpretOptions,
+    module: &simple::Module<LocTy>,
+    fun_name: &str,
+    args: Vec<KediValue>,
+) -> InterpretResult<LocTy> {
+    let name = syntax::Ident(fun_name.to_string());
+    match module.lookup(&name) {
+        Some(id) => run_def(options, module, id, args),
+        None => Err(InterpretError {
+            kind: InterpretErrorKind::UnknownFunction { name },
+            loc: None,
+            stack: vec![],
+            fuel_used: 0,
+        }),
+    }
+}
+
+pub fn run_def<LocTy: Clone>(
+    options: InterpretOptions,
+    module: &simple::Module<LocTy>,
+    id: DefId,
+    args: Vec<KediValue>,
+) -> InterpretResult<LocTy> {
+    let mut env = InterpretEnv::new(options, module);
+
+    let expected = module.get(id).arity();
+    if args.len() != expected {
+        return Err(env.error(
+            InterpretErrorKind::WrongArity {
+                expected,
+                actual: args.len(),
+            },
+            None,
+        ));
+    }
+
+    let value = env.call(id, args, None)?;
+    Ok(InterpretSuccess {
+        value,
+        fuel_used: env.fuel_used,
+    })
+}
+
+/// Like `run_def`, also returning the branches taken, even when the run fails.
+pub fn run_def_with_coverage<LocTy: Clone>(
+    options: InterpretOptions,
+    module: &simple::Module<LocTy>,
+    id: DefId,
+    args: Vec<KediValue>,
+) -> (InterpretResult<LocTy>, Coverage) {
+    let mut env = InterpretEnv::new(options, module);
+    env.coverage = Some(Coverage::new());
+
+    let result = env.call(id, args, None).map(|value| InterpretSuccess {
+        value,
+        fuel_used: env.fuel_used,
+    });
+    (result, env.coverage.unwrap_or_default())
+}
+
+/// Evaluates the predicate `pred` of function `id` on `args`, with `result`
+/// bound to the return value for result predicates. The value is returned as
+/// is, callers decide what a predicate that is not a boolean means.
+pub fn eval_pred<LocTy: Clone>(
+    options: InterpretOptions,
+    module: &simple::Module<LocTy>,
+    id: DefId,
+    pred: usize,
+    args: &[KediValue],
+    result: Option<&KediValue>,
+) -> InterpretResult<LocTy> {
+    let mut env = InterpretEnv::new(options, module);
+
+    let fun = match module.get(id) {
+        simple::Def::Fun(fun) => &fun.v,
+        simple::Def::Prim(_)
+        | simple::Def::Struct(_)
+        | simple::Def::StructPred(_)
+        | simple::Def::TypePred(_) => panic!("only functions have predicates"),
+    };
+
+    env.stack.push(StackFrame {
+        function: fun.name.v.clone(),
+        call_site: None,
+    });
+
+    let mut st = FuncState::new(&mut env, id, fun);
+    st.bind_params(args.iter().cloned());
+    if let Some(result) = result {
+        st.locals
+            .insert(fun.implementation.result.v, result.clone());
+    }
+
+    let pred = &fun.implementation.preds[pred];
+    match st.interpret_stmts(&pred.body.v, pred.body.tag())? {
+        Flow::Next => {}
+        _ => unreachable!("predicate bodies only compute their condition"),
+    }
+    let value = st.resolve(&pred.condition).clone();
+
+    Ok(InterpretSuccess {
+        value,
+        fuel_used: env.fuel_used,
+    })
+}
+
+struct InterpretEnv<'t, LocTy> {
+    module: &'t simple::Module<LocTy>,
+    options: InterpretOptions,
+    deadline: Option<Instant>,
+    fuel_used: u64,
+    stack: Vec<StackFrame<LocTy>>,
+    /// Branches taken so far, `None` when coverage is not recorded.
+    coverage: Option<Coverage>,
+}
+
+impl<'t, LocTy: Clone> InterpretEnv<'t, LocTy> {
+    fn new(options: InterpretOptions, module: &'t simple::Module<LocTy>) -> Self {
+        InterpretEnv {
+            module,
+            deadline: options.time_limit.map(|limit| Instant::now() + limit),
+            options,
+            fuel_used: 0,
+            stack: vec![],
+            coverage: None,
+        }
+    }
+
+    fn error(&self, kind: InterpretErrorKind, loc: Option<LocTy>) -> InterpretError<LocTy> {
+        InterpretError {
+            kind,
+            loc,
+            stack: self.stack.clone(),
+            fuel_used: self.fuel_used,
+        }
+    }
+
+    /// Charges a single step, failing if one of the limits is reached. A step
+    /// that runs out of fuel is not counted, so `fuel_used` never exceeds
+    /// the limit.
+    fn tick(&mut self) -> Result<(), InterpretErrorKind> {
+        if let Some(limit) = self.options.fuel_limit {
+            if self.fuel_used >= limit {
+                return Err(InterpretErrorKind::OutOfFuel { limit });
+            }
+        }
+        self.fuel_used += 1;
+        if let (Some(deadline), Some(limit)) = (self.deadline, self.options.time_limit) {
+            if Instant::now() >= deadline {
+                return Err(InterpretErrorKind::Timeout { limit });
+            }
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        id: DefId,
+        args: Vec<KediValue>,
+        call_site: Option<LocTy>,
+    ) -> Result<KediValue, InterpretError<LocTy>> {
+        let fun = match self.module.get(id) {
+            simple::Def::Fun(fun) => &fun.v,
+            simple::Def::Prim(prim) => {
+                return apply_prim(*prim, &args).map_err(|kind| self.error(kind, call_site))
+            }
+            simple::Def::Struct(def) => {
+                let fields = def.fields.v.iter().map(|field| field.v.clone()).collect();
+                return Ok(KediValue::struct_(id, def.name.v.clone(), fields, args));
+            }
+            simple::Def::StructPred(pred) => {
+                return Ok(KediValue::bool(matches!(
+                    &args[0],
+                    KediValue::KediStruct(s) if s.def == pred.struct_id
+                )))
+            }
+            simple::Def::TypePred(pred) => {
+                return Ok(KediValue::bool(matches!(
+                    &args[0],
+                    KediValue::KediStruct(s) if pred.variants.contains(&s.def)
+                )))
+            }
+        };
+
+        let limit = self.options.max_depth;
+        if self.stack.len() >= limit {
+            return Err(self.error(InterpretErrorKind::MaxDepthExceeded { limit }, call_site));
+        }
+
+        self.stack.push(StackFrame {
+            function: fun.name.v.clone(),
+            call_site,
+        });
+
+        let mut st = FuncState::new(self, id, fun);
+        st.bind_params(args);
+
+        let body = &fun.implementation.body;
+        match st.interpret_stmts(&body.v, body.tag())? {
+            Flow::Return(value) => {
+                self.stack.pop();
+                Ok(value)
+            }
+            Flow::Next | Flow::Break | Flow::Continue => {
+                let loc = fun.tag_map.resolve_tag(body.tag()).cloned();
+                Err(self.error(InterpretErrorKind::MissingReturn, loc))
+            }
+        }
+    }
+}
+
+fn apply_prim(prim: Prim, args: &[KediValue]) -> Result<KediValue, InterpretErrorKind> {
+    match prim {
+        Prim::IsNumber => return Ok(KediValue::bool(matches!(args[0], KediValue::KediNum(_)))),
+        Prim::IsBool => return Ok(KediValue::bool(matches!(args[0], KediValue::KediBool(_)))),
+        Prim::IsString => return Ok(KediValue::bool(matches!(args[0], KediValue::KediStr(_)))),
+        Prim::Eq => return Ok(KediValue::bool(args[0] == args[1])),
+        Prim::Concat => {
+            let (l, r) = (as_str(&args[0])?, as_str(&args[1])?);
+            return Ok(KediValue::str(format!("{}{}", l, r)));
+        }
+        Prim::IsArray => return Ok(KediValue::bool(matches!(args[0], KediValue::KediArray(_)))),
+        Prim::Length => {
+            return match &args[0] {
+                KediValue::KediStr(s) => Ok(KediValue::num(s.chars().count())),
+                KediValue::KediArray(items) => Ok(KediValue::num(items.borrow().len())),
+                value => Err(InterpretErrorKind::TypeMismatch {
+                    expected: "string or array",
+                    actual: value.clone(),
+                }),
+            }
+        }
+        Prim::Push => {
+            as_array(&args[0])?.borrow_mut().push(args[1].clone());
+            return Ok(args[0].clone());
+        }
+        Prim::Slice => return slice(as_str(&args[0])?, as_num(&args[1])?, as_num(&args[2])?),
+        Prim::Compare => {
+            let (l, r) = (as_str(&args[0])?, as_str(&args[1])?);
+            // Comparing UTF-8 bytes orders by characters, like wasm does.
+            return Ok(KediValue::num(l.cmp(r) as i8));
+        }
+        _ => {}
+    }
+
+    let (l, r) = (as_num(&args[0])?, as_num(&args[1])?);
+    Ok(match prim {
+        Prim::Gt => KediValue::bool(l > r),
+        Prim::Lt => KediValue::bool(l < r),
+        Prim::Gte => KediValue::bool(l >= r),
+        Prim::Lte => KediValue::bool(l <= r),
+        Prim::Add => KediValue::KediNum(l + r),
+        Prim::Sub => KediValue::KediNum(l - r),
+        Prim::Mul => KediValue::KediNum(l * r),
+        Prim::Div | Prim::Mod if r.sign() == Sign::NoSign => {
+            return Err(InterpretErrorKind::DivisionByZero)
+        }
+        // Both round towards zero, like wasm does.
+        Prim::Div => KediValue::KediNum(l / r),
+        Prim::Mod => KediValue::KediNum(l % r),
+        Prim::IsNumber
+        | Prim::IsBool
+        | Prim::IsString
+        | Prim::Eq
+        | Prim::Concat
+        | Prim::Length
+        | Prim::Slice
+        | Prim::Compare
+        | Prim::IsArray
+        | Prim::Push => unreachable!("handled above"),
+    })
+}
+
+/// The characters of `s` from `start` up to `end`.
+fn slice(s: &str, start: &BigInt, end: &BigInt) -> Result<KediValue, InterpretErrorKind> {
+    let length = s.chars().count();
+    let index = |ix: &BigInt| usize::try_from(ix).ok().filter(|ix| *ix <= length);
+    match (index(start), index(end)) {
+        (Some(from), Some(to)) if from <= to => Ok(KediValue::str(
+            s.chars().skip(from).take(to - from).collect::<String>(),
+        )),
+        _ => Err(InterpretErrorKind::InvalidSlice {
+            start: Box::new(start.clone()),
+            end: Box::new(end.clone()),
+            length,
+        }),
+    }
+}
+
+fn as_num(value: &KediValue) -> Result<&BigInt, InterpretErrorKind> {
+    match value {
+        KediValue::KediNum(n) => Ok(n),
+        value => Err(InterpretErrorKind::TypeMismatch {
+            expected: "number",
+            actual: value.clone(),
+        }),
+    }
+}
+
+fn as_array(value: &KediValue) -> Result<&RefCell<Vec<KediValue>>, InterpretErrorKind> {
+    match value {
+        KediValue::KediArray(items) => Ok(items),
+        value => Err(InterpretErrorKind::TypeMismatch {
+            expected: "array",
+            actual: value.clone(),
+        }),
+    }
+}
+
+fn as_str(value: &KediValue) -> Result<&str, InterpretErrorKind> {
+    match value {
+        KediValue::KediStr(s) => Ok(s),
+        value => Err(InterpretErrorKind::TypeMismatch {
+            expected: "string",
+            actual: value.clone(),
+        }),
+    }
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(KediValue),
+}
+
+type StmtResult<LocTy> = Result<Flow, InterpretError<LocTy>>;
+
+struct FuncState<'e, 't, LocTy> {
+    env: &'e mut InterpretEnv<'t, LocTy>,
+    id: DefId,
+    fun: &'t simple::FunDecl<LocTy>,
+    locals: HashMap<plain::LocalIdent, KediValue>,
+    single_use: HashMap<simple::SingleUseIdent, KediValue>,
+    /// The iterations done by each loop being run, the innermost last.
+    iterations: Vec<u64>,
+}
+
+impl<'e, 't, LocTy: Clone> FuncState<'e, 't, LocTy> {
+    fn new(
+        env: &'e mut InterpretEnv<'t, LocTy>,
+        id: DefId,
+        fun: &'t simple::FunDecl<LocTy>,
+    ) -> Self {
+        FuncState {
+            env,
+            id,
+            fun,
+            locals: HashMap::new(),
+            single_use: HashMap::new(),
+            iterations: vec![],
+        }
+    }
+
+    fn bind_params(&mut self, args: impl IntoIterator<Item = KediValue>) {
+        for (param, arg) in self.fun.implementation.parameters.v.iter().zip(args) {
+            self.locals.insert(param.v, arg);
+        }
+    }
+
+    fn loc(&self, tag: Tag) -> Option<LocTy> {
+        self.fun.tag_map.resolve_tag(tag).cloned()
+    }
+
+    fn error(&self, kind: InterpretErrorKind, tag: Tag) -> InterpretError<LocTy> {
+        self.env.error(kind, self.loc(tag))
+    }
+
+    fn record(&mut self, tag: Tag, edge: Edge) {
+        if let Some(coverage) = &mut self.env.coverage {
+            coverage.record(Branch {
+                fun: self.id,
+                tag,
+                edge,
+            });
+        }
+    }
+
+    fn resolve(&self, ident: &simple::Ident) -> &KediValue {
+        match ident {
+            simple::Ident::SingleUse(i) => self.single_use.get(&i.v),
+            simple::Ident::Local(i) => self.locals.get(&i.v),
+        }
+        .expect("identifiers are assigned before they are used")
+    }
+
+    /// The values of the variables of the function, in the order they are
+    /// declared in.
+    fn named_locals(&self) -> Vec<(syntax::Ident, KediValue)> {
+        let names = &self.fun.implementation.local_names;
+        let mut locals = self
+            .locals
+            .iter()
+            .filter_map(|(id, value)| {
+                let name = names.get(id.id as usize)?.clone()?;
+                Some((id.id, name, value.clone()))
+            })
+            .collect::<Vec<_>>();
+        locals.sort_by_key(|(id, _, _)| *id);
+        locals
+            .into_iter()
+            .map(|(_, name, value)| (name, value))
+            .collect()
+    }
+
+    /// The value of a condition, which has to be a boolean.
+    fn condition(&self, ident: &simple::Ident, tag: Tag) -> Result<bool, InterpretError<LocTy>> {
+        match self.resolve(ident) {
+            KediValue::KediBool(b) => Ok(*b),
+            value => Err(self.error(
+                InterpretErrorKind::TypeMismatch {
+                    expected: "boolean",
+                    actual: value.clone(),
+                },
+                tag,
+            )),
+        }
+    }
+
+    /// The array `index` points into and the position in it, failing at `tag`
+    /// when it is out of bounds.
+    fn element(
+        &self,
+        index: &simple::Index,
+        tag: Tag,
+    ) -> Result<(&RefCell<Vec<KediValue>>, usize), InterpretError<LocTy>> {
+        let find = || {
+            let items = as_array(self.resolve(&index.array))?;
+            let ix = as_num(self.resolve(&index.index))?;
+            let length = items.borrow().len();
+            match usize::try_from(ix) {
+                Ok(ix) if ix < length => Ok((items, ix)),
+                _ => Err(InterpretErrorKind::IndexOutOfBounds {
+                    index: Box::new(ix.clone()),
+                    length,
+                }),
+            }
+        };
+        find().map_err(|kind| self.error(kind, tag))
+    }
+
+    /// The value of the field `field` points at, failing at `tag` when there
+    /// is no such field.
+    fn field(&self, field: &simple::Field, tag: Tag) -> Result<KediValue, InterpretError<LocTy>> {
+        let value = self.resolve(&field.value);
+        if let KediValue::KediStruct(s) = value {
+            if let Some(ix) = s.position(&field.name) {
+                return Ok(s.values.borrow()[ix].clone());
+            }
+        }
+        let result = match value {
+            KediValue::KediStr(_) | KediValue::KediArray(_) if field.name.0 == LENGTH => {
+                apply_prim(Prim::Length, std::slice::from_ref(value))
+            }
+            _ => Err(InterpretErrorKind::MissingField {
+                field: field.name.clone(),
+                actual: value.clone(),
+            }),
+        };
+        result.map_err(|kind| self.error(kind, tag))
+    }
+
+    fn set_field(&self, set: &simple::SetField, tag: Tag) -> Result<(), InterpretError<LocTy>> {
+        let target = self.resolve(&set.target.value);
+        if let KediValue::KediStruct(s) = target {
+            if let Some(ix) = s.position(&set.target.name) {
+                s.values.borrow_mut()[ix] = self.resolve(&set.value).clone();
+                return Ok(());
+            }
+        }
+        Err(self.error(
+            InterpretErrorKind::MissingField {
+                field: set.target.name.clone(),
+                actual: target.clone(),
+            },
+            tag,
+        ))
+    }
+
+    /// Calls the function value `call.fun` holds, with its captures before
+    /// the arguments.
+    fn call_ref(
+        &mut self,
+        call: &simple::CallRef,
+        tag: Tag,
+    ) -> Result<KediValue, InterpretError<LocTy>> {
+        let fun = match self.resolve(&call.fun) {
+            KediValue::KediFun(fun) => fun.clone(),
+            value => {
+                return Err(self.error(
+                    InterpretErrorKind::TypeMismatch {
+                        expected: "function",
+                        actual: value.clone(),
+                    },
+                    tag,
+                ))
+            }
+        };
+
+        let expected = self.env.module.get(fun.def).arity() - fun.captures.len();
+        if call.arguments.v.len() != expected {
+            return Err(self.error(
+                InterpretErrorKind::WrongArity {
+                    expected,
+                    actual: call.arguments.v.len(),
+                },
+                tag,
+            ));
+        }
+
+        let args = fun
+            .captures
+            .iter()
+            .cloned()
+            .chain(call.arguments.v.iter().map(|x| self.resolve(x).clone()))
+            .collect();
+        let call_site = self.loc(tag);
+        self.env.call(fun.def, args, call_site)
+    }
+
+    fn assign(&mut self, target: &simple::Ident, value: KediValue) {
+        match target {
+            simple::Ident::SingleUse(i) => {
+                self.single_use.insert(i.v, value);
+            }
+            simple::Ident::Local(i) => {
+                self.locals.insert(i.v, value);
+            }
+        }
+    }
+
+    /// Runs `stmts`, `tag` is the location blamed for statements that do
+    /// not carry their own.
+    fn interpret_stmts(&mut self, stmts: &[simple::FunStmt], tag: Tag) -> StmtResult<LocTy> {
+        for stmt in stmts {
+            match self.interpret_stmt(stmt, tag)? {
+                Flow::Next => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn interpret_stmt(&mut self, stmt: &simple::FunStmt, enclosing: Tag) -> StmtResult<LocTy> {
+        let tag = stmt_tag(stmt).unwrap_or(enclosing);
+        if let Err(kind) = self.env.tick() {
+            return Err(self.error(kind, tag));
+        }
+
+        match stmt {
+            simple::FunStmt::Return(ident) => Ok(Flow::Return(self.resolve(ident).clone())),
+            simple::FunStmt::Assignment(assignment) => {
+                let value = match &assignment.v.value {
+                    simple::AssignmentValue::LitNum(lit) => KediValue::num(lit.v.0),
+                    simple::AssignmentValue::LitBool(lit) => KediValue::bool(lit.v.0),
+                    simple::AssignmentValue::LitStr(lit) => KediValue::str(lit.v.0.clone()),
+                    simple::AssignmentValue::Ident(ident) => self.resolve(ident).clone(),
+                    simple::AssignmentValue::Array(items) => {
+                        KediValue::array(items.v.iter().map(|x| self.resolve(x).clone()).collect())
+                    }
+                    simple::AssignmentValue::Index(index) => {
+                        let (items, ix) = self.element(&index.v, index.tag())?;
+                        let item = items.borrow()[ix].clone();
+                        item
+                    }
+                    simple::AssignmentValue::Field(field) => self.field(&field.v, field.tag())?,
+                    simple::AssignmentValue::Call(call) => {
+                        let args = call
+                            .v
+                            .arguments
+                            .v
+                            .iter()
+                            .map(|x| self.resolve(x).clone())
+                            .collect();
+                        let call_site = self.loc(call.tag());
+                        self.env.call(call.v.fun_name.v, args, call_site)?
+                    }
+                    simple::AssignmentValue::CallRef(call) => self.call_ref(&call.v, call.tag())?,
+                    simple::AssignmentValue::Closure(closure) => {
+                        let captures = closure
+                            .v
+                            .captures
+                            .iter()
+                            .map(|x| self.resolve(x).clone())
+                            .collect();
+                        let name = self.env.module.get(closure.v.fun).name();
+                        KediValue::fun(closure.v.fun, name, captures)
+                    }
+                };
+                self.assign(&assignment.v.target, value);
+                Ok(Flow::Next)
+            }
+            simple::FunStmt::SetIndex(set) => {
+                let (items, ix) = self.element(&set.v.target, set.tag())?;
+                items.borrow_mut()[ix] = self.resolve(&set.v.value).clone();
+                Ok(Flow::Next)
+            }
+            simple::FunStmt::SetField(set) => {
+                self.set_field(&set.v, set.tag())?;
+                Ok(Flow::Next)
+            }
+            simple::FunStmt::If(if_) => {
+                if self.condition(&if_.condition, tag)? {
+                    self.record(tag, Edge::Then);
+                    self.interpret_stmts(&if_.then.v, if_.then.tag())
+                } else {
+                    self.record(tag, Edge::Else);
+                    match &if_.else_ {
+                        Some(else_) => self.interpret_stmts(&else_.v, else_.tag()),
+                        None => Ok(Flow::Next),
+                    }
+                }
+            }
+            simple::FunStmt::Loop(loop_) => {
+                self.iterations.push(0);
+                loop {
+                    let flow = self.interpret_stmts(&loop_.v.body.v, loop_.v.body.tag())?;
+                    if let Flow::Next | Flow::Continue = flow {
+                        *self.iterations.last_mut().unwrap() += 1;
+                        continue;
+                    }
+
+                    let iterations = self.iterations.pop().unwrap();
+                    self.record(tag, Edge::iterations(iterations));
+                    return match flow {
+                        Flow::Break => Ok(Flow::Next),
+                        flow => Ok(flow),
+                    };
+                }
+            }
+            simple::FunStmt::Break() => Ok(Flow::Break),
+            simple::FunStmt::Continue() => Ok(Flow::Continue),
+            simple::FunStmt::Inv(inv) => {
+                if !self.env.options.check_invariants {
+                    return Ok(Flow::Next);
+                }
+                match self.interpret_stmts(&inv.v.body, inv.tag())? {
+                    Flow::Next => {}
+                    _ => unreachable!("invariant bodies only compute their condition"),
+                }
+                if self.condition(&inv.v.condition, inv.tag())? {
+                    return Ok(Flow::Next);
+                }
+                let kind = InterpretErrorKind::InvariantViolated {
+                    iteration: self.iterations.last().map(|done| done + 1),
+                    locals: self.named_locals(),
+                };
+                Err(self.error(kind, inv.tag()))
+            }
+            simple::FunStmt::NoMatch(value) => {
+                let actual = self.resolve(&value.v).clone();
+                Err(self.error(InterpretErrorKind::NoMatchingArm { actual }, tag))
+            }
+            simple::FunStmt::Nop => Ok(Flow::Next),
+        }
+    }
+}
+
+fn stmt_tag(stmt: &simple::FunStmt) -> Option<Tag> {
+    match stmt {
+        simple::FunStmt::Loop(l) => Some(l.tag()),
+        simple::FunStmt::Assignment(a) => Some(a.tag()),
+        simple::FunStmt::SetIndex(s) => Some(s.tag()),
+        simple::FunStmt::SetField(s) => Some(s.tag()),
+        simple::FunStmt::Return(i) => Some(i.tag()),
+        simple::FunStmt::If(i) => Some(i.condition.tag()),
+        simple::FunStmt::Inv(i) => Some(i.tag()),
+        simple::FunStmt::NoMatch(m) => Some(m.tag()),
+        simple::FunStmt::Break() | simple::FunStmt::Continue() | simple::FunStmt::Nop => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::runner::runner;
+    use crate::util::loc::{Span, SrcLoc};
+
+    const FIBONACCI: &str = "
+        fn fibonacci nth {
+            let ret = 0;
+            let prev = 1;
+
+            let i = 1;
+            while lte? i nth {
+                let temp = ret;
+                ret = add ret prev;
+                prev = temp;
+                i = add i 1;
+            }
+
+            return ret;
+        }";
+
+    fn interpret(
+        src: &str,
+        options: InterpretOptions,
+        fun_name: &str,
+        args: Vec<KediValue>,
+    ) -> InterpretResult<SrcLoc> {
+        let out = runner(src).unwrap();
+        run(options, &out.simple, fun_name, args)
+    }
+
+    fn span_of(src: &str, needle: &str) -> SrcLoc {
+        let start = src.rfind(needle).unwrap();
+        SrcLoc::Known(Span::from_offset_len(start, needle.len()))
+    }
+
+    #[test]
+    fn fibonacci() {
+        for (nth, expected) in [
+            (0, 0),
+            (1, 1),
+            (2, 1),
+            (3, 2),
+            (4, 3),
+            (5, 5),
+            (6, 8),
+            (7, 13),
+        ] {
+            let result = interpret(
+                FIBONACCI,
+                InterpretOptions::default(),
+                "fibonacci",
+                vec![nth.into()],
+            );
+            assert_eq!(result.unwrap().value, expected.into());
+        }
+    }
+
+    #[test]
+    fn out_of_fuel_reports_the_call_stack() {
+        let src = "
+            fn spin n { while true { } return n; }
+            fn main { let x = spin 1; return x; }";
+        let options = InterpretOptions {
+            fuel_limit: Some(100),
+            ..Default::default()
+        };
+
+        let err = interpret(src, options, "main", vec![]).unwrap_err();
+        assert_eq!(err.kind, InterpretErrorKind::OutOfFuel { limit: 100 });
+        assert_eq!(err.fuel_used, 100);
+        assert!(matches!(err.loc, Some(SrcLoc::Known(_))));
+
+        let names = err
+            .stack
+            .iter()
+            .map(|f| f.function.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["main", "spin"]);
+        assert_eq!(err.stack[0].call_site, None);
+        assert_eq!(err.stack[1].call_site, Some(span_of(src, "spin 1")));
+    }
+
+    #[test]
+    fn max_depth() {
+        let src = "fn down n { let x = down n; return x; }";
+        let options = InterpretOptions {
+            max_depth: 10,
+            ..Default::default()
+        };
+
+        let err = interpret(src, options, "down", vec![1.into()]).unwrap_err();
+        assert_eq!(err.kind, InterpretErrorKind::MaxDepthExceeded { limit: 10 });
+        assert_eq!(err.stack.len(), 10);
+        assert_eq!(err.loc, Some(span_of(src, "down n")));
+    }
+
+    #[test]
+    fn timeout() {
+        let src = "fn spin { while true { } return 1; }";
+        let options = InterpretOptions {
+            time_limit: Some(Duration::from_millis(10)),
+            ..Default::default()
+        };
+
+        let err = interpret(src, options, "spin", vec![]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::Timeout {
+                limit: Duration::from_millis(10)
+            }
+        );
+    }
+
+    #[test]
+    fn coverage_records_branches() {
+        let src = "
+            fn f n {
+                if gt? n 0 { return 1; }
+                let i = 0;
+                while lt? i 5 { i = add i 1; }
+                return 0;
+            }";
+        let out = runner(src).unwrap();
+        let id = out.simple.lookup(&syntax::Ident("f".to_string())).unwrap();
+
+        let (result, coverage) =
+            run_def_with_coverage(InterpretOptions::default(), &out.simple, id, vec![1.into()]);
+        assert_eq!(result.unwrap().value, 1.into());
+        let edges = coverage.iter().map(|b| b.edge).collect::<Vec<_>>();
+        assert_eq!(edges, vec![Edge::Then]);
+
+        let (result, coverage) =
+            run_def_with_coverage(InterpretOptions::default(), &out.simple, id, vec![0.into()]);
+        assert_eq!(result.unwrap().value, 0.into());
+        let edges = coverage.iter().map(|b| b.edge).collect::<HashSet<_>>();
+        assert_eq!(
+            edges,
+            HashSet::from([Edge::Then, Edge::Else, Edge::iterations(5)])
+        );
+        assert_eq!(Edge::iterations(5), Edge::Iterations(3));
+    }
+
+    #[test]
+    fn operators() {
+        let src = "fn f a b { return a + b * 2 - a / b % 3; }
+            fn g a b { return a < b and b <= 10 or a == 0; }";
+        let run = |f, a: i32, b: i32| {
+            interpret(
+                src,
+                InterpretOptions::default(),
+                f,
+                vec![a.into(), b.into()],
+            )
+            .map(|result| result.value)
+        };
+        assert_eq!(run("f", 9, 2), Ok(12.into()));
+        assert_eq!(run("f", -9, 2), Ok((-4).into()));
+        assert_eq!(run("g", 1, 10), Ok(KediValue::bool(true)));
+        assert_eq!(run("g", 1, 11), Ok(KediValue::bool(false)));
+        assert_eq!(run("g", 0, -1), Ok(KediValue::bool(true)));
+
+        let err = run("f", 1, 0).unwrap_err();
+        assert_eq!(err.kind, InterpretErrorKind::DivisionByZero);
+        assert_eq!(err.stack.last().unwrap().function.0, "div");
+    }
+
+    #[test]
+    fn booleans_short_circuit() {
+        let src = "fn f x { return x == 0 or 10 / x > 1 and not bool? x; }
+            fn g x { if x { return 1; } return 0; }";
+        let run = |f, x: i32| {
+            interpret(src, InterpretOptions::default(), f, vec![x.into()]).map(|r| r.value)
+        };
+        // The division is never evaluated with a zero divisor.
+        assert_eq!(run("f", 0), Ok(KediValue::bool(true)));
+        assert_eq!(run("f", 5), Ok(KediValue::bool(true)));
+        assert_eq!(run("f", 20), Ok(KediValue::bool(false)));
+
+        let err = run("g", 1).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::TypeMismatch {
+                expected: "boolean",
+                actual: 1.into()
+            }
+        );
+        assert_eq!(err.loc, Some(span_of(src, "x")));
+        assert_eq!(
+            err.kind.to_string(),
+            "Expected a boolean, got the number `1`"
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let src = r#"fn greet name { let s = slice name 0 3; let r = concat "hi, " s; return concat r "\n"; }
+            fn f a b { return length a + compare a b; }
+            fn g a b { return a == b and string? a; }"#;
+        let run = |f, args: Vec<KediValue>| {
+            interpret(src, InterpretOptions::default(), f, args).map(|r| r.value)
+        };
+        assert_eq!(
+            run("greet", vec![KediValue::str("bobby")]),
+            Ok(KediValue::str("hi, bob\n"))
+        );
+        assert_eq!(KediValue::str("a\"b").to_string(), r#""a\"b""#);
+        // Lengths count characters, not bytes.
+        let (a, b) = (KediValue::str("çay"), KediValue::str("cay"));
+        assert_eq!(run("f", vec![a.clone(), b.clone()]), Ok(4.into()));
+        assert_eq!(run("f", vec![b.clone(), a.clone()]), Ok(2.into()));
+        assert_eq!(run("g", vec![a.clone(), b]), Ok(KediValue::bool(false)));
+        assert_eq!(run("g", vec![a.clone(), a]), Ok(KediValue::bool(true)));
+
+        let err = run("greet", vec![KediValue::str("al")]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::InvalidSlice {
+                start: Box::new(0.into()),
+                end: Box::new(3.into()),
+                length: 2
+            }
+        );
+        let frame = err.stack.last().unwrap();
+        assert_eq!(frame.function.0, "slice");
+        assert_eq!(frame.call_site, Some(span_of(src, "slice name 0 3")));
+    }
+
+    #[test]
+    fn arrays() {
+        let src = "fn squares n {
+                let a = [];
+                let i = 0;
+                while i < n { a = push a (i * i); i = i + 1; }
+                return a;
+            }
+            fn swap a i j { let x = a[i]; a[i] = a[j]; a[j] = x; return a; }
+            fn shared a { let b = a; b[0] = [b.length]; return a == b and array? a; }
+            fn get a k { return a[k]; }";
+        let run = |f, args: Vec<KediValue>| {
+            interpret(src, InterpretOptions::default(), f, args).map(|r| r.value)
+        };
+        let nums = |ns: &[i32]| KediValue::array(ns.iter().map(|n| (*n).into()).collect());
+        assert_eq!(run("squares", vec![4.into()]), Ok(nums(&[0, 1, 4, 9])));
+        assert_eq!(
+            run("swap", vec![nums(&[1, 2, 3]), 0.into(), 2.into()]),
+            Ok(nums(&[3, 2, 1]))
+        );
+        assert_eq!(run("shared", vec![nums(&[1])]), Ok(KediValue::bool(true)));
+        assert_eq!(nums(&[1, 2]).to_string(), "[1, 2]");
+
+        let err = run("get", vec![nums(&[1]), (-1).into()]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::IndexOutOfBounds {
+                index: Box::new((-1).into()),
+                length: 1
+            }
+        );
+        assert_eq!(err.loc, Some(span_of(src, "a[k]")));
+        // Assignments point at the indexing expression too.
+        let err = run("shared", vec![nums(&[])]).unwrap_err();
+        assert_eq!(err.loc, Some(span_of(src, "b[0]")));
+    }
+
+    #[test]
+    fn cyclic_values() {
+        let src = "struct Node { next }
+            fn array n { let a = [n]; a[0] = a; return a; }
+            fn node n { let x = Node n; x.next = x; return x; }
+            fn same n { return array n == array n and node n == node n; }";
+        let run = |f, args: Vec<KediValue>| {
+            interpret(src, InterpretOptions::default(), f, args).map(|r| r.value.to_string())
+        };
+        assert_eq!(run("array", vec![0.into()]), Ok("[[...]]".to_string()));
+        assert_eq!(
+            run("node", vec![0.into()]),
+            Ok("Node { next: Node { ... } }".to_string())
+        );
+        assert_eq!(run("same", vec![0.into()]), Ok("true".to_string()));
+    }
+
+    #[test]
+    fn structs() {
+        let src = "struct Point { x, y }
+            struct Pair { x, y }
+            fn mk x y { return Point x y; }
+            fn norm p { return p.x * p.x + p.y * p.y; }
+            fn moved p { let q = p; q.x = q.x + 1; return p; }
+            fn same a b { return Point? a and Point a.x a.y == b and Pair 1 2 != Point 1 2; }
+            fn len p { return p.length; }
+            fn h p { p.z = 1; return p; }
+            struct Other { z }";
+        let run = |f, args: Vec<KediValue>| {
+            interpret(src, InterpretOptions::default(), f, args).map(|r| r.value)
+        };
+        let p = run("mk", vec![3.into(), 4.into()]).unwrap();
+        assert_eq!(p.to_string(), "Point { x: 3, y: 4 }");
+        assert_eq!(run("norm", vec![p.clone()]), Ok(25.into()));
+        // Structs are shared like arrays.
+        assert_eq!(run("moved", vec![p.clone()]), Ok(p.clone()));
+        assert_eq!(p.to_string(), "Point { x: 4, y: 4 }");
+
+        let q = run("mk", vec![4.into(), 4.into()]).unwrap();
+        assert_eq!(run("same", vec![p.clone(), q]), Ok(KediValue::bool(true)));
+        assert_eq!(
+            run("same", vec![1.into(), p.clone()]),
+            Ok(KediValue::bool(false))
+        );
+
+        let err = run("len", vec![p.clone()]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::MissingField {
+                field: syntax::Ident("length".to_string()),
+                actual: p.clone()
+            }
+        );
+        assert_eq!(err.loc, Some(span_of(src, "p.length")));
+        assert_eq!(run("len", vec![KediValue::str("ab")]), Ok(2.into()));
+
+        let err = run("h", vec![p]).unwrap_err();
+        assert_eq!(err.loc, Some(span_of(src, "p.z")));
+        assert_eq!(
+            err.kind.to_string(),
+            "The struct `Point { x: 4, y: 4 }` has no field `z`"
+        );
+    }
+
+    #[test]
+    fn for_break_and_continue() {
+        let src = "fn odds n {
+                let a = [];
+                for i in range(0, n) {
+                    if i % 2 == 0 { continue; }
+                    i = i * 10;
+                    a = push a i;
+                }
+                return a;
+            }
+            fn first_square_over n {
+                let i = 0;
+                while true {
+                    i = i + 1;
+                    if i * i > n { break; }
+                }
+                return i;
+            }
+            fn empty { for i in range(3, 1) { return i; } return 0; }
+            fn bad x { for i in range(0, x) {} return 0; }";
+        let run = |f, args: Vec<KediValue>| {
+            interpret(src, InterpretOptions::default(), f, args).map(|r| r.value)
+        };
+        assert_eq!(
+            run("odds", vec![6.into()]),
+            Ok(KediValue::array(vec![10.into(), 30.into(), 50.into()]))
+        );
+        assert_eq!(run("first_square_over", vec![10.into()]), Ok(4.into()));
+        assert_eq!(run("empty", vec![]), Ok(0.into()));
+
+        let err = run("bad", vec![KediValue::str("x")]).unwrap_err();
+        assert!(matches!(err.kind, InterpretErrorKind::TypeMismatch { .. }));
+        // The comparison is called from the range.
+        assert_eq!(err.stack[1].function.0, "lt?");
+        assert_eq!(err.stack[1].call_site, Some(span_of(src, "range(0, x)")));
+    }
+
+    #[test]
+    fn closures() {
+        let src = "fn adder n { return |x| { return x + n; }; }
+            fn apply f x { return f x; }
+            fn squares n { return map (range 0 n) |i| { return i * i; }; }
+            fn numbers a { return all? a number?; }
+            fn call f { return f 1 2; }";
+        let run = |f, args: Vec<KediValue>| {
+            interpret(src, InterpretOptions::default(), f, args).map(|r| r.value)
+        };
+        let add2 = run("adder", vec![2.into()]).unwrap();
+        assert_eq!(add2.to_string(), "<fn adder/lambda0>");
+        assert_eq!(run("apply", vec![add2.clone(), 3.into()]), Ok(5.into()));
+        assert_eq!(
+            run("squares", vec![3.into()]),
+            Ok(KediValue::array(vec![0.into(), 1.into(), 4.into()]))
+        );
+        assert_eq!(
+            run(
+                "numbers",
+                vec![KediValue::array(vec![1.into(), KediValue::str("x")])]
+            ),
+            Ok(KediValue::bool(false))
+        );
+
+        let err = run("call", vec![add2]).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            InterpretErrorKind::WrongArity {
+                expected: 1,
+                actual: 2
+            }
+        ));
+        let err = run("apply", vec![1.into(), 1.into()]).unwrap_err();
+        assert_eq!(err.loc, Some(span_of(src, "f x")));
+    }
+
+    #[test]
+    fn sum_types() {
+        let src = "type Tree = Leaf | Node left value right;
+            fn insert t x {
+                match t {
+                    Leaf => { return Node Leaf x Leaf; }
+                    Node l v r => {
+                        if x < v { return Node (insert l x) v r; }
+                        return Node l v (insert r x);
+                    }
+                }
+            }
+            fn sum t { match t { Leaf => { return 0; } Node l v r => { return sum l + v + sum r; } } }
+            fn total a b c { return sum (insert (insert (insert Leaf a) b) c); }
+            fn tree? t { return Tree? t and not Leaf? t; }
+            fn depth t { match t { Node l v r => { return 1; } Leaf => { return 0; } } }";
+        let run = |f, args: Vec<KediValue>| {
+            interpret(src, InterpretOptions::default(), f, args).map(|r| r.value)
+        };
+        assert_eq!(
+            run("total", vec![3.into(), 1.into(), 2.into()]),
+            Ok(6.into())
+        );
+        assert_eq!(run("tree?", vec![1.into()]), Ok(KediValue::bool(false)));
+
+        let err = run("depth", vec![1.into()]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::NoMatchingArm { actual: 1.into() }
+        );
+        assert_eq!(
+            err.loc,
+            Some(span_of(
+                src,
+                "match t { Node l v r => { return 1; } Leaf => { return 0; } }"
+            ))
+        );
+    }
+
+    #[test]
+    fn missing_return() {
+        let err = interpret(
+            "fn f { let x = 1; }",
+            InterpretOptions::default(),
+            "f",
+            vec![],
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, InterpretErrorKind::MissingReturn);
+    }
+
+    #[test]
+    fn operators_can_not_be_hijacked() {
+        let src = "
+            fn add a b { return \"hijacked\"; }
+            fn lt? a b { return false; }
+            fn sum n {
+                let s = 0;
+                for i in range(0, n) { s = s + i; }
+                return s;
+            }";
+        let ret = interpret(src, InterpretOptions::default(), "sum", vec![4.into()]).unwrap();
+        assert_eq!(ret.value, 6.into());
+        let ret = interpret(
+            src,
+            InterpretOptions::default(),
+            "add",
+            vec![1.into(), 2.into()],
+        );
+        assert_eq!(ret.unwrap().value, KediValue::str("hijacked"));
+    }
+
+    #[test]
+    fn bad_entrypoint() {
+        let err = interpret(FIBONACCI, InterpretOptions::default(), "fib", vec![]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::UnknownFunction {
+                name: syntax::Ident("fib".to_string())
+            }
+        );
+
+        let err =
+            interpret(FIBONACCI, InterpretOptions::default(), "fibonacci", vec![]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::WrongArity {
+                expected: 1,
+                actual: 0
+            }
+        );
+    }
+
+    #[test]
+    fn invariants_are_only_checked_when_asked() {
+        let module = runner("fn f x { invariant gt? x 0; return x; }")
+            .unwrap()
+            .simple;
+
+        let unchecked = run(InterpretOptions::default(), &module, "f", vec![0.into()]);
+        assert_eq!(unchecked.unwrap().value, 0.into());
+
+        let checked = InterpretOptions {
+            check_invariants: true,
+            ..Default::default()
+        };
+        let ok = run(checked.clone(), &module, "f", vec![1.into()]);
+        assert_eq!(ok.unwrap().value, 1.into());
+
+        let err = run(checked, &module, "f", vec![0.into()]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            InterpretErrorKind::InvariantViolated {
+                iteration: None,
+                locals: vec![(syntax::Ident("x".to_string()), 0.into())]
+            }
+        );
+    }
+}
@@ -0,0 +1,9 @@
+mod coverage;
+mod error;
+mod interpret;
+mod value;
+
+pub use coverage::*;
+pub use error::*;
+pub use interpret::*;
+pub use value::*;
@@ -0,0 +1,238 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::binder::bound::DefId;
+use crate::parser::syntax;
+
+#[derive(Clone, Debug)]
+pub enum KediValue {
+    KediNum(num_bigint::BigInt),
+    KediBool(bool),
+    KediStr(String),
+    /// Arrays are shared, an item assigned through one copy is seen through
+    /// all of them, so an array can contain itself. They compare by their
+    /// items.
+    KediArray(Rc<RefCell<Vec<KediValue>>>),
+    /// Shared like arrays. Structs are equal when they are built by the same
+    /// constructor from equal fields.
+    KediStruct(Rc<StructValue>),
+    /// A function with the values it captured. Functions are equal when they
+    /// are the same definition with equal captures.
+    KediFun(Rc<FunValue>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StructValue {
+    /// The constructor of the struct.
+    pub def: DefId,
+    pub name: syntax::Ident,
+    pub fields: Vec<syntax::Ident>,
+    /// One per field, in declaration order.
+    pub values: RefCell<Vec<KediValue>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunValue {
+    pub def: DefId,
+    pub name: syntax::Ident,
+    /// Passed before the arguments of every call.
+    pub captures: Vec<KediValue>,
+}
+
+impl StructValue {
+    /// The position of the field `name`, if the struct declares it.
+    pub fn position(&self, name: &syntax::Ident) -> Option<usize> {
+        self.fields.iter().position(|field| field == name)
+    }
+}
+
+impl KediValue {
+    pub fn num<T: Into<num_bigint::BigInt>>(n: T) -> Self {
+        KediValue::KediNum(n.into())
+    }
+
+    pub fn bool(b: bool) -> Self {
+        KediValue::KediBool(b)
+    }
+
+    pub fn str(s: impl Into<String>) -> Self {
+        KediValue::KediStr(s.into())
+    }
+
+    pub fn array(items: Vec<KediValue>) -> Self {
+        KediValue::KediArray(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn struct_(
+        def: DefId,
+        name: syntax::Ident,
+        fields: Vec<syntax::Ident>,
+        values: Vec<KediValue>,
+    ) -> Self {
+        KediValue::KediStruct(Rc::new(StructValue {
+            def,
+            name,
+            fields,
+            values: RefCell::new(values),
+        }))
+    }
+
+    pub fn fun(def: DefId, name: syntax::Ident, captures: Vec<KediValue>) -> Self {
+        KediValue::KediFun(Rc::new(FunValue {
+            def,
+            name,
+            captures,
+        }))
+    }
+
+    /// A copy sharing no array or struct with the value, which must not
+    /// contain itself.
+    pub fn deep_copy(&self) -> Self {
+        match self {
+            KediValue::KediArray(items) => {
+                KediValue::array(items.borrow().iter().map(Self::deep_copy).collect())
+            }
+            KediValue::KediStruct(s) => KediValue::struct_(
+                s.def,
+                s.name.clone(),
+                s.fields.clone(),
+                s.values.borrow().iter().map(Self::deep_copy).collect(),
+            ),
+            KediValue::KediFun(fun) => KediValue::fun(
+                fun.def,
+                fun.name.clone(),
+                fun.captures.iter().map(Self::deep_copy).collect(),
+            ),
+            KediValue::KediNum(_) | KediValue::KediBool(_) | KediValue::KediStr(_) => self.clone(),
+        }
+    }
+
+    /// The name of the type of the value, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            KediValue::KediNum(_) => "number",
+            KediValue::KediBool(_) => "boolean",
+            KediValue::KediStr(_) => "string",
+            KediValue::KediArray(_) => "array",
+            KediValue::KediStruct(_) => "struct",
+            KediValue::KediFun(_) => "function",
+        }
+    }
+}
+
+impl PartialEq for KediValue {
+    fn eq(&self, other: &Self) -> bool {
+        eq(self, other, &mut vec![])
+    }
+}
+
+impl Eq for KediValue {}
+
+/// Compares `l` and `r`. `seen` holds the pairs of arrays and structs
+/// compared further up, which are taken to be equal so that comparing
+/// cyclic values ends.
+fn eq(l: &KediValue, r: &KediValue, seen: &mut Vec<(*const (), *const ())>) -> bool {
+    fn items(l: &[KediValue], r: &[KediValue], seen: &mut Vec<(*const (), *const ())>) -> bool {
+        l.len() == r.len() && l.iter().zip(r).all(|(l, r)| eq(l, r, seen))
+    }
+
+    let pair = match (l, r) {
+        (KediValue::KediArray(l), KediValue::KediArray(r)) => {
+            Some((Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ()))
+        }
+        (KediValue::KediStruct(l), KediValue::KediStruct(r)) => {
+            Some((Rc::as_ptr(l) as *const (), Rc::as_ptr(r) as *const ()))
+        }
+        _ => None,
+    };
+    if let Some(pair) = pair {
+        if pair.0 == pair.1 || seen.contains(&pair) {
+            return true;
+        }
+        seen.push(pair);
+    }
+
+    let ret = match (l, r) {
+        (KediValue::KediNum(l), KediValue::KediNum(r)) => l == r,
+        (KediValue::KediBool(l), KediValue::KediBool(r)) => l == r,
+        (KediValue::KediStr(l), KediValue::KediStr(r)) => l == r,
+        (KediValue::KediArray(l), KediValue::KediArray(r)) => items(&l.borrow(), &r.borrow(), seen),
+        (KediValue::KediStruct(l), KediValue::KediStruct(r)) => {
+            l.def == r.def
+                && l.name == r.name
+                && l.fields == r.fields
+                && items(&l.values.borrow(), &r.values.borrow(), seen)
+        }
+        (KediValue::KediFun(l), KediValue::KediFun(r)) => {
+            l.def == r.def && l.name == r.name && items(&l.captures, &r.captures, seen)
+        }
+        _ => false,
+    };
+
+    if pair.is_some() {
+        seen.pop();
+    }
+    ret
+}
+
+impl<T: Into<num_bigint::BigInt>> From<T> for KediValue {
+    fn from(n: T) -> Self {
+        KediValue::num(n)
+    }
+}
+
+impl fmt::Display for KediValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        display(self, f, &mut vec![])
+    }
+}
+
+/// Writes `value`. `seen` holds the arrays and structs being written
+/// further up, which are written as `...` so that cyclic values end.
+fn display(
+    value: &KediValue,
+    f: &mut fmt::Formatter<'_>,
+    seen: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        KediValue::KediNum(n) => write!(f, "{}", n),
+        KediValue::KediBool(b) => write!(f, "{}", b),
+        // Quoted, so that values read back as literals.
+        KediValue::KediStr(s) => write!(f, "{}", syntax::quote(s)),
+        KediValue::KediArray(items) => {
+            let ptr = Rc::as_ptr(items) as *const ();
+            if seen.contains(&ptr) {
+                return write!(f, "[...]");
+            }
+            seen.push(ptr);
+            write!(f, "[")?;
+            for (ix, item) in items.borrow().iter().enumerate() {
+                if ix > 0 {
+                    write!(f, ", ")?;
+                }
+                display(item, f, seen)?;
+            }
+            seen.pop();
+            write!(f, "]")
+        }
+        KediValue::KediStruct(s) => {
+            let ptr = Rc::as_ptr(s) as *const ();
+            if seen.contains(&ptr) {
+                return write!(f, "{} {{ ... }}", s.name.0);
+            }
+            seen.push(ptr);
+            write!(f, "{} {{", s.name.0)?;
+            for (ix, (field, value)) in s.fields.iter().zip(s.values.borrow().iter()).enumerate() {
+                if ix > 0 {
+                    write!(f, ",")?;
+                }
+                write!(f, " {}: ", field.0)?;
+                display(value, f, seen)?;
+            }
+            seen.pop();
+            write!(f, " }}")
+        }
+        KediValue::KediFun(fun) => write!(f, "<fn {}>", fun.name.0),
+    }
+}
@@ -1,9 +1,19 @@
 #![feature(try_trait_v2)]
 
+pub mod binder;
+#[cfg(feature = "codegen-wasm")]
+pub mod codegen_wasm;
 pub mod error;
+pub mod formatter;
+pub mod hash;
+pub mod ide;
+pub mod interpreter;
 pub mod parser;
 pub mod phase;
 pub mod renamer;
+pub mod runner;
+pub mod simplifier;
 pub mod util;
+pub mod verify;
 
 mod scratchpad;
@@ -2,10 +2,11 @@ use crate::util::loc::Span;
 
 #[derive(Debug)]
 pub enum Error {
-    ParseFailed(ParseFailed),
+    /// Every syntax error of the module, in source order.
+    ParseFailed(Vec<ParseFailed>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParseFailed {
     pub msg: String,
     pub span: Span,
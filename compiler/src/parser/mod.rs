@@ -1,8 +1,10 @@
+#[allow(clippy::all)]
 pub mod grammar;
 pub mod syntax;
 
 mod error;
 pub use error::*;
 
+#[allow(clippy::module_inception)]
 mod parser;
 pub use parser::*;
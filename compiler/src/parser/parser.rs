@@ -1,71 +1,355 @@
-#![allow(unused)]
+use lalrpop_util::lexer::Token;
+use lalrpop_util::ParseError;
 
 use crate::parser::error::*;
 use crate::parser::syntax;
-use crate::phase::TransformPhase;
-use crate::util::loc::Span;
-use crate::util::loc::SrcLoc;
+use crate::util::loc::{Span, SrcLoc, WithLoc};
 
 pub fn parse(input: &str) -> Result<syntax::Module<SrcLoc>, Error> {
-    let parser = crate::parser::grammar::ModuleParser::new();
-    match parser.parse(input) {
-        Ok(module) => Ok(module),
+    let (module, errors) = parse_partial(input);
+    match errors.is_empty() {
+        true => Ok(module),
+        false => Err(Error::ParseFailed(errors)),
+    }
+}
+
+/// Parses as much of `input` as possible. Statements and definitions with
+/// syntax errors are left out of the module, and every error is returned.
+pub fn parse_partial(input: &str) -> (syntax::Module<SrcLoc>, Vec<ParseFailed>) {
+    let mut recovered = vec![];
+    let result = crate::parser::grammar::ModuleParser::new().parse(&mut recovered, input);
+
+    let mut errors = recovered
+        .into_iter()
+        .map(|e| parse_failed(e.error))
+        .collect::<Vec<_>>();
+    let module = match result {
+        Ok(module) => module,
         Err(e) => {
-            let msg: String;
-            let span: Span;
-
-            match e {
-                lalrpop_util::ParseError::InvalidToken { location } => {
-                    msg = "Unexpected token".to_string();
-                    span = Span::from_offset_len(location, 1);
-                }
-                lalrpop_util::ParseError::UnrecognizedEof { location, expected } => todo!(),
-                lalrpop_util::ParseError::UnrecognizedToken { token, expected } => {
-                    let (start, tok, end) = token;
-                    msg = format!(
-                        "Unexpected token `{}`. Expected one of: {}",
-                        tok,
-                        expected.join(", ")
-                    );
-                    span = Span::from_offset_bytes(start, end);
-                }
-                lalrpop_util::ParseError::ExtraToken { token } => todo!(),
-                lalrpop_util::ParseError::User { error } => todo!(),
+            errors.push(parse_failed(e));
+            syntax::Module {
+                statements: WithLoc::known(vec![], Span::from_offset_len(0, input.len())),
             }
-
-            Err(Error::ParseFailed(ParseFailed { msg, span }))
         }
+    };
+    errors.sort_by_key(|e| e.span.start.0);
+    (module, errors)
+}
+
+fn parse_failed(error: ParseError<usize, Token<'_>, ParseFailed>) -> ParseFailed {
+    match error {
+        ParseError::InvalidToken { location } => ParseFailed {
+            msg: "Invalid token".to_string(),
+            span: Span::from_offset_len(location, 1),
+        },
+        ParseError::UnrecognizedEof { location, expected } => ParseFailed {
+            msg: format!(
+                "Unexpected end of file. Expected one of: {}",
+                describe_expected(&expected)
+            ),
+            span: Span::from_offset_len(location, 0),
+        },
+        ParseError::UnrecognizedToken {
+            token: (start, tok, end),
+            expected,
+        } => ParseFailed {
+            msg: format!(
+                "Unexpected token `{}`. Expected one of: {}",
+                tok,
+                describe_expected(&expected)
+            ),
+            span: Span::from_offset_bytes(start, end),
+        },
+        ParseError::ExtraToken {
+            token: (start, tok, end),
+        } => ParseFailed {
+            msg: format!("Unexpected token `{}` after the end of the module", tok),
+            span: Span::from_offset_bytes(start, end),
+        },
+        ParseError::User { error } => error,
     }
 }
 
+/// Lists the terminals lalrpop expected, naming the ones that are regexes.
+fn describe_expected(expected: &[String]) -> String {
+    expected
+        .iter()
+        .map(|terminal| match terminal.as_str() {
+            r###"r#"[a-zA-Z_][a-zA-Z0-9_]*\\??"#"### => "identifier".to_string(),
+            r###"r#"[0-9]+"#"### => "number".to_string(),
+            r###"r#"\"(\\\\.|[^\"\\\\])*\""#"### => "string".to_string(),
+            r###"r#"///[^\\n\\r]*"#"### => "doc comment".to_string(),
+            terminal => terminal.replace('"', "`"),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::parser::grammar;
-    use crate::parser::syntax;
+    use sexpr::SExpr;
 
     #[test]
     fn identifier() {
-        let r = grammar::IdentParser::new().parse("hello");
+        let r = grammar::IdentParser::new().parse(&mut vec![], "hello");
         assert_eq!(r, Ok(syntax::Ident("hello".to_string())));
     }
 
     #[test]
     fn litnum() {
-        let r = grammar::LitNumParser::new().parse("42");
+        let r = grammar::LitNumParser::new().parse(&mut vec![], "42");
         assert_eq!(r, Ok(syntax::LitNum(42)));
     }
 
     #[test]
     fn litstr() {
-        let r = grammar::LitStrParser::new().parse("\"hello\"");
+        let r = grammar::LitStrParser::new().parse(&mut vec![], "\"hello\"");
         assert_eq!(r, Ok(syntax::LitStr("hello".to_string())));
+
+        let r = grammar::LitStrParser::new().parse(&mut vec![], r#""a\"b\\c\nd""#);
+        assert_eq!(r, Ok(syntax::LitStr("a\"b\\c\nd".to_string())));
+        assert_eq!(syntax::quote("a\"b\\c\nd"), r#""a\"b\\c\nd""#);
+
+        let (_, errors) = parse_partial(r#"fn a { return "\q"; }"#);
+        assert_eq!(errors[0].span, Span::from_offset_bytes(14, 18));
+
+        // The string regex is named in the expected tokens.
+        let (_, errors) = parse_partial("fn a { return ; }");
+        assert!(errors[0].msg.contains("string") && !errors[0].msg.contains("r#"));
     }
 
     #[test]
     fn funcall() {
-        grammar::FunCallParser::new().parse("f 42").unwrap();
         grammar::FunCallParser::new()
-            .parse("f \"hello\" 12 12")
+            .parse(&mut vec![], "f 42")
+            .unwrap();
+        grammar::FunCallParser::new()
+            .parse(&mut vec![], "f \"hello\" 12 12")
             .unwrap();
     }
+
+    fn def_names(module: &syntax::Module<SrcLoc>) -> Vec<&str> {
+        module
+            .statements
+            .v
+            .iter()
+            .map(|stmt| match stmt {
+                syntax::TopLevelStmt::FunDef(f) => f.name.v.0.as_str(),
+                syntax::TopLevelStmt::StructDef(s) => s.name.v.0.as_str(),
+                syntax::TopLevelStmt::TypeDef(t) => t.name.v.0.as_str(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn errors_are_collected() {
+        let src = "fn a { let = 1; return 2; }\nfn 3 { }\nfn b n { return n; let x 4; }";
+        let (module, errors) = parse_partial(src);
+        assert_eq!(def_names(&module), vec!["a", "b"]);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].span, Span::from_offset_bytes(11, 12));
+        assert_eq!(
+            errors[0].msg,
+            "Unexpected token `=`. Expected one of: identifier"
+        );
+
+        // Broken statements are left out of the body.
+        let syntax::TopLevelStmt::FunDef(a) = &module.statements.v[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(a.body.v.len(), 1);
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        let expr = |src| {
+            grammar::ExprParser::new()
+                .parse(&mut vec![], src)
+                .unwrap()
+                .to_sexpr()
+                .to_string()
+        };
+        assert_eq!(
+            expr("a or b and c == f x + 1 * 2"),
+            expr("a or (b and (c == ((f x) + (1 * 2))))")
+        );
+        assert_eq!(expr("a - b - c"), expr("(a - b) - c"));
+        assert_eq!(expr("a / b % c"), expr("(a / b) % c"));
+        assert_eq!(
+            expr("not a == b and not not true or false"),
+            expr("((not (a == b)) and (not (not true))) or false")
+        );
+
+        // Comparisons do not chain.
+        let (_, errors) = parse_partial("fn f { return a < b < c; }");
+        assert!(errors[0].msg.starts_with("Unexpected token `<`"));
+    }
+
+    #[test]
+    fn arrays_and_indexing() {
+        let expr = |src| {
+            grammar::ExprParser::new()
+                .parse(&mut vec![], src)
+                .unwrap()
+                .to_sexpr()
+                .to_string()
+        };
+        assert_eq!(expr("f a[0] b.length"), expr("f (a[0]) (b.length)"));
+        assert_eq!(expr("a[i][j].length + 1"), expr("((a[i])[j]).length + 1"));
+        assert_eq!(expr("[1, f x, [], ]"), expr("[1, (f x), []]"));
+
+        let src = "fn f a { a[0][1 + 1] = [2]; return a; }";
+        let module = parse(src).unwrap();
+        let syntax::TopLevelStmt::FunDef(f) = &module.statements.v[0] else {
+            panic!("expected a function");
+        };
+        let syntax::FunStmt::IndexAssignment(assign) = &f.body.v[0] else {
+            panic!("expected an index assignment");
+        };
+        assert_eq!(
+            assign.v.target.loc(),
+            SrcLoc::Known(Span::from_offset_len(9, 11))
+        );
+
+        // Array literals need parentheses to be arguments, `g [1]` indexes g.
+        assert_eq!(expr("g [1]"), expr("g[1]"));
+        assert_ne!(expr("g ([1])"), expr("g[1]"));
+    }
+
+    #[test]
+    fn structs() {
+        let src = "/// A point.\nstruct Point { x, y, }\nstruct Unit {}\nfn f p { p.x.y = p.y; return p.x; }";
+        let module = parse(src).unwrap();
+        assert_eq!(def_names(&module), vec!["Point", "Unit", "f"]);
+        let syntax::TopLevelStmt::StructDef(point) = &module.statements.v[0] else {
+            panic!("expected a struct");
+        };
+        assert_eq!(point.doc.as_deref(), Some("A point."));
+        let fields = point
+            .fields
+            .v
+            .iter()
+            .map(|f| f.v.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(fields, vec!["x", "y"]);
+
+        let syntax::TopLevelStmt::FunDef(f) = &module.statements.v[2] else {
+            panic!("expected a function");
+        };
+        let syntax::FunStmt::FieldAssignment(assign) = &f.body.v[0] else {
+            panic!("expected a field assignment");
+        };
+        assert_eq!(assign.v.target.name.v.0, "y");
+        assert_eq!(
+            assign.v.target.loc(),
+            SrcLoc::Known(Span::from_offset_len(src.find("p.x.y").unwrap(), 5))
+        );
+
+        // Structs are only defined at the top level.
+        let (_, errors) = parse_partial("fn f { struct A {} return 1; }");
+        assert!(errors[0].msg.starts_with("Unexpected token `struct`"));
+    }
+
+    #[test]
+    fn types_and_matches() {
+        let src = "/// A tree.\ntype Tree =\n  | Leaf\n  | Node left right;\n\
+                   fn f t { match g t { Leaf => {} Node l r => { return l; } } }";
+        let module = parse(src).unwrap();
+        assert_eq!(def_names(&module), vec!["Tree", "f"]);
+        let syntax::TopLevelStmt::TypeDef(tree) = &module.statements.v[0] else {
+            panic!("expected a type");
+        };
+        assert_eq!(tree.doc.as_deref(), Some("A tree."));
+        let variants = tree
+            .variants
+            .v
+            .iter()
+            .map(|v| (v.name.v.0.as_str(), v.fields.v.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(variants, vec![("Leaf", 0), ("Node", 2)]);
+
+        let syntax::TopLevelStmt::FunDef(f) = &module.statements.v[1] else {
+            panic!("expected a function");
+        };
+        let syntax::FunStmt::Match(match_) = &f.body.v[0] else {
+            panic!("expected a match");
+        };
+        assert!(matches!(match_.v.value, syntax::Expr::FunCall(_)));
+        let arms = &match_.v.arms.v;
+        assert_eq!(arms.len(), 2);
+        assert_eq!(arms[1].v.variant.v.0, "Node");
+        assert_eq!(arms[1].v.bindings.v.len(), 2);
+        assert_eq!(
+            arms[1].a,
+            SrcLoc::Known(Span::from_offset_len(
+                src.find("Node l r").unwrap(),
+                "Node l r => { return l; }".len()
+            ))
+        );
+
+        // A match needs at least one arm.
+        assert!(parse("fn f t { match t {} }").is_err());
+    }
+
+    #[test]
+    fn doc_comments() {
+        let src = "// Not a doc.\n/// Adds one.\n///\n///  Indented.\nfn inc n { return add n 1; }";
+        let module = parse(src).unwrap();
+        let syntax::TopLevelStmt::FunDef(inc) = &module.statements.v[0] else {
+            panic!("expected a function");
+        };
+        assert_eq!(inc.doc.as_deref(), Some("Adds one.\n\n Indented."));
+
+        // Doc comments only go before definitions.
+        let (_, errors) = parse_partial("fn f { /// Nope.\n return 1; }");
+        assert!(errors[0].msg.starts_with("Unexpected token `/// Nope.`"));
+    }
+
+    #[test]
+    fn every_error_kind_has_a_diagnostic() {
+        let (_, errors) = parse_partial("fn a { return 1;");
+        assert!(errors[0].msg.starts_with("Unexpected end of file"));
+        assert_eq!(errors[0].span, Span::from_offset_len(16, 0));
+
+        let (_, errors) = parse_partial("fn a { return 1 # 2; }");
+        assert_eq!(errors[0].msg, "Invalid token");
+        assert_eq!(errors[0].span, Span::from_offset_len(16, 1));
+
+        let (_, errors) = parse_partial("fn a { return 99999999999; }");
+        assert_eq!(
+            errors,
+            vec![ParseFailed {
+                msg: "Number literal `99999999999` does not fit in 32 bits".to_string(),
+                span: Span::from_offset_bytes(14, 25),
+            }]
+        );
+    }
+
+    #[test]
+    fn for_loops_only_take_ranges() {
+        let src = "fn f n { for i in range(0, n + 1) { if i > 2 { break; } continue; } return n; }";
+        let module = parse(src).unwrap();
+        let syntax::TopLevelStmt::FunDef(f) = &module.statements.v[0] else {
+            panic!("expected a function");
+        };
+        let syntax::FunStmt::For(for_) = &f.body.v[0] else {
+            panic!("expected a for loop");
+        };
+        assert_eq!(for_.v.var.v.0, "i");
+        assert!(matches!(for_.v.range.v.end, syntax::Expr::BinOp(_)));
+        assert!(matches!(
+            &for_.v.body.v[1],
+            syntax::FunStmt::Jump(jump) if jump.v == syntax::Jump::Continue
+        ));
+
+        let (_, errors) = parse_partial("fn f { for i in rnge(0, 1) {} return 0; }");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Span::from_offset_bytes(16, 20));
+        assert_eq!(
+            errors[0].msg,
+            "Expected `range(start, end)` after `in`, found `rnge`"
+        );
+    }
 }
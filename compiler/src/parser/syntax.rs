@@ -1,10 +1,19 @@
+use sexpr_derive::SExpr;
+
 use crate::util::ax::Ax;
+use crate::util::loc::LocLike;
 
 // Identifier
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub struct Ident(pub String);
 
+impl sexpr::SExpr for Ident {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::symbol(&self.0)
+    }
+}
+
 // Literals
 
 #[derive(Debug, Clone, PartialEq, Eq, Copy)]
@@ -13,79 +22,421 @@ pub struct LitNum(pub i32);
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LitStr(pub String);
 
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub struct LitBool(pub bool);
+
+impl sexpr::SExpr for LitNum {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::number(self.0)
+    }
+}
+
+impl sexpr::SExpr for LitStr {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::string(&self.0)
+    }
+}
+
+impl LitStr {
+    /// Reads the contents of a quoted literal, `None` for unknown escapes.
+    pub fn unquote(quoted: &str) -> Option<LitStr> {
+        let mut out = String::new();
+        let mut chars = quoted[1..quoted.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            out.push(match c {
+                '\\' => match chars.next()? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                },
+                c => c,
+            });
+        }
+        Some(LitStr(out))
+    }
+}
+
+/// Quotes `s`, escaping it so that `LitStr::unquote` gives it back.
+pub fn quote(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl sexpr::SExpr for LitBool {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        self.0.to_sexpr()
+    }
+}
+
 // Expressions
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, SExpr)]
 pub enum Expr<LocTy> {
     LitNum(Ax<LocTy, LitNum>),
     LitStr(Ax<LocTy, LitStr>),
+    LitBool(Ax<LocTy, LitBool>),
     Ident(Ax<LocTy, Ident>),
+    Array(Ax<LocTy, Vec<Expr<LocTy>>>),
+    Index(Index<LocTy>),
+    Field(Field<LocTy>),
     FunCall(FunCall<LocTy>),
+    BinOp(BinOp<LocTy>),
+    Not(Not<LocTy>),
+    Lambda(Ax<LocTy, Lambda<LocTy>>),
+}
+
+impl<LocTy: LocLike> Expr<LocTy> {
+    /// The location of the whole expression.
+    pub fn loc(&self) -> LocTy {
+        match self {
+            Expr::LitNum(n) => n.a.clone(),
+            Expr::LitStr(s) => s.a.clone(),
+            Expr::LitBool(b) => b.a.clone(),
+            Expr::Ident(ident) => ident.a.clone(),
+            Expr::Array(array) => array.a.clone(),
+            Expr::Index(index) => index.loc(),
+            Expr::Field(field) => field.loc(),
+            Expr::FunCall(call) => LocTy::enclosing(&call.name.a, &call.args.a),
+            Expr::BinOp(op) => LocTy::enclosing(&op.lhs.loc(), &op.rhs.loc()),
+            Expr::Not(not) => LocTy::enclosing(&not.keyword.a, &not.expr.loc()),
+            Expr::Lambda(lambda) => lambda.a.clone(),
+        }
+    }
+}
+
+impl<LocTy> Expr<LocTy> {
+    pub fn bin_op(op: Ax<LocTy, Op>, lhs: Expr<LocTy>, rhs: Expr<LocTy>) -> Expr<LocTy> {
+        Expr::BinOp(BinOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        })
+    }
+
+    pub fn index(array: Expr<LocTy>, index: Ax<LocTy, Expr<LocTy>>) -> Expr<LocTy> {
+        Expr::Index(Index {
+            array: Box::new(array),
+            index: index.map(Box::new),
+        })
+    }
+
+    pub fn field(expr: Expr<LocTy>, name: Ax<LocTy, Ident>) -> Expr<LocTy> {
+        Expr::Field(Field {
+            expr: Box::new(expr),
+            name,
+        })
+    }
+
+    pub fn not(keyword: Ax<LocTy, ()>, expr: Expr<LocTy>) -> Expr<LocTy> {
+        Expr::Not(Not {
+            keyword,
+            expr: Box::new(expr),
+        })
+    }
+}
+
+/// `array[index]`, the location of `index` includes the brackets.
+#[derive(Debug, Clone, SExpr)]
+pub struct Index<LocTy> {
+    pub array: Box<Expr<LocTy>>,
+    pub index: Ax<LocTy, Box<Expr<LocTy>>>,
+}
+
+impl<LocTy: LocLike> Index<LocTy> {
+    pub fn loc(&self) -> LocTy {
+        LocTy::enclosing(&self.array.loc(), &self.index.a)
+    }
+}
+
+/// `expr.name`, a field of a struct or the length of a string or an array.
+#[derive(Debug, Clone, SExpr)]
+pub struct Field<LocTy> {
+    pub expr: Box<Expr<LocTy>>,
+    pub name: Ax<LocTy, Ident>,
+}
+
+impl<LocTy: LocLike> Field<LocTy> {
+    pub fn loc(&self) -> LocTy {
+        LocTy::enclosing(&self.expr.loc(), &self.name.a)
+    }
+}
+
+/// `|param ...| { body }`, a function value which captures the locals it
+/// uses from the enclosing functions.
+#[derive(Debug, Clone, SExpr)]
+pub struct Lambda<LocTy> {
+    pub params: Ax<LocTy, Vec<Ax<LocTy, Ident>>>,
+    pub body: Ax<LocTy, Vec<FunStmt<LocTy>>>,
+}
+
+#[derive(Debug, Clone, SExpr)]
+pub struct BinOp<LocTy> {
+    pub op: Ax<LocTy, Op>,
+    pub lhs: Box<Expr<LocTy>>,
+    pub rhs: Box<Expr<LocTy>>,
+}
+
+/// `not`, binding looser than comparisons and tighter than `and`.
+#[derive(Debug, Clone, SExpr)]
+pub struct Not<LocTy> {
+    pub keyword: Ax<LocTy, ()>,
+    pub expr: Box<Expr<LocTy>>,
+}
+
+/// Infix operators. The renamer turns them into calls to the prelude, except
+/// for `and` and `or`, which short-circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, SExpr)]
+pub enum Op {
+    Or,
+    And,
+    Eq,
+    Neq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl Op {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Op::Or => "or",
+            Op::And => "and",
+            Op::Eq => "==",
+            Op::Neq => "!=",
+            Op::Lt => "<",
+            Op::Lte => "<=",
+            Op::Gt => ">",
+            Op::Gte => ">=",
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Mod => "%",
+        }
+    }
+
+    /// The prelude function the operator stands for, `None` for the
+    /// short-circuiting ones.
+    pub fn function(&self) -> Option<&'static str> {
+        Some(match self {
+            Op::Or | Op::And => return None,
+            Op::Eq => "eq?",
+            Op::Neq => "neq?",
+            Op::Lt => "lt?",
+            Op::Lte => "lte?",
+            Op::Gt => "gt?",
+            Op::Gte => "gte?",
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::Div => "div",
+            Op::Mod => "mod",
+        })
+    }
+
+    /// Operators with a higher precedence bind tighter. Function calls bind
+    /// tighter than any operator.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::And => 2,
+            Op::Eq | Op::Neq | Op::Lt | Op::Lte | Op::Gt | Op::Gte => 3,
+            Op::Add | Op::Sub => 4,
+            Op::Mul | Op::Div | Op::Mod => 5,
+        }
+    }
+
+    /// Comparisons can not be chained, the other operators associate to
+    /// the left.
+    pub fn is_associative(&self) -> bool {
+        self.precedence() != 3
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, SExpr)]
 pub struct FunDef<LocTy> {
+    /// The `///` comment before the definition.
+    pub doc: Option<String>,
     pub name: Ax<LocTy, Ident>,
     pub params: Ax<LocTy, Vec<Ax<LocTy, Ident>>>,
     pub preds: Ax<LocTy, Vec<Expr<LocTy>>>,
     pub body: Ax<LocTy, Vec<FunStmt<LocTy>>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, SExpr)]
 pub struct FunCall<LocTy> {
     pub name: Ax<LocTy, Ident>,
     pub args: Ax<LocTy, Vec<Expr<LocTy>>>,
 }
 
-#[derive(Debug, Clone)]
+/// `struct Name { field, ... }`, which defines the constructor `Name`, taking
+/// the fields in order, and the predicate `Name?`.
+#[derive(Debug, Clone, SExpr)]
+pub struct StructDef<LocTy> {
+    /// The `///` comment before the definition.
+    pub doc: Option<String>,
+    pub name: Ax<LocTy, Ident>,
+    pub fields: Ax<LocTy, Vec<Ax<LocTy, Ident>>>,
+}
+
+/// `type Name = Variant field ... | ...;`, where every variant is a struct
+/// with its constructor and predicate, and `Name?` holds for all of them.
+#[derive(Debug, Clone, SExpr)]
+pub struct TypeDef<LocTy> {
+    /// The `///` comment before the definition.
+    pub doc: Option<String>,
+    pub name: Ax<LocTy, Ident>,
+    pub variants: Ax<LocTy, Vec<Ax<LocTy, Variant<LocTy>>>>,
+}
+
+#[derive(Debug, Clone, SExpr)]
+pub struct Variant<LocTy> {
+    pub name: Ax<LocTy, Ident>,
+    pub fields: Ax<LocTy, Vec<Ax<LocTy, Ident>>>,
+}
+
+#[derive(Debug, Clone, SExpr)]
 pub enum TopLevelStmt<LocTy> {
     FunDef(Ax<LocTy, FunDef<LocTy>>),
+    StructDef(Ax<LocTy, StructDef<LocTy>>),
+    TypeDef(Ax<LocTy, TypeDef<LocTy>>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, SExpr)]
 pub enum FunStmt<LocTy> {
     Return(Ax<LocTy, Return<LocTy>>),
     Inv(Ax<LocTy, Inv<LocTy>>),
     LetDecl(Ax<LocTy, LetDecl<LocTy>>),
     While(Ax<LocTy, While<LocTy>>),
+    For(Ax<LocTy, For<LocTy>>),
+    Jump(Ax<LocTy, Jump>),
     Assignment(Ax<LocTy, Assignment<LocTy>>),
+    IndexAssignment(Ax<LocTy, IndexAssignment<LocTy>>),
+    FieldAssignment(Ax<LocTy, FieldAssignment<LocTy>>),
     If(Ax<LocTy, If<LocTy>>),
+    Match(Ax<LocTy, Match<LocTy>>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, SExpr)]
 pub struct Return<LocTy>(pub Expr<LocTy>);
 
-#[derive(Debug, Clone)]
+/// `invariant value;`, which has to hold whenever it is reached, when
+/// invariants are checked.
+#[derive(Debug, Clone, SExpr)]
 pub struct Inv<LocTy> {
     pub value: Ax<LocTy, Expr<LocTy>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, SExpr)]
 pub struct LetDecl<LocTy> {
     pub name: Ax<LocTy, Ident>,
     pub value: Expr<LocTy>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, SExpr)]
 pub struct While<LocTy> {
     pub condition: Expr<LocTy>,
     pub body: Ax<LocTy, Vec<FunStmt<LocTy>>>,
 }
 
-#[derive(Debug, Clone)]
+/// `for var in range(start, end) { ... }`, which runs the body with `var`
+/// set to each number from `start` up to, but not including, `end`.
+#[derive(Debug, Clone, SExpr)]
+pub struct For<LocTy> {
+    pub var: Ax<LocTy, Ident>,
+    /// `range(start, end)`.
+    pub range: Ax<LocTy, Range<LocTy>>,
+    pub body: Ax<LocTy, Vec<FunStmt<LocTy>>>,
+}
+
+#[derive(Debug, Clone, SExpr)]
+pub struct Range<LocTy> {
+    pub start: Expr<LocTy>,
+    pub end: Expr<LocTy>,
+}
+
+/// `break;` leaves the innermost loop, `continue;` goes on with its next
+/// iteration.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, SExpr)]
+pub enum Jump {
+    Break,
+    Continue,
+}
+
+impl Jump {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Jump::Break => "break",
+            Jump::Continue => "continue",
+        }
+    }
+}
+
+#[derive(Debug, Clone, SExpr)]
 pub struct Assignment<LocTy> {
     pub name: Ax<LocTy, Ident>,
     pub value: Expr<LocTy>,
 }
 
-#[derive(Debug, Clone)]
+/// `array[index] = value;`
+#[derive(Debug, Clone, SExpr)]
+pub struct IndexAssignment<LocTy> {
+    pub target: Index<LocTy>,
+    pub value: Expr<LocTy>,
+}
+
+/// `expr.name = value;`
+#[derive(Debug, Clone, SExpr)]
+pub struct FieldAssignment<LocTy> {
+    pub target: Field<LocTy>,
+    pub value: Expr<LocTy>,
+}
+
+#[derive(Debug, Clone, SExpr)]
 pub struct If<LocTy> {
     pub condition: Expr<LocTy>,
     pub then: Ax<LocTy, Vec<FunStmt<LocTy>>>,
     pub else_: Option<Ax<LocTy, Vec<FunStmt<LocTy>>>>,
 }
 
-#[derive(Debug, Clone)]
+/// `match value { Variant binding ... => { ... } ... }`, which runs the arm
+/// of the variant `value` was built with.
+#[derive(Debug, Clone, SExpr)]
+pub struct Match<LocTy> {
+    pub value: Expr<LocTy>,
+    pub arms: Ax<LocTy, Vec<Ax<LocTy, Arm<LocTy>>>>,
+}
+
+/// An arm binds the fields of its variant, in order, to `bindings`.
+#[derive(Debug, Clone, SExpr)]
+pub struct Arm<LocTy> {
+    pub variant: Ax<LocTy, Ident>,
+    pub bindings: Ax<LocTy, Vec<Ax<LocTy, Ident>>>,
+    pub body: Ax<LocTy, Vec<FunStmt<LocTy>>>,
+}
+
+#[derive(Debug, Clone, SExpr)]
 pub struct Module<LocTy> {
     pub statements: Ax<LocTy, Vec<TopLevelStmt<LocTy>>>,
 }
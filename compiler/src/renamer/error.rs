@@ -4,6 +4,10 @@ use crate::{parser::syntax, util::ax::Ax};
 pub enum Error<L> {
     IdentifierNotFound(IdentifierNotFoundError<L>),
     DuplicateIdentifier(DuplicateIdentifierError<L>),
+    UnknownField(UnknownFieldError<L>),
+    AssignToCapture(AssignToCaptureError<L>),
+    JumpOutsideLoop(JumpOutsideLoopError<L>),
+    ReservedIdentifier(ReservedIdentifierError<L>),
 }
 
 #[derive(Debug)]
@@ -28,3 +32,53 @@ impl<L> From<IdentifierNotFoundError<L>> for Error<L> {
         Error::IdentifierNotFound(e)
     }
 }
+
+/// A field no struct declares, other than `length`.
+#[derive(Debug)]
+pub struct UnknownFieldError<L> {
+    pub field: Ax<L, syntax::Ident>,
+}
+
+impl<L> From<UnknownFieldError<L>> for Error<L> {
+    fn from(e: UnknownFieldError<L>) -> Self {
+        Error::UnknownField(e)
+    }
+}
+
+/// An assignment in a lambda to a local of an enclosing function, which the
+/// lambda only has a copy of.
+#[derive(Debug)]
+pub struct AssignToCaptureError<L> {
+    pub assignment: Ax<L, syntax::Ident>,
+    pub lambda_loc: L,
+}
+
+impl<L> From<AssignToCaptureError<L>> for Error<L> {
+    fn from(e: AssignToCaptureError<L>) -> Self {
+        Error::AssignToCapture(e)
+    }
+}
+
+/// A `break` or `continue` which is not in a loop of its function or lambda.
+#[derive(Debug)]
+pub struct JumpOutsideLoopError<L> {
+    pub jump: Ax<L, syntax::Jump>,
+}
+
+impl<L> From<JumpOutsideLoopError<L>> for Error<L> {
+    fn from(e: JumpOutsideLoopError<L>) -> Self {
+        Error::JumpOutsideLoop(e)
+    }
+}
+
+/// A parameter named `result`, which predicates use for the return value.
+#[derive(Debug)]
+pub struct ReservedIdentifierError<L> {
+    pub identifier: Ax<L, syntax::Ident>,
+}
+
+impl<L> From<ReservedIdentifierError<L>> for Error<L> {
+    fn from(e: ReservedIdentifierError<L>) -> Self {
+        Error::ReservedIdentifier(e)
+    }
+}
@@ -1,5 +1,6 @@
 pub mod plain;
 
+#[allow(clippy::module_inception)]
 mod renamer;
 pub use renamer::*;
 
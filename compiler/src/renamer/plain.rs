@@ -1,4 +1,7 @@
+#![allow(clippy::needless_question_mark)]
+
 use functor_derive::Functor;
+use sexpr_derive::SExpr;
 
 use crate::parser::syntax;
 use crate::util::ax::Ax;
@@ -14,92 +17,228 @@ pub struct UnresolvedIdent {
     pub id: u32,
 }
 
-#[derive(Debug, Copy, Clone, Functor, PartialEq, Eq)]
+impl sexpr::SExpr for LocalIdent {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::call("local", &[self.id])
+    }
+}
+
+impl sexpr::SExpr for UnresolvedIdent {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::call("global", &[self.id])
+    }
+}
+
+#[derive(Debug, Copy, Clone, Functor, PartialEq, Eq, SExpr)]
 #[functor(LocTy as loc)]
 pub enum Ident<LocTy> {
     Local(Ax<LocTy, LocalIdent>),
     Global(Ax<LocTy, UnresolvedIdent>),
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 pub struct FunDef<LocTy, IdentTy> {
+    pub doc: Option<String>,
     pub name: Ax<LocTy, syntax::Ident>,
     pub implementation: FunImpl<LocTy, IdentTy>,
     pub refs: Bimap<UnresolvedIdent, syntax::Ident>,
+    /// References to the prelude functions operators stand for, which the
+    /// module can not shadow.
+    pub prelude_refs: Bimap<UnresolvedIdent, syntax::Ident>,
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 pub struct FunImpl<LocTy, IdentTy> {
     pub params: Ax<LocTy, Vec<Ax<LocTy, LocalIdent>>>,
+    /// The local standing for the return value, only visible in `preds`.
+    pub result: Ax<LocTy, LocalIdent>,
     pub preds: Ax<LocTy, Vec<Expr<LocTy, IdentTy>>>,
     pub body: Ax<LocTy, Vec<FunStmt<LocTy, IdentTy>>>,
+    /// The name of each local, by id, `None` for the ones the renamer adds.
+    pub local_names: Vec<Option<syntax::Ident>>,
 }
 
 pub type LitNum = syntax::LitNum;
 pub type LitStr = syntax::LitStr;
+pub type LitBool = syntax::LitBool;
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 #[functor(LocTy as loc, IdentTy as ident)]
 pub enum Expr<LocTy, IdentTy> {
     LitNum(Ax<LocTy, syntax::LitNum>),
     LitStr(Ax<LocTy, syntax::LitStr>),
+    LitBool(Ax<LocTy, syntax::LitBool>),
     Ident(IdentTy),
+    Array(Ax<LocTy, Vec<Expr<LocTy, IdentTy>>>),
+    Index(Index<LocTy, IdentTy>),
+    Field(Field<LocTy, IdentTy>),
     FunCall(FunCall<LocTy, IdentTy>),
+    Logic(Logic<LocTy, IdentTy>),
+    Lambda(Ax<LocTy, Lambda<LocTy, IdentTy>>),
+}
+
+/// The locals of the enclosing functions a lambda uses are its `captures`,
+/// copied when the lambda is evaluated. They keep their identifiers in the
+/// body.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+#[functor(LocTy as loc, IdentTy as ident)]
+pub struct Lambda<LocTy, IdentTy> {
+    pub params: Ax<LocTy, Vec<Ax<LocTy, LocalIdent>>>,
+    pub captures: Vec<LocalIdent>,
+    pub body: Ax<LocTy, Vec<FunStmt<LocTy, IdentTy>>>,
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+/// A call to a local is a call to the function value it holds.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 #[functor(LocTy as loc, IdentTy as ident)]
 pub struct FunCall<LocTy, IdentTy> {
-    pub name: Ax<LocTy, UnresolvedIdent>,
+    pub name: IdentTy,
     pub args: Ax<LocTy, Vec<Expr<LocTy, IdentTy>>>,
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+/// `array[index]`, the location of `index` includes the brackets.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+#[functor(LocTy as loc, IdentTy as ident)]
+pub struct Index<LocTy, IdentTy> {
+    pub array: Box<Expr<LocTy, IdentTy>>,
+    pub index: Ax<LocTy, Box<Expr<LocTy, IdentTy>>>,
+}
+
+/// `expr.name`, a field of a struct or the length of a string or an array.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+#[functor(LocTy as loc, IdentTy as ident)]
+pub struct Field<LocTy, IdentTy> {
+    pub expr: Box<Expr<LocTy, IdentTy>>,
+    pub name: Ax<LocTy, syntax::Ident>,
+}
+
+/// A short-circuiting operator, which only evaluates the operands it needs.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+#[functor(LocTy as loc, IdentTy as ident)]
+pub struct Logic<LocTy, IdentTy> {
+    pub op: Ax<LocTy, LogicOp>,
+    /// One operand for `Not`, two for the others.
+    pub args: Ax<LocTy, Vec<Expr<LocTy, IdentTy>>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, SExpr)]
+pub enum LogicOp {
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 #[functor(LocTy as loc, IdentTy as ident)]
 pub struct Return<LocTy, IdentTy>(pub Expr<LocTy, IdentTy>);
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 #[functor(LocTy as loc, IdentTy as ident)]
 pub enum FunStmt<LocTy, IdentTy> {
     Return(Ax<LocTy, Return<LocTy, IdentTy>>),
     Inv(Ax<LocTy, Expr<LocTy, IdentTy>>),
     LetDecl(Ax<LocTy, LetDecl<LocTy, IdentTy>>),
     While(Ax<LocTy, While<LocTy, IdentTy>>),
+    For(Ax<LocTy, For<LocTy, IdentTy>>),
+    Jump(Ax<LocTy, syntax::Jump>),
     Assignment(Ax<LocTy, Assignment<LocTy, IdentTy>>),
+    IndexAssignment(Ax<LocTy, IndexAssignment<LocTy, IdentTy>>),
+    FieldAssignment(Ax<LocTy, FieldAssignment<LocTy, IdentTy>>),
     If(Ax<LocTy, If<LocTy, IdentTy>>),
+    Match(Ax<LocTy, Match<LocTy, IdentTy>>),
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 pub struct LetDecl<LocTy, IdentTy> {
     pub name: Ax<LocTy, LocalIdent>,
     pub value: Expr<LocTy, IdentTy>,
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 pub struct While<LocTy, IdentTy> {
     pub condition: Expr<LocTy, IdentTy>,
     pub body: Ax<LocTy, Vec<FunStmt<LocTy, IdentTy>>>,
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+/// `for var in range(start, end) { body }`, with the unnamed locals the
+/// renamer adds for the next value of `var` and for `end`, which is only
+/// computed once.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+pub struct For<LocTy, IdentTy> {
+    pub var: Ax<LocTy, LocalIdent>,
+    pub start: Expr<LocTy, IdentTy>,
+    pub end: Expr<LocTy, IdentTy>,
+    pub next: Ax<LocTy, LocalIdent>,
+    pub limit: Ax<LocTy, LocalIdent>,
+    /// `next < limit`, as a call to the prelude.
+    pub condition: Expr<LocTy, IdentTy>,
+    /// `next + 1`, as a call to the prelude.
+    pub step: Expr<LocTy, IdentTy>,
+    pub body: Ax<LocTy, Vec<FunStmt<LocTy, IdentTy>>>,
+}
+
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 pub struct Assignment<LocTy, IdentTy> {
     pub id: Ax<LocTy, LocalIdent>,
     pub value: Expr<LocTy, IdentTy>,
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+pub struct IndexAssignment<LocTy, IdentTy> {
+    pub target: Index<LocTy, IdentTy>,
+    pub value: Expr<LocTy, IdentTy>,
+}
+
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+pub struct FieldAssignment<LocTy, IdentTy> {
+    pub target: Field<LocTy, IdentTy>,
+    pub value: Expr<LocTy, IdentTy>,
+}
+
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 pub struct If<LocTy, IdentTy> {
     pub condition: Expr<LocTy, IdentTy>,
     pub then: Ax<LocTy, Vec<FunStmt<LocTy, IdentTy>>>,
     pub else_: Option<Ax<LocTy, Vec<FunStmt<LocTy, IdentTy>>>>,
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+pub struct Match<LocTy, IdentTy> {
+    pub value: Expr<LocTy, IdentTy>,
+    pub arms: Ax<LocTy, Vec<Arm<LocTy, IdentTy>>>,
+}
+
+/// The bindings of an arm are only visible in its body.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+pub struct Arm<LocTy, IdentTy> {
+    pub variant: IdentTy,
+    pub bindings: Ax<LocTy, Vec<Ax<LocTy, LocalIdent>>>,
+    pub body: Ax<LocTy, Vec<FunStmt<LocTy, IdentTy>>>,
+}
+
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+pub struct StructDef<LocTy> {
+    pub doc: Option<String>,
+    pub name: Ax<LocTy, syntax::Ident>,
+    pub fields: Ax<LocTy, Vec<Ax<LocTy, syntax::Ident>>>,
+}
+
+/// The variants are structs without a doc comment of their own.
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
+pub struct TypeDef<LocTy> {
+    pub doc: Option<String>,
+    pub name: Ax<LocTy, syntax::Ident>,
+    pub variants: Ax<LocTy, Vec<Ax<LocTy, StructDef<LocTy>>>>,
+}
+
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 pub enum TopLevelStmt<LocTy, IdentTy> {
     FunDef(Ax<LocTy, FunDef<LocTy, IdentTy>>),
+    StructDef(Ax<LocTy, StructDef<LocTy>>),
+    TypeDef(Ax<LocTy, TypeDef<LocTy>>),
 }
 
-#[derive(Debug, Clone, Functor, PartialEq)]
+#[derive(Debug, Clone, Functor, PartialEq, SExpr)]
 #[functor(LocTy as loc, IdentTy as ident)]
 pub struct Module<LocTy, IdentTy> {
     pub statements: Vec<TopLevelStmt<LocTy, IdentTy>>,
@@ -1,21 +1,46 @@
 use bimap::BiHashMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 
-use super::error::{DuplicateIdentifierError, Error, IdentifierNotFoundError};
+use super::error::{
+    AssignToCaptureError, DuplicateIdentifierError, Error, IdentifierNotFoundError,
+    JumpOutsideLoopError, ReservedIdentifierError, UnknownFieldError,
+};
 use super::plain::Return;
 use crate::parser::syntax;
 use crate::renamer::plain;
 use crate::util::ax::{ax, Ax};
 use crate::util::loc::LocLike;
 
+/// The name predicates use to refer to the return value.
+pub const RESULT: &str = "result";
+
+/// The field every string and array has, besides the fields of structs.
+pub const LENGTH: &str = "length";
+
 pub fn rename<LocTy: LocLike + Debug>(
     input: &syntax::Module<LocTy>,
 ) -> Result<plain::Module<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
     let mut ret = vec![];
 
+    // Fields can be used before the struct that declares them.
+    let mut fields = HashSet::from([syntax::Ident(LENGTH.to_string())]);
+    for stmt in input.statements.v.iter() {
+        match stmt {
+            syntax::TopLevelStmt::StructDef(def) => {
+                fields.extend(def.v.fields.v.iter().map(|field| field.v.clone()));
+            }
+            syntax::TopLevelStmt::TypeDef(def) => {
+                for variant in def.v.variants.v.iter() {
+                    fields.extend(variant.v.fields.v.iter().map(|field| field.v.clone()));
+                }
+            }
+            syntax::TopLevelStmt::FunDef(_) => {}
+        }
+    }
+
     for syn_input in input.statements.v.iter() {
-        let input = rename_statement(syn_input)?;
+        let input = rename_statement(&fields, syn_input)?;
         ret.push(input);
     }
 
@@ -23,20 +48,88 @@ pub fn rename<LocTy: LocLike + Debug>(
 }
 
 fn rename_statement<LocTy: LocLike + Debug>(
+    fields: &HashSet<syntax::Ident>,
     input: &syntax::TopLevelStmt<LocTy>,
 ) -> Result<plain::TopLevelStmt<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
     match input {
         syntax::TopLevelStmt::FunDef(fun) => {
-            let fun = fun.as_ref().map(|f| rename_function(&f)).transpose()?;
+            let fun = fun
+                .as_ref()
+                .map(|f| rename_function(fields, f))
+                .transpose()?;
             Ok(plain::TopLevelStmt::FunDef(fun.clone_a()))
         }
+        syntax::TopLevelStmt::StructDef(def) => {
+            let def = def.as_ref().map(rename_struct).transpose()?;
+            Ok(plain::TopLevelStmt::StructDef(def.clone_a()))
+        }
+        syntax::TopLevelStmt::TypeDef(def) => {
+            let def = def.as_ref().map(rename_type).transpose()?;
+            Ok(plain::TopLevelStmt::TypeDef(def.clone_a()))
+        }
     }
 }
 
+fn rename_type<LocTy: LocLike + Debug>(
+    input: &syntax::TypeDef<LocTy>,
+) -> Result<plain::TypeDef<LocTy>, Error<LocTy>> {
+    let variants = input
+        .variants
+        .as_ref()
+        .map(|variants| {
+            variants
+                .iter()
+                .map(|variant| {
+                    variant
+                        .as_ref()
+                        .map(|variant| {
+                            rename_struct(&syntax::StructDef {
+                                doc: None,
+                                name: variant.name.clone(),
+                                fields: variant.fields.clone(),
+                            })
+                        })
+                        .transpose()
+                        .map(|variant| variant.clone_a())
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .clone_a();
+
+    Ok(plain::TypeDef {
+        doc: input.doc.clone(),
+        name: input.name.clone(),
+        variants,
+    })
+}
+
+fn rename_struct<LocTy: LocLike + Debug>(
+    input: &syntax::StructDef<LocTy>,
+) -> Result<plain::StructDef<LocTy>, Error<LocTy>> {
+    let mut seen: HashMap<&syntax::Ident, &LocTy> = HashMap::new();
+    for field in input.fields.v.iter() {
+        if let Some(original_loc) = seen.insert(&field.v, &field.a) {
+            return Err(DuplicateIdentifierError {
+                error: field.clone(),
+                original_loc: original_loc.clone(),
+            }
+            .into());
+        }
+    }
+
+    Ok(plain::StructDef {
+        doc: input.doc.clone(),
+        name: input.name.clone(),
+        fields: input.fields.clone(),
+    })
+}
+
 fn rename_function<LocTy: LocLike + Debug>(
+    fields: &HashSet<syntax::Ident>,
     input: &syntax::FunDef<LocTy>,
 ) -> Result<plain::FunDef<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
-    let mut env = RenamerEnv::new();
+    let mut env = RenamerEnv::new(fields);
 
     let params = input
         .params
@@ -44,6 +137,12 @@ fn rename_function<LocTy: LocLike + Debug>(
         .map(|ps| {
             ps.iter()
                 .map(|p| {
+                    if p.v.0 == RESULT {
+                        return Err(ReservedIdentifierError {
+                            identifier: p.clone(),
+                        }
+                        .into());
+                    }
                     let pid = env.mk_new_local(p)?;
                     // let predicate = p.predicate.map(|p| rename_expr(&mut env, &p));
                     Ok::<_, Error<_>>(pid)
@@ -53,6 +152,24 @@ fn rename_function<LocTy: LocLike + Debug>(
         .transpose()?
         .clone_a();
 
+    // Predicates see the parameters, and the return value as `result`.
+    let result = env.mk_new_local(&ax(
+        input.preds.a.clone(),
+        syntax::Ident(RESULT.to_string()),
+    ))?;
+    let preds = input
+        .preds
+        .as_ref()
+        .map(|preds| {
+            preds
+                .iter()
+                .map(|pred| rename_expr(&mut env, pred))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .clone_a();
+    env.forget_local(&syntax::Ident(RESULT.to_string()));
+
     let body = input
         .body
         .as_ref()
@@ -65,18 +182,26 @@ fn rename_function<LocTy: LocLike + Debug>(
         .clone_a();
 
     return Ok(plain::FunDef {
+        doc: input.doc.clone(),
         name: input.name.clone(),
         implementation: plain::FunImpl {
             params,
+            result,
+            preds,
             body,
-            preds: ax(input.preds.a.clone(), vec![]),
+            local_names: std::mem::take(&mut env.local_names),
         },
         refs: env.globals.iter().map(|(k, v)| (*v, k.clone())).collect(),
+        prelude_refs: env
+            .prelude_globals
+            .iter()
+            .map(|(k, v)| (*v, k.clone()))
+            .collect(),
     });
 }
 
 fn rename_fun_statement<LocTy: LocLike + Debug>(
-    env: &mut RenamerEnv<LocTy>,
+    env: &mut RenamerEnv<'_, LocTy>,
     input: &syntax::FunStmt<LocTy>,
 ) -> Result<plain::FunStmt<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
     match input {
@@ -86,7 +211,7 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
                 .clone_a()
                 .map(|decl| {
                     let pid = env.mk_new_local(&decl.name)?;
-                    let expr = rename_expr(env, &decl.value);
+                    let expr = rename_expr(env, &decl.value)?;
                     Ok::<_, Error<_>>(plain::LetDecl {
                         name: pid,
                         value: expr,
@@ -101,7 +226,8 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
             let ret = ret
                 .as_ref()
                 .clone_a()
-                .map(|ret| Return(rename_expr(env, &ret.0)));
+                .map(|ret| rename_expr(env, &ret.0).map(Return))
+                .transpose()?;
             Ok(plain::FunStmt::Return(ret))
         }
 
@@ -109,15 +235,11 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
             let ret = while_stmt
                 .as_ref()
                 .map(|while_stmt| {
-                    let condition = rename_expr(env, &while_stmt.condition);
+                    let condition = rename_expr(env, &while_stmt.condition)?;
                     let body = while_stmt
                         .body
                         .as_ref()
-                        .map(|body| {
-                            body.iter()
-                                .map(|stmt| rename_fun_statement(env, stmt))
-                                .collect::<Result<Vec<_>, _>>()
-                        })
+                        .map(|body| rename_loop_body(env, body))
                         .transpose()?
                         .clone_a();
                     Ok::<_, Error<_>>(plain::While { condition, body })
@@ -128,6 +250,22 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
             Ok(plain::FunStmt::While(ret))
         }
 
+        syntax::FunStmt::For(for_stmt) => {
+            let ret = for_stmt
+                .as_ref()
+                .map(|for_stmt| rename_for(env, for_stmt))
+                .transpose()?
+                .clone_a();
+            Ok(plain::FunStmt::For(ret))
+        }
+
+        syntax::FunStmt::Jump(jump) => {
+            if env.loops == 0 {
+                return Err(JumpOutsideLoopError { jump: jump.clone() }.into());
+            }
+            Ok(plain::FunStmt::Jump(jump.clone()))
+        }
+
         syntax::FunStmt::Assignment(assignment) => {
             let ret = assignment
                 .as_ref()
@@ -135,7 +273,7 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
                     let id = assignment
                         .name
                         .as_ref()
-                        .map(|lhs| match env.resolve_local(&lhs) {
+                        .map(|lhs| match env.resolve_local(lhs) {
                             Some(x) => Ok(x),
                             None => Err(IdentifierNotFoundError {
                                 identifier: assignment.name.clone(),
@@ -143,7 +281,14 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
                         })
                         .transpose()?
                         .clone_a();
-                    let value = rename_expr(env, &assignment.value);
+                    if let Some(lambda_loc) = env.capturing_lambda(id.v) {
+                        return Err(AssignToCaptureError {
+                            assignment: assignment.name.clone(),
+                            lambda_loc,
+                        }
+                        .into());
+                    }
+                    let value = rename_expr(env, &assignment.value)?;
                     Ok::<_, Error<_>>(plain::Assignment { id, value })
                 })
                 .transpose()?
@@ -152,19 +297,43 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
             Ok(plain::FunStmt::Assignment(ret))
         }
 
+        syntax::FunStmt::IndexAssignment(assignment) => {
+            let ret = assignment
+                .as_ref()
+                .map(|assignment| {
+                    let target = rename_index(env, &assignment.target)?;
+                    let value = rename_expr(env, &assignment.value)?;
+                    Ok::<_, Error<_>>(plain::IndexAssignment { target, value })
+                })
+                .transpose()?
+                .clone_a();
+
+            Ok(plain::FunStmt::IndexAssignment(ret))
+        }
+
+        syntax::FunStmt::FieldAssignment(assignment) => {
+            let ret = assignment
+                .as_ref()
+                .map(|assignment| {
+                    let target = rename_field(env, &assignment.target)?;
+                    let value = rename_expr(env, &assignment.value)?;
+                    Ok::<_, Error<_>>(plain::FieldAssignment { target, value })
+                })
+                .transpose()?
+                .clone_a();
+
+            Ok(plain::FunStmt::FieldAssignment(ret))
+        }
+
         syntax::FunStmt::If(if_stmt) => {
             let ret = if_stmt
                 .as_ref()
                 .map(|if_stmt| {
-                    let condition = rename_expr(env, &if_stmt.condition);
+                    let condition = rename_expr(env, &if_stmt.condition)?;
                     let then = if_stmt
                         .then
                         .as_ref()
-                        .map(|then| {
-                            then.iter()
-                                .map(|stmt| rename_fun_statement(env, stmt))
-                                .collect::<Result<Vec<_>, _>>()
-                        })
+                        .map(|then| rename_block(env, then))
                         .transpose()?
                         .clone_a();
                     let else_ = if_stmt
@@ -174,12 +343,7 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
                             else_
                                 .as_ref()
                                 .clone_a()
-                                .map(|else_| {
-                                    else_
-                                        .iter()
-                                        .map(|stmt| rename_fun_statement(env, stmt))
-                                        .collect::<Result<Vec<_>, _>>()
-                                })
+                                .map(|else_| rename_block(env, else_))
                                 .transpose()
                         })
                         .transpose()?;
@@ -194,55 +358,348 @@ fn rename_fun_statement<LocTy: LocLike + Debug>(
             Ok(plain::FunStmt::If(ret.clone_a()))
         }
 
-        otherwise => unimplemented!("{:?}", otherwise),
+        syntax::FunStmt::Match(match_stmt) => {
+            let ret = match_stmt
+                .as_ref()
+                .map(|match_stmt| {
+                    let value = rename_expr(env, &match_stmt.value)?;
+                    let arms = match_stmt
+                        .arms
+                        .as_ref()
+                        .map(|arms| {
+                            arms.iter()
+                                .map(|arm| rename_arm(env, &arm.v))
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                        .transpose()?
+                        .clone_a();
+                    Ok::<_, Error<_>>(plain::Match { value, arms })
+                })
+                .transpose()?
+                .clone_a();
+
+            Ok(plain::FunStmt::Match(ret))
+        }
+
+        syntax::FunStmt::Inv(inv) => {
+            let ret = inv
+                .as_ref()
+                .clone_a()
+                .map(|inv| rename_expr(env, &inv.value.v))
+                .transpose()?;
+            Ok(plain::FunStmt::Inv(ret))
+        }
     }
 }
 
+fn rename_loop_body<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    input: &[syntax::FunStmt<LocTy>],
+) -> Result<Vec<plain::FunStmt<LocTy, plain::Ident<LocTy>>>, Error<LocTy>> {
+    let loops = env.loops;
+    env.loops += 1;
+    let body = rename_block(env, input);
+    env.loops = loops;
+    body
+}
+
+/// Renames the statements of a block, the locals it declares are not
+/// visible after it.
+fn rename_block<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    input: &[syntax::FunStmt<LocTy>],
+) -> Result<Vec<plain::FunStmt<LocTy, plain::Ident<LocTy>>>, Error<LocTy>> {
+    let first = env.next_local_id;
+    let body = input
+        .iter()
+        .map(|stmt| rename_fun_statement(env, stmt))
+        .collect();
+    env.forget_locals_from(first);
+    body
+}
+
+// The range is computed before `var` is visible, and `var` is only visible
+// in the body. The comparison and the increment are located at the range.
+fn rename_for<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    input: &syntax::For<LocTy>,
+) -> Result<plain::For<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    let loc = &input.range.a;
+    let start = rename_expr(env, &input.range.v.start)?;
+    let end = rename_expr(env, &input.range.v.end)?;
+    let next = env.mk_unnamed_local(loc);
+    let limit = env.mk_unnamed_local(loc);
+
+    let local =
+        |id: &Ax<LocTy, plain::LocalIdent>| plain::Expr::Ident(plain::Ident::Local(id.clone()));
+    let mut call = |op: syntax::Op, args| {
+        let function = syntax::Ident(op.function().unwrap().to_string());
+        plain::Expr::FunCall(plain::FunCall {
+            name: plain::Ident::Global(ax(loc.clone(), env.get_prelude(&function))),
+            args: ax(loc.clone(), args),
+        })
+    };
+    let condition = call(syntax::Op::Lt, vec![local(&next), local(&limit)]);
+    let one = plain::Expr::LitNum(ax(loc.clone(), syntax::LitNum(1)));
+    let step = call(syntax::Op::Add, vec![local(&next), one]);
+
+    let var = env.mk_new_local(&input.var)?;
+    let body = input
+        .body
+        .as_ref()
+        .map(|body| rename_loop_body(env, body))
+        .transpose()?
+        .clone_a();
+    env.forget_local(&input.var.v);
+
+    Ok(plain::For {
+        var,
+        start,
+        end,
+        next,
+        limit,
+        condition,
+        step,
+        body,
+    })
+}
+
+fn rename_arm<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    input: &syntax::Arm<LocTy>,
+) -> Result<plain::Arm<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    let variant = plain::Ident::Global(input.variant.as_ref().map(|x| env.get_global(x)).clone_a());
+    let bindings = input
+        .bindings
+        .as_ref()
+        .map(|bindings| {
+            bindings
+                .iter()
+                .map(|binding| env.mk_new_local(binding))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .clone_a();
+    let body = input
+        .body
+        .as_ref()
+        .map(|body| rename_block(env, body))
+        .transpose()?
+        .clone_a();
+    for binding in input.bindings.v.iter() {
+        env.forget_local(&binding.v);
+    }
+
+    Ok(plain::Arm {
+        variant,
+        bindings,
+        body,
+    })
+}
+
 fn rename_expr<LocTy: LocLike + Debug>(
-    env: &mut RenamerEnv<LocTy>,
+    env: &mut RenamerEnv<'_, LocTy>,
     input: &syntax::Expr<LocTy>,
-) -> plain::Expr<LocTy, plain::Ident<LocTy>> {
-    match input {
+) -> Result<plain::Expr<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    Ok(match input {
         syntax::Expr::LitNum(x) => plain::Expr::LitNum(x.clone()),
         syntax::Expr::LitStr(x) => plain::Expr::LitStr(x.clone()),
+        syntax::Expr::LitBool(x) => plain::Expr::LitBool(x.clone()),
         syntax::Expr::Ident(x) => plain::Expr::Ident(env.resolve(x)),
-        syntax::Expr::FunCall(x) => plain::Expr::FunCall(rename_fun_call(env, x)),
+        syntax::Expr::Array(x) => plain::Expr::Array(
+            x.as_ref()
+                .map(|items| {
+                    items
+                        .iter()
+                        .map(|item| rename_expr(env, item))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()?
+                .clone_a(),
+        ),
+        syntax::Expr::Index(x) => plain::Expr::Index(rename_index(env, x)?),
+        syntax::Expr::Field(x) => plain::Expr::Field(rename_field(env, x)?),
+        syntax::Expr::FunCall(x) => plain::Expr::FunCall(rename_fun_call(env, x)?),
+        syntax::Expr::BinOp(x) => rename_bin_op(env, x)?,
+        syntax::Expr::Not(x) => plain::Expr::Logic(plain::Logic {
+            op: ax(x.keyword.a.clone(), plain::LogicOp::Not),
+            args: ax(x.expr.loc(), vec![rename_expr(env, &x.expr)?]),
+        }),
+        syntax::Expr::Lambda(x) => plain::Expr::Lambda(
+            x.as_ref()
+                .map(|lambda| rename_lambda(env, &x.a, lambda))
+                .transpose()?
+                .clone_a(),
+        ),
+    })
+}
+
+// The parameters and locals of a lambda are locals of the enclosing function
+// as far as identifiers go, they are forgotten after its body.
+fn rename_lambda<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    loc: &LocTy,
+    input: &syntax::Lambda<LocTy>,
+) -> Result<plain::Lambda<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    env.lambdas.push(LambdaScope {
+        loc: loc.clone(),
+        first_local: env.next_local_id,
+        captures: vec![],
+    });
+    let loops = std::mem::take(&mut env.loops);
+    let params = input
+        .params
+        .as_ref()
+        .map(|params| {
+            params
+                .iter()
+                .map(|param| env.mk_new_local(param))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .clone_a();
+    let body = input
+        .body
+        .as_ref()
+        .map(|body| {
+            body.iter()
+                .map(|stmt| rename_fun_statement(env, stmt))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .clone_a();
+    let scope = env.lambdas.pop().unwrap();
+    env.forget_locals_from(scope.first_local);
+    env.loops = loops;
+
+    Ok(plain::Lambda {
+        params,
+        captures: scope.captures,
+        body,
+    })
+}
+
+fn rename_index<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    input: &syntax::Index<LocTy>,
+) -> Result<plain::Index<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    Ok(plain::Index {
+        array: Box::new(rename_expr(env, &input.array)?),
+        index: ax(
+            input.index.a.clone(),
+            Box::new(rename_expr(env, &input.index.v)?),
+        ),
+    })
+}
+
+// Which struct the field belongs to is only known when the program runs, so
+// only fields that no struct declares are rejected here.
+fn rename_field<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    input: &syntax::Field<LocTy>,
+) -> Result<plain::Field<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    if !env.fields.contains(&input.name.v) {
+        return Err(UnknownFieldError {
+            field: input.name.clone(),
+        }
+        .into());
     }
+    Ok(plain::Field {
+        expr: Box::new(rename_expr(env, &input.expr)?),
+        name: input.name.clone(),
+    })
+}
+
+// Operators are calls to the prelude function they stand for, which is
+// named at the location of the operator and can not be shadowed. `and` and `or` are kept apart, as
+// their right operand is only evaluated when needed.
+fn rename_bin_op<LocTy: LocLike + Debug>(
+    env: &mut RenamerEnv<'_, LocTy>,
+    input: &syntax::BinOp<LocTy>,
+) -> Result<plain::Expr<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    let args = ax(
+        LocTy::enclosing(&input.lhs.loc(), &input.rhs.loc()),
+        vec![rename_expr(env, &input.lhs)?, rename_expr(env, &input.rhs)?],
+    );
+    if let Some(function) = input.op.v.function() {
+        let function = syntax::Ident(function.to_string());
+        let name = plain::Ident::Global(ax(input.op.a.clone(), env.get_prelude(&function)));
+        return Ok(plain::Expr::FunCall(plain::FunCall { name, args }));
+    }
+
+    let op = match input.op.v {
+        syntax::Op::And => plain::LogicOp::And,
+        syntax::Op::Or => plain::LogicOp::Or,
+        op => unreachable!("`{}` stands for a function", op.symbol()),
+    };
+    Ok(plain::Expr::Logic(plain::Logic {
+        op: ax(input.op.a.clone(), op),
+        args,
+    }))
 }
 
 fn rename_fun_call<LocTy: LocLike + Debug>(
-    env: &mut RenamerEnv<LocTy>,
+    env: &mut RenamerEnv<'_, LocTy>,
     input: &syntax::FunCall<LocTy>,
-) -> plain::FunCall<LocTy, plain::Ident<LocTy>> {
-    let name = input.name.as_ref().map(|x| env.get_global(x)).clone_a();
+) -> Result<plain::FunCall<LocTy, plain::Ident<LocTy>>, Error<LocTy>> {
+    let name = env.resolve(&input.name);
     let args = input
         .args
         .as_ref()
-        .map(|x| x.iter().map(|x| rename_expr(env, x)).collect::<Vec<_>>())
+        .map(|x| {
+            x.iter()
+                .map(|x| rename_expr(env, x))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
         .clone_a();
-    plain::FunCall { name, args }
+    Ok(plain::FunCall { name, args })
 }
 
-struct RenamerEnv<LocTy> {
+struct RenamerEnv<'t, LocTy> {
+    /// The fields of the structs of the module, and `length`.
+    fields: &'t HashSet<syntax::Ident>,
+
     next_local_id: u32,
     next_global_id: u32,
 
     locals: BiHashMap<syntax::Ident, plain::LocalIdent>,
     globals: BiHashMap<syntax::Ident, plain::UnresolvedIdent>,
+    /// Globals only looked up in the prelude.
+    prelude_globals: BiHashMap<syntax::Ident, plain::UnresolvedIdent>,
 
     local_locs: HashMap<syntax::Ident, LocTy>,
+    /// The name of every local so far, by id.
+    local_names: Vec<Option<syntax::Ident>>,
+
+    /// The lambdas the identifiers are in, the innermost last.
+    lambdas: Vec<LambdaScope<LocTy>>,
+    /// The number of loops around the statement, in its function or lambda.
+    loops: u32,
     _marker: std::marker::PhantomData<LocTy>,
 }
 
-impl<LocTy: LocLike + Debug> RenamerEnv<LocTy> {
-    fn new() -> Self {
+struct LambdaScope<LocTy> {
+    loc: LocTy,
+    /// Locals before this one belong to the enclosing functions.
+    first_local: u32,
+    captures: Vec<plain::LocalIdent>,
+}
+
+impl<'t, LocTy: LocLike + Debug> RenamerEnv<'t, LocTy> {
+    fn new(fields: &'t HashSet<syntax::Ident>) -> Self {
         RenamerEnv {
+            fields,
             next_local_id: 0,
             next_global_id: 0,
             locals: BiHashMap::new(),
             globals: BiHashMap::new(),
+            prelude_globals: BiHashMap::new(),
 
             local_locs: HashMap::new(),
+            local_names: vec![],
+            lambdas: vec![],
+            loops: 0,
             _marker: std::marker::PhantomData,
         }
     }
@@ -261,11 +718,25 @@ impl<LocTy: LocLike + Debug> RenamerEnv<LocTy> {
         let id = self.next_local_id;
         self.next_local_id += 1;
         let pid = plain::LocalIdent { id };
+        self.local_names.push(Some(input.v.clone()));
         self.locals.insert(input.v.clone(), pid);
         self.local_locs.insert(input.v.clone(), input.a.clone());
         Ok(ax(input.a.clone(), pid))
     }
 
+    /// A local no identifier refers to.
+    fn mk_unnamed_local(&mut self, loc: &LocTy) -> Ax<LocTy, plain::LocalIdent> {
+        let id = self.next_local_id;
+        self.next_local_id += 1;
+        self.local_names.push(None);
+        ax(loc.clone(), plain::LocalIdent { id })
+    }
+
+    fn forget_local(&mut self, input: &syntax::Ident) {
+        self.locals.remove_by_left(input);
+        self.local_locs.remove(input);
+    }
+
     fn get_global(&mut self, input: &syntax::Ident) -> plain::UnresolvedIdent {
         match self.globals.get_by_left(input) {
             Some(x) => *x,
@@ -279,12 +750,59 @@ impl<LocTy: LocLike + Debug> RenamerEnv<LocTy> {
         }
     }
 
+    /// Like `get_global`, for a function of the prelude.
+    fn get_prelude(&mut self, input: &syntax::Ident) -> plain::UnresolvedIdent {
+        match self.prelude_globals.get_by_left(input) {
+            Some(x) => *x,
+            None => {
+                let id = self.next_global_id;
+                self.next_global_id += 1;
+                let pid = plain::UnresolvedIdent { id };
+                self.prelude_globals.insert(input.clone(), pid);
+                pid
+            }
+        }
+    }
+
+    /// Forgets the locals declared since `first`.
+    fn forget_locals_from(&mut self, first: u32) {
+        let names = self
+            .locals
+            .iter()
+            .filter(|(_, local)| local.id >= first)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        for name in names {
+            self.forget_local(&name);
+        }
+    }
+
     fn resolve_local(&self, input: &syntax::Ident) -> Option<plain::LocalIdent> {
         self.locals.get_by_left(input).copied()
     }
 
+    /// The innermost lambda `local` is captured by, if any.
+    fn capturing_lambda(&self, local: plain::LocalIdent) -> Option<LocTy> {
+        self.lambdas
+            .last()
+            .filter(|scope| local.id < scope.first_local)
+            .map(|scope| scope.loc.clone())
+    }
+
+    /// Resolves a local which is read, capturing it in the lambdas it is
+    /// used from.
+    fn use_local(&mut self, input: &syntax::Ident) -> Option<plain::LocalIdent> {
+        let local = self.resolve_local(input)?;
+        for scope in self.lambdas.iter_mut() {
+            if local.id < scope.first_local && !scope.captures.contains(&local) {
+                scope.captures.push(local);
+            }
+        }
+        Some(local)
+    }
+
     fn resolve(&mut self, input: &Ax<LocTy, syntax::Ident>) -> plain::Ident<LocTy> {
-        match self.resolve_local(&input.v) {
+        match self.use_local(&input.v) {
             Some(x) => plain::Ident::Local(ax(input.a.clone(), x)),
             None => plain::Ident::Global(ax(input.a.clone(), self.get_global(&input.v))),
         }
@@ -295,8 +813,9 @@ impl<LocTy: LocLike + Debug> RenamerEnv<LocTy> {
 mod tests {
     use super::*;
     use crate::parser::syntax::LitNum;
-    use crate::util::ax::{ax, ax0};
+    use crate::util::ax::ax0;
     use crate::util::bimap::Bimap;
+    use crate::util::loc::{Span, SrcLoc};
 
     #[test]
     fn test_rename() {
@@ -304,6 +823,7 @@ mod tests {
             use syntax::*;
             Module {
                 statements: ax0(vec![TopLevelStmt::FunDef(ax0(FunDef {
+                    doc: None,
                     name: ax0(Ident("foo".to_string())),
                     params: ax0(vec![]),
                     preds: ax0(vec![]),
@@ -317,15 +837,19 @@ mod tests {
             use plain::*;
             Module {
                 statements: vec![TopLevelStmt::FunDef(ax0(FunDef {
+                    doc: None,
                     name: ax0(syntax::Ident("foo".to_string())),
                     implementation: FunImpl {
                         params: ax0(vec![]),
+                        result: ax0(LocalIdent { id: 0 }),
                         preds: ax0(vec![]),
                         body: ax0(vec![FunStmt::Return(ax0(Return(Expr::LitNum(ax0(
                             LitNum(42),
                         )))))]),
+                        local_names: vec![Some(syntax::Ident("result".to_string()))],
                     },
                     refs: Bimap::new(),
+                    prelude_refs: Bimap::new(),
                 }))],
             }
         };
@@ -333,4 +857,174 @@ mod tests {
         let output = rename(&input).unwrap();
         assert_eq!(output, expected);
     }
+
+    #[test]
+    fn only_length_is_a_field() {
+        let module = crate::parser::parse("fn f a { return a.length + a.size; }").unwrap();
+        match rename(&module) {
+            Err(Error::UnknownField(e)) => {
+                assert_eq!(e.field.v.0, "size");
+                assert_eq!(e.field.a, SrcLoc::Known(Span::from_offset_len(29, 4)));
+            }
+            other => panic!("expected an unknown field, got {:?}", other),
+        }
+
+        let module = crate::parser::parse("struct S { size } fn f a { return a.size; }").unwrap();
+        assert!(rename(&module).is_ok());
+    }
+
+    #[test]
+    fn duplicate_field() {
+        let module = crate::parser::parse("struct S { a, b, a }").unwrap();
+        match rename(&module) {
+            Err(Error::DuplicateIdentifier(e)) => {
+                assert_eq!(e.error.v.0, "a");
+                assert_eq!(e.error.a, SrcLoc::Known(Span::from_offset_len(17, 1)));
+                assert_eq!(e.original_loc, SrcLoc::Known(Span::from_offset_len(11, 1)));
+            }
+            other => panic!("expected a duplicate identifier, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bindings_are_only_visible_in_their_arm() {
+        let module = crate::parser::parse(
+            "type T = A x | B y; fn f t { match t { A v => { return v.x; } B v => { return v; } } }",
+        )
+        .unwrap();
+        assert!(rename(&module).is_ok());
+
+        let module =
+            crate::parser::parse("type T = A x; fn f t { match t { A v => {} } return v; }")
+                .unwrap();
+        let plain = rename(&module).unwrap();
+        let plain::TopLevelStmt::FunDef(f) = &plain.statements[1] else {
+            panic!("expected a function");
+        };
+        assert!(matches!(
+            &f.implementation.body.v[1],
+            plain::FunStmt::Return(ret) if matches!(ret.v.0, plain::Expr::Ident(plain::Ident::Global(_)))
+        ));
+
+        let module =
+            crate::parser::parse("type T = A x; fn f t { match t { A t => {} } }").unwrap();
+        assert!(matches!(
+            rename(&module),
+            Err(Error::DuplicateIdentifier(_))
+        ));
+    }
+
+    #[test]
+    fn result_is_not_a_parameter() {
+        let src = "fn f result { return result; }";
+        match rename(&crate::parser::parse(src).unwrap()) {
+            Err(Error::ReservedIdentifier(e)) => {
+                assert_eq!(e.identifier.v.0, RESULT);
+                assert_eq!(e.identifier.a, SrcLoc::Known(Span::from_offset_len(5, 6)));
+            }
+            other => panic!("expected a reserved identifier, got {:?}", other),
+        }
+
+        // The body can still use the name.
+        let src = "fn f n : gt? result n { let result = add n 1; return result; }";
+        assert!(rename(&crate::parser::parse(src).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn lets_are_only_visible_in_their_block() {
+        let src = "fn f c { if c > 0 { let x = 1; } return x; }";
+        let plain = rename(&crate::parser::parse(src).unwrap()).unwrap();
+        let plain::TopLevelStmt::FunDef(f) = &plain.statements[0] else {
+            panic!("expected a function");
+        };
+        assert!(matches!(
+            &f.implementation.body.v[1],
+            plain::FunStmt::Return(ret) if matches!(ret.v.0, plain::Expr::Ident(plain::Ident::Global(_)))
+        ));
+
+        // Sibling blocks can declare the same name.
+        let src =
+            "fn f c { if c { let x = 1; } else { let x = 2; } while c { let x = 3; } return c; }";
+        assert!(rename(&crate::parser::parse(src).unwrap()).is_ok());
+    }
+
+    #[test]
+    fn lambdas_capture_the_locals_they_use() {
+        let module = crate::parser::parse(
+            "fn f a b { let g = |x| { return |y| { return a + x + y; }; }; return g; }",
+        )
+        .unwrap();
+        let plain = rename(&module).unwrap();
+        let plain::TopLevelStmt::FunDef(f) = &plain.statements[0] else {
+            panic!("expected a function");
+        };
+        let plain::FunStmt::LetDecl(decl) = &f.implementation.body.v[0] else {
+            panic!("expected a let");
+        };
+        let plain::Expr::Lambda(outer) = &decl.v.value else {
+            panic!("expected a lambda");
+        };
+        assert_eq!(outer.v.captures, vec![f.implementation.params.v[0].v]);
+        let plain::FunStmt::Return(ret) = &outer.v.body.v[0] else {
+            panic!("expected a return");
+        };
+        let plain::Expr::Lambda(inner) = &ret.v.0 else {
+            panic!("expected a lambda");
+        };
+        assert_eq!(
+            inner.v.captures,
+            vec![f.implementation.params.v[0].v, outer.v.params.v[0].v]
+        );
+
+        let src = "fn f a { let g = |x| { a = x; return a; }; return g; }";
+        match rename(&crate::parser::parse(src).unwrap()) {
+            Err(Error::AssignToCapture(e)) => {
+                assert_eq!(e.assignment.v.0, "a");
+                assert_eq!(e.lambda_loc, SrcLoc::Known(Span::from_offset_len(17, 24)));
+            }
+            other => panic!("expected an assignment to a capture, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn jumps_must_be_in_a_loop() {
+        let src = "fn f n { for i in range(0, n) { while true { break; } continue; } return n; }";
+        assert!(rename(&crate::parser::parse(src).unwrap()).is_ok());
+
+        // Lambdas do not see the loops around them.
+        for (src, keyword, offset) in [
+            (
+                "fn f n { if n { break; } return n; }",
+                syntax::Jump::Break,
+                16,
+            ),
+            (
+                "fn f n { while true { let g = || { continue; }; } return n; }",
+                syntax::Jump::Continue,
+                35,
+            ),
+        ] {
+            match rename(&crate::parser::parse(src).unwrap()) {
+                Err(Error::JumpOutsideLoop(e)) => {
+                    assert_eq!(e.jump.v, keyword);
+                    assert_eq!(
+                        e.jump.a,
+                        SrcLoc::Known(Span::from_offset_len(offset, keyword.keyword().len() + 1))
+                    );
+                }
+                other => panic!("expected a jump outside of a loop, got {:?}", other),
+            }
+        }
+
+        // The loop variable is only visible in the body.
+        let src = "fn f n { for i in range(0, n) {} return i; }";
+        let plain = rename(&crate::parser::parse(src).unwrap()).unwrap();
+        let plain::TopLevelStmt::FunDef(f) = &plain.statements[0] else {
+            panic!("expected a function");
+        };
+        assert!(matches!(
+            &f.implementation.body.v[1],
+            plain::FunStmt::Return(ret) if matches!(ret.v.0, plain::Expr::Ident(plain::Ident::Global(_)))
+        ));
+    }
 }
@@ -0,0 +1,27 @@
+use crate::binder::{self, bound};
+use crate::renamer::{self, plain};
+use crate::simplifier::{self, simple};
+use crate::util::loc::SrcLoc;
+use crate::{error, parser};
+
+pub struct CompileResult {
+    pub syntax: parser::syntax::Module<SrcLoc>,
+    pub plain: plain::Module<SrcLoc, plain::Ident<SrcLoc>>,
+    pub bound: bound::Module<SrcLoc>,
+    pub simple: simple::Module<SrcLoc>,
+}
+
+/// Runs the frontend on `source`, linking it against the prelude.
+pub fn runner(source: &str) -> Result<CompileResult, error::Error> {
+    let syntax = parser::parse(source)?;
+    let plain = renamer::rename(&syntax)?;
+    let bound = binder::bind(&plain, &binder::prelude(SrcLoc::Unknown))?;
+    let simple = simplifier::simplify(&bound)?;
+
+    Ok(CompileResult {
+        syntax,
+        plain,
+        bound,
+        simple,
+    })
+}
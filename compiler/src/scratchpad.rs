@@ -1,4 +1,5 @@
-use sexpr;
+#![allow(dead_code)]
+
 use sexpr_derive::SExpr;
 
 #[derive(SExpr)]
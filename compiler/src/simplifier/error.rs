@@ -0,0 +1,17 @@
+#[derive(Debug)]
+pub enum Error<L> {
+    Unsupported(UnsupportedError<L>),
+}
+
+/// A construct that the later phases can not represent yet.
+#[derive(Debug)]
+pub struct UnsupportedError<L> {
+    pub construct: &'static str,
+    pub loc: L,
+}
+
+impl<L> From<UnsupportedError<L>> for Error<L> {
+    fn from(e: UnsupportedError<L>) -> Self {
+        Error::Unsupported(e)
+    }
+}
@@ -0,0 +1,9 @@
+pub mod optimizations;
+pub mod simple;
+
+#[allow(clippy::module_inception)]
+mod simplifier;
+pub use simplifier::*;
+
+mod error;
+pub use error::*;
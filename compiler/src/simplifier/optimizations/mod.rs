@@ -0,0 +1,20 @@
+mod prune_single_use;
+mod remove_nops;
+
+use super::simple;
+
+pub fn run<LocTy>(module: &mut simple::Module<LocTy>) {
+    for def in &mut module.defs {
+        match def {
+            simple::Def::Fun(fun) => {
+                let fun_impl = &mut fun.v.implementation;
+                prune_single_use::run(fun_impl);
+                remove_nops::run(fun_impl);
+            }
+            simple::Def::Prim(_)
+            | simple::Def::Struct(_)
+            | simple::Def::StructPred(_)
+            | simple::Def::TypePred(_) => {}
+        }
+    }
+}
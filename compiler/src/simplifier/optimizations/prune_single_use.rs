@@ -0,0 +1,61 @@
+use std::mem;
+
+use crate::simplifier::simple;
+
+// Merges a single-use assignment into the next statement, when that statement
+// only copies the single-use value:
+//
+//   $1 = call f x;
+//   y = $1;
+//
+// becomes `y = call f x;`.
+pub fn run(impl_: &mut simple::FunImpl) {
+    let body = &mut impl_.body.v;
+    let len = body.len();
+
+    let mut current_ix = 0;
+    let mut next_ix = 1;
+
+    while next_ix < len {
+        let (curr, next) = indices::indices!(&mut *body, current_ix, next_ix);
+
+        if let (
+            simple::FunStmt::Assignment(ref assignment_curr),
+            simple::FunStmt::Assignment(ref mut assignment_next),
+        ) = (&curr, next)
+        {
+            let sui_curr = assignment_target_single_use_identifier(&assignment_curr.v);
+            let sui_next = assignment_value_single_use_identifier(&assignment_next.v);
+
+            if sui_curr.is_some() && sui_curr == sui_next {
+                assignment_next.v = simple::Assignment {
+                    target: assignment_next.v.target,
+                    value: assignment_curr.v.value.clone(),
+                };
+
+                let _ = mem::replace(curr, simple::FunStmt::Nop);
+            }
+        }
+
+        current_ix += 1;
+        next_ix += 1;
+    }
+}
+
+fn assignment_target_single_use_identifier(
+    assignment: &simple::Assignment,
+) -> Option<simple::SingleUseIdent> {
+    match assignment.target {
+        simple::Ident::SingleUse(sid) => Some(sid.v),
+        _ => None,
+    }
+}
+
+fn assignment_value_single_use_identifier(
+    assignment: &simple::Assignment,
+) -> Option<simple::SingleUseIdent> {
+    match assignment.value {
+        simple::AssignmentValue::Ident(simple::Ident::SingleUse(sid)) => Some(sid.v),
+        _ => None,
+    }
+}
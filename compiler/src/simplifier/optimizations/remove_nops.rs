@@ -0,0 +1,8 @@
+use crate::simplifier::simple;
+
+pub fn run(impl_: &mut simple::FunImpl) {
+    impl_
+        .body
+        .v
+        .retain(|stmt| !matches!(stmt, simple::FunStmt::Nop));
+}
@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use sexpr_derive::SExpr;
+
+use crate::binder::bound::{self, DefId, Origin, Prim, StructDef, StructPred, TypePred};
+use crate::parser::syntax;
+use crate::renamer::plain;
+use crate::util::ax::Ax;
+use crate::util::loc::{Tag, TagMap, Tagged, WithTag};
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Module<LocTy> {
+    /// Every definition reachable from the module, indexed by `DefId`.
+    pub defs: Vec<Def<LocTy>>,
+    /// The names visible at the top-level of the module.
+    pub scope: HashMap<syntax::Ident, DefId>,
+}
+
+impl<LocTy> Module<LocTy> {
+    pub fn get(&self, id: DefId) -> &Def<LocTy> {
+        &self.defs[id.id as usize]
+    }
+
+    pub fn lookup(&self, name: &syntax::Ident) -> Option<DefId> {
+        self.scope.get(name).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (DefId, &Def<LocTy>)> {
+        self.defs
+            .iter()
+            .enumerate()
+            .map(|(ix, def)| (DefId { id: ix as u32 }, def))
+    }
+
+    pub fn funs(&self) -> impl Iterator<Item = (DefId, &Ax<LocTy, FunDecl<LocTy>>)> {
+        self.iter().filter_map(|(id, def)| match def {
+            Def::Fun(f) => Some((id, f)),
+            Def::Prim(_) | Def::Struct(_) | Def::StructPred(_) | Def::TypePred(_) => None,
+        })
+    }
+
+    pub fn module_funs(&self) -> impl Iterator<Item = (DefId, &Ax<LocTy, FunDecl<LocTy>>)> {
+        self.funs().filter(|(_, f)| f.origin == Origin::Module)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub enum Def<LocTy> {
+    Fun(Ax<LocTy, FunDecl<LocTy>>),
+    Prim(Prim),
+    /// A struct, called as its constructor.
+    Struct(Ax<LocTy, StructDef<LocTy>>),
+    StructPred(StructPred),
+    TypePred(TypePred<LocTy>),
+}
+
+impl<LocTy> Def<LocTy> {
+    pub fn name(&self) -> syntax::Ident {
+        match self {
+            Def::Fun(f) => f.name.v.clone(),
+            Def::Prim(p) => syntax::Ident(p.name().to_string()),
+            Def::Struct(s) => s.name.v.clone(),
+            Def::StructPred(p) => p.name.clone(),
+            Def::TypePred(p) => bound::struct_pred_name(&p.def.v.name.v),
+        }
+    }
+
+    pub fn arity(&self) -> usize {
+        match self {
+            Def::Fun(f) => f.arity(),
+            Def::Prim(p) => p.arity(),
+            Def::Struct(s) => s.fields.v.len(),
+            Def::StructPred(_) | Def::TypePred(_) => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct FunDecl<LocTy> {
+    pub name: Ax<LocTy, syntax::Ident>,
+    pub origin: Origin,
+    pub implementation: FunImpl,
+    pub tag_map: TagMap<LocTy>,
+}
+
+impl<LocTy> FunDecl<LocTy> {
+    pub fn arity(&self) -> usize {
+        self.implementation.parameters.v.len()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct FunImpl {
+    pub parameters: WithTag<Vec<WithTag<plain::LocalIdent>>>,
+    pub result: WithTag<plain::LocalIdent>,
+    pub preds: Vec<Pred>,
+    pub body: WithTag<Vec<FunStmt>>,
+    /// The name of each local, by id, `None` for the ones the renamer adds.
+    pub local_names: Vec<Option<syntax::Ident>>,
+}
+
+/// A predicate from the function signature. `body` computes `condition`
+/// from the parameters and, for result predicates, from `FunImpl::result`.
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Pred {
+    pub kind: PredKind,
+    pub body: WithTag<Vec<FunStmt>>,
+    pub condition: Ident,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, SExpr)]
+pub enum PredKind {
+    /// Constrains the arguments, the function is only checked on inputs
+    /// satisfying every argument predicate.
+    Argument,
+    /// Mentions `result`, must hold for whatever the function returns.
+    Result,
+}
+
+pub type LitNum = syntax::LitNum;
+pub type LitBool = syntax::LitBool;
+pub type LitStr = syntax::LitStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SingleUseIdent {
+    pub id: u32,
+}
+
+impl sexpr::SExpr for SingleUseIdent {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        sexpr::call("single-use", &[self.id])
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, SExpr)]
+pub enum Ident {
+    Local(WithTag<plain::LocalIdent>),
+    SingleUse(WithTag<SingleUseIdent>),
+}
+
+impl Tagged for Ident {
+    fn tag(&self) -> Tag {
+        match self {
+            Ident::Local(i) => i.tag(),
+            Ident::SingleUse(i) => i.tag(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub enum FunStmt {
+    Loop(WithTag<Loop>),
+    Assignment(WithTag<Assignment>),
+    SetIndex(WithTag<SetIndex>),
+    SetField(WithTag<SetField>),
+    Break(),
+    /// Goes back to the start of the innermost loop.
+    Continue(),
+    Return(Ident),
+    If(If),
+    Inv(WithTag<Inv>),
+    /// Reached when no arm of a match applies to the value, which can only
+    /// happen for values of another type. Tagged with the match.
+    NoMatch(WithTag<Ident>),
+    Nop,
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Assignment {
+    pub target: Ident,
+    pub value: AssignmentValue,
+}
+
+/// `array[index]`, tagged with the indexing expression that bounds errors
+/// point at.
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Index {
+    pub array: Ident,
+    pub index: Ident,
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct SetIndex {
+    pub target: Index,
+    pub value: Ident,
+}
+
+/// `value.name`, tagged with the field expression that errors point at.
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Field {
+    pub value: Ident,
+    pub name: syntax::Ident,
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct SetField {
+    pub target: Field,
+    pub value: Ident,
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct If {
+    pub condition: Ident,
+    pub then: WithTag<Vec<FunStmt>>,
+    pub else_: Option<WithTag<Vec<FunStmt>>>,
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Loop {
+    pub body: WithTag<Vec<FunStmt>>,
+}
+
+/// An invariant. `body` computes `condition`, and is only run when the
+/// invariant is checked.
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Inv {
+    pub body: Vec<FunStmt>,
+    pub condition: Ident,
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Call {
+    pub fun_name: WithTag<DefId>,
+    pub arguments: WithTag<Vec<Ident>>,
+}
+
+/// A call to the function value held by `fun`.
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct CallRef {
+    pub fun: Ident,
+    pub arguments: WithTag<Vec<Ident>>,
+}
+
+/// A function value, calling `fun` with `captures` before its arguments.
+/// Definitions named as values have no captures.
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub struct Closure {
+    pub fun: DefId,
+    pub captures: Vec<Ident>,
+}
+
+#[derive(Clone, Debug, PartialEq, SExpr)]
+pub enum AssignmentValue {
+    Call(WithTag<Call>),
+    CallRef(WithTag<CallRef>),
+    Closure(WithTag<Closure>),
+    Ident(Ident),
+    LitNum(WithTag<LitNum>),
+    LitBool(WithTag<LitBool>),
+    LitStr(WithTag<LitStr>),
+    Array(WithTag<Vec<Ident>>),
+    Index(WithTag<Index>),
+    Field(WithTag<Field>),
+}
+
+impl Tagged for AssignmentValue {
+    fn tag(&self) -> Tag {
+        match self {
+            AssignmentValue::Call(c) => c.tag(),
+            AssignmentValue::CallRef(c) => c.tag(),
+            AssignmentValue::Closure(c) => c.tag(),
+            AssignmentValue::Ident(i) => i.tag(),
+            AssignmentValue::LitNum(n) => n.tag(),
+            AssignmentValue::LitBool(b) => b.tag(),
+            AssignmentValue::LitStr(s) => s.tag(),
+            AssignmentValue::Array(a) => a.tag(),
+            AssignmentValue::Index(i) => i.tag(),
+            AssignmentValue::Field(f) => f.tag(),
+        }
+    }
+}
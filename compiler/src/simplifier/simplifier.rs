@@ -0,0 +1,937 @@
+use std::fmt::Debug;
+
+use super::error::Error;
+use super::optimizations;
+use crate::binder::bound::{self, DefId, Origin};
+use crate::parser::syntax;
+use crate::renamer::plain;
+use crate::simplifier::simple;
+use crate::util::ax::{ax, Ax};
+use crate::util::loc::{LocLike, Tag, TagMap, Tagged, WithTag};
+
+type Instrs = Vec<simple::FunStmt>;
+
+pub fn simplify<LocTy: LocLike + Debug>(
+    input: &bound::Module<LocTy>,
+) -> Result<simple::Module<LocTy>, Error<LocTy>> {
+    let mut lifted = Lifted {
+        first: input.defs.len(),
+        defs: vec![],
+    };
+    let mut defs = input
+        .defs
+        .iter()
+        .map(|def| match def {
+            bound::Def::Fun(fun) => fun
+                .as_ref()
+                .map(|fun| simplify_fun_decl(input, &mut lifted, fun))
+                .transpose()
+                .map(|fun| simple::Def::Fun(fun.clone_a())),
+            bound::Def::Prim(prim) => Ok(simple::Def::Prim(*prim)),
+            bound::Def::Struct(def) => Ok(simple::Def::Struct(def.clone())),
+            bound::Def::StructPred(pred) => Ok(simple::Def::StructPred(pred.clone())),
+            bound::Def::TypePred(pred) => Ok(simple::Def::TypePred(pred.clone())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    defs.extend(lifted.defs);
+
+    let mut module = simple::Module {
+        defs,
+        scope: input.scope.clone(),
+    };
+
+    optimizations::run(&mut module);
+
+    Ok(module)
+}
+
+/// The lambdas lifted out of functions, which are defined after the bound
+/// definitions.
+struct Lifted<LocTy> {
+    first: usize,
+    defs: Vec<simple::Def<LocTy>>,
+}
+
+impl<LocTy> Lifted<LocTy> {
+    fn next_id(&self) -> DefId {
+        DefId {
+            id: (self.first + self.defs.len()) as u32,
+        }
+    }
+}
+
+fn simplify_fun_decl<LocTy: LocLike + Debug>(
+    module: &bound::Module<LocTy>,
+    lifted: &mut Lifted<LocTy>,
+    fun: &bound::FunDef<LocTy>,
+) -> Result<simple::FunDecl<LocTy>, Error<LocTy>> {
+    let (implementation, tag_map) =
+        simplify_fun_impl(module, lifted, &fun.name.v, &fun.implementation)?;
+
+    Ok(simple::FunDecl {
+        name: fun.name.clone(),
+        origin: fun.origin,
+        implementation,
+        tag_map,
+    })
+}
+
+/// `module` is the one the function is bound in, to look up the structs it
+/// matches on. The lambdas in the function are added to `lifted`, named
+/// after `name`.
+fn simplify_fun_impl<LocTy: LocLike + Debug>(
+    module: &bound::Module<LocTy>,
+    lifted: &mut Lifted<LocTy>,
+    name: &syntax::Ident,
+    fun: &bound::FunImpl<LocTy>,
+) -> Result<(simple::FunImpl, TagMap<LocTy>), Error<LocTy>> {
+    let mut state =
+        SimplifyFunImplState::new(module, lifted, name.clone(), fun.result.v, &fun.local_names);
+    let mut instrs = vec![];
+
+    simplify_block(&mut state, &mut instrs, &fun.body.v)?;
+
+    let preds = fun
+        .preds
+        .v
+        .iter()
+        .map(|pred| simplify_pred(&mut state, &fun.result.v, pred))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let parameters = fun
+        .params
+        .as_ref()
+        .map(|ps| {
+            ps.iter()
+                .map(|p| p.clone().to_tagged(&mut state.tag_map))
+                .collect::<Vec<_>>()
+        })
+        .clone_a()
+        .to_tagged(&mut state.tag_map);
+    let result = fun.result.clone().to_tagged(&mut state.tag_map);
+    let body = ax(fun.body.a.clone(), instrs).to_tagged(&mut state.tag_map);
+
+    Ok((
+        simple::FunImpl {
+            parameters,
+            result,
+            preds,
+            body,
+            local_names: fun.local_names.clone(),
+        },
+        state.tag_map,
+    ))
+}
+
+fn simplify_pred<LocTy: LocLike + Debug>(
+    state: &mut SimplifyFunImplState<'_, LocTy>,
+    result: &plain::LocalIdent,
+    pred: &bound::Expr<LocTy>,
+) -> Result<simple::Pred, Error<LocTy>> {
+    let kind = if mentions_local(pred, result) {
+        simple::PredKind::Result
+    } else {
+        simple::PredKind::Argument
+    };
+
+    let mut instrs = vec![];
+    let condition = state.compile_expr(&mut instrs, pred)?;
+
+    Ok(simple::Pred {
+        kind,
+        body: ax(expr_loc(pred), instrs).to_tagged(&mut state.tag_map),
+        condition,
+    })
+}
+
+fn mentions_local<LocTy>(expr: &bound::Expr<LocTy>, local: &plain::LocalIdent) -> bool {
+    match expr {
+        plain::Expr::LitNum(_) | plain::Expr::LitStr(_) | plain::Expr::LitBool(_) => false,
+        plain::Expr::Ident(bound::Ident::Local(id)) => id.v == *local,
+        plain::Expr::Ident(bound::Ident::Global(_)) => false,
+        plain::Expr::Array(items) => items.v.iter().any(|item| mentions_local(item, local)),
+        plain::Expr::Index(index) => {
+            mentions_local(&index.array, local) || mentions_local(&index.index.v, local)
+        }
+        plain::Expr::Field(field) => mentions_local(&field.expr, local),
+        plain::Expr::FunCall(call) => {
+            matches!(&call.name, bound::Ident::Local(id) if id.v == *local)
+                || call.args.v.iter().any(|arg| mentions_local(arg, local))
+        }
+        plain::Expr::Logic(logic) => logic.args.v.iter().any(|arg| mentions_local(arg, local)),
+        plain::Expr::Lambda(lambda) => lambda.v.captures.contains(local),
+    }
+}
+
+fn expr_loc<LocTy: LocLike>(expr: &bound::Expr<LocTy>) -> LocTy {
+    match expr {
+        plain::Expr::LitNum(n) => n.a.clone(),
+        plain::Expr::LitStr(s) => s.a.clone(),
+        plain::Expr::LitBool(b) => b.a.clone(),
+        plain::Expr::Ident(id) => id.loc().clone(),
+        plain::Expr::Array(items) => items.a.clone(),
+        plain::Expr::Index(index) => LocTy::enclosing(&expr_loc(&index.array), &index.index.a),
+        plain::Expr::Field(field) => LocTy::enclosing(&expr_loc(&field.expr), &field.name.a),
+        plain::Expr::FunCall(call) => LocTy::enclosing(call.name.loc(), &call.args.a),
+        plain::Expr::Logic(logic) => LocTy::enclosing(&logic.op.a, &logic.args.a),
+        plain::Expr::Lambda(lambda) => lambda.a.clone(),
+    }
+}
+
+fn simplify_block<LocTy: LocLike + Debug>(
+    state: &mut SimplifyFunImplState<'_, LocTy>,
+    instrs: &mut Instrs,
+    stmts: &[bound::FunStmt<LocTy>],
+) -> Result<(), Error<LocTy>> {
+    for stmt in stmts.iter() {
+        match stmt {
+            plain::FunStmt::Return(ret) => {
+                let value = state.compile_expr(instrs, &ret.v.0)?;
+                instrs.push(simple::FunStmt::Return(value));
+            }
+            plain::FunStmt::Inv(inv) => {
+                let tag = state.tag_map.get_tag(inv.a.clone());
+
+                let mut inv_instrs = vec![];
+                let condition = state.compile_expr(&mut inv_instrs, &inv.v)?;
+
+                instrs.push(simple::FunStmt::Inv(ax(
+                    tag,
+                    simple::Inv {
+                        body: inv_instrs,
+                        condition,
+                    },
+                )));
+            }
+            plain::FunStmt::LetDecl(decl) => {
+                let tag = state.tag_map.get_tag(decl.a.clone());
+                let value = state.compile_expr(instrs, &decl.v.value)?;
+                instrs.push(simple::FunStmt::Assignment(ax(
+                    tag,
+                    simple::Assignment {
+                        target: simple::Ident::Local(
+                            decl.v.name.clone().to_tagged(&mut state.tag_map),
+                        ),
+                        value: simple::AssignmentValue::Ident(value),
+                    },
+                )));
+            }
+            plain::FunStmt::While(while_) => {
+                let tag = state.tag_map.get_tag(while_.a.clone());
+
+                let mut loop_instrs = vec![];
+
+                // Add the break condition
+                let condition = state.compile_expr(&mut loop_instrs, &while_.v.condition)?;
+                loop_instrs.push(simple::FunStmt::If(simple::If {
+                    condition,
+                    then: ax(condition.tag(), vec![]),
+                    else_: Some(ax(condition.tag(), vec![simple::FunStmt::Break()])),
+                }));
+
+                // Add the rest of the body
+                simplify_block(state, &mut loop_instrs, &while_.v.body.v)?;
+
+                // Insert the loop
+                let loop_ = simple::Loop {
+                    body: ax(tag, loop_instrs),
+                };
+                instrs.push(simple::FunStmt::Loop(ax(tag, loop_)));
+            }
+            // `var` is set from `next` before the body, so that `continue`
+            // goes on with the next number.
+            plain::FunStmt::For(for_) => {
+                let tag = state.tag_map.get_tag(for_.a.clone());
+                let mut local = |id: &Ax<LocTy, plain::LocalIdent>| {
+                    simple::Ident::Local(id.clone().to_tagged(&mut state.tag_map))
+                };
+                let (var, next, limit) = (
+                    local(&for_.v.var),
+                    local(&for_.v.next),
+                    local(&for_.v.limit),
+                );
+
+                let start = state.compile_expr(instrs, &for_.v.start)?;
+                instrs.push(assign(tag, next, simple::AssignmentValue::Ident(start)));
+                let end = state.compile_expr(instrs, &for_.v.end)?;
+                instrs.push(assign(tag, limit, simple::AssignmentValue::Ident(end)));
+
+                let mut loop_instrs = vec![];
+                let condition = state.compile_expr(&mut loop_instrs, &for_.v.condition)?;
+                loop_instrs.push(simple::FunStmt::If(simple::If {
+                    condition,
+                    then: ax(condition.tag(), vec![]),
+                    else_: Some(ax(condition.tag(), vec![simple::FunStmt::Break()])),
+                }));
+                loop_instrs.push(assign(tag, var, simple::AssignmentValue::Ident(next)));
+                let step = state.compile_expr(&mut loop_instrs, &for_.v.step)?;
+                loop_instrs.push(assign(tag, next, simple::AssignmentValue::Ident(step)));
+                simplify_block(state, &mut loop_instrs, &for_.v.body.v)?;
+
+                let loop_ = simple::Loop {
+                    body: ax(tag, loop_instrs),
+                };
+                instrs.push(simple::FunStmt::Loop(ax(tag, loop_)));
+            }
+            plain::FunStmt::Jump(jump) => instrs.push(match jump.v {
+                syntax::Jump::Break => simple::FunStmt::Break(),
+                syntax::Jump::Continue => simple::FunStmt::Continue(),
+            }),
+            plain::FunStmt::Assignment(assign) => {
+                let tag = state.tag_map.get_tag(assign.a.clone());
+                let value = state.compile_expr(instrs, &assign.v.value)?;
+                instrs.push(simple::FunStmt::Assignment(ax(
+                    tag,
+                    simple::Assignment {
+                        target: simple::Ident::Local(
+                            assign.v.id.clone().to_tagged(&mut state.tag_map),
+                        ),
+                        value: simple::AssignmentValue::Ident(value),
+                    },
+                )));
+            }
+            plain::FunStmt::IndexAssignment(assign) => {
+                let target = &assign.v.target;
+                let tag = state
+                    .tag_map
+                    .get_tag(LocTy::enclosing(&expr_loc(&target.array), &target.index.a));
+                let target = state.compile_index(instrs, target)?;
+                let value = state.compile_expr(instrs, &assign.v.value)?;
+                instrs.push(simple::FunStmt::SetIndex(ax(
+                    tag,
+                    simple::SetIndex { target, value },
+                )));
+            }
+            plain::FunStmt::FieldAssignment(assign) => {
+                let target = &assign.v.target;
+                let tag = state
+                    .tag_map
+                    .get_tag(LocTy::enclosing(&expr_loc(&target.expr), &target.name.a));
+                let target = simple::Field {
+                    value: state.compile_expr(instrs, &target.expr)?,
+                    name: target.name.v.clone(),
+                };
+                let value = state.compile_expr(instrs, &assign.v.value)?;
+                instrs.push(simple::FunStmt::SetField(ax(
+                    tag,
+                    simple::SetField { target, value },
+                )));
+            }
+            plain::FunStmt::If(if_) => {
+                let condition = state.compile_expr(instrs, &if_.v.condition)?;
+
+                let mut then_instrs = vec![];
+                simplify_block(state, &mut then_instrs, &if_.v.then.v)?;
+                let then = ax(if_.v.then.a.clone(), then_instrs).to_tagged(&mut state.tag_map);
+
+                let else_ = match &if_.v.else_ {
+                    Some(else_) => {
+                        let mut else_instrs = vec![];
+                        simplify_block(state, &mut else_instrs, &else_.v)?;
+                        Some(ax(else_.a.clone(), else_instrs).to_tagged(&mut state.tag_map))
+                    }
+                    None => None,
+                };
+
+                instrs.push(simple::FunStmt::If(simple::If {
+                    condition,
+                    then,
+                    else_,
+                }));
+            }
+            plain::FunStmt::Match(match_) => simplify_match(state, instrs, match_)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Lowers a match into a chain of `If`s testing the predicate of each
+/// variant, where the arm that applies first reads the fields into its
+/// bindings:
+///
+///   match v { Leaf => { a } Node l r => { b } }
+///     =>  if Leaf? v { a } else { if Node? v { l = v.left; r = v.right; b }
+///         else { no-match v } }
+fn simplify_match<LocTy: LocLike + Debug>(
+    state: &mut SimplifyFunImplState<'_, LocTy>,
+    instrs: &mut Instrs,
+    match_: &Ax<LocTy, bound::Match<LocTy>>,
+) -> Result<(), Error<LocTy>> {
+    let value = state.compile_expr(instrs, &match_.v.value)?;
+
+    let mut tests = vec![];
+    for arm in match_.v.arms.v.iter() {
+        let bound::Ident::Global(variant) = &arm.variant else {
+            unreachable!("the binder makes variants global")
+        };
+        let bound::Def::Struct(def) = state.module.get(variant.v) else {
+            unreachable!("the binder only matches on variants")
+        };
+
+        let tag = state
+            .tag_map
+            .get_tag(LocTy::enclosing(&variant.a, &arm.body.a));
+        let pred = ax(variant.a.clone(), state.module.struct_pred(variant.v));
+        let condition = simple::Ident::SingleUse(ax(tag, state.get_single_use_identifier()));
+        let call = simple::Call {
+            fun_name: pred.to_tagged(&mut state.tag_map),
+            arguments: ax(tag, vec![value]),
+        };
+        let test = assign(tag, condition, simple::AssignmentValue::Call(ax(tag, call)));
+
+        let mut then = vec![];
+        for (binding, field) in arm.bindings.v.iter().zip(def.v.fields.v.iter()) {
+            let binding = binding.clone().to_tagged(&mut state.tag_map);
+            let field = simple::Field {
+                value,
+                name: field.v.clone(),
+            };
+            then.push(assign(
+                binding.tag(),
+                simple::Ident::Local(binding),
+                simple::AssignmentValue::Field(ax(binding.tag(), field)),
+            ));
+        }
+        simplify_block(state, &mut then, &arm.body.v)?;
+        let then = ax(arm.body.a.clone(), then).to_tagged(&mut state.tag_map);
+
+        tests.push((tag, test, condition, then));
+    }
+
+    let tag = state.tag_map.get_tag(match_.a.clone());
+    let mut else_ = vec![simple::FunStmt::NoMatch(ax(tag, value))];
+    for (tag, test, condition, then) in tests.into_iter().rev() {
+        let if_ = simple::FunStmt::If(simple::If {
+            condition,
+            then,
+            else_: Some(ax(tag, else_)),
+        });
+        else_ = vec![test, if_];
+    }
+    instrs.extend(else_);
+
+    Ok(())
+}
+
+struct SimplifyFunImplState<'t, LocTy> {
+    module: &'t bound::Module<LocTy>,
+    lifted: &'t mut Lifted<LocTy>,
+    /// The name of the function, which its lambdas are named after.
+    name: syntax::Ident,
+    /// The return value of the enclosing function, lambdas have no
+    /// predicates to use their own.
+    result: plain::LocalIdent,
+    /// The names of the locals of the enclosing function, which its lambdas
+    /// share.
+    local_names: &'t [Option<syntax::Ident>],
+    lambdas: u32,
+    next_single_use_identifier: u32,
+    tag_map: TagMap<LocTy>,
+}
+
+impl<'t, LocTy: LocLike + Debug> SimplifyFunImplState<'t, LocTy> {
+    fn new(
+        module: &'t bound::Module<LocTy>,
+        lifted: &'t mut Lifted<LocTy>,
+        name: syntax::Ident,
+        result: plain::LocalIdent,
+        local_names: &'t [Option<syntax::Ident>],
+    ) -> Self {
+        SimplifyFunImplState {
+            module,
+            lifted,
+            name,
+            result,
+            local_names,
+            lambdas: 0,
+            next_single_use_identifier: 1,
+            tag_map: TagMap::new(),
+        }
+    }
+
+    fn get_single_use_identifier(&mut self) -> simple::SingleUseIdent {
+        let id = simple::SingleUseIdent {
+            id: self.next_single_use_identifier,
+        };
+        self.next_single_use_identifier += 1;
+        id
+    }
+
+    fn compile_expr(
+        &mut self,
+        instrs: &mut Instrs,
+        expr: &bound::Expr<LocTy>,
+    ) -> Result<simple::Ident, Error<LocTy>> {
+        match expr {
+            plain::Expr::LitNum(n) => {
+                let tag = self.tag_map.get_tag(n.a.clone());
+
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+                instrs.push(simple::FunStmt::Assignment(ax(
+                    tag,
+                    simple::Assignment {
+                        target,
+                        value: simple::AssignmentValue::LitNum(ax(tag, n.v)),
+                    },
+                )));
+
+                Ok(target)
+            }
+            plain::Expr::LitBool(b) => {
+                let tag = self.tag_map.get_tag(b.a.clone());
+
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+                instrs.push(simple::FunStmt::Assignment(ax(
+                    tag,
+                    simple::Assignment {
+                        target,
+                        value: simple::AssignmentValue::LitBool(ax(tag, b.v)),
+                    },
+                )));
+
+                Ok(target)
+            }
+            plain::Expr::LitStr(s) => {
+                let tag = self.tag_map.get_tag(s.a.clone());
+
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+                instrs.push(assign(
+                    tag,
+                    target,
+                    simple::AssignmentValue::LitStr(ax(tag, s.v.clone())),
+                ));
+
+                Ok(target)
+            }
+            plain::Expr::Ident(id) => self.compile_ident(instrs, id),
+            plain::Expr::Array(items) => {
+                let mut values = vec![];
+                for item in items.v.iter() {
+                    values.push(self.compile_expr(instrs, item)?);
+                }
+
+                let tag = self.tag_map.get_tag(items.a.clone());
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+                instrs.push(assign(
+                    tag,
+                    target,
+                    simple::AssignmentValue::Array(ax(tag, values)),
+                ));
+
+                Ok(target)
+            }
+            plain::Expr::Index(index) => {
+                let value = self.compile_index(instrs, index)?;
+
+                let tag = self.tag_map.get_tag(expr_loc(expr));
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+                instrs.push(assign(
+                    tag,
+                    target,
+                    simple::AssignmentValue::Index(ax(tag, value)),
+                ));
+
+                Ok(target)
+            }
+            plain::Expr::Field(field) => {
+                let value = simple::Field {
+                    value: self.compile_expr(instrs, &field.expr)?,
+                    name: field.name.v.clone(),
+                };
+
+                let tag = self.tag_map.get_tag(expr_loc(expr));
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+                instrs.push(assign(
+                    tag,
+                    target,
+                    simple::AssignmentValue::Field(ax(tag, value)),
+                ));
+
+                Ok(target)
+            }
+            plain::Expr::FunCall(fun) => {
+                let mut ps = vec![];
+                for arg in fun.args.v.iter() {
+                    ps.push(self.compile_expr(instrs, arg)?);
+                }
+
+                let tag = self.tag_map.get_tag(expr_loc(expr));
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+
+                let value = match &fun.name {
+                    bound::Ident::Global(id) => simple::AssignmentValue::Call(ax(
+                        tag,
+                        simple::Call {
+                            fun_name: id.clone().to_tagged(&mut self.tag_map),
+                            arguments: ax(tag, ps),
+                        },
+                    )),
+                    bound::Ident::Local(id) => simple::AssignmentValue::CallRef(ax(
+                        tag,
+                        simple::CallRef {
+                            fun: simple::Ident::Local(id.clone().to_tagged(&mut self.tag_map)),
+                            arguments: ax(tag, ps),
+                        },
+                    )),
+                };
+                instrs.push(assign(tag, target, value));
+
+                Ok(target)
+            }
+            plain::Expr::Logic(logic) => self.compile_logic(instrs, expr_loc(expr), logic),
+            plain::Expr::Lambda(lambda) => self.compile_lambda(instrs, lambda),
+        }
+    }
+
+    /// Lifts a lambda into a function of its own, taking the captures
+    /// before the parameters, and builds a closure of it:
+    ///
+    ///   |x| { return add x y; }  =>  $c = closure f/lambda1 y;
+    ///   fn f/lambda1 y x { return add x y; }
+    fn compile_lambda(
+        &mut self,
+        instrs: &mut Instrs,
+        lambda: &Ax<LocTy, bound::Lambda<LocTy>>,
+    ) -> Result<simple::Ident, Error<LocTy>> {
+        let name = syntax::Ident(format!("{}/lambda{}", self.name.0, self.lambdas));
+        self.lambdas += 1;
+
+        let mut state = SimplifyFunImplState::new(
+            self.module,
+            self.lifted,
+            name.clone(),
+            self.result,
+            self.local_names,
+        );
+        let mut body = vec![];
+        simplify_block(&mut state, &mut body, &lambda.v.body.v)?;
+
+        let captures = lambda
+            .v
+            .captures
+            .iter()
+            .map(|capture| ax(lambda.a.clone(), *capture));
+        let parameters = captures
+            .clone()
+            .chain(lambda.v.params.v.iter().cloned())
+            .map(|param| param.to_tagged(&mut state.tag_map))
+            .collect::<Vec<_>>();
+        let implementation = simple::FunImpl {
+            parameters: ax(lambda.v.params.a.clone(), parameters).to_tagged(&mut state.tag_map),
+            result: ax(lambda.a.clone(), self.result).to_tagged(&mut state.tag_map),
+            preds: vec![],
+            body: ax(lambda.v.body.a.clone(), body).to_tagged(&mut state.tag_map),
+            local_names: self.local_names.to_vec(),
+        };
+        let decl = simple::FunDecl {
+            name: ax(lambda.a.clone(), name),
+            origin: Origin::Lambda,
+            implementation,
+            tag_map: state.tag_map,
+        };
+
+        // Lambdas in the body were lifted first.
+        let fun = self.lifted.next_id();
+        self.lifted
+            .defs
+            .push(simple::Def::Fun(ax(lambda.a.clone(), decl)));
+
+        let tag = self.tag_map.get_tag(lambda.a.clone());
+        let captures = captures
+            .map(|capture| simple::Ident::Local(capture.to_tagged(&mut self.tag_map)))
+            .collect();
+        let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+        instrs.push(assign(
+            tag,
+            target,
+            simple::AssignmentValue::Closure(ax(tag, simple::Closure { fun, captures })),
+        ));
+
+        Ok(target)
+    }
+
+    fn compile_index(
+        &mut self,
+        instrs: &mut Instrs,
+        index: &bound::Index<LocTy>,
+    ) -> Result<simple::Index, Error<LocTy>> {
+        Ok(simple::Index {
+            array: self.compile_expr(instrs, &index.array)?,
+            index: self.compile_expr(instrs, &index.index.v)?,
+        })
+    }
+
+    /// Lowers a short-circuiting operator into an `If` on its first operand,
+    /// which assigns the result in both branches:
+    ///
+    ///   a and b  =>  if a { $r = b; } else { $r = false; }
+    ///   a or b   =>  if a { $r = true; } else { $r = b; }
+    ///   not a    =>  if a { $r = false; } else { $r = true; }
+    ///
+    /// The second operand is only computed in the branch that needs it.
+    fn compile_logic(
+        &mut self,
+        instrs: &mut Instrs,
+        loc: LocTy,
+        logic: &plain::Logic<LocTy, bound::Ident<LocTy>>,
+    ) -> Result<simple::Ident, Error<LocTy>> {
+        let condition = self.compile_expr(instrs, &logic.args.v[0])?;
+
+        let tag = self.tag_map.get_tag(loc);
+        let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+
+        let literal = |b: bool| {
+            let value = simple::AssignmentValue::LitBool(ax(tag, syntax::LitBool(b)));
+            ax(tag, vec![assign(tag, target, value)])
+        };
+        let (then, else_) = match logic.op.v {
+            plain::LogicOp::And => (
+                self.compile_operand(tag, target, &logic.args.v[1])?,
+                literal(false),
+            ),
+            plain::LogicOp::Or => (
+                literal(true),
+                self.compile_operand(tag, target, &logic.args.v[1])?,
+            ),
+            plain::LogicOp::Not => (literal(false), literal(true)),
+        };
+
+        instrs.push(simple::FunStmt::If(simple::If {
+            condition,
+            then,
+            else_: Some(else_),
+        }));
+
+        Ok(target)
+    }
+
+    /// A branch computing `operand` into `target`.
+    fn compile_operand(
+        &mut self,
+        tag: Tag,
+        target: simple::Ident,
+        operand: &bound::Expr<LocTy>,
+    ) -> Result<WithTag<Instrs>, Error<LocTy>> {
+        let mut instrs = vec![];
+        let value = self.compile_expr(&mut instrs, operand)?;
+        instrs.push(assign(tag, target, simple::AssignmentValue::Ident(value)));
+        Ok(ax(tag, instrs))
+    }
+
+    /// A definition named as a value is a closure without captures.
+    fn compile_ident(
+        &mut self,
+        instrs: &mut Instrs,
+        id: &bound::Ident<LocTy>,
+    ) -> Result<simple::Ident, Error<LocTy>> {
+        match id {
+            bound::Ident::Local(id) => Ok(simple::Ident::Local(
+                id.clone().to_tagged(&mut self.tag_map),
+            )),
+            bound::Ident::Global(id) => {
+                let tag = self.tag_map.get_tag(id.a.clone());
+                let target = simple::Ident::SingleUse(ax(tag, self.get_single_use_identifier()));
+                let closure = simple::Closure {
+                    fun: id.v,
+                    captures: vec![],
+                };
+                instrs.push(assign(
+                    tag,
+                    target,
+                    simple::AssignmentValue::Closure(ax(tag, closure)),
+                ));
+                Ok(target)
+            }
+        }
+    }
+}
+
+fn assign(tag: Tag, target: simple::Ident, value: simple::AssignmentValue) -> simple::FunStmt {
+    simple::FunStmt::Assignment(ax(tag, simple::Assignment { target, value }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{self, syntax};
+    use crate::util::loc::{Span, SrcLoc};
+    use crate::{binder, renamer};
+
+    fn simplify_src(src: &str) -> Result<simple::Module<SrcLoc>, Error<SrcLoc>> {
+        let syntax = parser::parse(src).unwrap();
+        let plain = renamer::rename(&syntax).unwrap();
+        let bound = binder::bind(&plain, &binder::prelude(SrcLoc::Unknown)).unwrap();
+        simplify(&bound)
+    }
+
+    fn fun<'t>(module: &'t simple::Module<SrcLoc>, name: &str) -> &'t simple::FunDecl<SrcLoc> {
+        let id = module.lookup(&syntax::Ident(name.to_string())).unwrap();
+        match module.get(id) {
+            simple::Def::Fun(f) => &f.v,
+            other => panic!("unexpected definition {:?}", other),
+        }
+    }
+
+    #[test]
+    fn single_use_calls_are_pruned() {
+        let module = simplify_src("fn f x { let y = add x 1; return y; }").unwrap();
+        let add = module.lookup(&syntax::Ident("add".to_string())).unwrap();
+
+        let body = &fun(&module, "f").implementation.body.v;
+        match body.as_slice() {
+            [simple::FunStmt::Assignment(lit), simple::FunStmt::Assignment(call), simple::FunStmt::Return(simple::Ident::Local(_))] =>
+            {
+                assert!(matches!(lit.v.value, simple::AssignmentValue::LitNum(_)));
+                assert!(matches!(call.v.target, simple::Ident::Local(_)));
+                match &call.v.value {
+                    simple::AssignmentValue::Call(c) => assert_eq!(c.v.fun_name.v, add),
+                    other => panic!("unexpected value {:?}", other),
+                }
+            }
+            other => panic!("unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn while_becomes_loop_with_break() {
+        let module = simplify_src("fn f x { while lt? x 10 { x = add x 1; } return x; }").unwrap();
+
+        let body = &fun(&module, "f").implementation.body.v;
+        let loop_ = match &body[0] {
+            simple::FunStmt::Loop(l) => &l.v,
+            other => panic!("unexpected statement {:?}", other),
+        };
+        let check = loop_
+            .body
+            .v
+            .iter()
+            .find_map(|stmt| match stmt {
+                simple::FunStmt::If(if_) => Some(if_),
+                _ => None,
+            })
+            .unwrap();
+        assert!(check.then.v.is_empty());
+        assert!(matches!(
+            check.else_.as_ref().unwrap().v.as_slice(),
+            [simple::FunStmt::Break()]
+        ));
+    }
+
+    #[test]
+    fn and_only_computes_its_right_operand_when_needed() {
+        let module = simplify_src("fn f x { return x > 0 and x < 10; }").unwrap();
+        let lt = module.lookup(&syntax::Ident("lt?".to_string())).unwrap();
+        let calls_lt = |stmts: &[simple::FunStmt]| {
+            stmts.iter().any(|stmt| {
+                matches!(stmt, simple::FunStmt::Assignment(a)
+                    if matches!(&a.v.value, simple::AssignmentValue::Call(c) if c.v.fun_name.v == lt))
+            })
+        };
+
+        let body = &fun(&module, "f").implementation.body.v;
+        assert!(!calls_lt(body));
+        let if_ = body
+            .iter()
+            .find_map(|stmt| match stmt {
+                simple::FunStmt::If(if_) => Some(if_),
+                _ => None,
+            })
+            .unwrap();
+        assert!(calls_lt(&if_.then.v));
+        assert!(matches!(
+            if_.else_.as_ref().unwrap().v.as_slice(),
+            [simple::FunStmt::Assignment(a)]
+                if a.v.value == simple::AssignmentValue::LitBool(ax(a.tag(), syntax::LitBool(false)))
+        ));
+    }
+
+    #[test]
+    fn tags_map_back_to_source() {
+        let src = "fn f { return 42; }";
+        let module = simplify_src(src).unwrap();
+
+        let f = fun(&module, "f");
+        let lit = match &f.implementation.body.v[0] {
+            simple::FunStmt::Assignment(a) => a.tag(),
+            other => panic!("unexpected statement {:?}", other),
+        };
+        assert_eq!(
+            f.tag_map.resolve_tag(lit),
+            Some(&SrcLoc::Known(Span::from_offset_bytes(14, 16)))
+        );
+    }
+
+    #[test]
+    fn invariants_keep_their_own_body() {
+        let module = simplify_src("fn f x { invariant gt? x 0; return x; }").unwrap();
+
+        match fun(&module, "f").implementation.body.v.as_slice() {
+            [simple::FunStmt::Inv(inv), simple::FunStmt::Return(_)] => {
+                assert_eq!(inv.v.body.len(), 2);
+            }
+            other => panic!("unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn preds_are_classified_by_result() {
+        let module =
+            simplify_src("fn f n : number? n : gt? result n { let result = n; return result; }")
+                .unwrap();
+
+        let kinds = fun(&module, "f")
+            .implementation
+            .preds
+            .iter()
+            .map(|p| p.kind)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![simple::PredKind::Argument, simple::PredKind::Result]
+        );
+    }
+
+    #[test]
+    fn string_literals_are_assigned() {
+        let module = simplify_src("fn f { return \"hello\"; }").unwrap();
+        let body = &fun(&module, "f").implementation.body.v;
+        match body.as_slice() {
+            [simple::FunStmt::Assignment(lit), simple::FunStmt::Return(_)] => match &lit.v.value {
+                simple::AssignmentValue::LitStr(s) => assert_eq!(s.v.0, "hello"),
+                other => panic!("unexpected value {:?}", other),
+            },
+            other => panic!("unexpected body {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lambdas_are_lifted_with_their_captures_first() {
+        let module = simplify_src("fn f a { return |x| { return a + x; }; }").unwrap();
+        let (id, lambda) = module
+            .funs()
+            .find(|(_, f)| f.v.name.v.0 == "f/lambda0")
+            .unwrap();
+        assert_eq!(lambda.v.origin, Origin::Lambda);
+        assert_eq!(lambda.v.arity(), 2);
+
+        let f = fun(&module, "f");
+        let body = &f.implementation.body.v;
+        match body.as_slice() {
+            [simple::FunStmt::Assignment(closure), simple::FunStmt::Return(_)] => {
+                match &closure.v.value {
+                    simple::AssignmentValue::Closure(c) => {
+                        assert_eq!(c.v.fun, id);
+                        let a = f.implementation.parameters.v[0].v;
+                        assert!(matches!(
+                            c.v.captures.as_slice(),
+                            [simple::Ident::Local(l)] if l.v == a
+                        ));
+                    }
+                    other => panic!("unexpected value {:?}", other),
+                }
+            }
+            other => panic!("unexpected body {:?}", other),
+        }
+    }
+}
@@ -15,6 +15,13 @@ impl<_Attachment, Value> Deref for Ax<_Attachment, Value> {
     }
 }
 
+/// Attachments are left out, only the value is printed.
+impl<Attachment, Value: sexpr::SExpr> sexpr::SExpr for Ax<Attachment, Value> {
+    fn to_sexpr(&self) -> sexpr::SExprTerm {
+        self.v.to_sexpr()
+    }
+}
+
 pub fn ax<A, V>(a: A, v: V) -> Ax<A, V> {
     Ax { a, v }
 }
@@ -39,16 +39,32 @@ where
     }
 }
 
+impl<K, V> Default for Bimap<K, V>
+where
+    K: Eq + std::hash::Hash,
+    V: Eq + std::hash::Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<K, V> SExpr for Bimap<K, V>
 where
     K: SExpr + Eq + std::hash::Hash,
     V: SExpr + Eq + std::hash::Hash,
 {
     fn to_sexpr(&self) -> sexpr::SExprTerm {
+        let mut entries = self
+            .bimap
+            .iter()
+            .map(|(k, v)| (k.to_sexpr(), v.to_sexpr()))
+            .collect::<Vec<_>>();
+        entries.sort_by_cached_key(|(k, _)| k.to_string());
         sexpr::SExprTerm::List(
-            self.bimap
-                .iter()
-                .map(|(k, v)| sexpr::list(&[k.to_sexpr(), v.to_sexpr()]))
+            entries
+                .into_iter()
+                .map(|(k, v)| sexpr::list(&[k, v]))
                 .collect::<Vec<_>>(),
         )
     }
@@ -10,12 +10,6 @@ pub type WithLoc<T> = Ax<SrcLoc, T>;
 
 pub type LVec<T> = WithLoc<Vec<T>>;
 
-impl<T: sexpr::SExpr> sexpr::SExpr for WithLoc<T> {
-    fn to_sexpr(&self) -> sexpr::SExprTerm {
-        self.v.to_sexpr()
-    }
-}
-
 impl<T> WithLoc<T> {
     pub fn known(value: T, location: Span) -> WithLoc<T> {
         ax(SrcLoc::Known(location), value)
@@ -24,8 +18,10 @@ impl<T> WithLoc<T> {
     pub fn unknown(value: T) -> WithLoc<T> {
         ax(SrcLoc::Unknown, value)
     }
+}
 
-    pub fn to_tagged(self, tag_map: &mut TagMap) -> WithTag<T> {
+impl<LocTy: Clone, T> Ax<LocTy, T> {
+    pub fn to_tagged(self, tag_map: &mut TagMap<LocTy>) -> WithTag<T> {
         let tag = tag_map.get_tag(self.a);
         ax(tag, self.v)
     }
@@ -94,6 +90,10 @@ impl Span {
             length: len,
         }
     }
+    /// The offset just after the span.
+    pub fn end(&self) -> usize {
+        self.start.0 + self.length
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SExpr)]
@@ -121,7 +121,7 @@ impl SrcLoc {
             .fold(SrcLoc::Unknown, |acc, &loc| SrcLoc::enclosing(&acc, &loc))
     }
 
-    pub fn to_tag(self, tag_map: &mut TagMap) -> Tag {
+    pub fn to_tag(self, tag_map: &mut TagMap<SrcLoc>) -> Tag {
         tag_map.get_tag(self)
     }
 }
@@ -139,13 +139,7 @@ impl sexpr::SExpr for Tag {
     }
 }
 
-type WithTag<T> = Ax<Tag, T>;
-
-impl<T: sexpr::SExpr> sexpr::SExpr for WithTag<T> {
-    fn to_sexpr(&self) -> sexpr::SExprTerm {
-        self.v.to_sexpr()
-    }
-}
+pub type WithTag<T> = Ax<Tag, T>;
 
 pub trait Tagged {
     fn tag(&self) -> Tag;
@@ -157,33 +151,39 @@ impl<T> Tagged for WithTag<T> {
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct TagMap {
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagMap<LocTy> {
     next_tag: u32,
-    map: HashMap<Tag, SrcLoc>,
+    map: HashMap<Tag, LocTy>,
 }
 
-impl SExpr for TagMap {
+impl<LocTy: SExpr> SExpr for TagMap<LocTy> {
     fn to_sexpr(&self) -> sexpr::SExprTerm {
-        sexpr::call("tag-map", &[self.map.to_sexpr()])
+        let mut entries = self.map.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(tag, _)| tag.value);
+        let entries = entries
+            .into_iter()
+            .map(|(tag, loc)| sexpr::list(&[tag.to_sexpr(), loc.to_sexpr()]))
+            .collect::<Vec<_>>();
+        sexpr::call("tag-map", &entries)
     }
 }
 
-impl Default for TagMap {
+impl<LocTy> Default for TagMap<LocTy> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl TagMap {
-    pub fn new() -> TagMap {
+impl<LocTy> TagMap<LocTy> {
+    pub fn new() -> TagMap<LocTy> {
         TagMap {
             next_tag: 0,
             map: HashMap::new(),
         }
     }
 
-    pub fn get_tag(&mut self, loc: SrcLoc) -> Tag {
+    pub fn get_tag(&mut self, loc: LocTy) -> Tag {
         let tag = Tag {
             value: self.next_tag,
         };
@@ -193,11 +193,8 @@ impl TagMap {
         tag
     }
 
-    pub fn resolve_tag(&self, tag: Tag) -> SrcLoc {
-        match self.map.get(&tag) {
-            Some(loc) => *loc,
-            None => SrcLoc::Unknown,
-        }
+    pub fn resolve_tag(&self, tag: Tag) -> Option<&LocTy> {
+        self.map.get(&tag)
     }
 }
 
@@ -212,7 +209,5 @@ impl LocLike for SrcLoc {
 }
 
 impl LocLike for () {
-    fn enclosing(_: &Self, _: &Self) -> Self {
-        ()
-    }
+    fn enclosing(_: &Self, _: &Self) -> Self {}
 }
@@ -0,0 +1,771 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use sha2::{Digest, Sha256};
+
+use super::encode::{self, decode, encode};
+use super::shrink::ShrinkStep;
+use super::verify::{run_case, verify_fun, Counterexample, Failure, FunctionReport, Outcome};
+use super::verify::{Verdict, VerifyOptions};
+use crate::binder::bound::DefId;
+use crate::hash::FunHash;
+use crate::interpreter::KediValue;
+use crate::simplifier::simple;
+
+/// The cache directory used when none is given.
+pub const DEFAULT_CACHE_DIR: &str = ".kedi-cache";
+
+/// Bumped whenever the entry format changes, so that older entries are
+/// rejected rather than misread.
+const CHECKSUM_VERSION: &str = "kedi-verdict-v4";
+
+/// What is remembered about one predicate of a function.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    pub verdict: CachedVerdict,
+    /// Number of inputs the function had to pass.
+    pub cases: u32,
+    pub seed: u64,
+    pub limits: CheckLimits,
+}
+
+/// The options a verdict depends on, besides the seed and the number of
+/// cases. The time limit is left out, running out of time is not
+/// reproducible anyway.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckLimits {
+    pub max_size: u64,
+    pub max_discards: u32,
+    pub max_shrinks: u32,
+    pub fuel_limit: Option<u64>,
+    pub max_depth: usize,
+    pub check_invariants: bool,
+}
+
+impl CheckLimits {
+    pub fn of(options: &VerifyOptions) -> Self {
+        CheckLimits {
+            max_size: options.max_size,
+            max_discards: options.max_discards,
+            max_shrinks: options.max_shrinks,
+            fuel_limit: options.interpret.fuel_limit,
+            max_depth: options.interpret.max_depth,
+            check_invariants: options.interpret.check_invariants,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CachedVerdict {
+    Passed,
+    /// The minimized arguments failing the predicate, and how they were
+    /// minimized.
+    Failed {
+        args: Vec<KediValue>,
+        shrinks: Vec<ShrinkStep>,
+    },
+    /// Only `passed` inputs satisfied the argument predicates.
+    GaveUp {
+        passed: u32,
+        discarded: u32,
+    },
+}
+
+impl CacheEntry {
+    /// Whether the entry answers for a run with `options`. Failures are
+    /// replayed under `options`, so they answer whatever the options. Other
+    /// verdicts need the same limits, and passing only counts if at least as
+    /// many inputs were checked.
+    fn answers(&self, options: &VerifyOptions) -> bool {
+        let same = self.seed == options.seed && self.limits == CheckLimits::of(options);
+        match self.verdict {
+            CachedVerdict::Failed { .. } => true,
+            CachedVerdict::Passed => same && self.cases >= options.cases,
+            CachedVerdict::GaveUp { .. } => same && self.cases == options.cases,
+        }
+    }
+}
+
+/// One `key value` pair per line, e.g. `verdict failed` and `cases 100`.
+/// Counterexamples take one `arg` line per encoded argument, since an
+/// argument can contain spaces, and one `shrink <arg> <from>` line per
+/// shrinking step.
+impl fmt::Display for CacheEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.verdict {
+            CachedVerdict::Passed => writeln!(f, "verdict passed")?,
+            CachedVerdict::Failed { .. } => writeln!(f, "verdict failed")?,
+            CachedVerdict::GaveUp { .. } => writeln!(f, "verdict gave-up")?,
+        }
+        writeln!(f, "cases {}", self.cases)?;
+        writeln!(f, "seed {}", self.seed)?;
+        writeln!(f, "max-size {}", self.limits.max_size)?;
+        writeln!(f, "max-discards {}", self.limits.max_discards)?;
+        writeln!(f, "max-shrinks {}", self.limits.max_shrinks)?;
+        writeln!(f, "fuel-limit {}", optional(self.limits.fuel_limit))?;
+        writeln!(f, "max-depth {}", self.limits.max_depth)?;
+        writeln!(f, "invariants {}", self.limits.check_invariants)?;
+        match &self.verdict {
+            CachedVerdict::Passed => {}
+            CachedVerdict::Failed { args, shrinks } => {
+                for arg in args {
+                    writeln!(f, "arg {}", encode(arg))?;
+                }
+                for step in shrinks {
+                    writeln!(f, "shrink {} {}", step.arg, encode(&step.from))?;
+                }
+            }
+            CachedVerdict::GaveUp { passed, discarded } => {
+                writeln!(f, "passed {}", passed)?;
+                writeln!(f, "discarded {}", discarded)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for CacheEntry {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lines = s
+            .lines()
+            .map(|line| line.split_once(' ').unwrap_or((line, "")))
+            .collect::<Vec<_>>();
+        let fields = lines.iter().copied().collect::<HashMap<_, _>>();
+        let field = |key: &str| fields.get(key).copied().ok_or(());
+
+        let verdict = match field("verdict")? {
+            "passed" => CachedVerdict::Passed,
+            "failed" => {
+                let args = lines
+                    .iter()
+                    .filter(|(key, _)| *key == "arg")
+                    .map(|(_, value)| decode(value))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(())?;
+                let steps = lines
+                    .iter()
+                    .filter(|(key, _)| *key == "shrink")
+                    .map(|(_, value)| {
+                        let (arg, from) = value.split_once(' ')?;
+                        Some((arg.parse().ok()?, decode(from)?))
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or(())?;
+                let shrinks = rebuild_shrinks(&args, steps).ok_or(())?;
+                CachedVerdict::Failed { args, shrinks }
+            }
+            "gave-up" => CachedVerdict::GaveUp {
+                passed: field("passed")?.parse().map_err(|_| ())?,
+                discarded: field("discarded")?.parse().map_err(|_| ())?,
+            },
+            _ => return Err(()),
+        };
+        Ok(CacheEntry {
+            verdict,
+            cases: field("cases")?.parse().map_err(|_| ())?,
+            seed: field("seed")?.parse().map_err(|_| ())?,
+            limits: CheckLimits {
+                max_size: field("max-size")?.parse().map_err(|_| ())?,
+                max_discards: field("max-discards")?.parse().map_err(|_| ())?,
+                max_shrinks: field("max-shrinks")?.parse().map_err(|_| ())?,
+                fuel_limit: parse_optional(field("fuel-limit")?)?,
+                max_depth: field("max-depth")?.parse().map_err(|_| ())?,
+                check_invariants: field("invariants")?.parse().map_err(|_| ())?,
+            },
+        })
+    }
+}
+
+fn optional<T: fmt::Display>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+fn parse_optional<T: FromStr>(value: &str) -> Result<Option<T>, ()> {
+    match value {
+        "none" => Ok(None),
+        value => value.parse().map(Some).map_err(|_| ()),
+    }
+}
+
+/// The steps taking the generated arguments to `args`, from the argument
+/// and the value each step replaced. A step replaced its value by the one
+/// the next step on the same argument replaced, or by the final argument.
+fn rebuild_shrinks(args: &[KediValue], steps: Vec<(usize, KediValue)>) -> Option<Vec<ShrinkStep>> {
+    let mut current = args.to_vec();
+    let mut ret = steps
+        .into_iter()
+        .rev()
+        .map(|(arg, from)| {
+            let to = std::mem::replace(current.get_mut(arg)?, from.clone());
+            Some(ShrinkStep { arg, from, to })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    ret.reverse();
+    Some(ret)
+}
+
+impl CacheEntry {
+    /// The entry as stored, followed by a `checksum` line covering both the
+    /// entry and the key it is stored under.
+    pub fn encode(&self, hash: &FunHash, pred: usize) -> String {
+        let body = self.to_string();
+        format!("{}checksum {}\n", body, checksum(hash, pred, &body))
+    }
+
+    /// Parses an encoded entry, rejecting it if it was corrupted or is stored
+    /// under a different key.
+    pub fn decode(contents: &str, hash: &FunHash, pred: usize) -> Option<CacheEntry> {
+        let (body, sum) = contents.strip_suffix('\n')?.rsplit_once('\n')?;
+        let body = format!("{}\n", body);
+        if sum.strip_prefix("checksum ")? != checksum(hash, pred, &body) {
+            return None;
+        }
+        body.parse().ok()
+    }
+}
+
+fn checksum(hash: &FunHash, pred: usize, body: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{}\n{}\n{}\n{}",
+        CHECKSUM_VERSION, hash, pred, body
+    ));
+    FunHash(hasher.finalize().into()).to_string()
+}
+
+/// Where verdicts are kept across runs, by function hash and predicate index.
+pub trait VerdictStore {
+    /// Looks up an entry, corrupted entries count as missing.
+    fn get(&self, hash: &FunHash, pred: usize) -> io::Result<Option<CacheEntry>>;
+
+    fn put(&self, hash: &FunHash, pred: usize, entry: &CacheEntry) -> io::Result<()>;
+}
+
+/// Verdicts of previous runs, keyed by function hash and predicate index.
+///
+/// On disk, every function hash is a directory holding one file per
+/// predicate. Hashes cover everything a function calls, so editing a
+/// function invalidates its entries and those of its dependents only.
+#[derive(Clone, Debug)]
+pub struct VerdictCache {
+    root: PathBuf,
+}
+
+impl VerdictCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        VerdictCache { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, hash: &FunHash, pred: usize) -> PathBuf {
+        self.root.join(hash.to_string()).join(pred.to_string())
+    }
+}
+
+impl VerdictStore for VerdictCache {
+    fn get(&self, hash: &FunHash, pred: usize) -> io::Result<Option<CacheEntry>> {
+        match fs::read_to_string(self.path(hash, pred)) {
+            Ok(contents) => Ok(CacheEntry::decode(&contents, hash, pred)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn put(&self, hash: &FunHash, pred: usize, entry: &CacheEntry) -> io::Result<()> {
+        let path = self.path(hash, pred);
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        // Write to a temporary file first, so that concurrent runs never see
+        // a partially written entry.
+        let tmp = path.with_extension(format!("tmp-{}", std::process::id()));
+        fs::write(&tmp, entry.encode(hash, pred))?;
+        fs::rename(tmp, path)
+    }
+}
+
+#[derive(Debug)]
+pub struct CachedReports<L> {
+    pub reports: Vec<FunctionReport<L>>,
+    /// The first error of the cache. The cache is not used after it, as it
+    /// only saves time, so that an unreachable server is only waited for
+    /// once.
+    pub cache_error: Option<io::Error>,
+}
+
+/// Like `verify`, but reuses the verdicts in `cache` for functions whose
+/// hash did not change, and stores the verdicts of the others. Failing to
+/// read the cache only means checking again.
+pub fn verify_cached<L: Clone>(
+    module: &simple::Module<L>,
+    hashes: &HashMap<DefId, FunHash>,
+    options: &VerifyOptions,
+    cache: &dyn VerdictStore,
+) -> CachedReports<L> {
+    let mut reports = vec![];
+    let mut cache_error = None;
+
+    for (id, fun) in module.module_funs() {
+        let preds = fun.implementation.preds.len();
+        if preds == 0 {
+            continue;
+        }
+        let hash = &hashes[&id];
+
+        let entries = match cache_error {
+            Some(_) => vec![None; preds],
+            None => (0..preds)
+                .map(|ix| cache.get(hash, ix))
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap_or_else(|e| {
+                    cache_error = Some(e);
+                    vec![None; preds]
+                }),
+        };
+        if let Some(verdict) = reuse(module, id, &entries, options) {
+            reports.push(FunctionReport {
+                id,
+                name: fun.name.clone(),
+                verdict,
+                cached: true,
+            });
+            continue;
+        }
+
+        let verdict = verify_fun(module, id, options);
+        for (ix, entry) in entries_of(&verdict, preds, options) {
+            if cache_error.is_some() {
+                break;
+            }
+            if let Err(e) = cache.put(hash, ix, &entry) {
+                cache_error = Some(e);
+            }
+        }
+        reports.push(FunctionReport {
+            id,
+            name: fun.name.clone(),
+            verdict,
+            cached: false,
+        });
+    }
+
+    CachedReports {
+        reports,
+        cache_error,
+    }
+}
+
+/// Rebuilds the verdict from the cached entries of a function, if they
+/// answer for every predicate.
+fn reuse<L: Clone>(
+    module: &simple::Module<L>,
+    id: DefId,
+    entries: &[Option<CacheEntry>],
+    options: &VerifyOptions,
+) -> Option<Verdict<L>> {
+    let entries = entries
+        .iter()
+        .map(|entry| entry.as_ref().filter(|e| e.answers(options)))
+        .collect::<Vec<_>>();
+
+    // A counterexample decides the verdict on its own. It is replayed to
+    // recover the failure with up-to-date source locations.
+    for entry in entries.iter().flatten() {
+        if let CachedVerdict::Failed { args, shrinks } = &entry.verdict {
+            if !args.iter().all(|arg| encode::resolves(module, arg)) {
+                return None;
+            }
+            return match run_case(module, id, args, options, None) {
+                Outcome::Fail(failure) => Some(Verdict::Failed(Counterexample {
+                    args: args.clone(),
+                    failure,
+                    shrinks: shrinks.clone(),
+                })),
+                _ => None,
+            };
+        }
+    }
+
+    let entries = entries.into_iter().collect::<Option<Vec<_>>>()?;
+    match entries.first()?.verdict {
+        CachedVerdict::GaveUp { passed, discarded } => Some(Verdict::GaveUp {
+            cases: passed,
+            discarded,
+        }),
+        _ => Some(Verdict::Passed {
+            cases: options.cases,
+        }),
+    }
+}
+
+/// The entries recording `verdict`, by predicate index.
+fn entries_of<L>(
+    verdict: &Verdict<L>,
+    preds: usize,
+    options: &VerifyOptions,
+) -> Vec<(usize, CacheEntry)> {
+    let entry = |verdict| CacheEntry {
+        verdict,
+        cases: options.cases,
+        seed: options.seed,
+        limits: CheckLimits::of(options),
+    };
+
+    match verdict {
+        Verdict::Passed { .. } => (0..preds)
+            .map(|ix| (ix, entry(CachedVerdict::Passed)))
+            .collect(),
+        Verdict::GaveUp { cases, discarded } => (0..preds)
+            .map(|ix| {
+                let gave_up = CachedVerdict::GaveUp {
+                    passed: *cases,
+                    discarded: *discarded,
+                };
+                (ix, entry(gave_up))
+            })
+            .collect(),
+        Verdict::Failed(counterexample) => {
+            let failed = entry(CachedVerdict::Failed {
+                args: counterexample.args.clone(),
+                shrinks: counterexample.shrinks.clone(),
+            });
+            match counterexample.failure {
+                Failure::Predicate { index, .. } => vec![(index, failed)],
+                // Runtime errors are not down to a single predicate.
+                Failure::Error(_) => (0..preds).map(|ix| (ix, failed.clone())).collect(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::hash_module;
+    use crate::interpreter::InterpretOptions;
+    use crate::runner::runner;
+    use crate::util::loc::SrcLoc;
+
+    fn verify_src(src: &str, cache: &VerdictCache) -> Vec<FunctionReport<SrcLoc>> {
+        verify_with(src, cache, &VerifyOptions::default())
+    }
+
+    fn verify_with(
+        src: &str,
+        cache: &VerdictCache,
+        options: &VerifyOptions,
+    ) -> Vec<FunctionReport<SrcLoc>> {
+        let out = runner(src).unwrap();
+        let hashes = hash_module(&out.bound);
+        let reports = verify_cached(&out.simple, &hashes, options, cache);
+        assert!(reports.cache_error.is_none(), "{:?}", reports.cache_error);
+        reports.reports
+    }
+
+    fn cached(reports: &[FunctionReport<SrcLoc>]) -> Vec<(&str, bool)> {
+        reports
+            .iter()
+            .map(|r| (r.name.v.0.as_str(), r.cached))
+            .collect()
+    }
+
+    fn temp_cache(name: &str) -> VerdictCache {
+        let dir = std::env::temp_dir().join(format!("kedi-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        VerdictCache::new(dir)
+    }
+
+    #[test]
+    fn entries_round_trip() {
+        let entries = [
+            CachedVerdict::Passed,
+            CachedVerdict::Failed {
+                args: vec![1.into(), (-20).into()],
+                shrinks: vec![],
+            },
+            CachedVerdict::Failed {
+                args: vec![0.into(), KediValue::str("a")],
+                shrinks: vec![
+                    ShrinkStep {
+                        arg: 1,
+                        from: KediValue::str("a b c"),
+                        to: KediValue::str("a b"),
+                    },
+                    ShrinkStep {
+                        arg: 0,
+                        from: 12.into(),
+                        to: 0.into(),
+                    },
+                    ShrinkStep {
+                        arg: 1,
+                        from: KediValue::str("a b"),
+                        to: KediValue::str("a"),
+                    },
+                ],
+            },
+            CachedVerdict::Failed {
+                args: vec![
+                    KediValue::str("a b"),
+                    KediValue::str(""),
+                    KediValue::bool(true),
+                ],
+                shrinks: vec![],
+            },
+            CachedVerdict::Failed {
+                args: vec![
+                    KediValue::array(vec![KediValue::str("a b"), 1.into()]),
+                    KediValue::struct_(
+                        DefId { id: 3 },
+                        crate::parser::syntax::Ident("Point".to_string()),
+                        vec![crate::parser::syntax::Ident("x".to_string())],
+                        vec![KediValue::array(vec![])],
+                    ),
+                ],
+                shrinks: vec![],
+            },
+            CachedVerdict::Failed {
+                args: vec![],
+                shrinks: vec![],
+            },
+            CachedVerdict::GaveUp {
+                passed: 3,
+                discarded: 1000,
+            },
+        ];
+        let limits = [
+            CheckLimits::of(&VerifyOptions::default()),
+            CheckLimits {
+                max_size: 5,
+                max_discards: 0,
+                max_shrinks: 1,
+                fuel_limit: None,
+                max_depth: 10,
+                check_invariants: false,
+            },
+        ];
+        for verdict in entries {
+            for limits in &limits {
+                let entry = CacheEntry {
+                    verdict: verdict.clone(),
+                    cases: 100,
+                    seed: 7,
+                    limits: limits.clone(),
+                };
+                assert_eq!(entry.to_string().parse(), Ok(entry));
+            }
+        }
+        assert_eq!(
+            "verdict maybe\ncases 1\nseed 0\n".parse::<CacheEntry>(),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn corrupted_entries_are_rejected() {
+        let cache = temp_cache("cache-corrupt");
+        let hash = FunHash([7; 32]);
+        let entry = CacheEntry {
+            verdict: CachedVerdict::Passed,
+            cases: 100,
+            seed: 0,
+            limits: CheckLimits::of(&VerifyOptions::default()),
+        };
+        cache.put(&hash, 0, &entry).unwrap();
+        assert_eq!(cache.get(&hash, 0).unwrap(), Some(entry.clone()));
+
+        let path = cache.path(&hash, 0);
+        let tampered = fs::read_to_string(&path)
+            .unwrap()
+            .replace("cases 100", "cases 100000");
+        fs::write(&path, tampered).unwrap();
+        assert_eq!(cache.get(&hash, 0).unwrap(), None);
+
+        // Entries are only valid under the key they were written for.
+        let encoded = entry.encode(&hash, 0);
+        assert_eq!(CacheEntry::decode(&encoded, &hash, 0), Some(entry));
+        assert_eq!(CacheEntry::decode(&encoded, &hash, 1), None);
+        assert_eq!(CacheEntry::decode(&encoded, &FunHash([8; 32]), 0), None);
+
+        fs::remove_dir_all(cache.root()).unwrap();
+    }
+
+    #[test]
+    fn only_changed_functions_and_dependents_are_rechecked() {
+        let cache = temp_cache("cache");
+        let src = |limit: u32| {
+            format!(
+                "fn small n : number? n : lt? result {limit} {{ return 1; }}
+                 fn user n : number? n : lt? result 10 {{ return small n; }}
+                 fn other n : number? n : number? result {{ return n; }}"
+            )
+        };
+
+        let first = verify_src(&src(5), &cache);
+        assert_eq!(
+            cached(&first),
+            vec![("small", false), ("user", false), ("other", false)]
+        );
+
+        let second = verify_src(&src(5), &cache);
+        assert_eq!(
+            cached(&second),
+            vec![("small", true), ("user", true), ("other", true)]
+        );
+        assert_eq!(
+            first.iter().map(|r| &r.verdict).collect::<Vec<_>>(),
+            second.iter().map(|r| &r.verdict).collect::<Vec<_>>()
+        );
+
+        let third = verify_src(&src(6), &cache);
+        assert_eq!(
+            cached(&third),
+            vec![("small", false), ("user", false), ("other", true)]
+        );
+
+        fs::remove_dir_all(cache.root()).unwrap();
+    }
+
+    struct Unreachable;
+
+    impl VerdictStore for Unreachable {
+        fn get(&self, _: &FunHash, _: usize) -> io::Result<Option<CacheEntry>> {
+            Err(io::ErrorKind::ConnectionRefused.into())
+        }
+
+        fn put(&self, _: &FunHash, _: usize, _: &CacheEntry) -> io::Result<()> {
+            Err(io::ErrorKind::ConnectionRefused.into())
+        }
+    }
+
+    #[test]
+    fn failing_caches_only_cost_rechecking() {
+        let src = "fn good n : number? n : gt? result n { return n + 1; }
+                   fn bad n : number? n : gt? result n { return n; }";
+        let out = runner(src).unwrap();
+        let hashes = hash_module(&out.bound);
+        let reports = verify_cached(&out.simple, &hashes, &Default::default(), &Unreachable);
+
+        assert_eq!(
+            reports.cache_error.map(|e| e.kind()),
+            Some(io::ErrorKind::ConnectionRefused)
+        );
+        assert_eq!(
+            cached(&reports.reports),
+            vec![("good", false), ("bad", false)]
+        );
+        assert!(matches!(reports.reports[0].verdict, Verdict::Passed { .. }));
+        assert!(matches!(reports.reports[1].verdict, Verdict::Failed(_)));
+    }
+
+    #[test]
+    fn counterexamples_are_replayed() {
+        let cache = temp_cache("cache-failed");
+        let src = "fn inc n : gt? n 0 : gt? result 10 { return add n 1; }";
+
+        let first = verify_src(src, &cache);
+        let second = verify_src(src, &cache);
+        assert!(second[0].cached);
+        match (&first[0].verdict, &second[0].verdict) {
+            (Verdict::Failed(a), Verdict::Failed(b)) => {
+                assert_eq!(a.args, vec![1.into()]);
+                assert_eq!(a.args, b.args);
+                assert_eq!(a.failure, b.failure);
+                assert_eq!(a.shrinks, b.shrinks);
+            }
+            other => panic!("unexpected verdicts {:?}", other),
+        }
+
+        fs::remove_dir_all(cache.root()).unwrap();
+    }
+
+    #[test]
+    fn shrinking_is_replayed() {
+        let cache = temp_cache("cache-shrinks");
+        let src = "fn id n : number? n : lt? result 7 { return n; }";
+
+        let first = verify_src(src, &cache);
+        let second = verify_src(src, &cache);
+        assert!(second[0].cached);
+        match (&first[0].verdict, &second[0].verdict) {
+            (Verdict::Failed(a), Verdict::Failed(b)) => {
+                assert_eq!(a.args, vec![7.into()]);
+                assert!(!a.shrinks.is_empty());
+                assert_eq!(a.shrinks, b.shrinks);
+            }
+            other => panic!("unexpected verdicts {:?}", other),
+        }
+
+        fs::remove_dir_all(cache.root()).unwrap();
+    }
+
+    #[test]
+    fn array_counterexamples_are_replayed() {
+        let cache = temp_cache("cache-array");
+        let src = "fn g a : array? a : lt? result 3 { return a.length; }";
+
+        let first = verify_src(src, &cache);
+        let second = verify_src(src, &cache);
+        assert!(second[0].cached);
+        match (&first[0].verdict, &second[0].verdict) {
+            (Verdict::Failed(a), Verdict::Failed(b)) => assert_eq!(a.args, b.args),
+            other => panic!("unexpected verdicts {:?}", other),
+        }
+
+        fs::remove_dir_all(cache.root()).unwrap();
+    }
+
+    #[test]
+    fn passing_only_answers_for_the_same_limits() {
+        let cache = temp_cache("cache-limits");
+        let src = "fn id n : number? n : number? result { return n; }";
+        let options = VerifyOptions::default();
+
+        verify_with(src, &cache, &options);
+        assert_eq!(
+            cached(&verify_with(src, &cache, &options)),
+            vec![("id", true)]
+        );
+
+        let stricter = [
+            VerifyOptions {
+                max_size: options.max_size * 2,
+                ..options.clone()
+            },
+            VerifyOptions {
+                max_shrinks: options.max_shrinks + 1,
+                ..options.clone()
+            },
+            VerifyOptions {
+                interpret: InterpretOptions {
+                    fuel_limit: Some(1000),
+                    ..options.interpret.clone()
+                },
+                ..options.clone()
+            },
+            VerifyOptions {
+                interpret: InterpretOptions {
+                    check_invariants: !options.interpret.check_invariants,
+                    ..options.interpret.clone()
+                },
+                ..options.clone()
+            },
+        ];
+        for options in &stricter {
+            let reports = verify_with(src, &cache, options);
+            assert_eq!(cached(&reports), vec![("id", false)], "{:?}", options);
+        }
+
+        fs::remove_dir_all(cache.root()).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use super::encode::{self, decode, encode};
+use crate::hash::FunHash;
+use crate::interpreter::KediValue;
+use crate::parser::syntax;
+use crate::simplifier::simple;
+
+/// Interesting inputs of a single function, kept across fuzzing runs.
+///
+/// On disk, a corpus is a directory with one file per input, holding one
+/// encoded argument per line and named after the SHA-256 of its contents.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Corpus {
+    entries: Vec<Vec<KediValue>>,
+}
+
+impl Corpus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[Vec<KediValue>] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Adds `args`, returning whether it was not in the corpus yet.
+    pub fn add(&mut self, args: Vec<KediValue>) -> bool {
+        if self.entries.contains(&args) {
+            return false;
+        }
+        self.entries.push(args);
+        true
+    }
+
+    /// Drops the entries that can not be passed to a function of `arity`.
+    pub fn retain_arity(&mut self, arity: usize) {
+        self.entries.retain(|args| args.len() == arity);
+    }
+
+    /// Drops the entries holding structs or functions that `module` no
+    /// longer defines.
+    pub fn retain_resolved<L>(&mut self, module: &simple::Module<L>) {
+        self.entries
+            .retain(|args| args.iter().all(|arg| encode::resolves(module, arg)));
+    }
+
+    /// Loads the corpus in `dir`, a missing directory is an empty corpus.
+    pub fn load(dir: &Path) -> io::Result<Corpus> {
+        let mut corpus = Corpus::new();
+        let mut paths = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .map(|e| e.map(|e| e.path()))
+                .collect::<io::Result<Vec<_>>>()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(corpus),
+            Err(e) => return Err(e),
+        };
+        paths.sort();
+
+        for path in paths {
+            let contents = fs::read_to_string(&path)?;
+            let args = contents
+                .lines()
+                .map(decode)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid corpus entry {}", path.display()),
+                    )
+                })?;
+            corpus.add(args);
+        }
+
+        Ok(corpus)
+    }
+
+    /// Writes the entries missing from `dir`, creating it if needed.
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        for args in &self.entries {
+            let mut contents = String::new();
+            for arg in args {
+                writeln!(contents, "{}", encode(arg)).unwrap();
+            }
+
+            let digest = FunHash(Sha256::digest(&contents).into());
+            let path = dir.join(digest.to_string());
+
+            if !path.exists() {
+                fs::write(path, contents)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The directory holding the corpus of the function `name` under `root`.
+pub fn corpus_dir(root: &Path, name: &syntax::Ident) -> PathBuf {
+    let escaped = name
+        .0
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => c.to_string(),
+            c => format!("-{:02x}", c as u32),
+        })
+        .collect::<String>();
+    root.join(escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::binder::bound::DefId;
+
+    #[test]
+    fn entries_round_trip_on_disk() {
+        let dir = std::env::temp_dir().join(format!("kedi-corpus-load-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let point = KediValue::struct_(
+            DefId { id: 3 },
+            syntax::Ident("Point".to_string()),
+            vec![syntax::Ident("x".to_string())],
+            vec![KediValue::str("a b")],
+        );
+        let mut corpus = Corpus::new();
+        corpus.add(vec![1.into(), KediValue::bool(true)]);
+        corpus.add(vec![
+            KediValue::str("line\nbreak"),
+            KediValue::array(vec![]),
+        ]);
+        corpus.add(vec![KediValue::array(vec![2.into(), point.clone()])]);
+        corpus.add(vec![point]);
+        corpus.save(&dir).unwrap();
+
+        let loaded = Corpus::load(&dir).unwrap();
+        assert_eq!(loaded.len(), corpus.len());
+        for args in corpus.entries() {
+            assert!(loaded.entries().contains(args));
+        }
+
+        // Saving again does not duplicate anything.
+        loaded.save(&dir).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), corpus.len());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use num_bigint::BigInt;
+
+use crate::binder::bound::DefId;
+use crate::interpreter::KediValue;
+use crate::parser::syntax;
+use crate::simplifier::simple;
+
+/// Encodes `value` on a single line that `decode` reads back.
+///
+/// Numbers, booleans and strings are written as literals. The others are
+/// s-expressions: `(array 1 2)`, `(struct 3 "Point" ("x" 1) ("y" 2))` and
+/// `(fn 4 "add" 1)`, which name the definition they were built from and
+/// hold the fields or captures. Values must not contain themselves.
+pub fn encode(value: &KediValue) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+fn write_value(out: &mut String, value: &KediValue) {
+    match value {
+        KediValue::KediNum(n) => write!(out, "{}", n).unwrap(),
+        KediValue::KediBool(b) => write!(out, "{}", b).unwrap(),
+        KediValue::KediStr(s) => out.push_str(&syntax::quote(s)),
+        KediValue::KediArray(items) => {
+            out.push_str("(array");
+            for item in items.borrow().iter() {
+                out.push(' ');
+                write_value(out, item);
+            }
+            out.push(')');
+        }
+        KediValue::KediStruct(s) => {
+            write!(out, "(struct {} {}", s.def.id, syntax::quote(&s.name.0)).unwrap();
+            for (field, value) in s.fields.iter().zip(s.values.borrow().iter()) {
+                write!(out, " ({} ", syntax::quote(&field.0)).unwrap();
+                write_value(out, value);
+                out.push(')');
+            }
+            out.push(')');
+        }
+        KediValue::KediFun(fun) => {
+            write!(out, "(fn {} {}", fun.def.id, syntax::quote(&fun.name.0)).unwrap();
+            for capture in &fun.captures {
+                out.push(' ');
+                write_value(out, capture);
+            }
+            out.push(')');
+        }
+    }
+}
+
+/// Reads back a value written by `encode`.
+pub fn decode(s: &str) -> Option<KediValue> {
+    let mut decoder = Decoder { rest: s };
+    let value = decoder.value()?;
+    decoder.rest.trim().is_empty().then_some(value)
+}
+
+struct Decoder<'t> {
+    rest: &'t str,
+}
+
+impl<'t> Decoder<'t> {
+    fn value(&mut self) -> Option<KediValue> {
+        self.skip_spaces();
+        if self.rest.starts_with('"') {
+            return self.string().map(KediValue::str);
+        }
+        if !self.eat('(') {
+            return match self.atom() {
+                "true" => Some(KediValue::bool(true)),
+                "false" => Some(KediValue::bool(false)),
+                atom => BigInt::from_str(atom).ok().map(KediValue::KediNum),
+            };
+        }
+
+        let value = match self.atom() {
+            "array" => KediValue::array(self.values()?),
+            "struct" => {
+                let def = self.def()?;
+                let name = self.ident()?;
+                let mut fields = vec![];
+                let mut values = vec![];
+                while !self.peek(')') {
+                    if !self.eat('(') {
+                        return None;
+                    }
+                    fields.push(self.ident()?);
+                    values.push(self.value()?);
+                    if !self.eat(')') {
+                        return None;
+                    }
+                }
+                KediValue::struct_(def, name, fields, values)
+            }
+            "fn" => {
+                let def = self.def()?;
+                let name = self.ident()?;
+                KediValue::fun(def, name, self.values()?)
+            }
+            _ => return None,
+        };
+        self.eat(')').then_some(value)
+    }
+
+    /// The values up to the closing parenthesis, which is left in place.
+    fn values(&mut self) -> Option<Vec<KediValue>> {
+        let mut ret = vec![];
+        while !self.peek(')') {
+            ret.push(self.value()?);
+        }
+        Some(ret)
+    }
+
+    fn def(&mut self) -> Option<DefId> {
+        self.skip_spaces();
+        self.atom().parse().ok().map(|id| DefId { id })
+    }
+
+    fn ident(&mut self) -> Option<syntax::Ident> {
+        self.skip_spaces();
+        self.string().map(syntax::Ident)
+    }
+
+    /// A quoted string, up to the first unescaped quote.
+    fn string(&mut self) -> Option<String> {
+        let mut escaped = false;
+        let (end, _) = self.rest.char_indices().skip(1).find(|(_, c)| {
+            let end = *c == '"' && !escaped;
+            escaped = *c == '\\' && !escaped;
+            end
+        })?;
+        let (quoted, rest) = self.rest.split_at(end + 1);
+        self.rest = rest;
+        syntax::LitStr::unquote(quoted).map(|s| s.0)
+    }
+
+    fn atom(&mut self) -> &'t str {
+        let end = self
+            .rest
+            .find(|c: char| c.is_whitespace() || c == '(' || c == ')' || c == '"')
+            .unwrap_or(self.rest.len());
+        let (atom, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        atom
+    }
+
+    fn peek(&mut self, c: char) -> bool {
+        self.skip_spaces();
+        self.rest.is_empty() || self.rest.starts_with(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_spaces();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+}
+
+/// Whether the structs and functions in `value` still name the definitions
+/// of `module` they were encoded with. Definitions move when the module is
+/// edited, so values read back from an older module can be stale.
+pub fn resolves<L>(module: &simple::Module<L>, value: &KediValue) -> bool {
+    let def = |id: DefId| module.defs.get(id.id as usize);
+    match value {
+        KediValue::KediNum(_) | KediValue::KediBool(_) | KediValue::KediStr(_) => true,
+        KediValue::KediArray(items) => items.borrow().iter().all(|v| resolves(module, v)),
+        KediValue::KediStruct(s) => {
+            matches!(def(s.def), Some(simple::Def::Struct(d))
+                if d.name.v == s.name
+                    && d.fields.v.iter().map(|f| &f.v).eq(s.fields.iter()))
+                && s.values.borrow().iter().all(|v| resolves(module, v))
+        }
+        KediValue::KediFun(fun) => {
+            def(fun.def).is_some_and(|d| d.name() == fun.name)
+                && fun.captures.iter().all(|v| resolves(module, v))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Vec<KediValue> {
+        let point = |x: i32, y| {
+            KediValue::struct_(
+                DefId { id: 3 },
+                syntax::Ident("Point".to_string()),
+                vec![
+                    syntax::Ident("x".to_string()),
+                    syntax::Ident("y".to_string()),
+                ],
+                vec![x.into(), y],
+            )
+        };
+        vec![
+            0.into(),
+            KediValue::num(BigInt::from(-10).pow(30)),
+            KediValue::bool(true),
+            KediValue::bool(false),
+            KediValue::str(""),
+            KediValue::str("a (b) \"c\"\n\\"),
+            KediValue::array(vec![]),
+            KediValue::array(vec![1.into(), KediValue::array(vec![KediValue::str(")")])]),
+            point(1, KediValue::str("two words")),
+            point(-1, KediValue::array(vec![point(0, 0.into())])),
+            KediValue::fun(DefId { id: 7 }, syntax::Ident("add?".to_string()), vec![]),
+            KediValue::fun(
+                DefId { id: 8 },
+                syntax::Ident("lambda".to_string()),
+                vec![1.into(), point(2, 3.into())],
+            ),
+        ]
+    }
+
+    #[test]
+    fn values_round_trip() {
+        for value in values() {
+            let encoded = encode(&value);
+            assert!(!encoded.contains('\n'), "{}", encoded);
+            assert_eq!(decode(&encoded), Some(value));
+        }
+    }
+
+    #[test]
+    fn literals_are_written_as_in_the_source() {
+        assert_eq!(encode(&(-12).into()), "-12");
+        assert_eq!(encode(&KediValue::str("a b")), "\"a b\"");
+        assert_eq!(
+            encode(&KediValue::array(vec![1.into(), KediValue::bool(false)])),
+            "(array 1 false)"
+        );
+    }
+
+    #[test]
+    fn moved_definitions_do_not_resolve() {
+        let old = crate::runner::runner("struct Point { x }").unwrap().simple;
+        let new = crate::runner::runner("struct Other { x } struct Point { x }")
+            .unwrap()
+            .simple;
+        let point = KediValue::struct_(
+            old.lookup(&syntax::Ident("Point".to_string())).unwrap(),
+            syntax::Ident("Point".to_string()),
+            vec![syntax::Ident("x".to_string())],
+            vec![1.into()],
+        );
+        assert!(resolves(&old, &point));
+        assert!(!resolves(&new, &point));
+        assert!(resolves(&new, &KediValue::array(vec![1.into()])));
+    }
+
+    #[test]
+    fn malformed_values_are_rejected() {
+        for s in [
+            "",
+            "1 2",
+            "\"open",
+            "(array 1",
+            "(array 1))",
+            "(struct x \"P\")",
+            "(struct 1 \"P\" (\"x\"))",
+            "(tuple 1)",
+            "maybe",
+        ] {
+            assert_eq!(decode(s), None, "{}", s);
+        }
+    }
+}
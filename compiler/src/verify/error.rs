@@ -0,0 +1,20 @@
+use super::Counterexample;
+use crate::{parser::syntax, util::ax::Ax};
+
+#[derive(Debug)]
+pub enum Error<L> {
+    ContractViolation(ContractViolationError<L>),
+}
+
+/// A function that does not satisfy its predicates.
+#[derive(Debug)]
+pub struct ContractViolationError<L> {
+    pub function: Ax<L, syntax::Ident>,
+    pub counterexample: Box<Counterexample<L>>,
+}
+
+impl<L> From<ContractViolationError<L>> for Error<L> {
+    fn from(e: ContractViolationError<L>) -> Self {
+        Error::ContractViolation(e)
+    }
+}
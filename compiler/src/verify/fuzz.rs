@@ -0,0 +1,393 @@
+use std::io;
+use std::path::PathBuf;
+
+use num_bigint::BigInt;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use super::corpus::{corpus_dir, Corpus};
+use super::generate;
+use super::verify::{minimize, run_case, Outcome, Verdict, VerifyOptions};
+use crate::binder::bound::DefId;
+use crate::interpreter::{Coverage, KediValue};
+use crate::parser::syntax;
+use crate::simplifier::simple;
+use crate::util::ax::Ax;
+
+/// Where corpora are kept by default, next to the verdict cache.
+pub const DEFAULT_CORPUS_DIR: &str = ".kedi-cache/corpus";
+
+#[derive(Clone, Debug)]
+pub struct FuzzOptions {
+    /// `cases` is the number of passing inputs to run, rather than to
+    /// generate up front.
+    pub verify: VerifyOptions,
+    /// Where to load the corpora from and save them to, one directory per
+    /// function. Corpora are kept in memory only if `None`.
+    pub corpus_dir: Option<PathBuf>,
+}
+
+impl Default for FuzzOptions {
+    fn default() -> Self {
+        FuzzOptions {
+            verify: VerifyOptions {
+                cases: 1000,
+                ..Default::default()
+            },
+            corpus_dir: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FuzzReport<L> {
+    pub id: DefId,
+    pub name: Ax<L, syntax::Ident>,
+    pub verdict: Verdict<L>,
+    /// Branches exercised by the passing inputs.
+    pub coverage: Coverage,
+    pub corpus: Corpus,
+}
+
+/// Fuzzes every function in the module that has predicates, resuming from
+/// and updating the corpora in `options.corpus_dir`.
+pub fn fuzz<L: Clone>(
+    module: &simple::Module<L>,
+    options: &FuzzOptions,
+) -> io::Result<Vec<FuzzReport<L>>> {
+    let mut reports = vec![];
+
+    for (id, fun) in module.module_funs() {
+        if fun.implementation.preds.is_empty() {
+            continue;
+        }
+
+        let dir = options
+            .corpus_dir
+            .as_ref()
+            .map(|root| corpus_dir(root, &fun.name.v));
+        let mut corpus = match &dir {
+            Some(dir) => Corpus::load(dir)?,
+            None => Corpus::new(),
+        };
+
+        let (verdict, coverage) = fuzz_fun(module, id, &options.verify, &mut corpus);
+
+        if let Some(dir) = &dir {
+            corpus.save(dir)?;
+        }
+        reports.push(FuzzReport {
+            id,
+            name: fun.name.clone(),
+            verdict,
+            coverage,
+            corpus,
+        });
+    }
+
+    Ok(reports)
+}
+
+/// Checks the predicates of a function like `verify_fun`, but derives new
+/// inputs by mutating the inputs in `corpus`. Inputs reaching branches no
+/// earlier input reached are added to the corpus.
+pub fn fuzz_fun<L: Clone>(
+    module: &simple::Module<L>,
+    id: DefId,
+    options: &VerifyOptions,
+    corpus: &mut Corpus,
+) -> (Verdict<L>, Coverage) {
+    let shapes = generate::shapes(module, id);
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let dictionary = dictionary(module);
+    corpus.retain_arity(shapes.len());
+    corpus.retain_resolved(module);
+
+    // Replay the corpus first, so that resumed runs start from its coverage.
+    let replay = corpus.len();
+    let mut replayed = 0;
+
+    let mut coverage = Coverage::new();
+    let mut cases = 0;
+    let mut discarded = 0;
+    while cases < options.cases {
+        let attempts = (cases + discarded) as u64;
+        let size = 1 + (options.max_size * attempts / options.cases as u64).min(options.max_size);
+
+        let args = if replayed < replay {
+            replayed += 1;
+            corpus.entries()[replayed - 1].clone()
+        } else if corpus.is_empty() || rng.gen_ratio(1, 4) {
+            shapes
+                .iter()
+                .map(|shape| generate::arbitrary(&mut rng, shape, size))
+                .collect()
+        } else {
+            let parent = corpus.entries().choose(&mut rng).unwrap().clone();
+            mutate(&mut rng, parent, &dictionary, size)
+        };
+
+        let mut covered = Coverage::new();
+        match run_case(module, id, &args, options, Some(&mut covered)) {
+            Outcome::Pass => {
+                cases += 1;
+                if coverage.merge(&covered) > 0 {
+                    corpus.add(args);
+                }
+            }
+            Outcome::Discard => {
+                discarded += 1;
+                if discarded >= options.max_discards {
+                    return (Verdict::GaveUp { cases, discarded }, coverage);
+                }
+            }
+            Outcome::Fail(failure) => {
+                let counterexample = minimize(module, id, args, failure, options);
+                return (Verdict::Failed(counterexample), coverage);
+            }
+        }
+    }
+
+    (Verdict::Passed { cases }, coverage)
+}
+
+/// Applies one to three random mutations to `args`.
+fn mutate<R: Rng>(
+    rng: &mut R,
+    mut args: Vec<KediValue>,
+    dictionary: &[BigInt],
+    size: u64,
+) -> Vec<KediValue> {
+    if args.is_empty() {
+        return args;
+    }
+
+    for _ in 0..rng.gen_range(1..=3) {
+        let ix = rng.gen_range(0..args.len());
+        args[ix] = mutate_value(rng, &args[ix], dictionary, size);
+    }
+    args
+}
+
+fn mutate_value<R: Rng>(
+    rng: &mut R,
+    value: &KediValue,
+    dictionary: &[BigInt],
+    size: u64,
+) -> KediValue {
+    match value {
+        KediValue::KediNum(n) => match rng.gen_range(0..6) {
+            0 => KediValue::num(n + rng.gen_range(-16..=16)),
+            1 => KediValue::num(-n),
+            2 => KediValue::num(n * 2),
+            3 => KediValue::num(n / 2),
+            // Literals from the program, and their neighbours, tend to
+            // sit right at the edge of a branch.
+            4 if !dictionary.is_empty() => {
+                let literal = dictionary.choose(rng).unwrap();
+                KediValue::num(literal + rng.gen_range(-1..=1))
+            }
+            _ => generate::number(rng, size),
+        },
+        KediValue::KediBool(b) => KediValue::bool(!b),
+        KediValue::KediStr(s) => {
+            let mut chars = s.chars().collect::<Vec<_>>();
+            match rng.gen_range(0..3) {
+                0 if !chars.is_empty() => {
+                    chars.remove(rng.gen_range(0..chars.len()));
+                }
+                1 if !chars.is_empty() => {
+                    let ix = rng.gen_range(0..chars.len());
+                    chars[ix] = rng.gen_range(' '..='~');
+                }
+                _ => chars.insert(rng.gen_range(0..=chars.len()), rng.gen_range(' '..='~')),
+            }
+            KediValue::str(chars.into_iter().collect::<String>())
+        }
+        KediValue::KediArray(items) => {
+            let mut items = items.borrow().clone();
+            match rng.gen_range(0..3) {
+                0 if !items.is_empty() => {
+                    items.remove(rng.gen_range(0..items.len()));
+                }
+                1 if !items.is_empty() => {
+                    let ix = rng.gen_range(0..items.len());
+                    items[ix] = mutate_value(rng, &items[ix], dictionary, size);
+                }
+                _ => items.insert(rng.gen_range(0..=items.len()), generate::number(rng, size)),
+            }
+            KediValue::array(items)
+        }
+        // The same struct with one of its fields mutated.
+        KediValue::KediStruct(s) => {
+            let mut values = s.values.borrow().clone();
+            if !values.is_empty() {
+                let ix = rng.gen_range(0..values.len());
+                values[ix] = mutate_value(rng, &values[ix], dictionary, size);
+            }
+            KediValue::struct_(s.def, s.name.clone(), s.fields.clone(), values)
+        }
+        // Functions are not generated, there is nothing to mutate them into.
+        KediValue::KediFun(_) => value.clone(),
+    }
+}
+
+/// The number literals appearing in the functions of the module.
+fn dictionary<L>(module: &simple::Module<L>) -> Vec<BigInt> {
+    fn walk(stmts: &[simple::FunStmt], out: &mut Vec<BigInt>) {
+        for stmt in stmts {
+            match stmt {
+                simple::FunStmt::Assignment(a) => {
+                    if let simple::AssignmentValue::LitNum(n) = &a.v.value {
+                        let n = BigInt::from(n.v.0);
+                        if !out.contains(&n) {
+                            out.push(n);
+                        }
+                    }
+                }
+                simple::FunStmt::Loop(l) => walk(&l.v.body.v, out),
+                simple::FunStmt::If(i) => {
+                    walk(&i.then.v, out);
+                    if let Some(else_) = &i.else_ {
+                        walk(&else_.v, out);
+                    }
+                }
+                simple::FunStmt::Inv(i) => walk(&i.v.body, out),
+                simple::FunStmt::SetIndex(_)
+                | simple::FunStmt::SetField(_)
+                | simple::FunStmt::Break()
+                | simple::FunStmt::Continue()
+                | simple::FunStmt::Return(_)
+                | simple::FunStmt::NoMatch(_)
+                | simple::FunStmt::Nop => {}
+            }
+        }
+    }
+
+    let mut out = vec![];
+    for (_, fun) in module.module_funs() {
+        walk(&fun.implementation.body.v, &mut out);
+        for pred in &fun.implementation.preds {
+            walk(&pred.body.v, &mut out);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::runner::runner;
+    use crate::util::loc::SrcLoc;
+    use crate::verify::encode::encode;
+    use crate::verify::{verify_fun, Counterexample, Failure};
+
+    const NARROW: &str = "
+        fn narrow n : number? n : gt? result 0 {
+            if eq? n 4242 {
+                return 0;
+            }
+            return 1;
+        }";
+
+    fn compile(src: &str) -> (simple::Module<SrcLoc>, DefId) {
+        let out = runner(src).unwrap();
+        let id = out.simple.lookup(&syntax::Ident("narrow".to_string()));
+        (out.simple, id.unwrap())
+    }
+
+    #[test]
+    fn fuzzing_reaches_narrow_branches() {
+        let (module, id) = compile(NARROW);
+        let options = FuzzOptions::default();
+
+        // Plain random testing never guesses the magic number...
+        assert_eq!(
+            verify_fun(&module, id, &options.verify),
+            Verdict::Passed { cases: 1000 }
+        );
+
+        // ...but it is a literal in the program, and fuzzing finds it.
+        match fuzz_fun(&module, id, &options.verify, &mut Corpus::new()).0 {
+            Verdict::Failed(Counterexample {
+                args,
+                failure: Failure::Predicate { result, .. },
+                ..
+            }) => {
+                assert_eq!(args, vec![4242.into()]);
+                assert_eq!(result, 0.into());
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn new_coverage_grows_the_corpus() {
+        let src = "
+            fn count n : gt? n 0 : gt? result 0 {
+                let i = 0;
+                while lt? i n {
+                    i = add i 1;
+                }
+                return i;
+            }";
+        let out = runner(src).unwrap();
+        let id = out
+            .simple
+            .lookup(&syntax::Ident("count".to_string()))
+            .unwrap();
+
+        let mut corpus = Corpus::new();
+        let (verdict, coverage) = fuzz_fun(&out.simple, id, &VerifyOptions::default(), &mut corpus);
+        assert_eq!(verdict, Verdict::Passed { cases: 100 });
+
+        // Every loop iteration bucket up to the maximum size is reached, and
+        // each corpus entry contributed something new.
+        assert!(coverage.len() >= 7);
+        assert!(corpus.len() >= 7);
+        assert!(corpus.len() <= coverage.len());
+    }
+
+    #[test]
+    fn corpus_is_persisted() {
+        let dir = std::env::temp_dir().join(format!("kedi-corpus-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let src = "fn positive? n : number? n : number? result {
+            if gt? n 0 { return 1; }
+            return 0;
+        }";
+        let out = runner(src).unwrap();
+        let options = FuzzOptions {
+            corpus_dir: Some(dir.clone()),
+            ..Default::default()
+        };
+
+        let first = fuzz(&out.simple, &options).unwrap();
+        assert_eq!(first.len(), 1);
+        assert!(!first[0].corpus.is_empty());
+
+        let fun_dir = corpus_dir(&dir, &syntax::Ident("positive?".to_string()));
+        assert!(fun_dir.ends_with("positive-3f"));
+        // Entries are loaded in file name order, not in the order they were
+        // found.
+        let entries = |corpus: &Corpus| {
+            corpus
+                .entries()
+                .iter()
+                .map(|args| args.iter().map(encode).collect::<Vec<_>>())
+                .collect::<HashSet<_>>()
+        };
+        let saved = entries(&first[0].corpus);
+        assert_eq!(entries(&Corpus::load(&fun_dir).unwrap()), saved);
+
+        // A second run resumes from the saved corpus.
+        let second = fuzz(&out.simple, &options).unwrap();
+        assert!(entries(&second[0].corpus).is_superset(&saved));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,212 @@
+use rand::Rng;
+
+use crate::binder::bound::{DefId, Origin, Prim};
+use crate::interpreter::KediValue;
+use crate::parser::syntax;
+use crate::simplifier::simple;
+
+/// Values that tend to hit edge cases, tried more often than their share.
+const INTERESTING: &[i64] = &[0, 1, -1, 2, -2];
+
+/// Bound on the length of generated strings and arrays, whatever the size.
+const MAX_LEN: u64 = 16;
+
+/// The kind of values generated for an argument.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    /// No type predicate guards the parameter, numbers are generated.
+    Unknown,
+    Number,
+    Bool,
+    String,
+    /// Arrays of numbers.
+    Array,
+    /// Built by the struct, from numbers.
+    Struct(StructShape),
+    /// Built by one of the variants of a type, from numbers and smaller
+    /// values of the type, so that recursive types like trees get built.
+    Type(Vec<StructShape>),
+}
+
+impl Shape {
+    /// Whether some of the values are guessed, as nothing says what the
+    /// parameter or the struct fields hold.
+    pub fn guesses(&self) -> bool {
+        matches!(self, Shape::Unknown | Shape::Struct(_) | Shape::Type(_))
+    }
+}
+
+/// The constructor of a struct, as the interpreter names its values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructShape {
+    pub def: DefId,
+    pub name: syntax::Ident,
+    pub fields: Vec<syntax::Ident>,
+}
+
+/// The shape of each parameter of the function `id`, taken from argument
+/// predicates like `string? s`.
+pub fn shapes<L>(module: &simple::Module<L>, id: DefId) -> Vec<Shape> {
+    let simple::Def::Fun(fun) = module.get(id) else {
+        return vec![Shape::Unknown; module.get(id).arity()];
+    };
+    let implementation = &fun.implementation;
+
+    let mut ret = vec![None; implementation.parameters.v.len()];
+    for pred in &implementation.preds {
+        if pred.kind != simple::PredKind::Argument {
+            continue;
+        }
+        let Some((param, shape)) = guard(module, implementation, pred) else {
+            continue;
+        };
+        if ret[param].is_none() {
+            ret[param] = Some(shape);
+        }
+    }
+    ret.into_iter()
+        .map(|shape| shape.unwrap_or(Shape::Unknown))
+        .collect()
+}
+
+/// The parameter a predicate guards and its shape, when the predicate is
+/// a single call of a type predicate on a parameter.
+fn guard<L>(
+    module: &simple::Module<L>,
+    implementation: &simple::FunImpl,
+    pred: &simple::Pred,
+) -> Option<(usize, Shape)> {
+    let [simple::FunStmt::Assignment(assignment)] = pred.body.v.as_slice() else {
+        return None;
+    };
+    let simple::AssignmentValue::Call(call) = &assignment.v.value else {
+        return None;
+    };
+    if !same_ident(&assignment.v.target, &pred.condition) {
+        return None;
+    }
+    let [simple::Ident::Local(arg)] = call.v.arguments.v.as_slice() else {
+        return None;
+    };
+    let param = implementation
+        .parameters
+        .v
+        .iter()
+        .position(|p| p.v == arg.v)?;
+
+    let shape = match module.get(call.v.fun_name.v) {
+        simple::Def::Prim(prim) => prim_shape(*prim)?,
+        // The prelude wraps each primitive in a function of the same name.
+        simple::Def::Fun(f) if f.origin == Origin::Prelude => match f.name.v.0.as_str() {
+            "number?" => Shape::Number,
+            "bool?" => Shape::Bool,
+            "string?" => Shape::String,
+            "array?" => Shape::Array,
+            _ => return None,
+        },
+        simple::Def::StructPred(pred) => Shape::Struct(struct_shape(module, pred.struct_id)?),
+        simple::Def::TypePred(pred) if !pred.variants.is_empty() => Shape::Type(
+            pred.variants
+                .iter()
+                .map(|variant| struct_shape(module, *variant))
+                .collect::<Option<_>>()?,
+        ),
+        _ => return None,
+    };
+    Some((param, shape))
+}
+
+fn struct_shape<L>(module: &simple::Module<L>, id: DefId) -> Option<StructShape> {
+    let simple::Def::Struct(def) = module.get(id) else {
+        return None;
+    };
+    Some(StructShape {
+        def: id,
+        name: def.name.v.clone(),
+        fields: def.fields.v.iter().map(|field| field.v.clone()).collect(),
+    })
+}
+
+fn same_ident(l: &simple::Ident, r: &simple::Ident) -> bool {
+    match (l, r) {
+        (simple::Ident::Local(l), simple::Ident::Local(r)) => l.v == r.v,
+        (simple::Ident::SingleUse(l), simple::Ident::SingleUse(r)) => l.v == r.v,
+        _ => false,
+    }
+}
+
+fn prim_shape(prim: Prim) -> Option<Shape> {
+    match prim {
+        Prim::IsNumber => Some(Shape::Number),
+        Prim::IsBool => Some(Shape::Bool),
+        Prim::IsString => Some(Shape::String),
+        Prim::IsArray => Some(Shape::Array),
+        _ => None,
+    }
+}
+
+/// Generates an arbitrary value of the given shape, whose magnitude or
+/// length is bounded by `size`.
+pub fn arbitrary<R: Rng>(rng: &mut R, shape: &Shape, size: u64) -> KediValue {
+    match shape {
+        Shape::Unknown | Shape::Number => number(rng, size),
+        Shape::Bool => KediValue::bool(rng.gen()),
+        Shape::String => {
+            let len = rng.gen_range(0..=size.min(MAX_LEN));
+            KediValue::str(
+                (0..len)
+                    .map(|_| rng.gen_range(' '..='~'))
+                    .collect::<String>(),
+            )
+        }
+        Shape::Array => {
+            let len = rng.gen_range(0..=size.min(MAX_LEN));
+            KediValue::array((0..len).map(|_| number(rng, size)).collect())
+        }
+        Shape::Struct(s) => {
+            let values = s.fields.iter().map(|_| number(rng, size)).collect();
+            KediValue::struct_(s.def, s.name.clone(), s.fields.clone(), values)
+        }
+        Shape::Type(variants) => variant(rng, variants, size),
+    }
+}
+
+/// Builds one of `variants`, each field being a number or, more often and
+/// while the size lasts, a value of the type half as large. The smallest
+/// values are built by the variants with the fewest fields, so that the
+/// recursion ends.
+fn variant<R: Rng>(rng: &mut R, variants: &[StructShape], size: u64) -> KediValue {
+    let candidates = if size > 1 {
+        variants.iter().collect::<Vec<_>>()
+    } else {
+        let fewest = variants.iter().map(|v| v.fields.len()).min().unwrap_or(0);
+        variants
+            .iter()
+            .filter(|v| v.fields.len() == fewest)
+            .collect()
+    };
+    let s = candidates[rng.gen_range(0..candidates.len())];
+    let values = s
+        .fields
+        .iter()
+        .map(|_| {
+            if size > 1 && rng.gen_ratio(3, 4) {
+                variant(rng, variants, size / 2)
+            } else {
+                number(rng, size)
+            }
+        })
+        .collect();
+    KediValue::struct_(s.def, s.name.clone(), s.fields.clone(), values)
+}
+
+/// Generates a number whose magnitude is bounded by `size`.
+pub fn number<R: Rng>(rng: &mut R, size: u64) -> KediValue {
+    if rng.gen_ratio(1, 5) {
+        let ix = rng.gen_range(0..INTERESTING.len());
+        return KediValue::num(INTERESTING[ix]);
+    }
+
+    let size = size as i64;
+    KediValue::num(rng.gen_range(-size..=size))
+}
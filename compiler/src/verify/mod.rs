@@ -0,0 +1,15 @@
+mod cache;
+pub use cache::*;
+pub mod corpus;
+pub mod encode;
+mod error;
+pub use error::*;
+mod fuzz;
+pub use fuzz::*;
+pub mod generate;
+mod remote;
+pub use remote::*;
+pub mod shrink;
+#[allow(clippy::module_inception)]
+mod verify;
+pub use verify::*;
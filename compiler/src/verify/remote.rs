@@ -0,0 +1,185 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use super::cache::{CacheEntry, VerdictStore};
+use crate::hash::FunHash;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The path of the entry of predicate `pred` of the function `hash`.
+pub fn verdict_path(hash: &FunHash, pred: usize) -> String {
+    format!("/verdicts/{}/{}", hash, pred)
+}
+
+/// Parses a path built by `verdict_path`.
+pub fn parse_verdict_path(path: &str) -> Option<(FunHash, usize)> {
+    let rest = path.strip_prefix("/verdicts/")?;
+    let (hash, pred) = rest.split_once('/')?;
+    Some((FunHash::from_hex(hash)?, pred.parse().ok()?))
+}
+
+/// A client for a verdict cache shared over HTTP, at a
+/// `http://host:port[/prefix]` URL. The server has to be started with the
+/// same prefix.
+///
+/// The protocol has a single resource, `<prefix>/verdicts/<hash>/<pred>`,
+/// holding the encoded `CacheEntry` of predicate `pred` of the function
+/// `hash`:
+///
+/// - `GET` answers `200` with the entry, or `404` if there is none.
+/// - `PUT` stores the entry in the request body, answering `204`. Entries
+///   with a bad checksum are refused with `400`.
+///
+/// Entries received are checked too, so neither side has to trust the other.
+#[derive(Clone, Debug)]
+pub struct RemoteCache {
+    host: String,
+    prefix: String,
+}
+
+impl RemoteCache {
+    pub fn new(url: &str) -> io::Result<Self> {
+        let rest = url.strip_prefix("http://").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported cache url {}, expected http://", url),
+            )
+        })?;
+        let (host, prefix) = match rest.find('/') {
+            Some(ix) => (&rest[..ix], rest[ix..].trim_end_matches('/')),
+            None => (rest, ""),
+        };
+        let host = match host.contains(':') {
+            true => host.to_string(),
+            false => format!("{}:80", host),
+        };
+
+        Ok(RemoteCache {
+            host,
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Sends a request, returning the status code and the body.
+    fn request(&self, method: &str, path: &str, body: &str) -> io::Result<(u16, String)> {
+        let mut stream = TcpStream::connect(&self.host)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+
+        write!(
+            stream,
+            "{} {}{} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            self.prefix,
+            path,
+            self.host,
+            body.len(),
+            body
+        )?;
+        stream.flush()?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        parse_response(&response)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed response"))
+    }
+}
+
+fn parse_response(response: &str) -> Option<(u16, String)> {
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    let mut lines = head.lines();
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    let chunked = lines
+        .filter_map(|line| line.split_once(':'))
+        .any(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("transfer-encoding")
+                && value.trim().eq_ignore_ascii_case("chunked")
+        });
+    let body = match chunked {
+        true => dechunk(body)?,
+        false => body.to_string(),
+    };
+    Some((status, body))
+}
+
+/// Decodes a body sent with `Transfer-Encoding: chunked`.
+fn dechunk(mut body: &str) -> Option<String> {
+    let mut decoded = String::new();
+    loop {
+        let (size, rest) = body.split_once("\r\n")?;
+        let size = size.split(';').next()?.trim();
+        let size = usize::from_str_radix(size, 16).ok()?;
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.push_str(rest.get(..size)?);
+        body = rest.get(size..)?.strip_prefix("\r\n")?;
+    }
+}
+
+impl VerdictStore for RemoteCache {
+    fn get(&self, hash: &FunHash, pred: usize) -> io::Result<Option<CacheEntry>> {
+        match self.request("GET", &verdict_path(hash, pred), "")? {
+            (200, body) => Ok(CacheEntry::decode(&body, hash, pred)),
+            (404, _) => Ok(None),
+            (status, _) => Err(io::Error::other(format!(
+                "cache server answered {}",
+                status
+            ))),
+        }
+    }
+
+    fn put(&self, hash: &FunHash, pred: usize, entry: &CacheEntry) -> io::Result<()> {
+        let body = entry.encode(hash, pred);
+        match self.request("PUT", &verdict_path(hash, pred), &body)? {
+            (200..=299, _) => Ok(()),
+            (status, _) => Err(io::Error::other(format!(
+                "cache server answered {}",
+                status
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_split_into_host_and_prefix() {
+        let cache = RemoteCache::new("http://cache.local:8080/kedi/").unwrap();
+        assert_eq!(cache.host, "cache.local:8080");
+        assert_eq!(cache.prefix, "/kedi");
+
+        let cache = RemoteCache::new("http://cache.local").unwrap();
+        assert_eq!(cache.host, "cache.local:80");
+        assert_eq!(cache.prefix, "");
+
+        assert!(RemoteCache::new("https://cache.local").is_err());
+    }
+
+    #[test]
+    fn paths_round_trip() {
+        let hash = FunHash([0xab; 32]);
+        let path = verdict_path(&hash, 3);
+        assert_eq!(parse_verdict_path(&path), Some((hash, 3)));
+        assert_eq!(parse_verdict_path("/verdicts/abc/0"), None);
+    }
+
+    #[test]
+    fn chunked_responses_are_decoded() {
+        let response = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                        4\r\nverd\r\n7;ext=1\r\nict yes\r\n0\r\n\r\n";
+        assert_eq!(
+            parse_response(response),
+            Some((200, "verdict yes".to_string()))
+        );
+
+        let plain = "HTTP/1.1 404 Not Found\r\nContent-Length: 3\r\n\r\n4\r\n";
+        assert_eq!(parse_response(plain), Some((404, "4\r\n".to_string())));
+
+        let truncated = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n9\r\nverd";
+        assert_eq!(parse_response(truncated), None);
+    }
+}
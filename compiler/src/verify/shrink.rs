@@ -0,0 +1,210 @@
+use num_bigint::{BigInt, Sign};
+
+use crate::interpreter::KediValue;
+
+/// Replacing argument `arg` by `to` kept the function failing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShrinkStep {
+    pub arg: usize,
+    pub from: KediValue,
+    pub to: KediValue,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shrunk<F> {
+    pub args: Vec<KediValue>,
+    pub failure: F,
+    /// The accepted steps, in the order they were taken.
+    pub steps: Vec<ShrinkStep>,
+    /// Number of candidates that were run, accepted or not.
+    pub attempts: u32,
+}
+
+/// Returns values simpler than `value`, simplest first.
+///
+/// Booleans shrink to `false`, strings and arrays to their prefixes, and
+/// structs to the structs they hold, then field by field. Functions do not
+/// shrink. Numbers shrink towards
+/// zero, preferring positive numbers over negative ones. The candidates halve
+/// the distance to zero each time, so a huge number reaches a failure
+/// threshold in logarithmically many steps.
+pub fn candidates(value: &KediValue) -> Vec<KediValue> {
+    match value {
+        KediValue::KediNum(n) => shrink_num(n).into_iter().map(KediValue::KediNum).collect(),
+        KediValue::KediBool(true) => vec![KediValue::bool(false)],
+        KediValue::KediBool(false) => vec![],
+        KediValue::KediStr(s) => prefixes(&s.chars().collect::<Vec<_>>())
+            .into_iter()
+            .map(|chars| KediValue::str(chars.into_iter().collect::<String>()))
+            .collect(),
+        KediValue::KediArray(items) => prefixes(&items.borrow())
+            .into_iter()
+            .map(KediValue::array)
+            .collect(),
+        KediValue::KediStruct(s) => {
+            let values = s.values.borrow();
+            let mut ret = values
+                .iter()
+                .filter(|value| matches!(value, KediValue::KediStruct(_)))
+                .cloned()
+                .collect::<Vec<_>>();
+            for (ix, value) in values.iter().enumerate() {
+                for candidate in candidates(value) {
+                    let mut values = values.clone();
+                    values[ix] = candidate;
+                    ret.push(KediValue::struct_(
+                        s.def,
+                        s.name.clone(),
+                        s.fields.clone(),
+                        values,
+                    ));
+                }
+            }
+            ret
+        }
+        KediValue::KediFun(_) => vec![],
+    }
+}
+
+fn shrink_num(n: &BigInt) -> Vec<BigInt> {
+    let mut ret: Vec<BigInt> = vec![];
+    let mut push = |c: BigInt| {
+        if c != *n && !ret.contains(&c) {
+            ret.push(c);
+        }
+    };
+
+    if n.sign() == Sign::NoSign {
+        return vec![];
+    }
+    push(BigInt::ZERO);
+    if n.sign() == Sign::Minus {
+        push(-n);
+    }
+
+    // n - n/2, n - n/4, ..., n - 1 (towards zero)
+    let mut distance: BigInt = n / 2;
+    while distance.sign() != Sign::NoSign {
+        push(n - &distance);
+        distance /= 2;
+    }
+    push(match n.sign() {
+        Sign::Minus => n + 1,
+        _ => n - 1,
+    });
+
+    ret
+}
+
+/// The empty prefix of `items`, the first half, and all but the last item.
+fn prefixes<T: Clone + PartialEq>(items: &[T]) -> Vec<Vec<T>> {
+    let mut ret: Vec<Vec<T>> = vec![];
+    for len in [0, items.len() / 2, items.len().saturating_sub(1)] {
+        let c = items[..len].to_vec();
+        if len != items.len() && !ret.contains(&c) {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+/// Shrinks `args` one argument at a time for as long as `fails` keeps
+/// reporting a failure, trying at most `max_attempts` candidates.
+pub fn minimize<F>(
+    args: Vec<KediValue>,
+    failure: F,
+    max_attempts: u32,
+    mut fails: impl FnMut(&[KediValue]) -> Option<F>,
+) -> Shrunk<F> {
+    let mut ret = Shrunk {
+        args,
+        failure,
+        steps: vec![],
+        attempts: 0,
+    };
+
+    'shrink: loop {
+        for ix in 0..ret.args.len() {
+            for candidate in candidates(&ret.args[ix]) {
+                if ret.attempts >= max_attempts {
+                    return ret;
+                }
+                ret.attempts += 1;
+
+                let mut next = ret.args.clone();
+                next[ix] = candidate.clone();
+                if let Some(f) = fails(&next) {
+                    ret.steps.push(ShrinkStep {
+                        arg: ix,
+                        from: std::mem::replace(&mut ret.args, next)[ix].clone(),
+                        to: candidate,
+                    });
+                    ret.failure = f;
+                    continue 'shrink;
+                }
+            }
+        }
+        return ret;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn num(value: &KediValue) -> i64 {
+        match value {
+            KediValue::KediNum(n) => n.try_into().unwrap(),
+            other => panic!("unexpected value {}", other),
+        }
+    }
+
+    #[test]
+    fn candidates_move_towards_zero() {
+        let ns = candidates(&100.into()).iter().map(num).collect::<Vec<_>>();
+        assert_eq!(ns, vec![0, 50, 75, 88, 94, 97, 99]);
+
+        let ns = candidates(&(-3).into()).iter().map(num).collect::<Vec<_>>();
+        assert_eq!(ns, vec![0, 3, -2]);
+
+        assert!(candidates(&0.into()).is_empty());
+    }
+
+    #[test]
+    fn minimize_finds_the_threshold() {
+        let huge = KediValue::num(BigInt::from(10).pow(30));
+        let threshold = BigInt::from(1_000_000);
+        let fails = |args: &[KediValue]| match &args[0] {
+            KediValue::KediNum(n) if *n > threshold => Some(()),
+            _ => None,
+        };
+
+        let shrunk = minimize(vec![huge.clone()], (), 10_000, fails);
+        assert_eq!(shrunk.args, vec![KediValue::num(1_000_001)]);
+        assert!(shrunk.attempts < 10_000);
+
+        // The trace connects the original input to the minimized one.
+        assert_eq!(shrunk.steps.first().unwrap().from, huge);
+        assert_eq!(shrunk.steps.last().unwrap().to, shrunk.args[0]);
+        for pair in shrunk.steps.windows(2) {
+            assert_eq!(pair[0].to, pair[1].from);
+        }
+    }
+
+    #[test]
+    fn minimize_shrinks_every_argument() {
+        let fails = |args: &[KediValue]| (num(&args[0]) + num(&args[1]) > 10).then_some(());
+        let shrunk = minimize(vec![40.into(), 70.into()], (), 1000, fails);
+        assert_eq!(num(&shrunk.args[0]) + num(&shrunk.args[1]), 11);
+        assert_eq!(shrunk.args[0], 0.into());
+    }
+
+    #[test]
+    fn minimize_respects_the_budget() {
+        let shrunk = minimize(vec![1000.into()], (), 3, |args| {
+            (num(&args[0]) > 1).then_some(())
+        });
+        assert_eq!(shrunk.attempts, 3);
+        assert_eq!(shrunk.args, vec![500.into()]);
+    }
+}
@@ -0,0 +1,684 @@
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use super::error::{ContractViolationError, Error};
+use super::generate::{self, Shape};
+use super::shrink::{self, ShrinkStep};
+use crate::binder::bound::DefId;
+use crate::interpreter::{
+    self, Coverage, InterpretError, InterpretErrorKind, InterpretOptions, InterpretSuccess,
+    KediValue, StackFrame,
+};
+use crate::parser::syntax;
+use crate::simplifier::simple;
+use crate::util::ax::Ax;
+use crate::util::loc::Tagged;
+
+#[derive(Clone, Debug)]
+pub struct VerifyOptions {
+    /// Seed for the input generator, the same seed generates the same inputs.
+    pub seed: u64,
+    /// Number of inputs a function has to pass to be accepted.
+    pub cases: u32,
+    /// Number of inputs the argument predicates can reject before giving up.
+    pub max_discards: u32,
+    /// Bound on the magnitude of generated numbers.
+    pub max_size: u64,
+    /// Number of candidates to try when minimizing a counterexample.
+    pub max_shrinks: u32,
+    /// Limits for each run of a function or predicate, invariants are
+    /// checked by default.
+    pub interpret: InterpretOptions,
+}
+
+impl Default for VerifyOptions {
+    fn default() -> Self {
+        VerifyOptions {
+            seed: 0,
+            cases: 100,
+            max_discards: 1000,
+            max_size: 100,
+            max_shrinks: 1000,
+            interpret: InterpretOptions {
+                fuel_limit: Some(100_000),
+                check_invariants: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionReport<L> {
+    pub id: DefId,
+    pub name: Ax<L, syntax::Ident>,
+    pub verdict: Verdict<L>,
+    /// Whether the verdict was taken from a cache instead of being checked.
+    pub cached: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict<L> {
+    Passed {
+        cases: u32,
+    },
+    Failed(Counterexample<L>),
+    /// Too few generated inputs satisfied the argument predicates.
+    GaveUp {
+        cases: u32,
+        discarded: u32,
+    },
+}
+
+/// Arguments satisfying the argument predicates that make the function fail.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Counterexample<L> {
+    pub args: Vec<KediValue>,
+    pub failure: Failure<L>,
+    /// How the generated input was minimized into `args`.
+    pub shrinks: Vec<ShrinkStep>,
+}
+
+impl<L> Counterexample<L> {
+    /// Renders the failing call, e.g. `fibonacci 3`.
+    pub fn call(&self, name: &syntax::Ident) -> String {
+        render_call(name, &self.args)
+    }
+
+    /// Renders the call as generated, before shrinking.
+    pub fn original_call(&self, name: &syntax::Ident) -> String {
+        render_call(name, &self.original_args())
+    }
+
+    /// The arguments as generated, before shrinking.
+    pub fn original_args(&self) -> Vec<KediValue> {
+        let mut args = self.args.clone();
+        for step in self.shrinks.iter().rev() {
+            args[step.arg] = step.from.clone();
+        }
+        args
+    }
+}
+
+fn render_call(name: &syntax::Ident, args: &[KediValue]) -> String {
+    let mut ret = name.0.clone();
+    for arg in args {
+        ret.push(' ');
+        ret.push_str(&arg.to_string());
+    }
+    ret
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Failure<L> {
+    /// The function returned a value violating the result predicate at `index`.
+    Predicate {
+        index: usize,
+        loc: Option<L>,
+        result: KediValue,
+    },
+    /// The function, or one of its result predicates, failed at runtime.
+    Error(InterpretError<L>),
+}
+
+impl<L> Failure<L> {
+    /// Whether both failures have the same cause, shrinking only keeps
+    /// candidates that fail the same way.
+    fn same_cause(&self, other: &Failure<L>) -> bool {
+        match (self, other) {
+            (Failure::Predicate { index: a, .. }, Failure::Predicate { index: b, .. }) => a == b,
+            (Failure::Error(a), Failure::Error(b)) => {
+                std::mem::discriminant(&a.kind) == std::mem::discriminant(&b.kind)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Checks the predicates of every function in the module that has some.
+pub fn verify<L: Clone>(
+    module: &simple::Module<L>,
+    options: &VerifyOptions,
+) -> Vec<FunctionReport<L>> {
+    module
+        .module_funs()
+        .filter(|(_, fun)| !fun.implementation.preds.is_empty())
+        .map(|(id, fun)| FunctionReport {
+            id,
+            name: fun.name.clone(),
+            verdict: verify_fun(module, id, options),
+            cached: false,
+        })
+        .collect()
+}
+
+/// Like `verify`, but fails on the first function with a counterexample.
+pub fn check<L: Clone>(
+    module: &simple::Module<L>,
+    options: &VerifyOptions,
+) -> Result<Vec<FunctionReport<L>>, Error<L>> {
+    check_reports(verify(module, options))
+}
+
+/// Fails on the first report with a counterexample.
+pub fn check_reports<L: Clone>(
+    reports: Vec<FunctionReport<L>>,
+) -> Result<Vec<FunctionReport<L>>, Error<L>> {
+    for report in &reports {
+        if let Verdict::Failed(counterexample) = &report.verdict {
+            return Err(ContractViolationError {
+                function: report.name.clone(),
+                counterexample: Box::new(counterexample.clone()),
+            }
+            .into());
+        }
+    }
+    Ok(reports)
+}
+
+pub fn verify_fun<L: Clone>(
+    module: &simple::Module<L>,
+    id: DefId,
+    options: &VerifyOptions,
+) -> Verdict<L> {
+    let shapes = generate::shapes(module, id);
+    let mut rng = StdRng::seed_from_u64(options.seed);
+
+    let mut cases = 0;
+    let mut discarded = 0;
+    while cases < options.cases {
+        // Start small, growing with every attempt so that discarding inputs
+        // eventually leads to larger ones.
+        let attempts = (cases + discarded) as u64;
+        let size = 1 + (options.max_size * attempts / options.cases as u64).min(options.max_size);
+        let args = shapes
+            .iter()
+            .map(|shape| generate::arbitrary(&mut rng, shape, size))
+            .collect::<Vec<_>>();
+
+        match run_case(module, id, &args, options, None) {
+            Outcome::Pass => cases += 1,
+            Outcome::Discard => {
+                discarded += 1;
+                if discarded >= options.max_discards {
+                    return Verdict::GaveUp { cases, discarded };
+                }
+            }
+            Outcome::Fail(failure) => {
+                return Verdict::Failed(minimize(module, id, args, failure, options));
+            }
+        }
+    }
+
+    Verdict::Passed { cases }
+}
+
+/// Shrinks the failing `args` into a counterexample that fails the same way.
+pub(super) fn minimize<L: Clone>(
+    module: &simple::Module<L>,
+    id: DefId,
+    args: Vec<KediValue>,
+    failure: Failure<L>,
+    options: &VerifyOptions,
+) -> Counterexample<L> {
+    let original = failure.clone();
+    let shrunk = shrink::minimize(
+        args,
+        failure,
+        options.max_shrinks,
+        |candidate| match run_case(module, id, candidate, options, None) {
+            Outcome::Fail(f) if f.same_cause(&original) => Some(f),
+            _ => None,
+        },
+    );
+
+    Counterexample {
+        args: shrunk.args,
+        failure: shrunk.failure,
+        shrinks: shrunk.steps,
+    }
+}
+
+pub(super) enum Outcome<L> {
+    Pass,
+    Discard,
+    Fail(Failure<L>),
+}
+
+/// Runs the function on `args` and checks its predicates, recording the
+/// branches taken by the function into `coverage` if given.
+pub(super) fn run_case<L: Clone>(
+    module: &simple::Module<L>,
+    id: DefId,
+    args: &[KediValue],
+    options: &VerifyOptions,
+    coverage: Option<&mut Coverage>,
+) -> Outcome<L> {
+    let fun = match module.get(id) {
+        simple::Def::Fun(fun) => &fun.v,
+        simple::Def::Prim(_)
+        | simple::Def::Struct(_)
+        | simple::Def::StructPred(_)
+        | simple::Def::TypePred(_) => return Outcome::Pass,
+    };
+    let preds = &fun.implementation.preds;
+
+    // Every run gets its own copy of the arguments, so that what the function
+    // assigns into them does not leak into later runs or counterexamples.
+    let args = &args.iter().map(KediValue::deep_copy).collect::<Vec<_>>();
+
+    // Inputs the argument predicates reject, or fail on, are not checked. A
+    // predicate that returns something else than a boolean is broken whatever
+    // the input.
+    for (ix, pred) in preds.iter().enumerate() {
+        if pred.kind != simple::PredKind::Argument {
+            continue;
+        }
+        match interpreter::eval_pred(options.interpret.clone(), module, id, ix, args, None) {
+            Ok(s) if s.value == KediValue::bool(true) => {}
+            Ok(s) if s.value == KediValue::bool(false) => return Outcome::Discard,
+            Ok(s) => return Outcome::Fail(Failure::Error(not_boolean(fun, pred, s))),
+            Err(_) => return Outcome::Discard,
+        }
+    }
+
+    let result = match coverage {
+        Some(coverage) => {
+            let (result, covered) = interpreter::run_def_with_coverage(
+                options.interpret.clone(),
+                module,
+                id,
+                args.to_vec(),
+            );
+            coverage.merge(&covered);
+            result
+        }
+        None => interpreter::run_def(options.interpret.clone(), module, id, args.to_vec()),
+    };
+    let result = match result {
+        Ok(s) => s.value,
+        Err(err) if guessed_wrong(module, id, &err) => return Outcome::Discard,
+        Err(err) => return Outcome::Fail(Failure::Error(err)),
+    };
+
+    for (ix, pred) in preds.iter().enumerate() {
+        if pred.kind != simple::PredKind::Result {
+            continue;
+        }
+        match interpreter::eval_pred(
+            options.interpret.clone(),
+            module,
+            id,
+            ix,
+            args,
+            Some(&result),
+        ) {
+            Ok(s) if s.value == KediValue::bool(true) => {}
+            Ok(s) if s.value != KediValue::bool(false) => {
+                return Outcome::Fail(Failure::Error(not_boolean(fun, pred, s)))
+            }
+            Ok(_) => {
+                return Outcome::Fail(Failure::Predicate {
+                    index: ix,
+                    loc: fun.tag_map.resolve_tag(pred.body.tag()).cloned(),
+                    result,
+                })
+            }
+            Err(err) if guessed_wrong(module, id, &err) => return Outcome::Discard,
+            Err(err) => return Outcome::Fail(Failure::Error(err)),
+        }
+    }
+
+    Outcome::Pass
+}
+
+/// The error for predicate `pred` of `fun` evaluating to `s`, which is not a
+/// boolean.
+fn not_boolean<L: Clone>(
+    fun: &simple::FunDecl<L>,
+    pred: &simple::Pred,
+    s: InterpretSuccess,
+) -> InterpretError<L> {
+    InterpretError {
+        kind: InterpretErrorKind::TypeMismatch {
+            expected: "boolean",
+            actual: s.value,
+        },
+        loc: fun.tag_map.resolve_tag(pred.body.tag()).cloned(),
+        stack: vec![StackFrame {
+            function: fun.name.v.clone(),
+            call_site: None,
+        }],
+        fuel_used: s.fuel_used,
+    }
+}
+
+/// Whether `err` is a type error that can come from an input the generator
+/// had to guess, like a struct field. Those say more about the guess than
+/// about the function.
+fn guessed_wrong<L>(module: &simple::Module<L>, id: DefId, err: &InterpretError<L>) -> bool {
+    matches!(
+        err.kind,
+        InterpretErrorKind::TypeMismatch { .. }
+            | InterpretErrorKind::MissingField { .. }
+            | InterpretErrorKind::NoMatchingArm { .. }
+    ) && generate::shapes(module, id).iter().any(Shape::guesses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::InterpretErrorKind;
+    use crate::runner::runner;
+    use crate::util::loc::{Span, SrcLoc};
+
+    const POSITIVE: &str = "fn positive? n : number? n { return gt? n 0; }";
+
+    fn verify_src(src: &str) -> Vec<FunctionReport<SrcLoc>> {
+        let src = format!("{}\n{}", POSITIVE, src);
+        let out = runner(&src).unwrap();
+        verify(&out.simple, &VerifyOptions::default())
+    }
+
+    fn verdict_of(src: &str, name: &str) -> Verdict<SrcLoc> {
+        verify_src(src)
+            .into_iter()
+            .find(|r| r.name.v.0 == name)
+            .unwrap()
+            .verdict
+    }
+
+    #[test]
+    fn fibonacci_passes() {
+        let src = "
+            fn fibonacci nth
+                : positive? nth
+                : positive? result {
+                let ret = 0;
+                let prev = 1;
+                let i = 1;
+                while lte? i nth {
+                    let temp = ret;
+                    ret = add ret prev;
+                    prev = temp;
+                    i = add i 1;
+                }
+                return ret;
+            }";
+        assert_eq!(verdict_of(src, "fibonacci"), Verdict::Passed { cases: 100 });
+    }
+
+    #[test]
+    fn functions_without_predicates_are_skipped() {
+        let reports = verify_src("fn id x { return x; }");
+        let names = reports
+            .iter()
+            .map(|r| r.name.v.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["positive?"]);
+    }
+
+    #[test]
+    fn counterexamples_are_minimized() {
+        let src = "fn inc n : positive? n : gt? result 10 { return add n 1; }";
+        let full = format!("{}\n{}", POSITIVE, src);
+        let pred_start = full.find("gt? result 10").unwrap();
+
+        match verdict_of(src, "inc") {
+            Verdict::Failed(Counterexample { args, failure, .. }) => {
+                assert_eq!(args, vec![1.into()]);
+                assert_eq!(
+                    failure,
+                    Failure::Predicate {
+                        index: 1,
+                        loc: Some(SrcLoc::Known(Span::from_offset_len(pred_start, 13))),
+                        result: 2.into(),
+                    }
+                );
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shrinking_is_traced() {
+        let src = "fn big n : gt? n 50 : gt? result 1000 { return n; }";
+        match verdict_of(src, "big") {
+            Verdict::Failed(counterexample) => {
+                assert_eq!(counterexample.args, vec![51.into()]);
+                let original = counterexample.original_args();
+                assert_ne!(original, counterexample.args);
+                assert_eq!(
+                    counterexample.shrinks.first().map(|s| &s.from),
+                    original.first()
+                );
+                assert_eq!(
+                    counterexample.shrinks.last().map(|s| &s.to),
+                    Some(&51.into())
+                );
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn argument_predicates_filter_inputs() {
+        let src = "fn big n : gt? n 5 : gt? result 5 { return n; }";
+        assert_eq!(verdict_of(src, "big"), Verdict::Passed { cases: 100 });
+    }
+
+    #[test]
+    fn string_arguments_are_generated() {
+        let src = "fn f s : string? s : lt? result 3 { return length s; }";
+        match verdict_of(src, "f") {
+            Verdict::Failed(Counterexample { args, .. }) => {
+                assert!(matches!(&args[..], [KediValue::KediStr(s)] if s.chars().count() == 3));
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn boolean_arguments_are_generated() {
+        let src = "fn f b : bool? b : eq? result 0 { if b { return 1; } return 0; }";
+        match verdict_of(src, "f") {
+            Verdict::Failed(Counterexample { args, .. }) => {
+                assert_eq!(args, vec![KediValue::bool(true)]);
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn array_arguments_are_generated() {
+        let src = "fn g a : array? a : lt? result 3 { return a.length; }";
+        match verdict_of(src, "g") {
+            Verdict::Failed(Counterexample { args, .. }) => {
+                assert!(matches!(&args[..], [KediValue::KediArray(a)] if a.borrow().len() == 3));
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn struct_arguments_are_generated() {
+        let src = "struct Point { x, y }
+            fn sum p : Point? p : lt? result 10 { return p.x + p.y; }";
+        match verdict_of(src, "sum") {
+            Verdict::Failed(Counterexample { args, failure, .. }) => {
+                assert!(matches!(&args[..], [KediValue::KediStruct(s)] if s.name.0 == "Point"));
+                assert!(
+                    matches!(failure, Failure::Predicate { result, .. } if result == 10.into())
+                );
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn type_arguments_are_generated_from_every_variant() {
+        let src = "type Shape = Circle r | Square side;
+            fn circle s : Shape? s : result { return Circle? s; }";
+        assert!(matches!(
+            verdict_of(src, "circle"),
+            Verdict::Failed(Counterexample { args, .. })
+                if matches!(&args[..], [KediValue::KediStruct(s)] if s.name.0 == "Square")
+        ));
+    }
+
+    #[test]
+    fn recursive_types_are_generated() {
+        let src = "type Tree = Leaf | Node left value right;
+            fn size t : Tree? t : number? result {
+                match t {
+                    Leaf => { return 0; }
+                    Node l v r => { return 1 + size l + size r; }
+                }
+            }
+            fn small t : Tree? t : lt? result 3 { return size t; }";
+        assert_eq!(verdict_of(src, "size"), Verdict::Passed { cases: 100 });
+        match verdict_of(src, "small") {
+            Verdict::Failed(Counterexample { args, failure, .. }) => {
+                assert!(matches!(&args[..], [KediValue::KediStruct(s)] if s.name.0 == "Node"));
+                assert!(matches!(failure, Failure::Predicate { result, .. } if result == 3.into()));
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unguarded_parameters_are_not_assumed_numbers() {
+        let src = "fn apply f x : number? x : number? result { return f x; }";
+        assert!(matches!(
+            verdict_of(src, "apply"),
+            Verdict::GaveUp { cases: 0, .. }
+        ));
+
+        // Other errors still count.
+        let src = "fn inv n : gt? n 0 : number? result { return div 1 (n - 1); }";
+        assert!(matches!(
+            verdict_of(src, "inv"),
+            Verdict::Failed(Counterexample {
+                failure: Failure::Error(InterpretError {
+                    kind: InterpretErrorKind::DivisionByZero,
+                    ..
+                }),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn unsatisfiable_argument_predicates_give_up() {
+        let src = "fn f n : eq? n 123456 : number? result { return n; }";
+        assert!(matches!(
+            verdict_of(src, "f"),
+            Verdict::GaveUp { cases: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn runtime_errors_are_failures() {
+        let src = "fn spin n : number? n : number? result { while true { } return n; }";
+        match verdict_of(src, "spin") {
+            Verdict::Failed(Counterexample { args, failure, .. }) => {
+                assert_eq!(args, vec![0.into()]);
+                assert!(matches!(
+                    failure,
+                    Failure::Error(InterpretError {
+                        kind: InterpretErrorKind::OutOfFuel { .. },
+                        ..
+                    })
+                ));
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invariants_report_the_iteration_and_variables() {
+        let src = "fn sum n : number? n : number? result {
+                let s = 0;
+                for i in range(0, n) {
+                    invariant s < 10;
+                    s = s + i;
+                }
+                return s;
+            }";
+        match verdict_of(src, "sum") {
+            Verdict::Failed(Counterexample { args, failure, .. }) => {
+                assert_eq!(args, vec![6.into()]);
+                let Failure::Error(err) = failure else {
+                    panic!("expected an error, got {:?}", failure);
+                };
+                assert_eq!(
+                    err.kind.to_string(),
+                    "Invariant violated in iteration 6, with n = 6, s = 10, i = 5"
+                );
+                let full = format!("{}\n{}", POSITIVE, src);
+                let inv_start = full.find("invariant s < 10;").unwrap();
+                assert_eq!(
+                    err.loc,
+                    Some(SrcLoc::Known(Span::from_offset_len(inv_start, 17)))
+                );
+            }
+            other => panic!("unexpected verdict {:?}", other),
+        }
+    }
+
+    #[test]
+    fn predicates_have_to_be_booleans() {
+        for src in [
+            "fn f n : add n 1 { return n; }",
+            "fn f n : number? n : add n 1 { return n; }",
+        ] {
+            let full = format!("{}\n{}", POSITIVE, src);
+            let pred_start = full.find("add n 1").unwrap();
+            match verdict_of(src, "f") {
+                Verdict::Failed(Counterexample { failure, .. }) => match failure {
+                    Failure::Error(InterpretError {
+                        kind:
+                            InterpretErrorKind::TypeMismatch {
+                                expected: "boolean",
+                                ..
+                            },
+                        loc,
+                        ..
+                    }) => assert_eq!(
+                        loc,
+                        Some(SrcLoc::Known(Span::from_offset_len(pred_start, 7)))
+                    ),
+                    other => panic!("unexpected failure {:?}", other),
+                },
+                other => panic!("unexpected verdict {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn errors_in_argument_predicates_discard_the_input() {
+        let src = "fn first s : gt? (length s) 0 : string? result { return slice s 0 1; }";
+        assert!(matches!(
+            verdict_of(src, "first"),
+            Verdict::GaveUp { cases: 0, .. }
+        ));
+
+        let src = "fn first s : string? s : gt? (length s) 0 : string? result {
+            return slice s 0 1;
+        }";
+        assert_eq!(verdict_of(src, "first"), Verdict::Passed { cases: 100 });
+    }
+
+    #[test]
+    fn check_reports_the_first_violation() {
+        let src = "fn inc n : positive? n : gt? result 10 { return add n 1; }";
+        let out = runner(&format!("{}\n{}", POSITIVE, src)).unwrap();
+        match check(&out.simple, &VerifyOptions::default()) {
+            Err(Error::ContractViolation(err)) => {
+                assert_eq!(err.function.v.0, "inc");
+                assert_eq!(err.counterexample.call(&err.function.v), "inc 1");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,322 @@
+mod position;
+mod rpc;
+
+use std::collections::HashMap;
+use std::io;
+
+use kedi_lang::ide::{Analysis, Diagnostic, SymbolKind};
+use position::LineIndex;
+use serde_json::{json, Value};
+
+// JSON-RPC error codes.
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// `SymbolKind.Function` in the protocol.
+const SYMBOL_KIND_FUNCTION: u32 = 12;
+/// `SymbolKind.Enum`, for types.
+const SYMBOL_KIND_ENUM: u32 = 10;
+/// `SymbolKind.Struct`.
+const SYMBOL_KIND_STRUCT: u32 = 23;
+
+/// A language server for kedi, speaking the Language Server Protocol on
+/// stdin and stdout.
+fn main() -> io::Result<()> {
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut server = Server::default();
+
+    while let Some(message) = rpc::read_message(&mut input)? {
+        for response in server.handle(&message) {
+            rpc::write_message(&mut output, &response)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The open documents, analysed again on every change.
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, Analysis>,
+    sources: HashMap<String, String>,
+    exited: bool,
+}
+
+impl Server {
+    /// Handles a request or a notification, returning the messages to send.
+    fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+
+        let Some(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+        let result = match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // Full document sync.
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                },
+                "serverInfo": { "name": "kedi-lsp" },
+            })),
+            "shutdown" => Ok(Value::Null),
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/documentSymbol" => self.symbols(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+
+        vec![match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        }]
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                vec![self.update(uri, text)]
+            }
+            "textDocument/didChange" => {
+                // With full sync, the last change holds the whole document.
+                let changes = params["contentChanges"].as_array();
+                match changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    Some(text) => vec![self.update(uri, text)],
+                    None => vec![],
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.sources.remove(uri);
+                vec![publish_diagnostics(uri, vec![])]
+            }
+            "exit" => {
+                self.exited = true;
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Analyses the new contents of a document, returning its diagnostics.
+    fn update(&mut self, uri: &str, text: &str) -> Value {
+        let analysis = Analysis::new(text);
+        let index = LineIndex::new(text);
+        let diagnostics = analysis
+            .diagnostics()
+            .iter()
+            .map(|d| diagnostic(uri, &index, d))
+            .collect();
+
+        self.documents.insert(uri.to_string(), analysis);
+        self.sources.insert(uri.to_string(), text.to_string());
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    /// The document and the offset a request is about.
+    fn document<'s>(
+        &'s self,
+        params: &'s Value,
+    ) -> Result<(&'s str, &'s Analysis, LineIndex<'s>, usize), Error> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let (Some(analysis), Some(source)) = (self.documents.get(uri), self.sources.get(uri))
+        else {
+            return Err((INVALID_PARAMS, format!("Unknown document {}", uri)));
+        };
+        let index = LineIndex::new(source);
+        let offset = index.offset(&params["position"]).unwrap_or_default();
+        Ok((uri, analysis, index, offset))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, Error> {
+        let (uri, analysis, index, offset) = self.document(params)?;
+        Ok(match analysis.definition(offset) {
+            Some(span) => json!({ "uri": uri, "range": index.range(span) }),
+            None => Value::Null,
+        })
+    }
+
+    fn references(&self, params: &Value) -> Result<Value, Error> {
+        let (uri, analysis, index, offset) = self.document(params)?;
+        let include_declaration = params["context"]["includeDeclaration"]
+            .as_bool()
+            .unwrap_or(true);
+        let definition = analysis.definition(offset);
+
+        let locations = analysis
+            .references(offset)
+            .into_iter()
+            .filter(|span| include_declaration || Some(*span) != definition)
+            .map(|span| json!({ "uri": uri, "range": index.range(span) }))
+            .collect::<Vec<_>>();
+        Ok(json!(locations))
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value, Error> {
+        let (_, analysis, index, offset) = self.document(params)?;
+        Ok(match analysis.hover(offset) {
+            Some((span, text)) => json!({
+                "contents": { "kind": "markdown", "value": format!("```kedi\n{}\n```", text) },
+                "range": index.range(span),
+            }),
+            None => Value::Null,
+        })
+    }
+
+    fn symbols(&self, params: &Value) -> Result<Value, Error> {
+        let (_, analysis, index, _) = self.document(params)?;
+        let symbols = analysis
+            .symbols()
+            .into_iter()
+            .map(|symbol| {
+                json!({
+                    "name": symbol.name,
+                    "kind": match symbol.kind {
+                        SymbolKind::Function => SYMBOL_KIND_FUNCTION,
+                        SymbolKind::Struct => SYMBOL_KIND_STRUCT,
+                        SymbolKind::Type => SYMBOL_KIND_ENUM,
+                    },
+                    "range": index.range(symbol.span),
+                    "selectionRange": index.range(symbol.name_span),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(json!(symbols))
+    }
+}
+
+/// A JSON-RPC error code and message.
+type Error = (i64, String);
+
+fn diagnostic(uri: &str, index: &LineIndex, diagnostic: &Diagnostic) -> Value {
+    let related = diagnostic
+        .related
+        .iter()
+        .map(|(span, message)| {
+            json!({
+                "location": { "uri": uri, "range": index.range(*span) },
+                "message": message,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "range": index.range(diagnostic.span),
+        // Error
+        "severity": 1,
+        "source": "kedi",
+        "message": diagnostic.message,
+        "relatedInformation": related,
+    })
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///test.kedi";
+
+    fn open(server: &mut Server, text: &str) -> Value {
+        let mut out = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "text": text } },
+        }));
+        out.remove(0)
+    }
+
+    fn request(server: &mut Server, method: &str, line: u32, character: u32) -> Value {
+        let mut out = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+            },
+        }));
+        out.remove(0)["result"].take()
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let mut server = Server::default();
+        let published = open(&mut server, "fn f x {\n  let x = 1;\n  return x;\n}");
+
+        let diagnostics = &published["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(
+            diagnostics[0]["range"]["start"],
+            json!({ "line": 1, "character": 6 })
+        );
+        assert_eq!(
+            diagnostics[0]["relatedInformation"][0]["location"]["range"]["start"],
+            json!({ "line": 0, "character": 5 })
+        );
+
+        let published = open(&mut server, "fn f x { return x; }");
+        assert_eq!(published["params"]["diagnostics"], json!([]));
+    }
+
+    #[test]
+    fn navigation() {
+        let mut server = Server::default();
+        open(
+            &mut server,
+            "fn inc n : gt? result n { let r = add n 1; return r; }\nfn main { let y = inc 1; return y; }",
+        );
+
+        let definition = request(&mut server, "textDocument/definition", 1, 19);
+        assert_eq!(
+            definition["range"]["start"],
+            json!({ "line": 0, "character": 3 })
+        );
+
+        let references = request(&mut server, "textDocument/references", 0, 4);
+        assert_eq!(references.as_array().unwrap().len(), 2);
+
+        let hover = request(&mut server, "textDocument/hover", 1, 19);
+        assert_eq!(
+            hover["contents"]["value"],
+            "```kedi\nfn inc n\n  : gt? result n\n```"
+        );
+
+        let symbols = request(&mut server, "textDocument/documentSymbol", 0, 0);
+        assert_eq!(symbols[1]["name"], "main");
+        assert_eq!(symbols[1]["kind"], SYMBOL_KIND_FUNCTION);
+    }
+
+    #[test]
+    fn structs_and_types_are_symbols() {
+        let mut server = Server::default();
+        open(&mut server, "struct P { x }\ntype T = A | B;");
+
+        let symbols = request(&mut server, "textDocument/documentSymbol", 0, 0);
+        assert_eq!(symbols[0]["kind"], SYMBOL_KIND_STRUCT);
+        assert_eq!(symbols[1]["kind"], SYMBOL_KIND_ENUM);
+    }
+}
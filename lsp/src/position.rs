@@ -0,0 +1,76 @@
+use kedi_lang::util::loc::Span;
+use serde_json::{json, Value};
+
+/// Converts between byte offsets and the line and UTF-16 column positions
+/// of the protocol.
+pub struct LineIndex<'t> {
+    source: &'t str,
+    /// Offset of the start of every line.
+    lines: Vec<usize>,
+}
+
+impl<'t> LineIndex<'t> {
+    pub fn new(source: &'t str) -> Self {
+        let mut lines = vec![0];
+        lines.extend(source.match_indices('\n').map(|(ix, _)| ix + 1));
+        LineIndex { source, lines }
+    }
+
+    pub fn position(&self, offset: usize) -> Value {
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+        let start = self.lines[line];
+        let character = self.source[start..offset.min(self.source.len())]
+            .encode_utf16()
+            .count();
+        json!({ "line": line, "character": character })
+    }
+
+    pub fn range(&self, span: Span) -> Value {
+        json!({
+            "start": self.position(span.start.0),
+            "end": self.position(span.start.0 + span.length),
+        })
+    }
+
+    pub fn offset(&self, position: &Value) -> Option<usize> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+
+        let start = *self.lines.get(line)?;
+        let end = self
+            .lines
+            .get(line + 1)
+            .copied()
+            .unwrap_or(self.source.len());
+        let mut units = 0;
+        for (ix, c) in self.source[start..end].char_indices() {
+            if units >= character {
+                return Some(start + ix);
+            }
+            units += c.len_utf16();
+        }
+        Some(end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_round_trip() {
+        let source = "fn f x {\n  return \"é😀\" x;\n}";
+        let index = LineIndex::new(source);
+
+        let x = source.rfind('x').unwrap();
+        let position = index.position(x);
+        assert_eq!(position, json!({ "line": 1, "character": 15 }));
+        assert_eq!(index.offset(&position), Some(x));
+
+        assert_eq!(index.position(0), json!({ "line": 0, "character": 0 }));
+        assert_eq!(
+            index.position(source.len()),
+            json!({ "line": 2, "character": 1 })
+        );
+    }
+}
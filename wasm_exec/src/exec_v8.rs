@@ -1,17 +1,25 @@
+use std::sync::Once;
 use std::{thread, time::Duration};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExecuteWasmResult {
     Ok(i32),
+    /// The module could not be instantiated, or the call trapped.
+    Trap(String),
     Timeout(),
 }
 
+static INIT: Once = Once::new();
+
 pub fn execute_wasm(wasm: &[u8], export: &str, inputs: &[i32]) -> ExecuteWasmResult {
     // v8::V8::set_flags_from_string("--experimental-wasm-gc");
 
-    let platform = v8::new_default_platform(0, false).make_shared();
-    v8::V8::initialize_platform(platform);
-    v8::V8::initialize();
+    // V8 can only be initialised once per process.
+    INIT.call_once(|| {
+        let platform = v8::new_default_platform(0, false).make_shared();
+        v8::V8::initialize_platform(platform);
+        v8::V8::initialize();
+    });
 
     let isolate = &mut v8::Isolate::new(v8::CreateParams::default());
 
@@ -21,13 +29,18 @@ pub fn execute_wasm(wasm: &[u8], export: &str, inputs: &[i32]) -> ExecuteWasmRes
     let context = v8::Context::new(handle_scope, v8::ContextOptions::default());
     let scope = &mut v8::ContextScope::new(handle_scope, context);
 
+    // Traps are caught and turned into strings, so that the result is
+    // either a number or the error message.
     let c_source = format!(
         r#"
-        let bytes = new Uint8Array({:?});
-        let module = new WebAssembly.Module(bytes);
-        let instance = new WebAssembly.Instance(module);
-        const ret = instance.exports.{}({:?});
-        12;
+        try {{
+            let bytes = new Uint8Array({:?});
+            let module = new WebAssembly.Module(bytes);
+            let instance = new WebAssembly.Instance(module);
+            instance.exports[{:?}](...{:?});
+        }} catch (e) {{
+            String(e);
+        }}
         "#,
         wasm, export, inputs
     );
@@ -49,9 +62,10 @@ pub fn execute_wasm(wasm: &[u8], export: &str, inputs: &[i32]) -> ExecuteWasmRes
     });
 
     let result = match script.run(scope) {
+        Some(result) if result.is_string() => {
+            ExecuteWasmResult::Trap(result.to_rust_string_lossy(scope))
+        }
         Some(result) => {
-            println!("GOT result");
-            println!("result: {:?}", result.to_object(scope));
             let result = result.to_integer(scope).unwrap();
             ExecuteWasmResult::Ok(result.value() as i32)
         }
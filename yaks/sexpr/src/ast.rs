@@ -93,18 +93,27 @@ impl<T: SExpr> SExpr for Option<T> {
     }
 }
 
+/// Entries are sorted by their keys as printed, so that the output does not
+/// depend on the iteration order.
 impl<T: SExpr, E: SExpr> SExpr for HashMap<T, E> {
     fn to_sexpr(&self) -> SExprTerm {
-        SExprTerm::List(
-            self.iter()
-                .map(|(k, v)| list(&[k.to_sexpr(), v.to_sexpr()]))
-                .collect(),
-        )
+        let mut entries = self
+            .iter()
+            .map(|(k, v)| (k.to_sexpr(), v.to_sexpr()))
+            .collect::<Vec<_>>();
+        entries.sort_by_cached_key(|(k, _)| k.to_string());
+        SExprTerm::List(entries.into_iter().map(|(k, v)| list(&[k, v])).collect())
     }
 }
 
 // Ptr
 
+impl<T: SExpr + ?Sized> SExpr for Box<T> {
+    fn to_sexpr(&self) -> SExprTerm {
+        self.as_ref().to_sexpr()
+    }
+}
+
 impl<T: SExpr + ?Sized> SExpr for &T {
     fn to_sexpr(&self) -> SExprTerm {
         (*self).to_sexpr()
@@ -1,3 +1,4 @@
 mod ast;
+mod print;
 
 pub use ast::*;
@@ -0,0 +1,96 @@
+use std::fmt;
+
+use crate::SExprTerm;
+
+/// Prints the term on a single line.
+impl fmt::Display for SExprTerm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SExprTerm::Symbol(s) => write!(f, "{}", s),
+            SExprTerm::String(s) => write!(f, "{:?}", s),
+            SExprTerm::Number(n) => write!(f, "{}", n),
+            SExprTerm::List(items) => {
+                write!(f, "(")?;
+                for (ix, item) in items.iter().enumerate() {
+                    if ix > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+impl SExprTerm {
+    /// Prints the term, breaking the lists that do not fit in `width`
+    /// columns with one element per line.
+    pub fn pretty(&self, width: usize) -> String {
+        let mut out = String::new();
+        pretty(self, 0, width, &mut out);
+        out
+    }
+}
+
+fn pretty(term: &SExprTerm, indent: usize, width: usize, out: &mut String) {
+    let flat = term.to_string();
+    let items = match term {
+        SExprTerm::List(items) if indent + flat.len() > width && !items.is_empty() => items,
+        _ => {
+            out.push_str(&flat);
+            return;
+        }
+    };
+
+    // Lists starting with a symbol are indented like calls, the others
+    // keep their elements aligned.
+    let step = match items[0] {
+        SExprTerm::List(_) => 1,
+        _ => 2,
+    };
+
+    out.push('(');
+    pretty(&items[0], indent + 1, width, out);
+    for item in &items[1..] {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent + step));
+        pretty(item, indent + step, width, out);
+    }
+    out.push(')');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn example() -> SExprTerm {
+        SExprTerm::List(vec![
+            symbol("fun"),
+            string("main"),
+            SExprTerm::List(vec![symbol("params"), number(1), number(2)]),
+        ])
+    }
+
+    #[test]
+    fn display_is_flat() {
+        assert_eq!(example().to_string(), r#"(fun "main" (params 1 2))"#);
+        assert_eq!(string("a\"b").to_string(), r#""a\"b""#);
+    }
+
+    #[test]
+    fn pretty_breaks_long_lists() {
+        assert_eq!(example().pretty(80), r#"(fun "main" (params 1 2))"#);
+        assert_eq!(example().pretty(16), "(fun\n  \"main\"\n  (params 1 2))");
+        assert_eq!(
+            example().pretty(8),
+            "(fun\n  \"main\"\n  (params\n    1\n    2))"
+        );
+
+        let nested = SExprTerm::List(vec![example(), example()]);
+        assert_eq!(
+            nested.pretty(30),
+            "((fun \"main\" (params 1 2))\n (fun \"main\" (params 1 2)))"
+        );
+    }
+}
@@ -15,6 +15,13 @@ pub fn derive_sexpr(input: TokenStream) -> TokenStream {
 fn impl_sexpr(ast: &DeriveInput) -> TokenStream {
     let name = &ast.ident;
 
+    // Every type parameter has to be printable too.
+    let mut generics = ast.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(sexpr::SExpr));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
     match &ast.data {
         syn::Data::Struct(ref data_struct) => {
             match &data_struct.fields {
@@ -36,7 +43,7 @@ fn impl_sexpr(ast: &DeriveInput) -> TokenStream {
                         .collect();
 
                     let expanded = quote! {
-                        impl sexpr::SExpr for #name {
+                        impl #impl_generics sexpr::SExpr for #name #ty_generics #where_clause {
                             fn to_sexpr(&self) -> sexpr::SExprTerm {
                                 sexpr::SExprTerm::List(vec![
                                     sexpr::SExprTerm::Symbol(stringify!(#name).to_string()),
@@ -61,7 +68,7 @@ fn impl_sexpr(ast: &DeriveInput) -> TokenStream {
                         .collect();
 
                     let expanded = quote! {
-                        impl sexpr::SExpr for #name {
+                        impl #impl_generics sexpr::SExpr for #name #ty_generics #where_clause {
                             fn to_sexpr(&self) -> sexpr::SExprTerm {
                                 sexpr::SExprTerm::List(vec![
                                     sexpr::SExprTerm::Symbol(stringify!(#name).to_string()),
@@ -76,7 +83,7 @@ fn impl_sexpr(ast: &DeriveInput) -> TokenStream {
                 // For unit structs
                 syn::Fields::Unit => {
                     let expanded = quote! {
-                        impl sexpr::SExpr for #name {
+                        impl #impl_generics sexpr::SExpr for #name #ty_generics #where_clause {
                             fn to_sexpr(&self) -> sexpr::SExprTerm {
                                 sexpr::SExprTerm::Symbol(stringify!(#name).to_string())
                             }
@@ -119,7 +126,7 @@ fn impl_sexpr(ast: &DeriveInput) -> TokenStream {
                     }
                     syn::Fields::Unit => {
                         quote! {
-                            #name::#variant_name => sexpr::symbol(stringify!(#variant_name)),
+                            #name::#variant_name => sexpr::symbol(stringify!(#variant_name))
                         }
                     }
                 };
@@ -127,7 +134,7 @@ fn impl_sexpr(ast: &DeriveInput) -> TokenStream {
             });
 
             let expanded = quote! {
-                impl sexpr::SExpr for #name {
+                impl #impl_generics sexpr::SExpr for #name #ty_generics #where_clause {
                     fn to_sexpr(&self) -> sexpr::SExprTerm {
                         match self {
                             #(#variants),*